@@ -4,7 +4,7 @@ use dez80::Instruction;
 
 use dskmanager::*;
 use dskmanager::amstrad_basic::decode_amstrad_basic_file;
-use dskmanager::sinclair_basic::decode_sinclair_basic_file;
+use dskmanager::screen::{cpc_palette_from_basic, CPC_SCREEN_SIZE};
 use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
@@ -26,12 +26,17 @@ impl CommandCompleter {
                 "protection",
                 "disassemble",
                 "exit",
+                "format",
+                "fs-check",
                 "fs-export",
                 "fs-info",
                 "fs-list",
                 "fs-read",
+                "fs-screen",
                 "fs-show",
+                "fs-snapshot",
                 "fs-switch",
+                "fs-undelete",
                 "help",
                 "info",
                 "load",
@@ -40,6 +45,7 @@ impl CommandCompleter {
                 "ls",
                 "map",
                 "open",
+                "positions",
                 "quit",
                 "read-sector",
                 "save",
@@ -166,10 +172,21 @@ fn main() {
             }
             "open" | "load" => {
                 if parts.len() < 2 {
-                    println!("Usage: open <path>");
+                    println!("Usage: open <path> [format]");
                     continue;
                 }
-                match DiskImage::open(&parts[1]) {
+                let opened = if dskmanager::io::is_raw_file(&parts[1]) {
+                    match parts.get(2).and_then(|name| preset_spec(name)) {
+                        Some(spec) => DiskImage::open_raw(&parts[1], &spec),
+                        None => {
+                            println!("Raw images need a format: open <path> <{}>", PRESET_NAMES.join("|"));
+                            continue;
+                        }
+                    }
+                } else {
+                    DiskImage::open(&parts[1])
+                };
+                match opened {
                     Ok(img) => {
                         println!("Opened: {}", parts[1]);
                         image = Some(img);
@@ -178,16 +195,10 @@ fn main() {
                 }
             }
             "create" => {
-                let spec = if parts.len() > 1 {
-                    match parts[1].as_str() {
-                        "amstrad" => FormatSpec::amstrad_data(),
-                        "spectrum" => FormatSpec::spectrum_plus3(),
-                        "pcw" => FormatSpec::pcw_ssdd(),
-                        _ => FormatSpec::amstrad_data(),
-                    }
-                } else {
-                    FormatSpec::amstrad_data()
-                };
+                let spec = parts
+                    .get(1)
+                    .and_then(|name| preset_spec(name))
+                    .unwrap_or_else(FormatSpec::amstrad_data);
 
                 match DiskImage::create(spec) {
                     Ok(img) => {
@@ -197,6 +208,39 @@ fn main() {
                     Err(e) => println!("Error: {}", e),
                 }
             }
+            "format" => {
+                if let Some(ref img) = image {
+                    let keep_system = parts.get(1).is_some_and(|arg| arg.to_lowercase() == "system");
+                    let spec = DiskSpecification::identify(img);
+
+                    let mut formatter = match CpmFormatter::from_image(img) {
+                        Ok(formatter) => formatter.format(img.format()),
+                        Err(e) => {
+                            println!("Error: {}", e);
+                            continue;
+                        }
+                    };
+                    if keep_system {
+                        formatter = formatter.system_tracks(img);
+                    }
+
+                    match formatter.build() {
+                        Ok(formatted) => {
+                            println!(
+                                "Formatted blank {} disk ({} KB free)",
+                                spec.format,
+                                spec.usable_capacity().saturating_sub(
+                                    spec.directory_blocks as usize * spec.block_size()
+                                ) / 1024
+                            );
+                            image = Some(formatted);
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("No image loaded.");
+                }
+            }
             "info" => {
                 if let Some(ref img) = image {
                     print_info(img);
@@ -238,46 +282,20 @@ fn main() {
             }
             "fs-info" => {
                 if let Some(ref img) = image {
-                    // Determine effective filesystem type
-                    let effective_fs = match filesystem_mode {
-                        FileSystemType::Auto => img.default_filesystem(),
-                        other => other,
-                    };
-
-                    // Use appropriate filesystem
-                    match effective_fs {
-                        FileSystemType::Mgt => {
-                            match DiscipleFileSystem::new(img) {
-                                Ok(fs) => {
-                                    let mgt_info = fs.mgt().info();
-                                    println!("MGT filesystem ({})", mgt_info.system_type);
-                                    // MGT uses sectors (512 bytes each)
-                                    let sector_size = 512;
-                                    let total_sectors = mgt_info.total_sectors;
-                                    let free_sectors = mgt_info.free_sectors;
-                                    println!("Block size: {} bytes", sector_size);
-                                    println!("Total blocks: {}", total_sectors);
-                                    println!("Usable capacity: {} KB", total_sectors * sector_size / 1024);
-                                    println!("Free blocks: {}", free_sectors);
-                                    println!("Free space: {} KB", free_sectors * sector_size / 1024);
-                                }
-                                Err(e) => println!("Error: {}", e),
-                            }
-                        }
-                        FileSystemType::Cpm | FileSystemType::Auto => {
-                            match CpmFileSystem::from_image(img) {
-                                Ok(fs) => {
-                                    let info = fs.info();
-                                    println!("{} filesystem", info.fs_type);
-                                    println!("Block size: {} bytes", info.block_size);
-                                    println!("Total blocks: {}", info.total_blocks);
-                                    println!("Usable capacity: {} KB", info.total_blocks * info.block_size / 1024);
-                                    println!("Free blocks: {}", info.free_blocks);
-                                    println!("Free space: {} KB", info.free_blocks * info.block_size / 1024);
-                                }
-                                Err(e) => println!("Error: {}", e),
+                    match open_filesystem(img, filesystem_mode) {
+                        Ok(fs) => {
+                            let info = fs.info();
+                            println!("{} filesystem", info.fs_type);
+                            for (name, value) in fs.details() {
+                                println!("{}: {}", name, value);
                             }
+                            println!("Block size: {} bytes", info.block_size);
+                            println!("Total blocks: {}", info.total_blocks);
+                            println!("Usable capacity: {} KB", info.total_blocks * info.block_size / 1024);
+                            println!("Free blocks: {}", info.free_blocks);
+                            println!("Free space: {} KB", info.free_blocks * info.block_size / 1024);
                         }
+                        Err(e) => println!("Error: {}", e),
                     }
                 } else {
                     println!("No image loaded.");
@@ -285,27 +303,8 @@ fn main() {
             }
             "fs-list" | "dir" | "cat" | "ls" => {
                 if let Some(ref img) = image {
-                    // Determine effective filesystem type
-                    let effective_fs = match filesystem_mode {
-                        FileSystemType::Auto => img.default_filesystem(),
-                        other => other,
-                    };
-
-                    // Use appropriate filesystem
-                    let entries_result: Result<Vec<ExtendedDirEntry>> = match effective_fs {
-                        FileSystemType::Mgt => {
-                            match DiscipleFileSystem::new(img) {
-                                Ok(fs) => fs.read_dir_extended(),
-                                Err(e) => Err(e),
-                            }
-                        }
-                        FileSystemType::Cpm | FileSystemType::Auto => {
-                            match CpmFileSystem::from_image(img) {
-                                Ok(fs) => fs.read_dir_extended_with_deleted(),
-                                Err(e) => Err(e),
-                            }
-                        }
-                    };
+                    let entries_result = open_filesystem(img, filesystem_mode)
+                        .and_then(|fs| fs.read_dir_extended_with_deleted());
 
                     match entries_result {
                         Ok(entries) => {
@@ -361,30 +360,11 @@ fn main() {
             "fs-read" => {
                 if let Some(ref img) = image {
                     if parts.len() < 2 {
-                        println!("Usage: fs-read <filename>");
+                        println!("Usage: fs-read [user:]<filename>");
                         continue;
                     }
 
-                    // Determine effective filesystem type
-                    let effective_fs = match filesystem_mode {
-                        FileSystemType::Auto => img.default_filesystem(),
-                        other => other,
-                    };
-
-                    let data_result: Result<Vec<u8>> = match effective_fs {
-                        FileSystemType::Mgt => {
-                            match DiscipleFileSystem::new(img) {
-                                Ok(fs) => fs.read_file(&parts[1]),
-                                Err(e) => Err(e),
-                            }
-                        }
-                        FileSystemType::Cpm | FileSystemType::Auto => {
-                            match CpmFileSystem::from_image(img) {
-                                Ok(fs) => fs.read_file(&parts[1]),
-                                Err(e) => Err(e),
-                            }
-                        }
-                    };
+                    let data_result = open_filesystem(img, filesystem_mode).and_then(|fs| fs.read_file(&parts[1]));
 
                     match data_result {
                         Ok(data) => {
@@ -401,57 +381,13 @@ fn main() {
                 if let Some(ref img) = image {
                     if parts.len() < 2 {
                         println!("Usage: fs-show <filename>");
-                        println!("  Shows AMSDOS and PLUS3DOS BASIC files as text.");
+                        println!("  Shows AMSDOS, PLUS3DOS, TR-DOS and Opus BASIC files as text.");
                         continue;
                     }
 
-                    // Determine effective filesystem type
-                    let effective_fs = match filesystem_mode {
-                        FileSystemType::Auto => img.default_filesystem(),
-                        other => other,
-                    };
-
-                    // Read file with header (we need to check the header)
-                    let data_result: Result<Vec<u8>> = match effective_fs {
-                        FileSystemType::Mgt => {
-                            match DiscipleFileSystem::new(img) {
-                                Ok(fs) => fs.read_file(&parts[1]),
-                                Err(e) => Err(e),
-                            }
-                        }
-                        FileSystemType::Cpm | FileSystemType::Auto => {
-                            match CpmFileSystem::from_image(img) {
-                                Ok(fs) => {
-                                    // Read with header to check if it's BASIC
-                                    fs.read_file_binary(&parts[1], true)
-                                }
-                                Err(e) => Err(e),
-                            }
-                        }
-                    };
-
-                    match data_result {
-                        Ok(data) => {
-                            // Try Amstrad BASIC first (AMSDOS)
-                            match decode_amstrad_basic_file(&data) {
-                                Ok(Some(text)) => {
-                                    print!("{}", text);
-                                }
-                                Ok(None) => {
-                                    // Try Sinclair BASIC (PLUS3DOS)
-                                    match decode_sinclair_basic_file(&data) {
-                                        Ok(Some(text)) => {
-                                            print!("{}", text);
-                                        }
-                                        Ok(None) => {
-                                            println!("File '{}' is not a recognized BASIC file (AMSDOS or PLUS3DOS).", parts[1]);
-                                        }
-                                        Err(e) => println!("Error decoding BASIC: {}", e),
-                                    }
-                                }
-                                Err(e) => println!("Error decoding BASIC: {}", e),
-                            }
-                        }
+                    match open_filesystem(img, filesystem_mode).and_then(|fs| fs.read_basic(&parts[1])) {
+                        Ok(Some(text)) => print!("{}", text),
+                        Ok(None) => println!("File '{}' is not a recognized BASIC file.", parts[1]),
                         Err(e) => println!("Error reading file: {}", e),
                     }
                 } else {
@@ -461,10 +397,12 @@ fn main() {
             "fs-export" => {
                 if let Some(ref img) = image {
                     if parts.len() < 2 {
-                        println!("Usage: fs-export <filename> [output_path] [raw]");
+                        println!("Usage: fs-export [user:]<filename> [output_path] [raw]");
+                        println!("  CP/M files in other user areas can be selected with a prefix, e.g. 3:GAME.BIN");
                         println!("  CP/M files: AMSDOS and PLUS3DOS headers are stripped by default.");
                         println!("             Use 'raw' option to preserve headers (CP/M only).");
-                        println!("  MGT files: Data is truncated to actual file length (raw option ignored).");
+                        println!("  MGT, TR-DOS, Opus and FAT files: Data is truncated to actual file length (raw option ignored).");
+                        println!("  FAT files in subdirectories are given as a path, e.g. GAMES/PACMAN.COM");
                         continue;
                     }
                     let src_filename = &parts[1];
@@ -480,8 +418,13 @@ fn main() {
                         }
                     }
 
-                    // If no output path specified, use the source filename
-                    let output_path = output_path.unwrap_or_else(|| src_filename.clone());
+                    // If no output path specified, use the source filename without any user prefix
+                    let output_path = output_path.unwrap_or_else(|| {
+                        src_filename
+                            .split_once(':')
+                            .map_or(src_filename.as_str(), |(_, name)| name)
+                            .to_string()
+                    });
 
                     // Determine effective filesystem type
                     let effective_fs = match filesystem_mode {
@@ -491,27 +434,11 @@ fn main() {
 
                     // Read file data
                     // Raw mode only applies to CP/M filesystems (they have headers in file data)
-                    // MGT filesystems store metadata in directory entries, so raw mode is ignored
-                    let data_result: Result<Vec<u8>> = match effective_fs {
-                        FileSystemType::Mgt => {
-                            // Raw mode ignored for MGT - always truncate to real file length
-                            match DiscipleFileSystem::new(img) {
-                                Ok(fs) => fs.read_file(src_filename),
-                                Err(e) => Err(e),
-                            }
-                        }
-                        FileSystemType::Cpm | FileSystemType::Auto => {
-                            match CpmFileSystem::from_image(img) {
-                                Ok(fs) => {
-                                    if raw_mode {
-                                        fs.read_file_binary(src_filename, true)
-                                    } else {
-                                        fs.read_file(src_filename)
-                                    }
-                                }
-                                Err(e) => Err(e),
-                            }
-                        }
+                    // Other filesystems store metadata in directory entries, so raw mode is ignored
+                    let data_result: Result<Vec<u8>> = if raw_mode && matches!(effective_fs, FileSystemType::Cpm | FileSystemType::Auto) {
+                        CpmFileSystem::from_image(img).and_then(|fs| fs.read_file_binary(src_filename, true))
+                    } else {
+                        open_filesystem(img, effective_fs).and_then(|fs| fs.read_file(src_filename))
                     };
 
                     match data_result {
@@ -535,6 +462,239 @@ fn main() {
                     println!("No image loaded.");
                 }
             }
+            "fs-snapshot" => {
+                if let Some(ref img) = image {
+                    if parts.len() < 3 {
+                        println!("Usage: fs-snapshot <filename> <output.sna|output.z80>");
+                        println!("  Rebuilds a DISCiPLE/+D 48K or 128K snapshot for use in emulators.");
+                        continue;
+                    }
+
+                    match DiscipleFileSystem::new(img).and_then(|fs| fs.read_snapshot(&parts[1])) {
+                        Ok(snapshot) => match snapshot.save(&parts[2]) {
+                            Ok(_) => println!(
+                                "Exported {} ({} snapshot, PC {:04X}) to {}",
+                                parts[1],
+                                if snapshot.is_128k() { "128K" } else { "48K" },
+                                snapshot.registers.pc,
+                                parts[2]
+                            ),
+                            Err(e) => println!("Error writing snapshot: {}", e),
+                        },
+                        Err(e) => println!("Error reading snapshot: {}", e),
+                    }
+                } else {
+                    println!("No image loaded.");
+                }
+            }
+            "fs-screen" => {
+                if let Some(ref img) = image {
+                    if parts.len() < 3 {
+                        println!("Usage: fs-screen <filename> <output.png|output.ppm> [mode <n>] [flash] [loader <basic_file>]");
+                        println!("  Spectrum SCREEN$ files are 6912 bytes; larger files are CPC screens (mode 1 by default).");
+                        println!("  SAM screens use the mode saved in the directory unless 'mode' is given.");
+                        println!("  'flash' renders the second FLASH frame, with flashing ink and paper swapped.");
+                        println!("  'loader' takes the CPC pen colours from INK statements in a BASIC loader.");
+                        continue;
+                    }
+
+                    let mut mode = None;
+                    let mut flash = false;
+                    let mut loader = None;
+                    let mut bad_mode = false;
+                    let mut args = parts.iter().skip(3);
+                    while let Some(arg) = args.next() {
+                        match arg.to_lowercase().as_str() {
+                            "mode" => match args.next().map(|m| (m, m.parse::<u8>())) {
+                                Some((_, Ok(m))) => mode = Some(m),
+                                Some((m, Err(_))) => {
+                                    println!("Invalid mode '{}'", m);
+                                    bad_mode = true;
+                                }
+                                None => {
+                                    println!("Missing mode number after 'mode'");
+                                    bad_mode = true;
+                                }
+                            },
+                            "flash" => flash = true,
+                            "loader" => loader = args.next(),
+                            _ => println!("Ignoring unknown option '{}'", arg),
+                        }
+                    }
+                    if bad_mode {
+                        continue;
+                    }
+
+                    let effective_fs = match filesystem_mode {
+                        FileSystemType::Auto => img.default_filesystem(),
+                        other => other,
+                    };
+                    let sam = effective_fs == FileSystemType::Mgt && is_sam_disk(img).unwrap_or(false);
+
+                    let result = open_filesystem(img, effective_fs)
+                        .and_then(|fs| fs.read_file(&parts[1]))
+                        .and_then(|data| {
+                            if sam {
+                                let mode = match mode {
+                                    Some(mode) => mode,
+                                    None => SamFileSystem::new(img)?.screen_mode(&parts[1])?.unwrap_or(4),
+                                };
+                                render_sam(&data, mode, flash)
+                            } else if data.len() < CPC_SCREEN_SIZE {
+                                render_spectrum(&data, flash)
+                            } else {
+                                let palette = match loader {
+                                    Some(name) => {
+                                        let fs = CpmFileSystem::from_image(img)?;
+                                        // Tokenised loaders are listed first; ASCII ones are searched as saved
+                                        let listing = match decode_amstrad_basic_file(&fs.read_file_binary(name, true)?)? {
+                                            Some(text) => text,
+                                            None => String::from_utf8_lossy(&fs.read_file(name)?).into_owned(),
+                                        };
+                                        let palette = cpc_palette_from_basic(&listing);
+                                        if palette.is_none() {
+                                            println!("No INK statements in {}, using the default colours.", name);
+                                        }
+                                        palette
+                                    }
+                                    None => None,
+                                };
+                                render_cpc(&data, mode.unwrap_or(1), palette.as_ref().map(|p| &p[..]))
+                            }
+                        });
+
+                    match result {
+                        Ok(screen) => match screen.save(&parts[2]) {
+                            Ok(_) => println!(
+                                "Rendered {} ({}x{}) to {}",
+                                parts[1],
+                                screen.width(),
+                                screen.height(),
+                                parts[2]
+                            ),
+                            Err(e) => println!("Error writing image: {}", e),
+                        },
+                        Err(e) => println!("Error rendering screen: {}", e),
+                    }
+                } else {
+                    println!("No image loaded.");
+                }
+            }
+            "fs-check" => {
+                if let Some(ref mut img) = image {
+                    let repair = parts.get(1).is_some_and(|arg| arg.to_lowercase() == "repair");
+
+                    let effective_fs = match filesystem_mode {
+                        FileSystemType::Auto => img.default_filesystem(),
+                        other => other,
+                    };
+                    if !matches!(effective_fs, FileSystemType::Cpm | FileSystemType::Einstein) {
+                        println!("Checking is only supported on CP/M and Einstein filesystems.");
+                        continue;
+                    }
+
+                    let result = CpmFileSystem::from_image_mut(img).and_then(|mut fs| {
+                        if repair {
+                            fs.repair()
+                        } else {
+                            fs.check()
+                        }
+                    });
+
+                    match result {
+                        Ok(report) => {
+                            for problem in &report.problems {
+                                println!("{}", problem);
+                            }
+                            println!(
+                                "{} directory entries checked, {} problems found",
+                                report.entries_checked,
+                                report.problems.len()
+                            );
+                            if repair {
+                                println!("{} directory entries repaired", report.entries_repaired);
+                            }
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("No image loaded.");
+                }
+            }
+            "fs-undelete" => {
+                if let Some(ref mut img) = image {
+                    if parts.len() < 2 {
+                        println!("Usage: fs-undelete <filename> [user] [partial]");
+                        println!("  Restores a deleted CP/M file into a user area (default 0).");
+                        println!("  With 'partial', restores the part before its first reused block.");
+                        continue;
+                    }
+
+                    let partial = parts.last().is_some_and(|p| p.eq_ignore_ascii_case("partial"));
+                    let user_arg = parts.get(2).filter(|_| parts.len() > 3 || !partial);
+                    let user = match user_arg.map(|u| u.parse::<u8>()) {
+                        None => 0,
+                        Some(Ok(user)) => user,
+                        Some(Err(_)) => {
+                            println!("Invalid user number: {}", parts[2]);
+                            continue;
+                        }
+                    };
+
+                    let effective_fs = match filesystem_mode {
+                        FileSystemType::Auto => img.default_filesystem(),
+                        other => other,
+                    };
+                    if !matches!(effective_fs, FileSystemType::Cpm | FileSystemType::Einstein) {
+                        println!("Undelete is only supported on CP/M and Einstein filesystems.");
+                        continue;
+                    }
+
+                    let mut fs = match CpmFileSystem::from_image_mut(img) {
+                        Ok(fs) => fs,
+                        Err(e) => {
+                            println!("Error: {}", e);
+                            continue;
+                        }
+                    };
+
+                    match fs.check_deleted(&parts[1]) {
+                        Ok(report) if partial && !report.is_recoverable() => {
+                            match fs.undelete_file_partial(&parts[1], user) {
+                                Ok(_) => println!(
+                                    "Restored part of {} to user {}, lost blocks: {:?}",
+                                    report.name, user, report.lost_blocks
+                                ),
+                                Err(e) => println!("Error: {}", e),
+                            }
+                        }
+                        Ok(report) if report.is_recoverable() => {
+                            match fs.undelete_file(&parts[1], user) {
+                                Ok(_) => println!(
+                                    "Restored {} ({} bytes, {} blocks) to user {}",
+                                    report.name,
+                                    report.size,
+                                    report.intact_blocks.len(),
+                                    user
+                                ),
+                                Err(e) => println!("Error: {}", e),
+                            }
+                        }
+                        Ok(report) => {
+                            println!("{} cannot be fully recovered.", report.name);
+                            if report.missing_extents {
+                                println!("  Some directory entries have been overwritten.");
+                            }
+                            println!("  Reused blocks: {:?}", report.reused_blocks);
+                            println!("  Intact blocks: {:?}", report.intact_blocks);
+                            println!("  Blocks lost by a partial restore: {:?}", report.lost_blocks);
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("No image loaded.");
+                }
+            }
             "fs-switch" => {
                 if parts.len() < 2 {
                     // Show current mode
@@ -547,7 +707,7 @@ fn main() {
                         filesystem_mode
                     };
                     println!("Filesystem mode: {} (effective: {})", filesystem_mode, effective);
-                    println!("Options: auto, cpm, mgt");
+                    println!("Options: auto, cpm, mgt, trdos, opus, fat, einstein");
                 } else {
                     match FileSystemType::from_str(&parts[1]) {
                         Some(mode) => {
@@ -556,7 +716,7 @@ fn main() {
                         }
                         None => {
                             println!("Unknown filesystem type: {}", parts[1]);
-                            println!("Options: auto, cpm, mgt");
+                            println!("Options: auto, cpm, mgt, trdos, opus, fat, einstein");
                         }
                     }
                 }
@@ -567,6 +727,11 @@ fn main() {
                         println!("Usage: save <path>");
                         continue;
                     }
+                    if dskmanager::io::is_imd_file(&parts[1]) {
+                        for loss in dskmanager::io::imd_losses(img) {
+                            println!("Warning: {}", loss);
+                        }
+                    }
                     match img.save(&parts[1]) {
                         Ok(_) => println!("Saved to: {}", parts[1]),
                         Err(e) => println!("Error: {}", e),
@@ -615,6 +780,23 @@ fn main() {
                     println!("No image loaded.");
                 }
             }
+            "positions" => {
+                if let Some(ref img) = image {
+                    if parts.len() >= 2 {
+                        let track: u8 = parts[1].parse().unwrap_or(0);
+                        let side: u8 = if parts.len() >= 3 {
+                            parts[2].parse().unwrap_or(0)
+                        } else {
+                            0
+                        };
+                        list_sector_positions(img, side, track);
+                    } else {
+                        println!("Usage: positions <track> [side]");
+                    }
+                } else {
+                    println!("No image loaded.");
+                }
+            }
             "specification" | "spec" => {
                 if let Some(ref img) = image {
                     let spec = DiskSpecification::identify(img);
@@ -750,22 +932,50 @@ fn parse_command_line(input: &str) -> Vec<String> {
     parts
 }
 
+/// Format names accepted by `create` and by `open` for raw images
+const PRESET_NAMES: &[&str] = &["amstrad", "amstrad-ds", "spectrum", "spectrum-ds", "pcw", "pcw-ds", "pc360", "pc720"];
+
+/// Look up a format preset by name
+fn preset_spec(name: &str) -> Option<FormatSpec> {
+    match name.to_lowercase().as_str() {
+        "amstrad" => Some(FormatSpec::amstrad_data()),
+        "amstrad-ds" => Some(FormatSpec::amstrad_data_ds()),
+        "spectrum" => Some(FormatSpec::spectrum_plus3()),
+        "spectrum-ds" => Some(FormatSpec::spectrum_plus3_ds()),
+        "pcw" => Some(FormatSpec::pcw_ssdd()),
+        "pcw-ds" => Some(FormatSpec::pcw_dsdd()),
+        "pc360" => Some(FormatSpec::ibm_pc_360k()),
+        "pc720" => Some(FormatSpec::ibm_pc_720k()),
+        _ => None,
+    }
+}
+
 fn print_help() {
     println!("Available commands:");
-    println!("  open <path>                    - Open a disk image file (use quotes for paths with spaces)");
-    println!("  create [amstrad|spectrum|pcw]  - Create a new DSK image");
+    println!("  open <path> [format]           - Open a disk image file (use quotes for paths with spaces)");
+    println!("                                   Raw .img/.raw/.cpm dumps need a format, as for create");
+    println!("  create [format]                - Create a new DSK image (amstrad, amstrad-ds, spectrum,");
+    println!("                                   spectrum-ds, pcw, pcw-ds, pc360 or pc720)");
+    println!("  format [system]                - Reformat as a blank disk of the detected specification");
+    println!("                                   (system copies the reserved tracks from the current disk)");
     println!("  info                           - Show disk information");
     println!("  tracks                         - List all tracks");
     println!("  sectors [track] [side]         - List sectors (all or specific track/side)");
+    println!("  positions <track> [side]       - Show sector header positions from the Offset-Info block");
     println!("  read-sector <s> <t> <id>       - Read and display a sector");
     println!("  fs-info                        - Show filesystem information");
     println!("  fs-list                        - List files on disk");
-    println!("  fs-read <filename>             - Read and hex dump file from disk");
-    println!("  fs-show <filename>             - Display AMSDOS and PLUS3DOS BASIC files as text");
-    println!("  fs-export <file> [output_path] [raw] - Export file from disk to host filesystem");
+    println!("  fs-read [user:]<filename>      - Read and hex dump file from disk (e.g. 3:GAME.BIN)");
+    println!("  fs-show <filename>             - Display AMSDOS, PLUS3DOS, TR-DOS and Opus BASIC files as text");
+    println!("  fs-export [user:]<file> [output_path] [raw] - Export file from disk to host filesystem");
     println!("                                         (output_path defaults to filename if not specified)");
     println!("                                         (strips AMSDOS/PLUS3DOS headers by default, use 'raw' to preserve)");
-    println!("  fs-switch [auto|cpm|mgt]       - Show or set filesystem type (auto detects from image format)");
+    println!("  fs-snapshot <file> <output>    - Export a DISCiPLE/+D snapshot as .sna or .z80 (by output extension)");
+    println!("  fs-screen <file> <output> [mode n] [flash] [loader <file>] - Render a Spectrum, CPC or SAM screen");
+    println!("                                   as .png or .ppm (by output extension)");
+    println!("  fs-check [repair]              - Check a CP/M directory for damage, optionally repairing it");
+    println!("  fs-undelete <file> [user] [partial] - Restore a deleted CP/M file to a user area (default 0)");
+    println!("  fs-switch [auto|cpm|mgt|trdos|opus|fat|einstein] - Show or set filesystem type (auto detects from image format)");
     println!("  protection                     - Detect copy protection scheme");
     println!("  specification                  - Detect and display disk specification (spec)");
     println!("  disassemble [track] [sector]   - Disassemble Z80 code from sector (dasm)");
@@ -860,7 +1070,11 @@ fn list_sectors_on_track(image: &DiskImage, side: u8, track: u8) {
             for (idx, sector) in track_data.sectors().iter().enumerate() {
                 let fdc_size = format!("{} ({})", sector.id.size_code, sector.advertised_size());
                 let fdc_flags = format!("{},{}", sector.fdc_status1.0, sector.fdc_status2.0);
-                let status = sector.status(filler);
+                let status = if sector.is_weak() {
+                    format!("Weak x{}", sector.copy_count())
+                } else {
+                    sector.status(filler).to_string()
+                };
                 println!(
                     "{:<6} {:<6} {:<6} {:<6} {:<12} {:<10} {:<10} {:<12}",
                     idx,
@@ -882,6 +1096,33 @@ fn list_sectors_on_track(image: &DiskImage, side: u8, track: u8) {
     }
 }
 
+fn list_sector_positions(image: &DiskImage, side: u8, track: u8) {
+    let Some(track_data) = image.disks().get(side as usize).and_then(|d| d.get_track(track)) else {
+        println!("Track {} not found on side {}.", track, side);
+        return;
+    };
+
+    if !track_data.has_positions() {
+        println!("No sector positions recorded for this track (needs an Offset-Info block).");
+        return;
+    }
+
+    match track_data.track_length {
+        Some(length) if length > 0 => println!("Track length: {} bytes", length),
+        _ => println!("Track length: unknown"),
+    }
+    println!("{:<6} {:<6} {:<10} {:<10}", "Index", "ID", "Offset", "Distance");
+    println!("{}", "-".repeat(34));
+
+    for (idx, position) in track_data.sector_positions().iter().enumerate() {
+        let distance = position
+            .distance
+            .map(|d| d.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!("{:<6} {:<6} {:<10} {:<10}", idx, position.sector_id, position.offset, distance);
+    }
+}
+
 fn list_all_sectors(image: &DiskImage) {
     for (_side_idx, disk) in image.disks().iter().enumerate() {
         for track in disk.tracks() {
@@ -895,7 +1136,11 @@ fn list_all_sectors(image: &DiskImage) {
             for (idx, sector) in track.sectors().iter().enumerate() {
                 let fdc_size = format!("{} ({})", sector.id.size_code, sector.advertised_size());
                 let fdc_flags = format!("{},{}", sector.fdc_status1.0, sector.fdc_status2.0);
-                let status = sector.status(filler);
+                let status = if sector.is_weak() {
+                    format!("Weak x{}", sector.copy_count())
+                } else {
+                    sector.status(filler).to_string()
+                };
                 println!(
                     "{:<6} {:<6} {:<6} {:<6} {:<12} {:<10} {:<10} {:<12}",
                     idx,
@@ -3,12 +3,144 @@
 use crate::error::{DskError, Result};
 use crate::filesystem::{
     try_parse_header, DirEntry, ExtendedDirEntry, FileAttributes, FileHeader, FileSystem,
-    FileSystemInfo, HeaderType,
+    FileSystemInfo, HeaderType, ImageRef,
 };
 use crate::format::{AllocationSize, DiskSpecification};
 use crate::image::DiskImage;
 use std::collections::HashMap;
 
+/// Key identifying a file in the directory: user number and filename
+type FileKey = (u8, String);
+
+/// How much of a deleted CP/M file is still recoverable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedFileReport {
+    /// Filename (8.3 format, e.g., "FILENAME.TXT")
+    pub name: String,
+    /// File size in bytes recorded in the deleted directory entries
+    pub size: usize,
+    /// Allocation blocks not claimed by any live file
+    pub intact_blocks: Vec<u16>,
+    /// Allocation blocks since reused by live files or the directory
+    pub reused_blocks: Vec<u16>,
+    /// Whether directory entries for some of the file's extents have been overwritten
+    pub missing_extents: bool,
+    /// Blocks a partial recovery leaves behind, from the first reused block
+    /// or overwritten directory entry on
+    pub lost_blocks: Vec<u16>,
+}
+
+impl DeletedFileReport {
+    /// Check whether the whole file can be restored
+    pub fn is_recoverable(&self) -> bool {
+        self.reused_blocks.is_empty() && !self.missing_extents
+    }
+}
+
+/// A problem found by [`CpmFileSystem::check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpmProblem {
+    /// Index of the directory entry at fault
+    pub entry: usize,
+    /// File the entry belongs to, as `user:NAME.EXT`
+    pub file: String,
+    /// What is wrong with the entry
+    pub kind: CpmProblemKind,
+}
+
+impl std::fmt::Display for CpmProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Entry {} ({}): {}", self.entry, self.file, self.kind)
+    }
+}
+
+/// Kinds of CP/M directory damage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpmProblemKind {
+    /// A block is allocated to more than one directory entry
+    SharedBlock {
+        /// Block number
+        block: u16,
+        /// File that claimed the block first
+        first_file: String,
+    },
+    /// A block number is past the end of the disk
+    BlockOutOfRange {
+        /// Block number
+        block: u16,
+    },
+    /// A file has been allocated one of the directory's own blocks
+    DirectoryBlock {
+        /// Block number
+        block: u16,
+    },
+    /// A file's extent numbers skip or repeat
+    ExtentOutOfSequence {
+        /// Extent number expected at this point
+        expected: u16,
+        /// Extent number found
+        found: u16,
+    },
+    /// An extent's record count does not match the blocks it allocates
+    RecordCountMismatch {
+        /// Records in the extent
+        records: usize,
+        /// Blocks allocated to the extent
+        blocks: usize,
+    },
+    /// An extent other than the last one is not full
+    ShortExtent {
+        /// Records in the extent
+        records: usize,
+    },
+    /// The filename contains characters CP/M does not allow
+    BadFilename,
+}
+
+impl std::fmt::Display for CpmProblemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CpmProblemKind::SharedBlock { block, first_file } => {
+                write!(f, "block {} is also allocated to {}", block, first_file)
+            }
+            CpmProblemKind::BlockOutOfRange { block } => {
+                write!(f, "block {} is past the end of the disk", block)
+            }
+            CpmProblemKind::DirectoryBlock { block } => {
+                write!(f, "block {} belongs to the directory", block)
+            }
+            CpmProblemKind::ExtentOutOfSequence { expected, found } => {
+                write!(f, "extent {} found where extent {} was expected", found, expected)
+            }
+            CpmProblemKind::RecordCountMismatch { records, blocks } => {
+                write!(f, "{} records do not match {} allocated blocks", records, blocks)
+            }
+            CpmProblemKind::ShortExtent { records } => {
+                write!(f, "only {} records but further extents follow", records)
+            }
+            CpmProblemKind::BadFilename => write!(f, "invalid characters in filename"),
+        }
+    }
+}
+
+/// Result of a CP/M filesystem consistency check
+#[derive(Debug, Clone, Default)]
+pub struct CpmCheckReport {
+    /// Number of live directory entries examined
+    pub entries_checked: usize,
+    /// Problems found
+    pub problems: Vec<CpmProblem>,
+    /// Number of directory entries rewritten by [`CpmFileSystem::repair`]
+    pub entries_repaired: usize,
+}
+
+impl CpmCheckReport {
+    /// Check whether no problems were found
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
 /// CP/M directory entry (32 bytes)
 #[derive(Debug, Clone)]
 struct CpmDirEntry {
@@ -87,7 +219,6 @@ impl CpmDirEntry {
     }
 
     /// Check if this entry is deleted (user == 0xE5)
-    #[allow(dead_code)]
     fn is_deleted(&self) -> bool {
         self.user == 0xE5
     }
@@ -145,21 +276,51 @@ impl CpmDirEntry {
         ((self.extent_high as u16) << 5) | ((self.extent_low as u16) & 0x1F)
     }
 
+    /// Number of 128-byte records held by this entry
+    ///
+    /// When an entry covers several 16K logical extents (`extent_mask` > 0) the low
+    /// bits of the extent number count the full logical extents before the last one.
+    fn records(&self, extent_mask: u8) -> usize {
+        (self.extent_low & extent_mask) as usize * 128 + self.record_count as usize
+    }
+
     /// Calculate the size from this extent's record count
-    fn extent_size(&self, bytes_in_last: u8) -> usize {
-        let records = self.record_count as usize;
+    fn extent_size(&self, extent_mask: u8, is_last: bool) -> usize {
+        let records = self.records(extent_mask);
         if records == 0 {
             return 0;
         }
 
-        if bytes_in_last > 0 {
+        if is_last && self.bytes_in_last_record > 0 {
             // Last record is partial
-            (records - 1) * 128 + bytes_in_last as usize
+            (records - 1) * 128 + self.bytes_in_last_record as usize
         } else {
             records * 128
         }
     }
 
+    /// Set the number of records held by this entry, keeping the extent number's high bits
+    fn set_records(&mut self, records: usize, extent_mask: u8) {
+        let full_logical = records.saturating_sub(1) / 128;
+        self.extent_low = (self.extent_low & !extent_mask) | full_logical as u8;
+        self.record_count = (records - full_logical * 128) as u8;
+    }
+
+    /// Serialize this entry back into its 32-byte directory form
+    fn to_bytes(&self) -> [u8; 32] {
+        let mut data = [0u8; 32];
+        data[0] = self.user;
+        data[1..9].copy_from_slice(&self.filename);
+        data[9..12].copy_from_slice(&self.extension);
+        data[12] = self.extent_low;
+        data[13] = self.bytes_in_last_record;
+        data[14] = self.extent_high;
+        data[15] = self.record_count;
+        let len = self.allocation.len().min(16);
+        data[16..16 + len].copy_from_slice(&self.allocation[..len]);
+        data
+    }
+
     /// Extract allocation blocks from this directory entry
     fn extract_blocks_for_validation(&self, spec: &DiskSpecification) -> Vec<u16> {
         let mut blocks = Vec::new();
@@ -223,7 +384,7 @@ impl CpmDirEntry {
 
 /// CP/M filesystem implementation using disk specification
 pub struct CpmFileSystem<'a> {
-    image: &'a DiskImage,
+    image: ImageRef<'a>,
     spec: DiskSpecification,
     directory_entries: Vec<CpmDirEntry>,
 }
@@ -234,12 +395,29 @@ impl<'a> CpmFileSystem<'a> {
         let directory_entries = Self::read_directory(image, &spec)?;
 
         Ok(Self {
-            image,
+            image: ImageRef::Shared(image),
+            spec,
+            directory_entries,
+        })
+    }
+
+    /// Create a new writable CP/M filesystem from an image using a detected specification
+    pub fn new_mut(image: &'a mut DiskImage, spec: DiskSpecification) -> Result<Self> {
+        let directory_entries = Self::read_directory(image, &spec)?;
+
+        Ok(Self {
+            image: ImageRef::Exclusive(image),
             spec,
             directory_entries,
         })
     }
 
+    /// Re-read the directory after it has been modified
+    fn refresh_directory(&mut self) -> Result<()> {
+        self.directory_entries = Self::read_directory(self.image.get(), &self.spec)?;
+        Ok(())
+    }
+
     /// Read the directory entries from the disk
     fn read_directory(image: &DiskImage, spec: &DiskSpecification) -> Result<Vec<CpmDirEntry>> {
         Self::read_directory_internal(image, spec, false)
@@ -289,52 +467,40 @@ impl<'a> CpmFileSystem<'a> {
         let dir_size_bytes = max_entries * 32;
         let mut dir_data = Vec::with_capacity(dir_size_bytes);
 
-        // Directory starts at the first sector of the first track after reserved tracks
-        let start_track = spec.reserved_tracks;
-
-        // Read sectors in logical order (by sector ID) starting from reserved_tracks
-        let disk = image.get_disk(0).ok_or_else(|| DskError::filesystem("No disk side 0"))?;
-
-        let mut bytes_read = 0;
-        for track_num in start_track..spec.tracks_per_side {
-            let track = match disk.get_track(track_num) {
-                Some(t) => t,
-                None => continue,
-            };
-
-            // Get sectors sorted by ID (logical order)
-            let mut sector_ids: Vec<u8> = track.sectors().iter().map(|s| s.id.sector).collect();
-            sector_ids.sort();
-
-            for sector_id in sector_ids {
-                if let Some(sector) = track.get_sector(sector_id) {
-                    let data = sector.data();
-                    let to_copy = (dir_size_bytes - bytes_read).min(data.len());
+        // Directory starts at the first logical sector after the reserved tracks
+        let mut logical_sector = spec.reserved_tracks as usize * spec.sectors_per_track as usize;
+        let total_sectors = spec.tracks_per_side as usize
+            * spec.side_count() as usize
+            * spec.sectors_per_track as usize;
+
+        while dir_data.len() < dir_size_bytes && logical_sector < total_sectors {
+            if let Some((side, track, sector_id)) =
+                Self::locate_sector(image, spec, logical_sector)
+            {
+                if let Ok(data) = image.read_sector(side, track, sector_id) {
+                    let to_copy = (dir_size_bytes - dir_data.len()).min(data.len());
                     dir_data.extend_from_slice(&data[..to_copy]);
-                    bytes_read += to_copy;
-
-                    if bytes_read >= dir_size_bytes {
-                        return Ok(dir_data);
-                    }
                 }
             }
+            logical_sector += 1;
         }
 
         Ok(dir_data)
     }
 
     /// Merge extents for files that span multiple directory entries
-    fn merge_extents(&self) -> HashMap<String, Vec<&CpmDirEntry>> {
+    fn merge_extents(&self) -> HashMap<FileKey, Vec<&CpmDirEntry>> {
         Self::merge_extents_from_entries(&self.directory_entries)
     }
 
     /// Merge extents from a slice of directory entries
-    fn merge_extents_from_entries(entries: &[CpmDirEntry]) -> HashMap<String, Vec<&CpmDirEntry>> {
-        let mut files: HashMap<String, Vec<&CpmDirEntry>> = HashMap::new();
+    ///
+    /// Files with the same name in different user areas are kept apart.
+    fn merge_extents_from_entries(entries: &[CpmDirEntry]) -> HashMap<FileKey, Vec<&CpmDirEntry>> {
+        let mut files: HashMap<FileKey, Vec<&CpmDirEntry>> = HashMap::new();
 
         for entry in entries {
-            let filename = entry.filename_str();
-            files.entry(filename).or_default().push(entry);
+            files.entry((entry.user, entry.filename_str())).or_default().push(entry);
         }
 
         // Sort extents by extent number
@@ -345,6 +511,48 @@ impl<'a> CpmFileSystem<'a> {
         files
     }
 
+    /// Resolve a filename, optionally prefixed with a user number (`3:NAME.EXT`)
+    ///
+    /// A bare name matches any user area. If it is present in several, user 0
+    /// is chosen when it has the file, otherwise the name is ambiguous.
+    fn resolve_file(files: &HashMap<FileKey, Vec<&CpmDirEntry>>, name: &str) -> Result<FileKey> {
+        let (user, filename) = split_user_prefix(name)?;
+        let filename = filename.trim();
+
+        let mut users: Vec<u8> = files
+            .keys()
+            .filter(|(u, n)| n == filename && user.is_none_or(|wanted| *u == wanted))
+            .map(|(u, _)| *u)
+            .collect();
+        users.sort();
+
+        let user = match users.as_slice() {
+            [] => return Err(DskError::FileNotFound(name.to_string())),
+            [only] => *only,
+            [0, ..] => 0,
+            _ => {
+                let areas: Vec<String> = users.iter().map(|u| u.to_string()).collect();
+                return Err(DskError::filesystem(format!(
+                    "{} exists in user areas {}, use user:NAME to choose one",
+                    filename,
+                    areas.join(", ")
+                )));
+            }
+        };
+
+        Ok((user, filename.to_string()))
+    }
+
+    /// Calculate a file's size in bytes from its sorted extents
+    fn file_size(&self, extents: &[&CpmDirEntry]) -> usize {
+        let extent_mask = self.spec.extent_mask();
+        extents
+            .iter()
+            .enumerate()
+            .map(|(i, extent)| extent.extent_size(extent_mask, i == extents.len() - 1))
+            .sum()
+    }
+
     /// Convert a block number to a logical sector number (accounting for reserved tracks)
     fn block_to_sector(&self, block_num: u16) -> usize {
         let block_size = self.spec.block_size();
@@ -359,20 +567,43 @@ impl<'a> CpmFileSystem<'a> {
         (block_num as usize) * sectors_per_block + reserved_sectors
     }
 
+    /// Find the physical location (side, track, sector ID) of a logical sector
+    fn sector_location(&self, logical_sector: usize) -> Option<(u8, u8, u8)> {
+        Self::locate_sector(self.image.get(), &self.spec, logical_sector)
+    }
+
+    /// Map a logical sector to its physical (side, track, sector ID)
+    ///
+    /// Logical tracks are spread across sides according to the specification's
+    /// side mode, and logical sectors within a track are taken in ascending
+    /// sector ID order.
+    pub(crate) fn locate_sector(
+        image: &DiskImage,
+        spec: &DiskSpecification,
+        logical_sector: usize,
+    ) -> Option<(u8, u8, u8)> {
+        let sectors_per_track = spec.sectors_per_track as usize;
+        let (side, track_num) = spec.physical_track(logical_sector / sectors_per_track)?;
+        let sector_in_track = logical_sector % sectors_per_track;
+
+        let track = image.get_disk(side)?.get_track(track_num)?;
+        let mut sector_ids = track.sector_ids();
+        sector_ids.sort();
+
+        sector_ids
+            .get(sector_in_track)
+            .map(|&sector_id| (side, track_num, sector_id))
+    }
+
     /// Read data from allocation blocks
     fn read_blocks(&self, blocks: &[u16]) -> Result<Vec<u8>> {
         let block_size = self.spec.block_size();
         let sector_size = self.spec.sector_size as usize;
         let sectors_per_block = block_size / sector_size;
-        let sectors_per_track = self.spec.sectors_per_track as usize;
+        let image = self.image.get();
 
         let mut data = Vec::new();
 
-        let disk = self
-            .image
-            .get_disk(0)
-            .ok_or_else(|| DskError::filesystem("No disk side 0"))?;
-
         for &block_num in blocks {
             if block_num == 0 {
                 continue;
@@ -383,31 +614,16 @@ impl<'a> CpmFileSystem<'a> {
 
             // Read all sectors for this block
             for i in 0..sectors_per_block {
-                let absolute_sector = start_sector + i;
-                let track_num = absolute_sector / sectors_per_track;
-                let sector_in_track = absolute_sector % sectors_per_track;
-
-                if let Some(track) = disk.get_track(track_num as u8) {
-                    // Get sectors sorted by ID and pick the nth one
-                    let mut sector_ids: Vec<u8> =
-                        track.sectors().iter().map(|s| s.id.sector).collect();
-                    sector_ids.sort();
-
-                    if sector_in_track < sector_ids.len() {
-                        let sector_id = sector_ids[sector_in_track];
-                        if let Some(sector) = track.get_sector(sector_id) {
-                            data.extend_from_slice(sector.data());
-                        } else {
-                            // Sector not found, pad with zeros
-                            data.extend(std::iter::repeat(0).take(sector_size));
-                        }
-                    } else {
-                        // Not enough sectors, pad with zeros
-                        data.extend(std::iter::repeat(0).take(sector_size));
-                    }
-                } else {
-                    // Track not found, pad with zeros
-                    data.extend(std::iter::repeat(0).take(sector_size));
+                let sector_data = self
+                    .sector_location(start_sector + i)
+                    .and_then(|(side, track, sector_id)| {
+                        image.read_sector(side, track, sector_id).ok()
+                    });
+
+                match sector_data {
+                    Some(sector_data) => data.extend_from_slice(sector_data),
+                    // Sector or track not found, pad with zeros
+                    None => data.resize(data.len() + sector_size, 0),
                 }
             }
         }
@@ -415,6 +631,114 @@ impl<'a> CpmFileSystem<'a> {
         Ok(data)
     }
 
+    /// Write a whole allocation block (data must be exactly one block long)
+    fn write_block(&mut self, block_num: u16, data: &[u8]) -> Result<()> {
+        let sector_size = self.spec.sector_size as usize;
+        let start_sector = self.block_to_sector(block_num);
+
+        for (i, chunk) in data.chunks(sector_size).enumerate() {
+            let (side, track, sector_id) =
+                self.sector_location(start_sector + i).ok_or_else(|| {
+                    DskError::filesystem(format!("Block {} is outside the disk", block_num))
+                })?;
+            self.image
+                .get_mut()?
+                .write_sector(side, track, sector_id, chunk)?;
+        }
+
+        Ok(())
+    }
+
+    /// Overwrite a single 32-byte directory entry in place
+    fn write_directory_entry(&mut self, index: usize, entry: &[u8; 32]) -> Result<()> {
+        let sector_size = self.spec.sector_size as usize;
+        let offset = index * 32;
+        let logical_sector = self.block_to_sector(0) + offset / sector_size;
+        let offset_in_sector = offset % sector_size;
+
+        let (side, track, sector_id) = self.sector_location(logical_sector).ok_or_else(|| {
+            DskError::filesystem(format!("Directory entry {} is outside the disk", index))
+        })?;
+
+        let mut sector_data = self
+            .image
+            .get()
+            .read_sector(side, track, sector_id)?
+            .to_vec();
+        if sector_data.len() < offset_in_sector + 32 {
+            return Err(DskError::filesystem(format!(
+                "Directory sector for entry {} is too short",
+                index
+            )));
+        }
+        sector_data[offset_in_sector..offset_in_sector + 32].copy_from_slice(entry);
+
+        self.image
+            .get_mut()?
+            .write_sector(side, track, sector_id, &sector_data)
+    }
+
+    /// Find the indexes of unused (0xE5) directory entries
+    fn free_directory_entries(&self) -> Result<Vec<usize>> {
+        let dir_data =
+            Self::read_directory_data(self.image.get(), &self.spec, self.spec.directory_entries())?;
+
+        Ok(dir_data
+            .chunks_exact(32)
+            .enumerate()
+            .filter(|(_, chunk)| chunk[0] == 0xE5)
+            .map(|(index, _)| index)
+            .collect())
+    }
+
+    /// Find all blocks not claimed by the directory or by any file, in ascending order
+    fn free_blocks(&self) -> Vec<u16> {
+        let used = self.block_usage();
+
+        (0..used.len())
+            .filter(|&block| !used[block])
+            .map(|block| block as u16)
+            .collect()
+    }
+
+    /// Flag each allocation block that is used by the directory or a live file
+    fn block_usage(&self) -> Vec<bool> {
+        let block_count = self.spec.block_count() as usize;
+        let mut used = vec![false; block_count];
+
+        // Directory blocks always come first
+        let dir_blocks = (self.spec.directory_blocks as usize).min(block_count);
+        used[..dir_blocks].fill(true);
+
+        for entry in &self.directory_entries {
+            for block in self.extract_blocks(entry) {
+                if let Some(flag) = used.get_mut(block as usize) {
+                    *flag = true;
+                }
+            }
+        }
+
+        used
+    }
+
+    /// Encode block numbers into a 16-byte allocation map
+    fn encode_allocation(&self, blocks: &[u16]) -> Vec<u8> {
+        let mut allocation = vec![0u8; 16];
+        match self.spec.allocation_size {
+            AllocationSize::Byte => {
+                for (slot, &block) in allocation.iter_mut().zip(blocks) {
+                    *slot = block as u8;
+                }
+            }
+            AllocationSize::Word => {
+                for (slot, &block) in allocation.chunks_exact_mut(2).zip(blocks) {
+                    slot.copy_from_slice(&block.to_le_bytes());
+                }
+            }
+        }
+        allocation
+    }
+
     /// Extract allocation blocks from directory entry
     fn extract_blocks(&self, entry: &CpmDirEntry) -> Vec<u16> {
         let mut blocks = Vec::new();
@@ -451,6 +775,11 @@ impl<'a> CpmFileSystem<'a> {
         &self.spec
     }
 
+    /// Get the mounted disk image
+    pub(crate) fn image(&self) -> &DiskImage {
+        self.image.get()
+    }
+
     /// Read the first block of a file to parse headers
     fn read_first_block(&self, blocks: &[u16]) -> Result<Vec<u8>> {
         if blocks.is_empty() {
@@ -474,14 +803,15 @@ impl<'a> CpmFileSystem<'a> {
     /// Internal method to list directory entries with extended information
     fn read_dir_extended_internal(&self, include_deleted: bool) -> Result<Vec<ExtendedDirEntry>> {
         // Read directory entries (with or without deleted)
-        let dir_entries = Self::read_directory_internal(self.image, &self.spec, include_deleted)?;
+        let dir_entries =
+            Self::read_directory_internal(self.image.get(), &self.spec, include_deleted)?;
         
         // Merge extents from the directory entries
         let files = Self::merge_extents_from_entries(&dir_entries);
         let mut entries = Vec::new();
         let block_size = self.spec.block_size();
 
-        for (filename, extents) in files {
+        for ((_, filename), extents) in files {
             if extents.is_empty() {
                 continue;
             }
@@ -495,15 +825,7 @@ impl<'a> CpmFileSystem<'a> {
             }
 
             // Calculate total file size from all extents
-            let mut total_size = 0;
-            for (i, extent) in extents.iter().enumerate() {
-                let is_last = i == extents.len() - 1;
-                if is_last {
-                    total_size += extent.extent_size(extent.bytes_in_last_record);
-                } else {
-                    total_size += extent.record_count as usize * 128;
-                }
-            }
+            let total_size = self.file_size(&extents);
 
             // Calculate allocated size
             let allocated = all_blocks.len() * block_size;
@@ -530,8 +852,8 @@ impl<'a> CpmFileSystem<'a> {
             });
         }
 
-        // Sort by filename
-        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        // Sort by filename, then user area
+        entries.sort_by(|a, b| a.name.cmp(&b.name).then(a.user.cmp(&b.user)));
 
         Ok(entries)
     }
@@ -551,6 +873,16 @@ impl CpmFileSystem<'_> {
         CpmFileSystem::new(image, spec)
     }
 
+    /// Create a writable CP/M filesystem from an image, auto-detecting the specification
+    pub fn from_image_mut(image: &mut DiskImage) -> Result<CpmFileSystem<'_>> {
+        let spec = DiskSpecification::identify(image);
+
+        if spec.sector_size == 0 || spec.sectors_per_track == 0 {
+            return Err(DskError::filesystem("Invalid disk specification"));
+        }
+
+        CpmFileSystem::new_mut(image, spec)
+    }
 }
 
 impl<'a> FileSystem for CpmFileSystem<'a> {
@@ -568,7 +900,7 @@ impl<'a> FileSystem for CpmFileSystem<'a> {
         Self: Sized,
     {
         Err(DskError::filesystem(
-            "Mutable CP/M filesystem not yet implemented",
+            "Use CpmFileSystem::from_image_mut() directly",
         ))
     }
 
@@ -576,7 +908,7 @@ impl<'a> FileSystem for CpmFileSystem<'a> {
         let files = self.merge_extents();
         let mut entries = Vec::new();
 
-        for (filename, extents) in files {
+        for ((_, filename), extents) in files {
             if extents.is_empty() {
                 continue;
             }
@@ -584,17 +916,7 @@ impl<'a> FileSystem for CpmFileSystem<'a> {
             let first_extent = extents[0];
 
             // Calculate total file size from all extents
-            let mut total_size = 0;
-            for (i, extent) in extents.iter().enumerate() {
-                let is_last = i == extents.len() - 1;
-                if is_last {
-                    // Last extent may have partial last record
-                    total_size += extent.extent_size(extent.bytes_in_last_record);
-                } else {
-                    // Full extent
-                    total_size += extent.record_count as usize * 128;
-                }
-            }
+            let total_size = self.file_size(&extents);
 
             entries.push(DirEntry {
                 name: filename,
@@ -605,18 +927,15 @@ impl<'a> FileSystem for CpmFileSystem<'a> {
             });
         }
 
-        // Sort by filename
-        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        // Sort by filename, then user area
+        entries.sort_by(|a, b| a.name.cmp(&b.name).then(a.user.cmp(&b.user)));
 
         Ok(entries)
     }
 
     fn read_file(&self, name: &str) -> Result<Vec<u8>> {
         let files = self.merge_extents();
-
-        let extents = files
-            .get(name)
-            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        let extents = &files[&Self::resolve_file(&files, name)?];
 
         if extents.is_empty() {
             return Ok(Vec::new());
@@ -632,15 +951,7 @@ impl<'a> FileSystem for CpmFileSystem<'a> {
         }
 
         // Trim to actual file size
-        let mut actual_size = 0;
-        for (i, extent) in extents.iter().enumerate() {
-            let is_last = i == extents.len() - 1;
-            if is_last {
-                actual_size += extent.extent_size(extent.bytes_in_last_record);
-            } else {
-                actual_size += extent.record_count as usize * 128;
-            }
-        }
+        let actual_size = self.file_size(extents);
 
         if file_data.len() > actual_size {
             file_data.truncate(actual_size);
@@ -663,12 +974,104 @@ impl<'a> FileSystem for CpmFileSystem<'a> {
         Ok(file_data)
     }
 
-    fn write_file(&mut self, _name: &str, _data: &[u8]) -> Result<()> {
-        Err(DskError::filesystem("Writing files not yet implemented"))
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        // Fail early rather than after allocating if mounted read-only
+        self.image.get_mut()?;
+
+        let (user, bare_name) = split_user_prefix(name)?;
+        let (filename, extension) = encode_filename(bare_name)?;
+        let mut entry = CpmDirEntry {
+            index: 0,
+            user: user.unwrap_or(0),
+            filename,
+            extension,
+            extent_low: 0,
+            bytes_in_last_record: 0,
+            extent_high: 0,
+            record_count: 0,
+            allocation: vec![0; 16],
+        };
+
+        if self.merge_extents().contains_key(&(entry.user, entry.filename_str())) {
+            return Err(DskError::filesystem(format!(
+                "File already exists: {}:{}",
+                entry.user,
+                entry.filename_str()
+            )));
+        }
+
+        let block_size = self.spec.block_size();
+        let blocks_per_extent = self.spec.blocks_per_extent();
+        let records_per_extent = blocks_per_extent * block_size / 128;
+        let logical_per_extent = self.spec.extent_mask() as usize + 1;
+
+        // Even an empty file needs one directory entry
+        let blocks_needed = data.len().div_ceil(block_size);
+        let entries_needed = blocks_needed.div_ceil(blocks_per_extent).max(1);
+
+        let free_blocks = self.free_blocks();
+        let free_entries = self.free_directory_entries()?;
+        if free_blocks.len() < blocks_needed || free_entries.len() < entries_needed {
+            return Err(DskError::DiskFull);
+        }
+        let blocks = &free_blocks[..blocks_needed];
+
+        // Write the data, padding the final block with CP/M end-of-file markers
+        for (&block, chunk) in blocks.iter().zip(data.chunks(block_size)) {
+            let mut block_data = chunk.to_vec();
+            block_data.resize(block_size, 0x1A);
+            self.write_block(block, &block_data)?;
+        }
+
+        // Write one directory entry per group of blocks
+        let total_records = data.len().div_ceil(128);
+        for (i, &dir_index) in free_entries.iter().take(entries_needed).enumerate() {
+            let records = total_records
+                .saturating_sub(i * records_per_extent)
+                .min(records_per_extent);
+            let full_logical = records.saturating_sub(1) / 128;
+            let extent = i * logical_per_extent + full_logical;
+            let is_last = i == entries_needed - 1;
+
+            let entry_blocks: Vec<u16> = blocks
+                .iter()
+                .skip(i * blocks_per_extent)
+                .take(blocks_per_extent)
+                .copied()
+                .collect();
+
+            entry.extent_low = (extent & 0x1F) as u8;
+            entry.extent_high = (extent >> 5) as u8;
+            entry.record_count = (records - full_logical * 128) as u8;
+            entry.bytes_in_last_record = if is_last { (data.len() % 128) as u8 } else { 0 };
+            entry.allocation = self.encode_allocation(&entry_blocks);
+
+            self.write_directory_entry(dir_index, &entry.to_bytes())?;
+        }
+
+        self.refresh_directory()
+    }
+
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        let extents = self.file_extents(name)?;
+        if extents[0].is_read_only() {
+            return Err(DskError::filesystem(format!("File is read-only: {}", name)));
+        }
+
+        self.update_extents(&extents, |entry| entry.user = 0xE5)
     }
 
-    fn delete_file(&mut self, _name: &str) -> Result<()> {
-        Err(DskError::filesystem("Deleting files not yet implemented"))
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        CpmFileSystem::read_dir_extended(self)
+    }
+
+    fn read_dir_extended_with_deleted(&self) -> Result<Vec<ExtendedDirEntry>> {
+        CpmFileSystem::read_dir_extended_with_deleted(self)
+    }
+
+    /// Read a BASIC program, using its AMSDOS or PLUS3DOS header
+    fn read_basic(&self, name: &str) -> Result<Option<String>> {
+        super::decode_basic(&self.read_file_binary(name, true)?)
     }
 
     fn info(&self) -> FileSystemInfo {
@@ -702,10 +1105,7 @@ impl CpmFileSystem<'_> {
     ///                      If false, strips headers and returns only file data.
     pub fn read_file_binary(&self, name: &str, include_header: bool) -> Result<Vec<u8>> {
         let files = self.merge_extents();
-
-        let extents = files
-            .get(name)
-            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        let extents = &files[&Self::resolve_file(&files, name)?];
 
         if extents.is_empty() {
             return Ok(Vec::new());
@@ -721,15 +1121,7 @@ impl CpmFileSystem<'_> {
         }
 
         // Trim to actual file size
-        let mut actual_size = 0;
-        for (i, extent) in extents.iter().enumerate() {
-            let is_last = i == extents.len() - 1;
-            if is_last {
-                actual_size += extent.extent_size(extent.bytes_in_last_record);
-            } else {
-                actual_size += extent.record_count as usize * 128;
-            }
-        }
+        let actual_size = self.file_size(extents);
 
         if file_data.len() > actual_size {
             file_data.truncate(actual_size);
@@ -753,6 +1145,477 @@ impl CpmFileSystem<'_> {
 
         Ok(file_data)
     }
+
+    /// Rename a file, keeping its attributes
+    ///
+    /// A `user:` prefix on the new name moves the file to that user area.
+    pub fn rename_file(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        let extents = self.file_extents(old_name)?;
+        if extents[0].is_read_only() {
+            return Err(DskError::filesystem(format!("File is read-only: {}", old_name)));
+        }
+
+        let (user, bare_name) = split_user_prefix(new_name)?;
+        let (filename, extension) = encode_filename(bare_name)?;
+        let mut renamed = extents[0].clone();
+        renamed.user = user.unwrap_or(renamed.user);
+        renamed.filename = filename;
+        renamed.extension = extension;
+        if self.merge_extents().contains_key(&(renamed.user, renamed.filename_str())) {
+            return Err(DskError::filesystem(format!(
+                "File already exists: {}:{}",
+                renamed.user,
+                renamed.filename_str()
+            )));
+        }
+
+        // High bits carry attributes, so only replace the low seven bits of each character
+        let new_user = renamed.user;
+        self.update_extents(&extents, |entry| {
+            entry.user = new_user;
+            for (byte, &new) in entry.filename.iter_mut().zip(&filename) {
+                *byte = (*byte & 0x80) | new;
+            }
+            for (byte, &new) in entry.extension.iter_mut().zip(&extension) {
+                *byte = (*byte & 0x80) | new;
+            }
+        })
+    }
+
+    /// Set the read-only, system and archive attributes of a file
+    pub fn set_attributes(&mut self, name: &str, attributes: &FileAttributes) -> Result<()> {
+        let extents = self.file_extents(name)?;
+        let flags = [attributes.read_only, attributes.system, attributes.archive];
+
+        self.update_extents(&extents, |entry| {
+            for (byte, &set) in entry.extension.iter_mut().zip(&flags) {
+                *byte = if set { *byte | 0x80 } else { *byte & 0x7F };
+            }
+        })
+    }
+
+    /// Check how much of a deleted file can still be recovered
+    pub fn check_deleted(&self, name: &str) -> Result<DeletedFileReport> {
+        let extents = self.deleted_extents(name)?;
+        Ok(self.deleted_report(&extents))
+    }
+
+    /// Restore a deleted file into the given user area
+    ///
+    /// Nothing is written unless the whole file is intact. Otherwise the error
+    /// lists which blocks were reused and which survive, and
+    /// `undelete_file_partial` restores what it can.
+    pub fn undelete_file(&mut self, name: &str, user: u8) -> Result<DeletedFileReport> {
+        Self::check_user(user)?;
+
+        let extents = self.deleted_extents(name)?;
+        let report = self.deleted_report(&extents);
+
+        if report.missing_extents {
+            return Err(DskError::filesystem(format!(
+                "Cannot recover {}: some of its directory entries have been overwritten",
+                report.name
+            )));
+        }
+        if !report.reused_blocks.is_empty() {
+            return Err(DskError::filesystem(format!(
+                "Cannot fully recover {}: blocks {} have been reused (intact blocks: {})",
+                report.name,
+                format_blocks(&report.reused_blocks),
+                format_blocks(&report.intact_blocks)
+            )));
+        }
+        self.check_not_live(&report.name, user)?;
+
+        self.update_extents(&extents, |entry| entry.user = user)?;
+        Ok(report)
+    }
+
+    /// Restore the intact start of a deleted file into the given user area
+    ///
+    /// The file is cut short at its first reused block or overwritten
+    /// directory entry, and the report's `lost_blocks` lists what is left
+    /// behind. Fails if not even the first block survives.
+    pub fn undelete_file_partial(&mut self, name: &str, user: u8) -> Result<DeletedFileReport> {
+        Self::check_user(user)?;
+
+        let extents = self.deleted_extents(name)?;
+        let report = self.deleted_report(&extents);
+        let (restored, _) = self.recoverable_extents(&extents);
+
+        if restored.is_empty() {
+            return Err(DskError::filesystem(format!(
+                "Cannot recover {}: its first block has been reused",
+                report.name
+            )));
+        }
+        self.check_not_live(&report.name, user)?;
+
+        self.update_extents(&restored, |entry| entry.user = user)?;
+        Ok(report)
+    }
+
+    /// Check the directory for damage without changing anything
+    pub fn check(&self) -> Result<CpmCheckReport> {
+        Ok(self.analyse()?.0)
+    }
+
+    /// Check the directory and write back corrected entries
+    ///
+    /// Bad, shared and directory block references are dropped from the later
+    /// entry, record counts are trimmed to the remaining blocks and invalid
+    /// filename characters become `_`. Extent sequence problems are reported
+    /// but left alone, as there is no safe way to fix them.
+    pub fn repair(&mut self) -> Result<CpmCheckReport> {
+        self.image.get_mut()?;
+
+        let (mut report, fixes) = self.analyse()?;
+        for entry in &fixes {
+            self.write_directory_entry(entry.index, &entry.to_bytes())?;
+        }
+        report.entries_repaired = fixes.len();
+
+        self.refresh_directory()?;
+        Ok(report)
+    }
+
+    /// Find directory problems, along with corrected copies of the damaged entries
+    fn analyse(&self) -> Result<(CpmCheckReport, Vec<CpmDirEntry>)> {
+        // Read entries without the validity pruning applied when mounting
+        let dir_data = Self::read_directory_data(
+            self.image.get(),
+            &self.spec,
+            self.spec.directory_entries(),
+        )?;
+        let entries: Vec<CpmDirEntry> = dir_data
+            .chunks_exact(32)
+            .enumerate()
+            .filter_map(|(index, chunk)| CpmDirEntry::parse(chunk, index))
+            .collect();
+
+        let block_count = self.spec.block_count();
+        let directory_blocks = self.spec.directory_blocks as u16;
+        let records_per_block = self.spec.block_size() / 128;
+        let extent_mask = self.spec.extent_mask();
+
+        let mut problems = Vec::new();
+        let mut claims: HashMap<u16, String> = HashMap::new();
+        let mut fixes = Vec::new();
+
+        for original in &entries {
+            let mut entry = original.clone();
+            let file = format!("{}:{}", entry.user, entry.filename_str());
+            let index = entry.index;
+
+            let problem = |kind| CpmProblem { entry: index, file: file.clone(), kind };
+
+            for block in self.extract_blocks(original) {
+                let kind = if block >= block_count {
+                    CpmProblemKind::BlockOutOfRange { block }
+                } else if block < directory_blocks {
+                    CpmProblemKind::DirectoryBlock { block }
+                } else if let Some(first_file) = claims.get(&block) {
+                    CpmProblemKind::SharedBlock { block, first_file: first_file.clone() }
+                } else {
+                    claims.insert(block, file.clone());
+                    continue;
+                };
+                problems.push(problem(kind));
+                self.clear_block(&mut entry, block);
+            }
+
+            let blocks = self.extract_blocks(&entry);
+            let records = entry.records(extent_mask);
+            let blocks_needed = records.div_ceil(records_per_block);
+            if blocks_needed != blocks.len() {
+                problems.push(problem(CpmProblemKind::RecordCountMismatch {
+                    records,
+                    blocks: blocks.len(),
+                }));
+                if blocks.len() < blocks_needed {
+                    entry.set_records(blocks.len() * records_per_block, extent_mask);
+                } else {
+                    for &block in &blocks[blocks_needed..] {
+                        self.clear_block(&mut entry, block);
+                    }
+                }
+            }
+
+            if !valid_directory_name(&entry.filename, &entry.extension) {
+                problems.push(problem(CpmProblemKind::BadFilename));
+                repair_directory_field(&mut entry.filename);
+                repair_directory_field(&mut entry.extension);
+                if entry.filename[0] & 0x7F == b' ' {
+                    entry.filename[0] = (entry.filename[0] & 0x80) | b'_';
+                }
+            }
+
+            if entry.to_bytes() != original.to_bytes() {
+                fixes.push(entry);
+            }
+        }
+
+        // Extents of each file must run on from one another, and all but the last must be full
+        let logical_per_entry = extent_mask as usize + 1;
+        let records_per_entry = self.spec.blocks_per_extent() * records_per_block;
+        let mut files: Vec<_> = Self::merge_extents_from_entries(&entries).into_iter().collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for ((user, name), extents) in files {
+            let file = format!("{}:{}", user, name);
+            for (i, extent) in extents.iter().enumerate() {
+                let problem = |kind| CpmProblem { entry: extent.index, file: file.clone(), kind };
+
+                let found = extent.extent_number();
+                if found as usize / logical_per_entry != i {
+                    let expected = (i * logical_per_entry) as u16;
+                    problems.push(problem(CpmProblemKind::ExtentOutOfSequence { expected, found }));
+                    break;
+                }
+
+                let records = extent.records(extent_mask);
+                if i < extents.len() - 1 && records < records_per_entry {
+                    problems.push(problem(CpmProblemKind::ShortExtent { records }));
+                }
+            }
+        }
+
+        let report = CpmCheckReport {
+            entries_checked: entries.len(),
+            problems,
+            entries_repaired: 0,
+        };
+        Ok((report, fixes))
+    }
+
+    /// Remove the last reference to a block from an entry's allocation map
+    fn clear_block(&self, entry: &mut CpmDirEntry, block: u16) {
+        match self.spec.allocation_size {
+            AllocationSize::Byte => {
+                if let Some(slot) = entry.allocation.iter().rposition(|&b| b as u16 == block) {
+                    entry.allocation[slot] = 0;
+                }
+            }
+            AllocationSize::Word => {
+                let slot = entry
+                    .allocation
+                    .chunks_exact(2)
+                    .rposition(|pair| u16::from_le_bytes([pair[0], pair[1]]) == block);
+                if let Some(slot) = slot {
+                    entry.allocation[slot * 2..slot * 2 + 2].fill(0);
+                }
+            }
+        }
+    }
+
+    /// Get a copy of the directory entries of a deleted file, sorted by extent
+    fn deleted_extents(&self, name: &str) -> Result<Vec<CpmDirEntry>> {
+        let name = name.trim();
+        let mut extents: Vec<CpmDirEntry> =
+            Self::read_directory_internal(self.image.get(), &self.spec, true)?
+                .into_iter()
+                .filter(|entry| entry.is_deleted() && entry.filename_str() == name)
+                .collect();
+
+        if extents.is_empty() {
+            return Err(DskError::FileNotFound(name.to_string()));
+        }
+
+        extents.sort_by_key(|e| e.extent_number());
+        if extents.windows(2).any(|w| w[0].extent_number() == w[1].extent_number()) {
+            return Err(DskError::filesystem(format!(
+                "Several deleted files are named {}, their extents cannot be told apart",
+                name
+            )));
+        }
+
+        Ok(extents)
+    }
+
+    /// Check a user number given for a restored file
+    fn check_user(user: u8) -> Result<()> {
+        if user > 0x1F {
+            return Err(DskError::filesystem(format!("Invalid user number: {}", user)));
+        }
+        Ok(())
+    }
+
+    /// Check that restoring a file will not clash with a live one
+    fn check_not_live(&self, name: &str, user: u8) -> Result<()> {
+        if self.merge_extents().contains_key(&(user, name.to_string())) {
+            return Err(DskError::filesystem(format!("File already exists: {}:{}", user, name)));
+        }
+        Ok(())
+    }
+
+    /// Split a deleted file's extents at the first reused block or missing
+    /// extent, giving the entries to restore and the blocks left behind
+    ///
+    /// The entry holding the first reused block keeps only the blocks before it.
+    fn recoverable_extents(&self, extents: &[CpmDirEntry]) -> (Vec<CpmDirEntry>, Vec<u16>) {
+        let used = self.block_usage();
+        let extent_mask = self.spec.extent_mask();
+        let logical_per_entry = extent_mask as usize + 1;
+        let records_per_block = self.spec.block_size() / 128;
+
+        let mut restored = Vec::new();
+        let mut lost = Vec::new();
+        for (i, extent) in extents.iter().enumerate() {
+            let blocks = self.extract_blocks(extent);
+            if !lost.is_empty() || extent.extent_number() as usize / logical_per_entry != i {
+                lost.extend(blocks);
+                continue;
+            }
+
+            let intact = blocks
+                .iter()
+                .take_while(|&&block| !used.get(block as usize).copied().unwrap_or(true))
+                .count();
+            let mut entry = extent.clone();
+            if intact < blocks.len() {
+                for &block in &blocks[intact..] {
+                    self.clear_block(&mut entry, block);
+                }
+                entry.set_records(entry.records(extent_mask).min(intact * records_per_block), extent_mask);
+                entry.bytes_in_last_record = 0;
+                lost.extend(&blocks[intact..]);
+            }
+            if intact > 0 {
+                restored.push(entry);
+            }
+        }
+        (restored, lost)
+    }
+
+    /// Compare a deleted file's blocks against those in use by live files
+    fn deleted_report(&self, extents: &[CpmDirEntry]) -> DeletedFileReport {
+        let used = self.block_usage();
+        let mut intact_blocks = Vec::new();
+        let mut reused_blocks = Vec::new();
+
+        for extent in extents {
+            for block in self.extract_blocks(extent) {
+                if used.get(block as usize).copied().unwrap_or(true) {
+                    reused_blocks.push(block);
+                } else {
+                    intact_blocks.push(block);
+                }
+            }
+        }
+
+        // Each directory entry covers extent_mask + 1 logical extents
+        let logical_per_entry = self.spec.extent_mask() as usize + 1;
+        let missing_extents = extents
+            .iter()
+            .enumerate()
+            .any(|(i, e)| e.extent_number() as usize / logical_per_entry != i);
+
+        let extent_refs: Vec<&CpmDirEntry> = extents.iter().collect();
+        DeletedFileReport {
+            name: extents[0].filename_str(),
+            size: self.file_size(&extent_refs),
+            intact_blocks,
+            reused_blocks,
+            missing_extents,
+            lost_blocks: self.recoverable_extents(extents).1,
+        }
+    }
+
+    /// Get a copy of all directory entries belonging to a file
+    fn file_extents(&self, name: &str) -> Result<Vec<CpmDirEntry>> {
+        let files = self.merge_extents();
+        let extents = &files[&Self::resolve_file(&files, name)?];
+        Ok(extents.iter().map(|&e| e.clone()).collect())
+    }
+
+    /// Apply a change to each of a file's directory entries and write them back
+    fn update_extents<F>(&mut self, extents: &[CpmDirEntry], change: F) -> Result<()>
+    where
+        F: Fn(&mut CpmDirEntry),
+    {
+        self.image.get_mut()?;
+
+        for extent in extents {
+            let mut entry = extent.clone();
+            change(&mut entry);
+            self.write_directory_entry(entry.index, &entry.to_bytes())?;
+        }
+
+        self.refresh_directory()
+    }
+}
+
+/// Format a list of block numbers for messages
+fn format_blocks(blocks: &[u16]) -> String {
+    if blocks.is_empty() {
+        return "none".to_string();
+    }
+    blocks.iter().map(|b| b.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Split an optional `user:` prefix (0-31) from a filename
+fn split_user_prefix(name: &str) -> Result<(Option<u8>, &str)> {
+    match name.split_once(':') {
+        Some((user, filename)) => {
+            let user = user
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|&u| u <= 0x1F)
+                .ok_or_else(|| DskError::InvalidFilename(name.to_string()))?;
+            Ok((Some(user), filename))
+        }
+        None => Ok((None, name)),
+    }
+}
+
+/// Check whether a character may appear in a CP/M filename
+fn valid_directory_char(c: u8) -> bool {
+    c > b' ' && c < 0x7F && !b"<>.,;:=?*[]|".contains(&c)
+}
+
+/// Length of a space-padded directory field, ignoring attribute bits
+fn directory_field_len(field: &[u8]) -> usize {
+    field.iter().rposition(|&b| b & 0x7F != b' ').map_or(0, |p| p + 1)
+}
+
+/// Check a space-padded directory name, ignoring attribute bits
+fn valid_directory_name(filename: &[u8; 8], extension: &[u8; 3]) -> bool {
+    let valid_field = |field: &[u8]| {
+        field[..directory_field_len(field)].iter().all(|&b| valid_directory_char(b & 0x7F))
+    };
+    filename[0] & 0x7F != b' ' && valid_field(filename) && valid_field(extension)
+}
+
+/// Replace invalid characters before the padding of a directory field with `_`
+fn repair_directory_field(field: &mut [u8]) {
+    let len = directory_field_len(field);
+    for byte in &mut field[..len] {
+        if !valid_directory_char(*byte & 0x7F) {
+            *byte = (*byte & 0x80) | b'_';
+        }
+    }
+}
+
+/// Convert a filename into space-padded 8.3 directory form
+fn encode_filename(name: &str) -> Result<([u8; 8], [u8; 3])> {
+    let upper = name.trim().to_uppercase();
+    let (base, ext) = upper.rsplit_once('.').unwrap_or((upper.as_str(), ""));
+
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || !base.bytes().chain(ext.bytes()).all(valid_directory_char)
+    {
+        return Err(DskError::InvalidFilename(name.to_string()));
+    }
+
+    let mut filename = [b' '; 8];
+    let mut extension = [b' '; 3];
+    filename[..base.len()].copy_from_slice(base.as_bytes());
+    extension[..ext.len()].copy_from_slice(ext.as_bytes());
+
+    Ok((filename, extension))
 }
 
 #[cfg(test)]
@@ -816,4 +1679,313 @@ mod tests {
         // Extent number = (high << 5) | (low & 0x1F) = (1 << 5) | 3 = 35
         assert_eq!(entry.extent_number(), 35);
     }
+
+    /// Create a blank, formatted +3 disk (all sectors 0xE5)
+    fn blank_plus3_image() -> DiskImage {
+        DiskImage::create(crate::format::FormatSpec::spectrum_plus3()).unwrap()
+    }
+
+    #[test]
+    fn test_encode_filename() {
+        let (name, ext) = encode_filename("game.bin").unwrap();
+        assert_eq!(&name, b"GAME    ");
+        assert_eq!(&ext, b"BIN");
+
+        let (name, ext) = encode_filename("README").unwrap();
+        assert_eq!(&name, b"README  ");
+        assert_eq!(&ext, b"   ");
+
+        assert!(encode_filename("TOOLONGNAME.TXT").is_err());
+        assert!(encode_filename("A.TEXT").is_err());
+        assert!(encode_filename("BAD*.TXT").is_err());
+        assert!(encode_filename(".TXT").is_err());
+    }
+
+    #[test]
+    fn test_write_and_read_file() {
+        let mut image = blank_plus3_image();
+        let data: Vec<u8> = (0..40_000).map(|i| (i % 251) as u8).collect();
+
+        {
+            let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+            fs.write_file("big.dat", &data).unwrap();
+            fs.write_file("EMPTY", &[]).unwrap();
+        }
+
+        let fs = CpmFileSystem::from_image(&image).unwrap();
+        let entries = fs.read_dir().unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let big = entries.iter().find(|e| e.name == "BIG.DAT").unwrap();
+        assert_eq!(big.size, data.len());
+        assert_eq!(fs.read_file_binary("BIG.DAT", true).unwrap(), data);
+        assert!(fs.read_file("EMPTY").unwrap().is_empty());
+
+        // 40000 bytes = 40 blocks of 1K, plus 2 directory blocks
+        let info = fs.info();
+        assert_eq!(info.free_blocks, info.total_blocks - 42);
+    }
+
+    #[test]
+    fn test_double_sided_layouts() {
+        use crate::format::specification::{AllocationSize, DiskSpecSide};
+
+        let data: Vec<u8> = (0..250_000).map(|i| (i % 253) as u8).collect();
+
+        for side in [
+            DiskSpecSide::DoubleAlternate,
+            DiskSpecSide::DoubleSuccessive,
+            DiskSpecSide::DoubleReverse,
+        ] {
+            let mut image =
+                DiskImage::create(crate::format::FormatSpec::spectrum_plus3_ds()).unwrap();
+            let mut spec = DiskSpecification::identify(&image);
+            spec.side = side;
+            spec.allocation_size = AllocationSize::Word;
+
+            {
+                let mut fs = CpmFileSystem::new_mut(&mut image, spec.clone()).unwrap();
+                fs.write_file("BIG.DAT", &data).unwrap();
+            }
+
+            // More than one side's worth of data, so side 1 must have been used
+            let side1_used = image
+                .get_disk(1)
+                .unwrap()
+                .tracks()
+                .iter()
+                .any(|t| t.sectors().iter().any(|s| s.data().iter().any(|&b| b != 0xE5)));
+            assert!(side1_used, "{} did not use side 1", side);
+
+            let fs = CpmFileSystem::new(&image, spec).unwrap();
+            assert_eq!(fs.read_file_binary("BIG.DAT", true).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_write_file_with_extent_mask() {
+        let mut image = blank_plus3_image();
+        let mut spec = DiskSpecification::identify(&image);
+        spec.block_shift = 4;
+        let data: Vec<u8> = (0..50_000).map(|i| (i % 13) as u8).collect();
+
+        {
+            let mut fs = CpmFileSystem::new_mut(&mut image, spec.clone()).unwrap();
+            assert_eq!(fs.specification().extent_mask(), 1);
+            fs.write_file("TEST.BIN", &data).unwrap();
+        }
+
+        let fs = CpmFileSystem::new(&image, spec).unwrap();
+        assert_eq!(fs.read_dir().unwrap()[0].size, data.len());
+        assert_eq!(fs.read_file_binary("TEST.BIN", true).unwrap(), data);
+    }
+
+    #[test]
+    fn test_write_file_disk_full() {
+        let mut image = blank_plus3_image();
+        let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+
+        let too_big = vec![0u8; 200 * 1024];
+        assert!(matches!(fs.write_file("BIG", &too_big), Err(DskError::DiskFull)));
+
+        // Run out of directory entries with empty files
+        for i in 0..64 {
+            fs.write_file(&format!("F{}", i), &[]).unwrap();
+        }
+        assert!(matches!(fs.write_file("ONEMORE", &[]), Err(DskError::DiskFull)));
+    }
+
+    #[test]
+    fn test_delete_file() {
+        let mut image = blank_plus3_image();
+        let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+        fs.write_file("KEEP.TXT", b"keep").unwrap();
+        fs.write_file("GONE.BIN", &vec![0x55; 20_000]).unwrap();
+        let free_before = fs.info().free_blocks;
+
+        fs.delete_file("GONE.BIN").unwrap();
+        assert!(matches!(fs.read_file("GONE.BIN"), Err(DskError::FileNotFound(_))));
+        assert_eq!(fs.read_dir().unwrap().len(), 1);
+        assert_eq!(fs.info().free_blocks, free_before + 20);
+
+        // Both extents are left behind as deleted entries
+        let deleted = fs.read_dir_extended_with_deleted().unwrap();
+        assert!(deleted.iter().any(|e| e.name == "GONE.BIN" && e.user == 0xE5));
+    }
+
+    #[test]
+    fn test_rename_and_attributes() {
+        let mut image = blank_plus3_image();
+        let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+        fs.write_file("OLD.TXT", b"content").unwrap();
+        fs.write_file("OTHER.TXT", b"other").unwrap();
+
+        let attributes = FileAttributes {
+            read_only: false,
+            system: true,
+            archive: true,
+        };
+        fs.set_attributes("OLD.TXT", &attributes).unwrap();
+        fs.rename_file("OLD.TXT", "new.doc").unwrap();
+        assert!(fs.rename_file("NEW.DOC", "OTHER.TXT").is_err());
+
+        let entries = fs.read_dir().unwrap();
+        let renamed = entries.iter().find(|e| e.name == "NEW.DOC").unwrap();
+        assert_eq!(renamed.attributes, attributes);
+        assert_eq!(fs.read_file("NEW.DOC").unwrap(), b"content");
+
+        // Read-only files cannot be deleted or renamed
+        fs.set_attributes("NEW.DOC", &FileAttributes { read_only: true, ..attributes }).unwrap();
+        assert!(fs.delete_file("NEW.DOC").is_err());
+        assert!(fs.rename_file("NEW.DOC", "X.DOC").is_err());
+    }
+
+    #[test]
+    fn test_user_areas() {
+        let mut image = blank_plus3_image();
+        let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+        fs.write_file("X.BIN", b"user zero").unwrap();
+        fs.write_file("3:X.BIN", b"user three").unwrap();
+        assert!(fs.write_file("3:X.BIN", b"again").is_err());
+        assert!(fs.write_file("32:X.BIN", b"bad user").is_err());
+
+        let entries = fs.read_dir().unwrap();
+        let users: Vec<u8> = entries.iter().map(|e| e.user).collect();
+        assert_eq!(users, vec![0, 3]);
+        assert_eq!(entries[1].size, 10);
+
+        assert_eq!(fs.read_file("3:X.BIN").unwrap(), b"user three");
+        assert_eq!(fs.read_file_binary("0:X.BIN", true).unwrap(), b"user zero");
+        // A bare name prefers user 0
+        assert_eq!(fs.read_file("X.BIN").unwrap(), b"user zero");
+        assert!(fs.read_file("5:X.BIN").is_err());
+
+        // With user 0 gone the bare name finds the only remaining copy
+        fs.delete_file("X.BIN").unwrap();
+        assert_eq!(fs.read_file("X.BIN").unwrap(), b"user three");
+
+        // ...but is ambiguous once it exists in two other user areas
+        fs.rename_file("3:X.BIN", "5:X.BIN").unwrap();
+        fs.write_file("7:X.BIN", b"user seven").unwrap();
+        assert!(fs.read_file("X.BIN").is_err());
+        assert_eq!(fs.read_file("5:X.BIN").unwrap(), b"user three");
+    }
+
+    #[test]
+    fn test_undelete_file() {
+        let mut image = blank_plus3_image();
+        let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+        let data: Vec<u8> = (0..3000).map(|i| (i % 199) as u8).collect();
+        fs.write_file("GONE.BIN", &data).unwrap();
+        fs.write_file("LOST.BIN", &data).unwrap();
+        fs.write_file("KEEP.BIN", &data).unwrap();
+        for name in ["GONE.BIN", "LOST.BIN", "KEEP.BIN"] {
+            fs.delete_file(name).unwrap();
+        }
+
+        // Takes GONE.BIN's directory entry and blocks 2-5, reusing LOST.BIN's first block
+        fs.write_file("NEW.BIN", &[0; 3 * 1024 + 1]).unwrap();
+        assert!(fs.check_deleted("GONE.BIN").is_err());
+
+        let report = fs.check_deleted("KEEP.BIN").unwrap();
+        assert!(report.is_recoverable());
+        assert_eq!(report.size, 3000);
+        assert_eq!(report.intact_blocks.len(), 3);
+
+        let report = fs.check_deleted("LOST.BIN").unwrap();
+        assert!(!report.is_recoverable());
+        assert_eq!(report.reused_blocks, vec![5]);
+        assert_eq!(report.intact_blocks, vec![6, 7]);
+        assert_eq!(report.lost_blocks, vec![5, 6, 7]);
+        assert!(fs.undelete_file("LOST.BIN", 0).is_err());
+        assert!(fs.undelete_file_partial("LOST.BIN", 0).is_err());
+
+        fs.undelete_file("KEEP.BIN", 4).unwrap();
+        assert_eq!(fs.read_file("4:KEEP.BIN").unwrap(), data);
+        assert!(fs.check_deleted("KEEP.BIN").is_err());
+        assert!(fs.undelete_file("MISSING.BIN", 0).is_err());
+    }
+
+    #[test]
+    fn test_undelete_file_partial() {
+        let mut image = blank_plus3_image();
+        let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+        let data: Vec<u8> = (0..3000).map(|i| (i % 199) as u8).collect();
+        fs.write_file("FIRST.BIN", &[1; 1024]).unwrap();
+        fs.write_file("OLD.BIN", &[2; 1024]).unwrap();
+        fs.delete_file("FIRST.BIN").unwrap();
+        fs.delete_file("OLD.BIN").unwrap();
+        fs.write_file("PART.BIN", &data).unwrap();
+        fs.delete_file("PART.BIN").unwrap();
+
+        // Brings back OLD.BIN's block, which PART.BIN went on to use second
+        fs.undelete_file("OLD.BIN", 0).unwrap();
+        let report = fs.check_deleted("PART.BIN").unwrap();
+        assert_eq!(report.reused_blocks, vec![3]);
+        assert_eq!(report.lost_blocks, vec![3, 4]);
+
+        let report = fs.undelete_file_partial("PART.BIN", 0).unwrap();
+        assert_eq!(report.lost_blocks, vec![3, 4]);
+        assert_eq!(fs.read_file("PART.BIN").unwrap(), &data[..1024]);
+        assert!(fs.check().unwrap().problems.is_empty());
+    }
+
+    #[test]
+    fn test_check_and_repair() {
+        let mut image = blank_plus3_image();
+        let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+        fs.write_file("GOOD.BIN", &[1; 2048]).unwrap();
+        fs.write_file("BAD.BIN", &[2; 4096]).unwrap();
+        assert!(fs.check().unwrap().is_clean());
+
+        // GOOD.BIN has blocks 2-3, so point BAD.BIN at block 2, a directory
+        // block and a block past the end of the disk, and give it a bad name
+        let mut bad = fs.file_extents("BAD.BIN").unwrap().remove(0);
+        bad.allocation[..4].copy_from_slice(&[2, 1, 200, 4]);
+        bad.filename[3] = b'?';
+        fs.write_directory_entry(bad.index, &bad.to_bytes()).unwrap();
+
+        // A lone second extent with no first
+        let mut orphan = bad.clone();
+        orphan.filename = *b"ORPHAN  ";
+        orphan.extent_low = 1;
+        orphan.record_count = 8;
+        orphan.allocation = vec![0; 16];
+        orphan.allocation[0] = 7;
+        fs.write_directory_entry(5, &orphan.to_bytes()).unwrap();
+
+        let report = fs.check().unwrap();
+        let kinds: Vec<&CpmProblemKind> = report.problems.iter().map(|p| &p.kind).collect();
+        assert_eq!(report.entries_checked, 3);
+        assert!(kinds.contains(&&CpmProblemKind::SharedBlock {
+            block: 2,
+            first_file: "0:GOOD.BIN".to_string()
+        }));
+        assert!(kinds.contains(&&CpmProblemKind::DirectoryBlock { block: 1 }));
+        assert!(kinds.contains(&&CpmProblemKind::BlockOutOfRange { block: 200 }));
+        assert!(kinds.contains(&&CpmProblemKind::RecordCountMismatch { records: 32, blocks: 1 }));
+        assert!(kinds.contains(&&CpmProblemKind::BadFilename));
+        assert!(kinds.contains(&&CpmProblemKind::ExtentOutOfSequence { expected: 0, found: 1 }));
+
+        let report = fs.repair().unwrap();
+        assert_eq!(report.entries_repaired, 1);
+
+        // Only the unfixable extent sequence problem remains
+        let report = fs.check().unwrap();
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].file, "0:ORPHAN.BIN");
+        assert_eq!(fs.read_file("BAD_.BIN").unwrap(), vec![2; 1024]);
+        assert_eq!(fs.read_file("GOOD.BIN").unwrap(), vec![1; 2048]);
+    }
+
+    #[test]
+    fn test_write_file_read_only() {
+        let mut image = blank_plus3_image();
+        let mut fs = CpmFileSystem::from_image(&image).unwrap();
+        assert!(fs.write_file("TEST", b"data").is_err());
+
+        let mut fs = CpmFileSystem::from_image_mut(&mut image).unwrap();
+        fs.write_file("TEST", b"data").unwrap();
+        assert!(fs.write_file("test", b"again").is_err());
+    }
 }
@@ -0,0 +1,285 @@
+/// Formatting of blank CP/M disks
+///
+/// Builds a ready-to-use image from a `DiskSpecification`: every sector is
+/// filled with 0xE5, the directory is initialised as empty, and optionally the
+/// PCW/+3 spec block and system tracks from a template image are written.
+
+use crate::error::{DskError, Result};
+use crate::filesystem::CpmFileSystem;
+use crate::format::{DiskImageFormat, DiskSpecSide, DiskSpecification};
+use crate::image::DiskImage;
+
+/// Builder for formatted CP/M disk images
+pub struct CpmFormatter<'t> {
+    spec: DiskSpecification,
+    format: DiskImageFormat,
+    first_sector_id: u8,
+    spec_block: bool,
+    system_tracks: Option<&'t DiskImage>,
+}
+
+impl<'t> CpmFormatter<'t> {
+    /// Create a formatter for a disk specification, numbering the sectors on
+    /// each track from `first_sector_id`
+    ///
+    /// Amstrad CPC system and data disks number their sectors from 0x41 and
+    /// 0xC1, PCW/+3 disks from 0x01, and Einstein and TS2068 disks from 0x00.
+    /// No spec block is written unless asked for with [`spec_block`](Self::spec_block).
+    pub fn new(spec: DiskSpecification, first_sector_id: u8) -> Self {
+        Self {
+            spec,
+            format: DiskImageFormat::StandardDSK,
+            first_sector_id,
+            spec_block: false,
+            system_tracks: None,
+        }
+    }
+
+    /// Create a formatter with the same layout as an existing disk
+    ///
+    /// The specification is identified from the image, sectors are numbered
+    /// from the lowest sector ID on its first track, and a spec block is
+    /// written if the image's first sector holds one for that specification.
+    pub fn from_image(image: &DiskImage) -> Result<Self> {
+        let first = image
+            .get_disk(0)
+            .and_then(|disk| disk.get_track(0))
+            .and_then(|track| track.sectors().iter().min_by_key(|s| s.id.sector))
+            .ok_or_else(|| DskError::invalid_format("Image has no sectors on its first track"))?;
+
+        let spec = DiskSpecification::identify(image);
+        let spec_block = first.data().starts_with(&spec.spec_block()[..10]);
+        Ok(Self::new(spec, first.id.sector).spec_block(spec_block))
+    }
+
+    /// Set the DSK format
+    pub fn format(mut self, format: DiskImageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the ID of the first sector on each track
+    pub fn first_sector_id(mut self, first_sector_id: u8) -> Self {
+        self.first_sector_id = first_sector_id;
+        self
+    }
+
+    /// Set whether the PCW/+3 spec block is written to track 0 sector 0
+    pub fn spec_block(mut self, spec_block: bool) -> Self {
+        self.spec_block = spec_block;
+        self
+    }
+
+    /// Copy the reserved (system) tracks from a template image
+    pub fn system_tracks(mut self, template: &'t DiskImage) -> Self {
+        self.system_tracks = Some(template);
+        self
+    }
+
+    /// Build the formatted image
+    pub fn build(self) -> Result<DiskImage> {
+        self.validate()?;
+
+        let mut image = DiskImage::builder()
+            .format(self.format)
+            .spec(self.spec.format_spec(self.first_sector_id))
+            .build()?;
+
+        if let Some(template) = self.system_tracks {
+            self.copy_system_tracks(template, &mut image)?;
+        }
+        if self.spec_block {
+            self.write_spec_block(&mut image)?;
+        }
+        self.initialise_directory(&mut image)?;
+
+        Ok(image)
+    }
+
+    /// Check the specification describes a disk that can be formatted
+    fn validate(&self) -> Result<()> {
+        let spec = &self.spec;
+        if spec.side == DiskSpecSide::Invalid
+            || spec.tracks_per_side == 0
+            || spec.sectors_per_track == 0
+            || spec.sector_size == 0
+        {
+            return Err(DskError::invalid_format(
+                "Cannot format from an invalid disk specification",
+            ));
+        }
+        if !spec.block_size().is_multiple_of(spec.sector_size as usize) {
+            return Err(DskError::invalid_format(format!(
+                "Block size {} is not a multiple of the sector size {}",
+                spec.block_size(),
+                spec.sector_size
+            )));
+        }
+        if self.first_sector_id as usize + spec.sectors_per_track as usize > 256 {
+            return Err(DskError::invalid_format(format!(
+                "{} sectors numbered from 0x{:02X} do not fit in a sector ID",
+                spec.sectors_per_track, self.first_sector_id
+            )));
+        }
+        if spec.directory_blocks == 0 || spec.directory_blocks as u16 > spec.block_count() {
+            return Err(DskError::invalid_format(format!(
+                "Invalid directory size of {} blocks",
+                spec.directory_blocks
+            )));
+        }
+        if self.spec_block && spec.reserved_tracks == 0 {
+            return Err(DskError::invalid_format(
+                "A spec block needs at least one reserved track",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Replace the reserved tracks with copies of the template's
+    fn copy_system_tracks(&self, template: &DiskImage, image: &mut DiskImage) -> Result<()> {
+        for logical_track in 0..self.spec.reserved_tracks as usize {
+            let (side, track) = self
+                .spec
+                .physical_track(logical_track)
+                .ok_or_else(|| DskError::invalid_format("Reserved tracks exceed the disk size"))?;
+
+            let source = template
+                .get_disk(side)
+                .and_then(|disk| disk.get_track(track))
+                .ok_or_else(|| {
+                    DskError::filesystem(format!(
+                        "Template image has no track {} on side {}",
+                        track, side
+                    ))
+                })?;
+
+            let target = image
+                .get_disk_mut(side)
+                .and_then(|disk| disk.get_track_mut(track))
+                .ok_or_else(|| DskError::filesystem("Formatted image is missing a system track"))?;
+            *target = source.clone();
+        }
+        Ok(())
+    }
+
+    /// Write the 16-byte spec block to the start of the first logical sector
+    fn write_spec_block(&self, image: &mut DiskImage) -> Result<()> {
+        let (side, track, sector_id) = CpmFileSystem::locate_sector(image, &self.spec, 0)
+            .ok_or_else(|| DskError::filesystem("Formatted image has no first sector"))?;
+
+        let mut data = image.read_sector(side, track, sector_id)?.to_vec();
+        let block = self.spec.spec_block();
+        let len = block.len().min(data.len());
+        data[..len].copy_from_slice(&block[..len]);
+
+        image.write_sector(side, track, sector_id, &data)
+    }
+
+    /// Fill the directory blocks with 0xE5 so every entry reads as unused
+    fn initialise_directory(&self, image: &mut DiskImage) -> Result<()> {
+        let sector_size = self.spec.sector_size as usize;
+        let first = self.spec.reserved_tracks as usize * self.spec.sectors_per_track as usize;
+        let count = self.spec.directory_blocks as usize * self.spec.block_size() / sector_size;
+        let empty = vec![0xE5; sector_size];
+
+        for logical_sector in first..first + count {
+            let (side, track, sector_id) =
+                CpmFileSystem::locate_sector(image, &self.spec, logical_sector).ok_or_else(
+                    || DskError::filesystem("Directory does not fit on the formatted image"),
+                )?;
+            image.write_sector(side, track, sector_id, &empty)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::FileSystem;
+    use crate::format::{AllocationSize, DiskSpecTrack, FormatSpec};
+
+    #[test]
+    fn test_format_plus3() {
+        let image = CpmFormatter::new(DiskSpecification::new(), 0x01).spec_block(true).build().unwrap();
+
+        let sector = image.read_sector(0, 0, 1).unwrap();
+        assert_eq!(&sector[..10], &[0, 0, 40, 9, 2, 1, 3, 2, 42, 82]);
+        assert!(sector[16..].iter().all(|&b| b == 0xE5));
+
+        let spec = DiskSpecification::identify(&image);
+        assert_eq!(spec.format, "Amstrad PCW/+3 DD/SS/ST");
+        assert_eq!(spec.source, "Sector 0 spec block (format byte 0)");
+
+        let fs = CpmFileSystem::from_image(&image).unwrap();
+        assert!(fs.read_dir().unwrap().is_empty());
+        assert_eq!(fs.info().free_blocks, fs.info().total_blocks - 2);
+
+        // Reformatting keeps the spec block
+        let reformatted = CpmFormatter::from_image(&image).unwrap().build().unwrap();
+        assert_eq!(reformatted.read_sector(0, 0, 1).unwrap(), sector);
+    }
+
+    #[test]
+    fn test_format_pcw_double_sided() {
+        let mut spec = DiskSpecification::new();
+        spec.format = "Amstrad PCW DD/DS/DT".to_string();
+        spec.side = DiskSpecSide::DoubleAlternate;
+        spec.track = DiskSpecTrack::Double;
+        spec.tracks_per_side = 80;
+        spec.block_shift = 4;
+        spec.directory_blocks = 4;
+        spec.allocation_size = AllocationSize::Word;
+
+        let mut image = CpmFormatter::new(spec, 0x01).spec_block(true).build().unwrap();
+        assert_eq!(image.disk_count(), 2);
+
+        let detected = DiskSpecification::identify(&image);
+        assert_eq!(detected.format, "Amstrad PCW DD/DS/DT");
+        assert_eq!(detected.side, DiskSpecSide::DoubleAlternate);
+        assert_eq!(detected.tracks_per_side, 80);
+        assert_eq!(detected.allocation_size, AllocationSize::Word);
+
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 241) as u8).collect();
+        CpmFileSystem::from_image_mut(&mut image)
+            .unwrap()
+            .write_file("DATA.BIN", &data)
+            .unwrap();
+        let fs = CpmFileSystem::from_image(&image).unwrap();
+        assert_eq!(fs.read_file_binary("DATA.BIN", true).unwrap(), data);
+    }
+
+    #[test]
+    fn test_format_with_system_tracks() {
+        let boot: Vec<u8> = (0..512).map(|i| i as u8 ^ 0xAA).collect();
+        let mut template = DiskImage::create(FormatSpec {
+            first_sector_id: 0x41,
+            ..FormatSpec::amstrad_system()
+        })
+        .unwrap();
+        template.write_sector(0, 0, 0x41, &boot).unwrap();
+        template.write_sector(0, 1, 0x49, &[0xBB; 512]).unwrap();
+        template.write_sector(0, 2, 0x41, &[0xCC; 512]).unwrap();
+
+        let image = CpmFormatter::from_image(&template).unwrap().system_tracks(&template).build().unwrap();
+
+        assert_eq!(image.read_sector(0, 0, 0x41).unwrap(), &boot[..]);
+        assert_eq!(image.read_sector(0, 1, 0x49).unwrap(), &[0xBB; 512]);
+        // Only the reserved tracks are copied
+        assert_eq!(image.read_sector(0, 2, 0x41).unwrap(), &[0xE5; 512]);
+        assert_eq!(DiskSpecification::identify(&image).format, "Amstrad CPC DD/SS/ST system");
+
+        let mut spec = DiskSpecification::new();
+        spec.reserved_tracks = 0;
+        assert!(CpmFormatter::new(spec, 0x01).spec_block(true).build().is_err());
+    }
+
+    #[test]
+    fn test_format_unsupported() {
+        assert!(CpmFormatter::new(DiskSpecification::new(), 0xF8).build().is_err());
+
+        let mut image = DiskImage::create(FormatSpec::amstrad_data()).unwrap();
+        image.get_disk_mut(0).unwrap().get_track_mut(0).unwrap().clear();
+        assert!(CpmFormatter::from_image(&image).is_err());
+    }
+}
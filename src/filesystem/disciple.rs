@@ -7,11 +7,25 @@
 ///
 /// Directory entry extensions at offset 0xD2 (210):
 /// - 10 bytes for Disciple/+D metadata
+/// - Offsets 211-219 hold a copy of the 9 byte header stored at the start
+///   of BASIC, array, CODE and SCREEN$ files: tape type, length, start,
+///   program length and autostart line
+///
+/// Snapshots (types 5 and 9) keep the registers in the directory entry
+/// from offset 220 (0xDC): IY, IX, DE', BC', HL', AF', DE, BC, HL, an
+/// unused byte, I and SP. The rest are on the saved stack: the flags from
+/// LD A,R (IFF2 in bit 2) with R, then AF and the program counter. 48K
+/// snapshot files hold the RAM from 16384; 128K snapshot files hold the
+/// last value written to port 0x7FFD followed by RAM pages 0-7.
 
-use crate::error::Result;
-use crate::filesystem::mgt::{MgtDirEntry, MgtFileSystem, MgtFileType};
-use crate::filesystem::{ExtendedDirEntry, FileHeader, HeaderType};
+use crate::error::{DskError, Result};
+use crate::filesystem::mgt::{
+    MgtDirEntry, MgtFileSystem, MgtFileType, MgtSystemType, DISCIPLE_TYPE_SNAPSHOT_128K, MGT_FILE_HEADER_SIZE, SNAPSHOT_128K_LENGTH,
+    SNAPSHOT_48K_LENGTH,
+};
+use crate::filesystem::{DirEntry, ExtendedDirEntry, FileHeader, FileSystem, FileSystemInfo, HeaderType};
 use crate::image::DiskImage;
+use crate::snapshot::{SpectrumSnapshot, Z80Registers};
 
 /// Disciple/+D specific file metadata
 #[derive(Debug, Clone)]
@@ -115,6 +129,55 @@ pub struct DiscipleFileSystem<'a> {
     mgt: MgtFileSystem<'a>,
 }
 
+/// Directory file type codes
+const DISCIPLE_TYPE_BASIC: u8 = 1;
+const DISCIPLE_TYPE_CODE: u8 = 4;
+
+/// Tape header types
+const TAPE_TYPE_BASIC: u8 = 0;
+const TAPE_TYPE_CODE: u8 = 3;
+
+/// Start of a BASIC program in a standard 48K Spectrum
+const BASIC_PROG_START: u16 = 23755;
+
+/// Load address for CODE files saved without one
+pub(crate) const DISCIPLE_DEFAULT_START: u16 = 32768;
+
+/// Offset of the snapshot registers in a directory entry
+const SNAPSHOT_REGISTERS: usize = 220;
+
+/// Address of the BORDCR system variable, with the border colour in bits 3-5
+const BORDCR: u16 = 23624;
+
+/// Build a file with a header, with the start, program length and autostart parameters
+///
+/// Returns the directory file type, the file body starting with its header
+/// and the directory bytes from offset 210, which hold a copy of the header.
+fn typed_file(dir_type: u8, tape_type: u8, params: [u16; 3], data: &[u8]) -> Result<(MgtFileType, Vec<u8>, Vec<u8>)> {
+    let length = u16::try_from(data.len())
+        .map_err(|_| DskError::filesystem("File too long for DISCiPLE/+D"))?;
+
+    let mut header = [0u8; MGT_FILE_HEADER_SIZE];
+    header[0] = tape_type;
+    header[1..3].copy_from_slice(&length.to_le_bytes());
+    for (i, param) in params.iter().enumerate() {
+        header[3 + i * 2..5 + i * 2].copy_from_slice(&param.to_le_bytes());
+    }
+
+    // The header goes at the start of the file and into bytes 211-219 of the entry
+    let mut body = header.to_vec();
+    body.extend_from_slice(data);
+    let mut info = vec![0u8];
+    info.extend_from_slice(&header);
+
+    Ok((MgtFileType::Other(dir_type), body, info))
+}
+
+/// Build a CODE file, as for [`typed_file`]
+pub(crate) fn code_file(start: u16, data: &[u8]) -> Result<(MgtFileType, Vec<u8>, Vec<u8>)> {
+    typed_file(DISCIPLE_TYPE_CODE, TAPE_TYPE_CODE, [start, 0, 0], data)
+}
+
 impl<'a> DiscipleFileSystem<'a> {
     /// Create a new Disciple filesystem from an image
     pub fn new(image: &'a DiskImage) -> Result<Self> {
@@ -122,21 +185,132 @@ impl<'a> DiscipleFileSystem<'a> {
         Ok(Self { mgt })
     }
 
+    /// Create a new writable Disciple filesystem from an image
+    pub fn new_mut(image: &'a mut DiskImage) -> Result<Self> {
+        let mgt = MgtFileSystem::new_mut(image)?;
+        Ok(Self { mgt })
+    }
+
     /// Get the underlying MGT filesystem
     pub fn mgt(&self) -> &MgtFileSystem<'a> {
         &self.mgt
     }
 
-    /// Get file size from Disciple directory entry (offsets 212-213)
-    fn get_file_size(&self, entry: &MgtDirEntry) -> usize {
-        let raw = &entry.raw_data;
-        if raw.len() >= 214 {
-            // File length at offset 212-213 ($d4-$d5), little endian
-            u16::from_le_bytes([raw[212], raw[213]]) as usize
+    /// Get the underlying MGT filesystem for writing
+    pub fn mgt_mut(&mut self) -> &mut MgtFileSystem<'a> {
+        &mut self.mgt
+    }
+
+    /// List all files
+    #[deprecated(note = "use `FileSystem::read_dir`, or `mgt().directory()` for the raw entries")]
+    pub fn list_files(&self) -> Vec<&MgtDirEntry> {
+        self.mgt.directory().iter().collect()
+    }
+
+    /// Get filesystem info
+    #[deprecated(note = "use `FileSystem::info`, or `mgt().disk_info()` for the MGT details")]
+    pub fn info(&self) -> String {
+        let mgt_info = self.mgt.disk_info();
+        format!(
+            "DISCiPLE/+D Filesystem\n  Files: {}\n  Used: {} KB\n  Free: {} KB",
+            mgt_info.file_count,
+            mgt_info.used_sectors / 2,
+            mgt_info.free_sectors / 2
+        )
+    }
+
+    /// Save a CODE file
+    pub fn save_code(&mut self, name: &str, start: u16, data: &[u8]) -> Result<()> {
+        let (file_type, body, info) = code_file(start, data)?;
+        self.mgt.save_file(name, file_type, &body, &info)
+    }
+
+    /// Save a BASIC program, optionally run from an autostart line
+    pub fn save_basic(&mut self, name: &str, autostart: Option<u16>, program: &[u8]) -> Result<()> {
+        let autostart = autostart.unwrap_or(0xFFFF);
+        let params = [BASIC_PROG_START, program.len() as u16, autostart];
+        let (file_type, body, info) = typed_file(DISCIPLE_TYPE_BASIC, TAPE_TYPE_BASIC, params, program)?;
+        self.mgt.save_file(name, file_type, &body, &info)
+    }
+
+    /// Rename a file
+    pub fn rename_file(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        self.mgt.rename_file(old_name, new_name)
+    }
+
+    /// Read a 48K or 128K snapshot, rebuilding the registers saved on its stack
+    ///
+    /// The border colour is taken from the BORDCR system variable.
+    pub fn read_snapshot(&self, name: &str) -> Result<SpectrumSnapshot> {
+        let entry = self
+            .mgt
+            .find_file(name)
+            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        let is_128k = match entry.file_type {
+            MgtFileType::ZxSnapshot => false,
+            MgtFileType::Other(DISCIPLE_TYPE_SNAPSHOT_128K) => true,
+            _ => {
+                return Err(DskError::filesystem(format!(
+                    "{} is not a snapshot",
+                    entry.filename
+                )))
+            }
+        };
+
+        let length = if is_128k { SNAPSHOT_128K_LENGTH } else { SNAPSHOT_48K_LENGTH };
+        let data = self.mgt.read_file_raw(entry)?;
+        let data = data.get(..length).ok_or_else(|| {
+            DskError::integrity(format!(
+                "Snapshot {} has {} bytes, expected {}",
+                entry.filename,
+                data.len(),
+                length
+            ))
+        })?;
+
+        let raw = &entry.raw_data[SNAPSHOT_REGISTERS..];
+        let word = |offset: usize| u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+        let registers = Z80Registers {
+            iy: word(0),
+            ix: word(2),
+            de_alt: word(4),
+            bc_alt: word(6),
+            hl_alt: word(8),
+            af_alt: word(10),
+            de: word(12),
+            bc: word(14),
+            hl: word(16),
+            i: raw[19],
+            sp: word(20),
+            ..Z80Registers::default()
+        };
+
+        let mut snapshot = if is_128k {
+            SpectrumSnapshot::new_128k(registers, data[0], &data[1..])?
         } else {
-            // Fallback to allocated size if header not available
-            entry.file_size()
-        }
+            SpectrumSnapshot::new_48k(registers, data)?
+        };
+
+        // Pushed by the NMI and the snapshot routine: PC, AF, then R with IFF2
+        let [r, flags] = snapshot.pop()?.to_be_bytes();
+        let af = snapshot.pop()?;
+        let pc = snapshot.pop()?;
+        let regs = &mut snapshot.registers;
+        regs.r = r;
+        regs.iff1 = flags & 0x04 != 0;
+        regs.iff2 = regs.iff1;
+        regs.af = af;
+        regs.pc = pc;
+        // IM 2 programs point I away from the ROM
+        regs.im = if matches!(regs.i, 0x00 | 0x3F) { 1 } else { 2 };
+
+        snapshot.border = snapshot.read_byte(BORDCR).map_or(0, |b| (b >> 3) & 0x07);
+        Ok(snapshot)
+    }
+
+    /// Get file size from the file header, matching Disciple directory entry offsets 212-213
+    fn get_file_size(&self, entry: &MgtDirEntry) -> usize {
+        self.mgt.file_length(entry)
     }
 
     /// Read directory with Disciple-specific information
@@ -232,30 +406,59 @@ impl<'a> DiscipleFileSystem<'a> {
         }
     }
 
+}
+
+impl<'a> FileSystem for DiscipleFileSystem<'a> {
+    fn from_image<'b>(_image: &'b DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use DiscipleFileSystem::new() directly",
+        ))
+    }
+
+    fn from_image_mut<'b>(_image: &'b mut DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use DiscipleFileSystem::new_mut() directly",
+        ))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>> {
+        self.mgt.read_dir()
+    }
+
     /// Read a file by name
     /// Returns file data truncated to actual file length (not allocated size)
-    pub fn read_file(&self, name: &str) -> Result<Vec<u8>> {
-        let entry = self
-            .mgt
-            .find_file(name)
-            .ok_or_else(|| crate::error::DskError::FileNotFound(name.to_string()))?;
-        self.mgt.read_file(entry)
+    fn read_file(&self, name: &str) -> Result<Vec<u8>> {
+        FileSystem::read_file(&self.mgt, name)
     }
 
-    /// List all files
-    pub fn list_files(&self) -> Vec<&MgtDirEntry> {
-        self.mgt.directory().iter().collect()
+    /// Save a CODE file at 32768
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.save_code(name, DISCIPLE_DEFAULT_START, data)
     }
 
-    /// Get filesystem info
-    pub fn info(&self) -> String {
-        let mgt_info = self.mgt.info();
-        format!(
-            "DISCiPLE/+D Filesystem\n  Files: {}\n  Used: {} KB\n  Free: {} KB",
-            mgt_info.file_count,
-            mgt_info.used_sectors / 2,
-            mgt_info.free_sectors / 2
-        )
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        self.mgt.delete_file(name)
+    }
+
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        DiscipleFileSystem::read_dir_extended(self)
+    }
+
+    fn info(&self) -> FileSystemInfo {
+        FileSystemInfo {
+            fs_type: MgtSystemType::Disciple.to_string(),
+            ..FileSystem::info(&self.mgt)
+        }
+    }
+
+    fn details(&self) -> Vec<(String, String)> {
+        self.mgt.details()
     }
 }
 
@@ -270,4 +473,94 @@ mod tests {
         assert_eq!(format!("{}", DiscipleFileType::Snapshot48k), "48K Snapshot");
         assert_eq!(format!("{}", DiscipleFileType::Screen), "SCREEN$");
     }
+
+    #[test]
+    fn test_save_round_trip() {
+        let mut image = MgtFileSystem::blank_image().unwrap();
+        let code: Vec<u8> = (0..6912).map(|i| i as u8).collect();
+        let program = [0x00, 0x0A, 0x02, 0x00, 0xF5, 0x0D];
+        {
+            let mut fs = DiscipleFileSystem::new_mut(&mut image).unwrap();
+            fs.save_code("screen", 16384, &code).unwrap();
+            fs.save_basic("run", Some(10), &program).unwrap();
+            fs.save_code("old", 32768, &[0; 10]).unwrap();
+            fs.rename_file("old", "new").unwrap();
+            fs.delete_file("new").unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!("dsk_disciple_{}.mgt", std::process::id()));
+        crate::io::writer::write_dsk(&image, &path).unwrap();
+        let loaded = crate::io::mgt_reader::read_mgt(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let fs = DiscipleFileSystem::new(&loaded).unwrap();
+        assert_eq!(fs.read_file("SCREEN").unwrap(), code);
+        assert_eq!(fs.read_file("run").unwrap(), program);
+        assert!(fs.read_file("new").is_err());
+
+        let entries = fs.read_dir_extended().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].size, 6912);
+        assert_eq!(entries[0].blocks, 14);
+        assert_eq!(entries[0].header.meta, "CODE 16384,6912");
+        assert_eq!(entries[1].header.meta, "BASIC LINE 10");
+    }
+
+    /// Directory bytes from offset 210 for a snapshot with the given I and SP
+    fn snapshot_info(i: u8, sp: u16) -> Vec<u8> {
+        let mut info = vec![0u8; 32];
+        info[10..12].copy_from_slice(&0x5C3Au16.to_le_bytes());
+        info[26..28].copy_from_slice(&0x2758u16.to_le_bytes());
+        info[29] = i;
+        info[30..32].copy_from_slice(&sp.to_le_bytes());
+        info
+    }
+
+    #[test]
+    fn test_read_snapshot() {
+        let mut image = MgtFileSystem::blank_image().unwrap();
+
+        // Stack: flags and R, AF, PC
+        let stack = [0x04, 0x21, 0x44, 0x12, 0x34, 0x12];
+        let mut ram = vec![0u8; 49152];
+        ram[0x7FFA - 0x4000..0x8000 - 0x4000].copy_from_slice(&stack);
+        ram[BORDCR as usize - 0x4000] = 0x10;
+
+        let mut ram_128k = vec![0u8; 1 + 8 * 16384];
+        ram_128k[0] = 0x17;
+        let stack_offset = 1 + 2 * 16384 + 0x3FF0;
+        ram_128k[stack_offset..stack_offset + 6].copy_from_slice(&[0x00, 0x7F, 0, 0, 0x00, 0xC0]);
+        {
+            let mut fs = DiscipleFileSystem::new_mut(&mut image).unwrap();
+            let mgt = fs.mgt_mut();
+            mgt.save_file("game48", MgtFileType::ZxSnapshot, &ram, &snapshot_info(0x3F, 0x7FFA)).unwrap();
+            mgt.save_file("game128", MgtFileType::Other(9), &ram_128k, &snapshot_info(0xFE, 0xBFF0)).unwrap();
+            fs.save_code("code", 32768, &[0; 10]).unwrap();
+        }
+
+        let fs = DiscipleFileSystem::new(&image).unwrap();
+        let snapshot = fs.read_snapshot("GAME48").unwrap();
+        assert!(!snapshot.is_128k());
+        let regs = &snapshot.registers;
+        assert_eq!((regs.iy, regs.hl), (0x5C3A, 0x2758));
+        assert_eq!((regs.af, regs.pc, regs.sp), (0x1244, 0x1234, 0x8000));
+        assert_eq!((regs.r, regs.i, regs.im), (0x21, 0x3F, 1));
+        assert!(regs.iff1 && regs.iff2);
+        assert_eq!(snapshot.border, 2);
+
+        let sna = snapshot.to_sna().unwrap();
+        assert_eq!(sna.len(), 27 + 49152);
+        assert_eq!(&sna[27 + 0x3FFE..27 + 0x4000], &[0x34, 0x12]);
+
+        let snapshot = fs.read_snapshot("game128").unwrap();
+        assert!(snapshot.is_128k());
+        assert_eq!(snapshot.paged_bank(), 7);
+        let regs = &snapshot.registers;
+        assert_eq!((regs.pc, regs.sp, regs.r, regs.im), (0xC000, 0xBFF6, 0x7F, 2));
+        assert!(!regs.iff1);
+        assert_eq!(snapshot.to_z80()[34..36], [4, 0x17]);
+
+        assert!(fs.read_snapshot("code").is_err());
+        assert!(fs.read_snapshot("none").is_err());
+    }
 }
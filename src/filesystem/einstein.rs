@@ -0,0 +1,296 @@
+/// Tatung Einstein DOS filesystem implementation
+///
+/// The Einstein runs XTAL DOS, a CP/M 2.2 compatible DOS. Disks have 10
+/// sectors of 512 bytes per track, numbered from 0:
+/// - Tracks 0-1: system tracks, starting with the boot sector (signature
+///   00 E1 00 FB 00 FA) followed by the XTAL DOS image loaded at boot
+/// - Track 2 onwards: data area in 2K blocks, the first block holding the
+///   64 entry directory
+///
+/// Double sided disks store their logical tracks alternating between sides.
+/// Files are kept in 128 byte records as on CP/M, with no AMSDOS or +3DOS
+/// style header, so file data is returned exactly as stored. Text files
+/// end with a ^Z (0x1A) marker inside the last record.
+
+use crate::error::{DskError, Result};
+use crate::filesystem::{
+    CpmFileSystem, DirEntry, ExtendedDirEntry, FileHeader, FileSystem, FileSystemInfo,
+};
+use crate::format::specification::{Einstein, FormatDetector};
+use crate::format::DiskSpecification;
+use crate::image::DiskImage;
+
+/// Sector size in bytes
+pub const EINSTEIN_SECTOR_SIZE: usize = 512;
+
+/// Sectors per track
+pub const EINSTEIN_SECTORS_PER_TRACK: u8 = 10;
+
+/// Number of system tracks holding the boot sector and XTAL DOS
+pub const EINSTEIN_SYSTEM_TRACKS: u8 = 2;
+
+/// Signature at the start of the boot sector
+pub const EINSTEIN_BOOT_SIGNATURE: [u8; 6] = [0x00, 0xE1, 0x00, 0xFB, 0x00, 0xFA];
+
+/// CP/M end of file marker used by text files
+const EOF_MARKER: u8 = 0x1A;
+
+/// Tatung Einstein DOS filesystem
+pub struct EinsteinFileSystem<'a> {
+    cpm: CpmFileSystem<'a>,
+}
+
+impl<'a> EinsteinFileSystem<'a> {
+    /// Mount an Einstein filesystem
+    pub fn new(image: &'a DiskImage) -> Result<Self> {
+        let spec = Self::detect(image)?;
+        Ok(Self {
+            cpm: CpmFileSystem::new(image, spec)?,
+        })
+    }
+
+    /// Mount an Einstein filesystem for writing
+    pub fn new_mut(image: &'a mut DiskImage) -> Result<Self> {
+        let spec = Self::detect(image)?;
+        Ok(Self {
+            cpm: CpmFileSystem::new_mut(image, spec)?,
+        })
+    }
+
+    /// Check whether an image holds an Einstein disk
+    pub fn is_einstein(image: &DiskImage) -> bool {
+        Einstein.detect(image).is_some()
+    }
+
+    /// Disk specification for an Einstein disk, from its boot sector and geometry
+    fn detect(image: &DiskImage) -> Result<DiskSpecification> {
+        Einstein
+            .detect(image)
+            .ok_or_else(|| DskError::filesystem("Not a Tatung Einstein disk"))
+    }
+
+    /// Get the disk specification
+    pub fn specification(&self) -> &DiskSpecification {
+        self.cpm.specification()
+    }
+
+    /// Get the underlying CP/M filesystem
+    pub fn cpm(&self) -> &CpmFileSystem<'a> {
+        &self.cpm
+    }
+
+    /// Get the underlying CP/M filesystem for writing
+    pub fn cpm_mut(&mut self) -> &mut CpmFileSystem<'a> {
+        &mut self.cpm
+    }
+
+    /// Read the system tracks, boot sector first
+    ///
+    /// Missing sectors are returned as the 0xE5 filler byte.
+    pub fn system_tracks(&self) -> Vec<u8> {
+        let image = self.cpm.image();
+        let spec = self.specification();
+        let sectors = EINSTEIN_SYSTEM_TRACKS as usize * EINSTEIN_SECTORS_PER_TRACK as usize;
+
+        let mut data = Vec::with_capacity(sectors * EINSTEIN_SECTOR_SIZE);
+        for logical in 0..sectors {
+            let sector = CpmFileSystem::locate_sector(image, spec, logical)
+                .and_then(|(side, track, id)| image.read_sector(side, track, id).ok());
+            let mut sector = sector.map_or_else(Vec::new, |s| s.to_vec());
+            sector.resize(EINSTEIN_SECTOR_SIZE, 0xE5);
+            data.extend_from_slice(&sector);
+        }
+        data
+    }
+
+    /// Check whether the system tracks hold a copy of XTAL DOS
+    ///
+    /// Data disks are formatted with the boot sector but leave the rest of
+    /// the system tracks blank.
+    pub fn is_system_disk(&self) -> bool {
+        self.system_tracks()[EINSTEIN_SECTOR_SIZE..]
+            .iter()
+            .any(|&b| b != 0xE5 && b != 0x00)
+    }
+
+    /// Read a text file, stopping at the ^Z end of file marker
+    pub fn read_text(&self, name: &str) -> Result<String> {
+        let mut data = self.read_file(name)?;
+        if let Some(end) = data.iter().position(|&b| b == EOF_MARKER) {
+            data.truncate(end);
+        }
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    /// List directory entries with extended information
+    pub fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        self.cpm.read_dir_extended().map(Self::without_headers)
+    }
+
+    /// List directory entries with extended information, including deleted files
+    pub fn read_dir_extended_with_deleted(&self) -> Result<Vec<ExtendedDirEntry>> {
+        self.cpm
+            .read_dir_extended_with_deleted()
+            .map(Self::without_headers)
+    }
+
+    /// Clear headers guessed from file contents, as Einstein files have none
+    fn without_headers(mut entries: Vec<ExtendedDirEntry>) -> Vec<ExtendedDirEntry> {
+        for entry in &mut entries {
+            entry.header = FileHeader {
+                checksum_valid: true,
+                file_size: entry.size,
+                ..FileHeader::default()
+            };
+        }
+        entries
+    }
+}
+
+impl<'a> FileSystem for EinsteinFileSystem<'a> {
+    fn from_image<'b>(_image: &'b DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use EinsteinFileSystem::new() directly",
+        ))
+    }
+
+    fn from_image_mut<'b>(_image: &'b mut DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use EinsteinFileSystem::new_mut() directly",
+        ))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>> {
+        self.cpm.read_dir()
+    }
+
+    /// Read a file as stored, in whole 128 byte records
+    fn read_file(&self, name: &str) -> Result<Vec<u8>> {
+        self.cpm.read_file_binary(name, true)
+    }
+
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.cpm.write_file(name, data)
+    }
+
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        self.cpm.delete_file(name)
+    }
+
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        EinsteinFileSystem::read_dir_extended(self)
+    }
+
+    fn read_dir_extended_with_deleted(&self) -> Result<Vec<ExtendedDirEntry>> {
+        EinsteinFileSystem::read_dir_extended_with_deleted(self)
+    }
+
+    fn info(&self) -> FileSystemInfo {
+        FileSystemInfo {
+            fs_type: "Einstein DOS".to_string(),
+            ..self.cpm.info()
+        }
+    }
+
+    fn details(&self) -> Vec<(String, String)> {
+        let system = if self.is_system_disk() { "XTAL DOS" } else { "none (data disk)" };
+        vec![("System tracks".to_string(), system.to_string())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::{CpmFormatter, FileSystemType};
+    use crate::format::AllocationSize;
+
+    /// Format a blank 40 track single sided Einstein disk
+    fn blank_disk(system: bool) -> DiskImage {
+        let mut spec = DiskSpecification::new();
+        spec.format = "Tatung Einstein".to_string();
+        spec.sectors_per_track = EINSTEIN_SECTORS_PER_TRACK;
+        spec.reserved_tracks = EINSTEIN_SYSTEM_TRACKS;
+        spec.block_shift = 4;
+        spec.directory_blocks = 1;
+        let mut image = CpmFormatter::new(spec, 0x00).build().unwrap();
+
+        let mut boot = vec![0u8; EINSTEIN_SECTOR_SIZE];
+        boot[..6].copy_from_slice(&EINSTEIN_BOOT_SIGNATURE);
+        image.write_sector(0, 0, 0, &boot).unwrap();
+        if system {
+            image.write_sector(0, 1, 3, &[0xC3; EINSTEIN_SECTOR_SIZE]).unwrap();
+        }
+        image
+    }
+
+    #[test]
+    fn test_detect() {
+        let image = blank_disk(false);
+        assert!(EinsteinFileSystem::is_einstein(&image));
+        assert_eq!(image.default_filesystem(), FileSystemType::Einstein);
+
+        let fs = EinsteinFileSystem::new(&image).unwrap();
+        let spec = fs.specification();
+        assert_eq!(spec.tracks_per_side, 40);
+        assert_eq!(spec.allocation_size, AllocationSize::Byte);
+        assert_eq!(spec.directory_entries(), 64);
+
+        let info = fs.info();
+        assert_eq!(info.fs_type, "Einstein DOS");
+        assert_eq!(info.block_size, 2048);
+        assert_eq!(info.total_blocks, 95);
+        assert_eq!(info.free_blocks, 94);
+
+        assert!(!EinsteinFileSystem::is_einstein(&DiskImage::builder().build().unwrap()));
+        assert!(EinsteinFileSystem::new(&DiskImage::builder().build().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_system_tracks() {
+        let image = blank_disk(false);
+        let fs = EinsteinFileSystem::new(&image).unwrap();
+        let system = fs.system_tracks();
+        assert_eq!(system.len(), 20 * EINSTEIN_SECTOR_SIZE);
+        assert!(system.starts_with(&EINSTEIN_BOOT_SIGNATURE));
+        assert!(!fs.is_system_disk());
+
+        let image = blank_disk(true);
+        let fs = EinsteinFileSystem::new(&image).unwrap();
+        assert_eq!(fs.system_tracks()[13 * EINSTEIN_SECTOR_SIZE], 0xC3);
+        assert!(fs.is_system_disk());
+    }
+
+    #[test]
+    fn test_files_keep_leading_bytes() {
+        let mut image = blank_disk(false);
+        // Data that looks like an AMSDOS header must not be stripped
+        let mut binary = vec![0u8; 300];
+        binary[0x12] = 2;
+        let checksum: u16 = binary[..67].iter().map(|&b| b as u16).sum();
+        binary[67..69].copy_from_slice(&checksum.to_le_bytes());
+        {
+            let mut fs = EinsteinFileSystem::new_mut(&mut image).unwrap();
+            fs.write_file("PROG.COM", &binary).unwrap();
+            fs.write_file("README.TXT", b"Hello\x1A").unwrap();
+        }
+
+        // Directory at track 2 sector 0
+        assert_eq!(&image.read_sector(0, 2, 0).unwrap()[1..9], b"PROG    ");
+
+        let fs = EinsteinFileSystem::new(&image).unwrap();
+        assert_eq!(fs.read_file("PROG.COM").unwrap(), binary);
+        assert_eq!(fs.cpm().read_file("PROG.COM").unwrap().len(), 300 - 128);
+        assert_eq!(fs.read_text("README.TXT").unwrap(), "Hello");
+
+        let entries = fs.read_dir_extended().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|e| e.header.header_size == 0));
+        assert_eq!(fs.read_dir().unwrap()[0].name, "PROG.COM");
+    }
+}
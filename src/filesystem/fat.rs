@@ -0,0 +1,1104 @@
+/// FAT12 filesystem implementation
+///
+/// MS-DOS and MSX-DOS floppy filesystem, as found on IBM PC and MSX disks
+/// and on transfer disks used to move files to and from other machines:
+/// - Logical sector 0: boot sector with the BIOS parameter block (BPB)
+/// - File allocation tables (usually two copies) of 12-bit cluster links
+/// - Fixed size root directory of 32 byte entries
+/// - Data area of clusters numbered from 2, holding files and subdirectories
+///
+/// Logical sectors count through each track from sector 1, alternating
+/// between sides. Disks without a valid BPB (DOS 1.x and some MSX disks)
+/// are recognised by the media byte at the start of the FAT.
+///
+/// Paths use `/` or `\` between directory names, e.g. `GAMES/PACMAN.COM`.
+
+use crate::error::{DskError, Result};
+use crate::filesystem::{
+    DirEntry, ExtendedDirEntry, FileAttributes, FileHeader, FileSystem, FileSystemInfo,
+    HeaderType, ImageRef,
+};
+use crate::image::DiskImage;
+
+/// Size of a directory entry in bytes
+pub const FAT_DIR_ENTRY_SIZE: usize = 32;
+
+/// Largest number of clusters on a FAT12 volume
+pub const FAT12_MAX_CLUSTERS: usize = 4084;
+
+/// Read-only attribute
+pub const FAT_ATTR_READ_ONLY: u8 = 0x01;
+/// Hidden attribute
+pub const FAT_ATTR_HIDDEN: u8 = 0x02;
+/// System attribute
+pub const FAT_ATTR_SYSTEM: u8 = 0x04;
+/// Volume label attribute
+pub const FAT_ATTR_VOLUME: u8 = 0x08;
+/// Directory attribute
+pub const FAT_ATTR_DIRECTORY: u8 = 0x10;
+/// Archive attribute
+pub const FAT_ATTR_ARCHIVE: u8 = 0x20;
+/// Attribute combination marking a long filename entry
+const FAT_ATTR_LONG_NAME: u8 = 0x0F;
+
+/// First byte of a directory entry marking the end of the directory
+const DIR_END: u8 = 0x00;
+/// First byte of a directory entry marking a deleted file
+const DIR_DELETED: u8 = 0xE5;
+/// First byte standing in for a real 0xE5 first character
+const DIR_KANJI_E5: u8 = 0x05;
+
+/// FAT12 value marking the last cluster of a chain
+const FAT12_END_OF_CHAIN: u16 = 0xFFF;
+/// Lowest FAT12 value marking the end of a chain
+const FAT12_END_MIN: u16 = 0xFF8;
+
+/// DOS date of 1980-01-01, used for entries written by this library
+const DOS_DATE_1980: u16 = 0x0021;
+
+/// Standard floppy layouts: media byte, tracks, sides, sectors per track,
+/// sectors per cluster, root directory entries and sectors per FAT
+const FAT_GEOMETRIES: &[(u8, u8, u8, u8, u8, u16, u16)] = &[
+    (0xFE, 40, 1, 8, 1, 64, 1),
+    (0xFF, 40, 2, 8, 2, 112, 1),
+    (0xFC, 40, 1, 9, 1, 64, 2),
+    (0xFD, 40, 2, 9, 2, 112, 2),
+    (0xF8, 80, 1, 9, 2, 112, 2),
+    (0xF9, 80, 2, 9, 2, 112, 3),
+    (0xF0, 80, 2, 18, 1, 224, 9),
+];
+
+/// BIOS parameter block from a FAT boot sector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FatBootSector {
+    /// OEM name (empty when the layout came from the media byte)
+    pub oem_name: String,
+    /// Bytes per sector
+    pub bytes_per_sector: u16,
+    /// Sectors per cluster
+    pub sectors_per_cluster: u8,
+    /// Reserved sectors before the first FAT
+    pub reserved_sectors: u16,
+    /// Number of FAT copies
+    pub num_fats: u8,
+    /// Number of root directory entries
+    pub root_entries: u16,
+    /// Total sectors on the volume
+    pub total_sectors: u32,
+    /// Media descriptor byte
+    pub media: u8,
+    /// Sectors per FAT
+    pub sectors_per_fat: u16,
+    /// Sectors per track
+    pub sectors_per_track: u16,
+    /// Number of heads (sides)
+    pub num_heads: u16,
+    /// Volume label from the extended boot record, if present
+    pub volume_label: Option<String>,
+}
+
+impl FatBootSector {
+    /// Parse the BPB of a boot sector, returning `None` if it is not valid
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 512 || !(data[0] == 0xEB || data[0] == 0xE9) {
+            return None;
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let total_sectors = match word(0x13) {
+            0 => u32::from_le_bytes([data[0x20], data[0x21], data[0x22], data[0x23]]),
+            n => n as u32,
+        };
+        let volume_label = (data[0x26] == 0x29)
+            .then(|| String::from_utf8_lossy(&data[0x2B..0x36]).trim_end().to_string())
+            .filter(|label| !label.is_empty() && label != "NO NAME");
+
+        let bpb = Self {
+            oem_name: String::from_utf8_lossy(&data[3..11]).trim_end().to_string(),
+            bytes_per_sector: word(0x0B),
+            sectors_per_cluster: data[0x0D],
+            reserved_sectors: word(0x0E),
+            num_fats: data[0x10],
+            root_entries: word(0x11),
+            total_sectors,
+            media: data[0x15],
+            sectors_per_fat: word(0x16),
+            sectors_per_track: word(0x18),
+            num_heads: word(0x1A),
+            volume_label,
+        };
+
+        let valid = bpb.bytes_per_sector.is_power_of_two()
+            && (128..=4096).contains(&bpb.bytes_per_sector)
+            && bpb.sectors_per_cluster.is_power_of_two()
+            && bpb.reserved_sectors >= 1
+            && (1..=2).contains(&bpb.num_fats)
+            && bpb.root_entries > 0
+            && bpb.sectors_per_fat > 0
+            && bpb.sectors_per_track > 0
+            && (1..=2).contains(&bpb.num_heads)
+            && bpb.media >= 0xF0;
+        valid.then_some(bpb)
+    }
+
+    /// Standard layout for a media byte
+    pub fn from_media(media: u8) -> Option<Self> {
+        FAT_GEOMETRIES
+            .iter()
+            .find(|g| g.0 == media)
+            .map(|&g| Self::from_geometry(g, String::new()))
+    }
+
+    /// Standard layout for a disk geometry
+    pub fn for_geometry(num_tracks: u8, num_sides: u8, sectors_per_track: u8) -> Option<Self> {
+        FAT_GEOMETRIES
+            .iter()
+            .find(|g| (g.1, g.2, g.3) == (num_tracks, num_sides, sectors_per_track))
+            .map(|&g| Self::from_geometry(g, "DSKMGR".to_string()))
+    }
+
+    fn from_geometry(
+        (media, tracks, sides, spt, spc, root, spf): (u8, u8, u8, u8, u8, u16, u16),
+        oem_name: String,
+    ) -> Self {
+        Self {
+            oem_name,
+            bytes_per_sector: 512,
+            sectors_per_cluster: spc,
+            reserved_sectors: 1,
+            num_fats: 2,
+            root_entries: root,
+            total_sectors: tracks as u32 * sides as u32 * spt as u32,
+            media,
+            sectors_per_fat: spf,
+            sectors_per_track: spt as u16,
+            num_heads: sides as u16,
+            volume_label: None,
+        }
+    }
+
+    /// Encode as a boot sector
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0u8; self.bytes_per_sector as usize];
+        data[..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        let mut oem = [b' '; 8];
+        for (dst, src) in oem.iter_mut().zip(self.oem_name.bytes()) {
+            *dst = src;
+        }
+        data[3..11].copy_from_slice(&oem);
+        data[0x0B..0x0D].copy_from_slice(&self.bytes_per_sector.to_le_bytes());
+        data[0x0D] = self.sectors_per_cluster;
+        data[0x0E..0x10].copy_from_slice(&self.reserved_sectors.to_le_bytes());
+        data[0x10] = self.num_fats;
+        data[0x11..0x13].copy_from_slice(&self.root_entries.to_le_bytes());
+        data[0x13..0x15].copy_from_slice(&(self.total_sectors as u16).to_le_bytes());
+        data[0x15] = self.media;
+        data[0x16..0x18].copy_from_slice(&self.sectors_per_fat.to_le_bytes());
+        data[0x18..0x1A].copy_from_slice(&self.sectors_per_track.to_le_bytes());
+        data[0x1A..0x1C].copy_from_slice(&self.num_heads.to_le_bytes());
+
+        // Extended boot record
+        data[0x26] = 0x29;
+        let mut label = [b' '; 11];
+        let name = self.volume_label.as_deref().unwrap_or("NO NAME");
+        for (dst, src) in label.iter_mut().zip(name.bytes()) {
+            *dst = src;
+        }
+        data[0x2B..0x36].copy_from_slice(&label);
+        data[0x36..0x3E].copy_from_slice(b"FAT12   ");
+
+        let len = data.len();
+        data[len - 2..].copy_from_slice(&[0x55, 0xAA]);
+        data
+    }
+
+    /// Bytes per cluster
+    pub fn cluster_size(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
+
+    /// First logical sector of the root directory
+    fn root_start(&self) -> u32 {
+        self.reserved_sectors as u32 + self.num_fats as u32 * self.sectors_per_fat as u32
+    }
+
+    /// Number of sectors in the root directory
+    fn root_sectors(&self) -> u32 {
+        (self.root_entries as u32 * FAT_DIR_ENTRY_SIZE as u32).div_ceil(self.bytes_per_sector as u32)
+    }
+
+    /// First logical sector of cluster 2
+    fn data_start(&self) -> u32 {
+        self.root_start() + self.root_sectors()
+    }
+
+    /// Number of data clusters
+    pub fn cluster_count(&self) -> usize {
+        (self.total_sectors.saturating_sub(self.data_start()) / self.sectors_per_cluster as u32) as usize
+    }
+}
+
+/// FAT directory entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FatDirEntry {
+    /// Entry index within its directory
+    pub index: usize,
+    /// Filename in NAME.EXT form
+    pub name: String,
+    /// Attribute byte
+    pub attributes: u8,
+    /// First cluster (0 for an empty file)
+    pub first_cluster: u16,
+    /// File size in bytes
+    pub size: u32,
+    /// DOS modification time
+    pub time: u16,
+    /// DOS modification date
+    pub date: u16,
+}
+
+impl FatDirEntry {
+    /// Parse a directory entry, returning `None` for free, deleted, long
+    /// filename and `.`/`..` entries
+    pub fn parse(data: &[u8], index: usize) -> Option<Self> {
+        if data.len() < FAT_DIR_ENTRY_SIZE
+            || data[0] == DIR_END
+            || data[0] == DIR_DELETED
+            || data[0] == b'.'
+            || data[11] & FAT_ATTR_LONG_NAME == FAT_ATTR_LONG_NAME
+        {
+            return None;
+        }
+
+        let mut base = data[..8].to_vec();
+        if base[0] == DIR_KANJI_E5 {
+            base[0] = DIR_DELETED;
+        }
+        let base = String::from_utf8_lossy(&base).trim_end().to_string();
+        let ext = String::from_utf8_lossy(&data[8..11]).trim_end().to_string();
+        let attributes = data[11];
+        let name = if ext.is_empty() || attributes & FAT_ATTR_VOLUME != 0 {
+            format!("{}{}", base, ext)
+        } else {
+            format!("{}.{}", base, ext)
+        };
+
+        Some(Self {
+            index,
+            name,
+            attributes,
+            first_cluster: u16::from_le_bytes([data[26], data[27]]),
+            size: u32::from_le_bytes([data[28], data[29], data[30], data[31]]),
+            time: u16::from_le_bytes([data[22], data[23]]),
+            date: u16::from_le_bytes([data[24], data[25]]),
+        })
+    }
+
+    /// Whether this entry is a subdirectory
+    pub fn is_directory(&self) -> bool {
+        self.attributes & FAT_ATTR_DIRECTORY != 0
+    }
+
+    /// Whether this entry is the volume label
+    pub fn is_volume_label(&self) -> bool {
+        self.attributes & FAT_ATTR_VOLUME != 0
+    }
+
+    /// Generic file attributes
+    pub fn file_attributes(&self) -> FileAttributes {
+        FileAttributes {
+            read_only: self.attributes & FAT_ATTR_READ_ONLY != 0,
+            system: self.attributes & (FAT_ATTR_SYSTEM | FAT_ATTR_HIDDEN) != 0,
+            archive: self.attributes & FAT_ATTR_ARCHIVE != 0,
+        }
+    }
+}
+
+/// Location of a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirLocation {
+    /// The fixed root directory
+    Root,
+    /// A subdirectory starting at a cluster
+    Cluster(u16),
+}
+
+/// Position of a directory entry on disk
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    lba: u32,
+    offset: usize,
+}
+
+/// FAT12 filesystem
+pub struct FatFileSystem<'a> {
+    image: ImageRef<'a>,
+    bpb: FatBootSector,
+    fat: Vec<u8>,
+}
+
+impl<'a> FatFileSystem<'a> {
+    /// Mount a FAT12 filesystem read-only
+    pub fn new(image: &'a DiskImage) -> Result<Self> {
+        Self::mount(ImageRef::Shared(image))
+    }
+
+    /// Mount a FAT12 filesystem read-write
+    pub fn new_mut(image: &'a mut DiskImage) -> Result<Self> {
+        Self::mount(ImageRef::Exclusive(image))
+    }
+
+    fn mount(image: ImageRef<'a>) -> Result<Self> {
+        let bpb = Self::detect(image.get())
+            .ok_or_else(|| DskError::filesystem("Not a FAT12 disk"))?;
+        if bpb.cluster_count() > FAT12_MAX_CLUSTERS {
+            return Err(DskError::filesystem("Only FAT12 volumes are supported"));
+        }
+
+        let mut fs = Self {
+            image,
+            bpb,
+            fat: Vec::new(),
+        };
+        let mut fat = Vec::with_capacity(fs.bpb.sectors_per_fat as usize * fs.bpb.bytes_per_sector as usize);
+        for lba in 0..fs.bpb.sectors_per_fat as u32 {
+            fat.extend_from_slice(fs.read_lba(fs.bpb.reserved_sectors as u32 + lba)?);
+        }
+        // Every cluster, plus the two reserved entries, must have a FAT entry
+        if fat.len() < (fs.bpb.cluster_count() + 2) * 3 / 2 + 1 {
+            return Err(DskError::filesystem(format!(
+                "FAT of {} sectors is too small for {} clusters",
+                fs.bpb.sectors_per_fat,
+                fs.bpb.cluster_count()
+            )));
+        }
+        fs.fat = fat;
+        Ok(fs)
+    }
+
+    /// Check whether an image holds a FAT12 disk
+    pub fn is_fat(image: &DiskImage) -> bool {
+        Self::detect(image).is_some()
+    }
+
+    /// Find the BPB, falling back to the media byte at the start of the FAT
+    fn detect(image: &DiskImage) -> Option<FatBootSector> {
+        let boot = image.read_sector(0, 0, 1).ok()?;
+        FatBootSector::parse(boot).or_else(|| {
+            let fat = image.read_sector(0, 0, 2).ok()?;
+            if fat.len() >= 3 && fat[1] == 0xFF && fat[2] == 0xFF {
+                FatBootSector::from_media(fat[0])
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Write an empty FAT12 filesystem to an image
+    ///
+    /// The layout is chosen from the image's geometry, which must be one
+    /// of the standard PC or MSX floppy formats.
+    pub fn format_image(image: &mut DiskImage, label: &str) -> Result<()> {
+        let spec = image.spec();
+        let mut bpb = FatBootSector::for_geometry(spec.num_tracks, spec.num_sides, spec.sectors_per_track)
+            .filter(|_| spec.sector_size == 512)
+            .ok_or_else(|| {
+                DskError::filesystem(format!(
+                    "No FAT layout for {} tracks, {} sides, {} sectors of {} bytes",
+                    spec.num_tracks, spec.num_sides, spec.sectors_per_track, spec.sector_size
+                ))
+            })?;
+        let label = label.trim().to_uppercase();
+        if label.len() > 11 {
+            return Err(DskError::InvalidFilename(label));
+        }
+        bpb.volume_label = (!label.is_empty()).then_some(label);
+
+        let mut fs = FatFileSystem {
+            image: ImageRef::Exclusive(image),
+            fat: vec![0; bpb.sectors_per_fat as usize * bpb.bytes_per_sector as usize],
+            bpb,
+        };
+        let boot = fs.bpb.to_bytes();
+        fs.write_lba(0, &boot)?;
+
+        fs.fat[..3].copy_from_slice(&[fs.bpb.media, 0xFF, 0xFF]);
+        fs.flush_fat()?;
+
+        let empty = vec![0u8; fs.bpb.bytes_per_sector as usize];
+        for lba in fs.bpb.root_start()..fs.bpb.data_start() {
+            fs.write_lba(lba, &empty)?;
+        }
+
+        if let Some(label) = fs.bpb.volume_label.clone() {
+            let mut name = [b' '; 11];
+            name[..label.len()].copy_from_slice(label.as_bytes());
+            let slot = Slot { lba: fs.bpb.root_start(), offset: 0 };
+            fs.write_slot(slot, &encode_entry(&name, FAT_ATTR_VOLUME, 0, 0))?;
+        }
+        Ok(())
+    }
+
+    /// Get the BIOS parameter block
+    pub fn boot_sector(&self) -> &FatBootSector {
+        &self.bpb
+    }
+
+    /// Volume label from the root directory or boot sector
+    pub fn volume_label(&self) -> Option<String> {
+        self.read_directory(DirLocation::Root)
+            .ok()
+            .and_then(|entries| entries.into_iter().find(|(_, e)| e.is_volume_label()))
+            .map(|(_, e)| e.name.trim_end().to_string())
+            .or_else(|| self.bpb.volume_label.clone())
+    }
+
+    /// Physical (side, track, sector ID) of a logical sector
+    fn physical(&self, lba: u32) -> (u8, u8, u8) {
+        let spt = self.bpb.sectors_per_track as u32;
+        let heads = self.bpb.num_heads as u32;
+        let track = lba / spt;
+        ((track % heads) as u8, (track / heads) as u8, (lba % spt) as u8 + 1)
+    }
+
+    fn read_lba(&self, lba: u32) -> Result<&[u8]> {
+        let (side, track, id) = self.physical(lba);
+        self.image.get().read_sector(side, track, id)
+    }
+
+    fn write_lba(&mut self, lba: u32, data: &[u8]) -> Result<()> {
+        let (side, track, id) = self.physical(lba);
+        self.image.get_mut()?.write_sector(side, track, id, data)
+    }
+
+    /// Logical sectors of a cluster
+    fn cluster_sectors(&self, cluster: u16) -> std::ops::Range<u32> {
+        let spc = self.bpb.sectors_per_cluster as u32;
+        let start = self.bpb.data_start() + (cluster as u32 - 2) * spc;
+        start..start + spc
+    }
+
+    /// Read a FAT entry
+    fn fat_entry(&self, cluster: u16) -> u16 {
+        let offset = cluster as usize * 3 / 2;
+        let pair = u16::from_le_bytes([self.fat[offset], self.fat[offset + 1]]);
+        if cluster.is_multiple_of(2) {
+            pair & 0x0FFF
+        } else {
+            pair >> 4
+        }
+    }
+
+    /// Set a FAT entry in the cached FAT
+    fn set_fat_entry(&mut self, cluster: u16, value: u16) {
+        let offset = cluster as usize * 3 / 2;
+        let pair = u16::from_le_bytes([self.fat[offset], self.fat[offset + 1]]);
+        let pair = if cluster.is_multiple_of(2) {
+            (pair & 0xF000) | (value & 0x0FFF)
+        } else {
+            (pair & 0x000F) | (value << 4)
+        };
+        self.fat[offset..offset + 2].copy_from_slice(&pair.to_le_bytes());
+    }
+
+    /// Write the cached FAT to every FAT copy
+    fn flush_fat(&mut self) -> Result<()> {
+        let sector_size = self.bpb.bytes_per_sector as usize;
+        // The cache is moved out so it can be written through `write_lba`,
+        // and is put back whether or not every write succeeds
+        let fat = std::mem::take(&mut self.fat);
+        let result = (0..self.bpb.num_fats as u32).try_for_each(|copy| {
+            let start = self.bpb.reserved_sectors as u32 + copy * self.bpb.sectors_per_fat as u32;
+            fat.chunks(sector_size)
+                .enumerate()
+                .try_for_each(|(i, chunk)| self.write_lba(start + i as u32, chunk))
+        });
+        self.fat = fat;
+        result
+    }
+
+    /// Run a change to the cached FAT, putting it back as it was on error
+    fn with_fat_rollback<T>(&mut self, change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let saved = self.fat.clone();
+        let result = change(self);
+        if result.is_err() {
+            self.fat = saved;
+        }
+        result
+    }
+
+    /// Whether a cluster number lies in the data area
+    fn valid_cluster(&self, cluster: u16) -> bool {
+        (2..self.bpb.cluster_count() + 2).contains(&(cluster as usize))
+    }
+
+    /// Follow a cluster chain from its first cluster
+    pub fn cluster_chain(&self, first: u16) -> Result<Vec<u16>> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        while cluster != 0 && cluster < FAT12_END_MIN {
+            if !self.valid_cluster(cluster) || chain.len() > self.bpb.cluster_count() {
+                return Err(DskError::filesystem(format!(
+                    "Broken cluster chain from cluster {}",
+                    first
+                )));
+            }
+            chain.push(cluster);
+            cluster = self.fat_entry(cluster);
+        }
+        Ok(chain)
+    }
+
+    /// Free data clusters
+    fn free_clusters(&self) -> Vec<u16> {
+        (2..self.bpb.cluster_count() as u16 + 2)
+            .filter(|&c| self.fat_entry(c) == 0)
+            .collect()
+    }
+
+    /// Claim clusters and link them into a chain
+    fn allocate(&mut self, count: usize) -> Result<Vec<u16>> {
+        let clusters: Vec<u16> = self.free_clusters().into_iter().take(count).collect();
+        if clusters.len() < count {
+            return Err(DskError::DiskFull);
+        }
+        for (i, &cluster) in clusters.iter().enumerate() {
+            let next = clusters.get(i + 1).copied().unwrap_or(FAT12_END_OF_CHAIN);
+            self.set_fat_entry(cluster, next);
+        }
+        Ok(clusters)
+    }
+
+    /// Logical sectors of a directory
+    fn dir_sectors(&self, location: DirLocation) -> Result<Vec<u32>> {
+        match location {
+            DirLocation::Root => Ok((self.bpb.root_start()..self.bpb.data_start()).collect()),
+            DirLocation::Cluster(first) => Ok(self
+                .cluster_chain(first)?
+                .into_iter()
+                .flat_map(|c| self.cluster_sectors(c))
+                .collect()),
+        }
+    }
+
+    /// Entry positions of a directory
+    fn slots(&self, location: DirLocation) -> Result<Vec<Slot>> {
+        let per_sector = self.bpb.bytes_per_sector as usize / FAT_DIR_ENTRY_SIZE;
+        Ok(self
+            .dir_sectors(location)?
+            .into_iter()
+            .flat_map(|lba| (0..per_sector).map(move |i| Slot { lba, offset: i * FAT_DIR_ENTRY_SIZE }))
+            .collect())
+    }
+
+    fn read_slot(&self, slot: Slot) -> Result<&[u8]> {
+        let sector = self.read_lba(slot.lba)?;
+        sector
+            .get(slot.offset..slot.offset + FAT_DIR_ENTRY_SIZE)
+            .ok_or_else(|| DskError::filesystem("Directory sector is too short"))
+    }
+
+    fn write_slot(&mut self, slot: Slot, entry: &[u8; FAT_DIR_ENTRY_SIZE]) -> Result<()> {
+        let mut sector = self.read_lba(slot.lba)?.to_vec();
+        sector[slot.offset..slot.offset + FAT_DIR_ENTRY_SIZE].copy_from_slice(entry);
+        self.write_lba(slot.lba, &sector)
+    }
+
+    /// Read the live entries of a directory
+    fn read_directory(&self, location: DirLocation) -> Result<Vec<(Slot, FatDirEntry)>> {
+        let mut entries = Vec::new();
+        for (index, slot) in self.slots(location)?.into_iter().enumerate() {
+            let raw = self.read_slot(slot)?;
+            if raw[0] == DIR_END {
+                break;
+            }
+            if let Some(entry) = FatDirEntry::parse(raw, index) {
+                entries.push((slot, entry));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Find the directory at a path
+    fn resolve_dir(&self, path: &str) -> Result<DirLocation> {
+        let mut location = DirLocation::Root;
+        for component in split_path(path) {
+            let (_, entry) = self
+                .find_in(location, component)?
+                .filter(|(_, e)| e.is_directory())
+                .ok_or_else(|| DskError::FileNotFound(path.to_string()))?;
+            location = DirLocation::Cluster(entry.first_cluster);
+        }
+        Ok(location)
+    }
+
+    /// Find an entry by name in a directory (case-insensitive)
+    fn find_in(&self, location: DirLocation, name: &str) -> Result<Option<(Slot, FatDirEntry)>> {
+        Ok(self
+            .read_directory(location)?
+            .into_iter()
+            .find(|(_, e)| !e.is_volume_label() && e.name.eq_ignore_ascii_case(name)))
+    }
+
+    /// Find the entry at a path, with the directory holding it
+    fn find_path(&self, path: &str) -> Result<(DirLocation, Slot, FatDirEntry)> {
+        let (parent, name) = split_parent(path);
+        let location = self.resolve_dir(parent)?;
+        let (slot, entry) = self
+            .find_in(location, name)?
+            .ok_or_else(|| DskError::FileNotFound(path.to_string()))?;
+        Ok((location, slot, entry))
+    }
+
+    /// List a directory by path (empty or `/` for the root)
+    pub fn list_dir(&self, path: &str) -> Result<Vec<FatDirEntry>> {
+        let location = self.resolve_dir(path)?;
+        Ok(self
+            .read_directory(location)?
+            .into_iter()
+            .map(|(_, e)| e)
+            .filter(|e| !e.is_volume_label())
+            .collect())
+    }
+
+    /// List every file and directory with its full path, depth first
+    pub fn walk(&self) -> Result<Vec<(String, FatDirEntry)>> {
+        let mut out = Vec::new();
+        self.walk_into(DirLocation::Root, "", &mut out, 0)?;
+        Ok(out)
+    }
+
+    fn walk_into(
+        &self,
+        location: DirLocation,
+        prefix: &str,
+        out: &mut Vec<(String, FatDirEntry)>,
+        depth: usize,
+    ) -> Result<()> {
+        // Guard against directory loops on damaged disks
+        if depth > 32 {
+            return Err(DskError::filesystem("Directories nested too deeply"));
+        }
+        for (_, entry) in self.read_directory(location)? {
+            if entry.is_volume_label() {
+                continue;
+            }
+            let path = format!("{}{}", prefix, entry.name);
+            let subdir = entry.is_directory().then_some(entry.first_cluster);
+            out.push((path.clone(), entry));
+            if let Some(cluster) = subdir.filter(|&c| self.valid_cluster(c)) {
+                self.walk_into(DirLocation::Cluster(cluster), &format!("{}/", path), out, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a file's data
+    pub fn read_entry(&self, entry: &FatDirEntry) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(entry.size as usize);
+        for cluster in self.cluster_chain(entry.first_cluster)? {
+            for lba in self.cluster_sectors(cluster) {
+                data.extend_from_slice(self.read_lba(lba)?);
+            }
+        }
+        if data.len() < entry.size as usize {
+            return Err(DskError::filesystem(format!(
+                "{} is shorter than its directory size",
+                entry.name
+            )));
+        }
+        data.truncate(entry.size as usize);
+        Ok(data)
+    }
+
+    /// Read directory with paths, attributes and cluster counts
+    pub fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        let cluster_size = self.bpb.cluster_size();
+        self.walk()?
+            .into_iter()
+            .map(|(path, entry)| {
+                let clusters = self.cluster_chain(entry.first_cluster).map(|c| c.len()).unwrap_or(0);
+                let (name, meta) = if entry.is_directory() {
+                    (format!("{}/", path), "<DIR>".to_string())
+                } else {
+                    (path, String::new())
+                };
+                Ok(ExtendedDirEntry {
+                    name,
+                    user: 0,
+                    index: entry.index,
+                    blocks: clusters,
+                    allocated: clusters * cluster_size,
+                    size: entry.size as usize,
+                    attributes: entry.file_attributes(),
+                    header: FileHeader {
+                        header_type: HeaderType::None,
+                        checksum_valid: true,
+                        file_size: entry.size as usize,
+                        header_size: 0,
+                        meta,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Find a free entry in a directory, growing a subdirectory if needed
+    ///
+    /// `reserve` clusters are kept free for the caller's data.
+    fn free_slot(&mut self, location: DirLocation, reserve: usize) -> Result<Slot> {
+        for slot in self.slots(location)? {
+            let first = self.read_slot(slot)?[0];
+            if first == DIR_END || first == DIR_DELETED {
+                return Ok(slot);
+            }
+        }
+
+        let DirLocation::Cluster(first) = location else {
+            return Err(DskError::DiskFull);
+        };
+        if self.free_clusters().len() < reserve + 1 {
+            return Err(DskError::DiskFull);
+        }
+        let cluster = self.allocate(1)?[0];
+        let last = *self.cluster_chain(first)?.last().unwrap_or(&first);
+        self.set_fat_entry(last, cluster);
+
+        let empty = vec![0u8; self.bpb.bytes_per_sector as usize];
+        for lba in self.cluster_sectors(cluster) {
+            self.write_lba(lba, &empty)?;
+        }
+        Ok(Slot { lba: self.cluster_sectors(cluster).start, offset: 0 })
+    }
+
+    /// Create a new entry at `path`, returning its first cluster
+    ///
+    /// Clusters claimed before a failure are released again.
+    fn create_entry(&mut self, path: &str, attributes: u8, data: &[u8]) -> Result<(u16, DirLocation)> {
+        self.with_fat_rollback(|fs| fs.write_entry(path, attributes, data))
+    }
+
+    fn write_entry(&mut self, path: &str, attributes: u8, data: &[u8]) -> Result<(u16, DirLocation)> {
+        self.image.get_mut()?;
+        let (parent, name) = split_parent(path);
+        let encoded = encode_filename(name)?;
+        let location = self.resolve_dir(parent)?;
+        if self.find_in(location, name)?.is_some() {
+            return Err(DskError::filesystem(format!("File already exists: {}", path)));
+        }
+
+        let cluster_size = self.bpb.cluster_size();
+        let needed = data.len().div_ceil(cluster_size);
+        let slot = self.free_slot(location, needed)?;
+        let clusters = self.allocate(needed)?;
+
+        let sector_size = self.bpb.bytes_per_sector as usize;
+        for (&cluster, chunk) in clusters.iter().zip(data.chunks(cluster_size)) {
+            let mut cluster_data = chunk.to_vec();
+            cluster_data.resize(cluster_size, 0);
+            for (lba, sector) in self.cluster_sectors(cluster).zip(cluster_data.chunks(sector_size)) {
+                self.write_lba(lba, sector)?;
+            }
+        }
+
+        let first = clusters.first().copied().unwrap_or(0);
+        let size = if attributes & FAT_ATTR_DIRECTORY != 0 { 0 } else { data.len() as u32 };
+        self.write_slot(slot, &encode_entry(&encoded, attributes, first, size))?;
+        self.flush_fat()?;
+        Ok((first, location))
+    }
+
+    /// Create an empty subdirectory
+    pub fn create_dir(&mut self, path: &str) -> Result<()> {
+        let empty = vec![0u8; self.bpb.cluster_size()];
+        let (cluster, parent) = self.create_entry(path, FAT_ATTR_DIRECTORY, &empty)?;
+
+        let parent_cluster = match parent {
+            DirLocation::Root => 0,
+            DirLocation::Cluster(c) => c,
+        };
+        let mut dot = [b' '; 11];
+        dot[0] = b'.';
+        let mut dotdot = dot;
+        dotdot[1] = b'.';
+
+        let lba = self.cluster_sectors(cluster).start;
+        self.write_slot(Slot { lba, offset: 0 }, &encode_entry(&dot, FAT_ATTR_DIRECTORY, cluster, 0))?;
+        self.write_slot(
+            Slot { lba, offset: FAT_DIR_ENTRY_SIZE },
+            &encode_entry(&dotdot, FAT_ATTR_DIRECTORY, parent_cluster, 0),
+        )
+    }
+}
+
+impl<'a> FileSystem for FatFileSystem<'a> {
+    fn from_image<'b>(_image: &'b DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use FatFileSystem::new() directly",
+        ))
+    }
+
+    fn from_image_mut<'b>(_image: &'b mut DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use FatFileSystem::new_mut() directly",
+        ))
+    }
+
+    /// List every file on the disk with its full path
+    fn read_dir(&self) -> Result<Vec<DirEntry>> {
+        Ok(self
+            .walk()?
+            .into_iter()
+            .filter(|(_, e)| !e.is_directory())
+            .map(|(path, e)| DirEntry {
+                name: path,
+                user: 0,
+                extent: 0,
+                size: e.size as usize,
+                attributes: e.file_attributes(),
+            })
+            .collect())
+    }
+
+    fn read_file(&self, name: &str) -> Result<Vec<u8>> {
+        let (_, _, entry) = self.find_path(name)?;
+        if entry.is_directory() {
+            return Err(DskError::filesystem(format!("{} is a directory", name)));
+        }
+        self.read_entry(&entry)
+    }
+
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.create_entry(name, FAT_ATTR_ARCHIVE, data).map(|_| ())
+    }
+
+    /// Delete a file or an empty subdirectory
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        self.image.get_mut()?;
+        let (_, slot, entry) = self.find_path(name)?;
+        if entry.attributes & FAT_ATTR_READ_ONLY != 0 {
+            return Err(DskError::filesystem(format!("File is read-only: {}", name)));
+        }
+        if entry.is_directory() && !self.read_directory(DirLocation::Cluster(entry.first_cluster))?.is_empty() {
+            return Err(DskError::filesystem(format!("Directory is not empty: {}", name)));
+        }
+
+        self.with_fat_rollback(|fs| {
+            for cluster in fs.cluster_chain(entry.first_cluster)? {
+                fs.set_fat_entry(cluster, 0);
+            }
+            let mut raw = [0u8; FAT_DIR_ENTRY_SIZE];
+            raw.copy_from_slice(fs.read_slot(slot)?);
+            raw[0] = DIR_DELETED;
+            fs.write_slot(slot, &raw)?;
+            fs.flush_fat()
+        })
+    }
+
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        FatFileSystem::read_dir_extended(self)
+    }
+
+    fn info(&self) -> FileSystemInfo {
+        FileSystemInfo {
+            fs_type: "FAT12".to_string(),
+            total_blocks: self.bpb.cluster_count(),
+            free_blocks: self.free_clusters().len(),
+            block_size: self.bpb.cluster_size(),
+        }
+    }
+
+    fn details(&self) -> Vec<(String, String)> {
+        self.volume_label()
+            .map(|label| vec![("Label".to_string(), label)])
+            .unwrap_or_default()
+    }
+}
+
+/// Split a path into its components
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split(['/', '\\']).filter(|c| !c.is_empty())
+}
+
+/// Split a path into its parent directory and final name
+fn split_parent(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches(['/', '\\']);
+    match path.rfind(['/', '\\']) {
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("", path),
+    }
+}
+
+/// Check whether a character may appear in a DOS filename
+fn valid_dos_char(c: u8) -> bool {
+    c > b' ' && !b"\"*+,./:;<=>?[\\]|".contains(&c) && c != 0x7F
+}
+
+/// Convert a filename into space-padded 8.3 directory form
+fn encode_filename(name: &str) -> Result<[u8; 11]> {
+    let upper = name.trim().to_uppercase();
+    let (base, ext) = upper.rsplit_once('.').unwrap_or((upper.as_str(), ""));
+
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || !base.bytes().chain(ext.bytes()).all(valid_dos_char)
+    {
+        return Err(DskError::InvalidFilename(name.to_string()));
+    }
+
+    let mut encoded = [b' '; 11];
+    encoded[..base.len()].copy_from_slice(base.as_bytes());
+    encoded[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    if encoded[0] == DIR_DELETED {
+        encoded[0] = DIR_KANJI_E5;
+    }
+    Ok(encoded)
+}
+
+/// Build a directory entry
+fn encode_entry(name: &[u8; 11], attributes: u8, first_cluster: u16, size: u32) -> [u8; FAT_DIR_ENTRY_SIZE] {
+    let mut entry = [0u8; FAT_DIR_ENTRY_SIZE];
+    entry[..11].copy_from_slice(name);
+    entry[11] = attributes;
+    entry[24..26].copy_from_slice(&DOS_DATE_1980.to_le_bytes());
+    entry[26..28].copy_from_slice(&first_cluster.to_le_bytes());
+    entry[28..32].copy_from_slice(&size.to_le_bytes());
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::FileSystemType;
+    use crate::format::FormatSpec;
+    use crate::image::DiskImageBuilder;
+
+    fn blank_disk(spec: FormatSpec, label: &str) -> DiskImage {
+        let mut image = DiskImageBuilder::new().spec(spec).build().unwrap();
+        FatFileSystem::format_image(&mut image, label).unwrap();
+        image
+    }
+
+    #[test]
+    fn test_fat12_entries() {
+        let image = blank_disk(FormatSpec::ibm_pc_360k(), "");
+        let mut fs = FatFileSystem::new(&image).unwrap();
+        fs.set_fat_entry(2, 0x123);
+        fs.set_fat_entry(3, 0xABC);
+        assert_eq!(&fs.fat[3..6], &[0x23, 0xC1, 0xAB]);
+        assert_eq!(fs.fat_entry(2), 0x123);
+        assert_eq!(fs.fat_entry(3), 0xABC);
+    }
+
+    #[test]
+    fn test_format_360k() {
+        let image = blank_disk(FormatSpec::ibm_pc_360k(), "transfer");
+        assert_eq!(image.default_filesystem(), FileSystemType::Fat);
+
+        let fs = FatFileSystem::new(&image).unwrap();
+        assert_eq!(fs.boot_sector().media, 0xFD);
+        assert_eq!(fs.boot_sector().cluster_count(), 354);
+        assert_eq!(fs.volume_label().as_deref(), Some("TRANSFER"));
+        assert_eq!(fs.info().free_blocks, 354);
+        assert!(fs.read_dir().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_read_delete() {
+        let mut image = blank_disk(FormatSpec::ibm_pc_720k(), "");
+        let big: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        {
+            let mut fs = FatFileSystem::new_mut(&mut image).unwrap();
+            fs.write_file("readme.txt", b"hello").unwrap();
+            fs.create_dir("GAMES").unwrap();
+            fs.write_file("games/big.bin", &big).unwrap();
+            fs.write_file("empty", &[]).unwrap();
+            assert!(fs.write_file("README.TXT", b"again").is_err());
+            assert!(matches!(fs.write_file("bad*name", b""), Err(DskError::InvalidFilename(_))));
+            assert!(matches!(fs.write_file("nodir/file", b""), Err(DskError::FileNotFound(_))));
+        }
+
+        let fs = FatFileSystem::new(&image).unwrap();
+        let names: Vec<_> = fs.read_dir().unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["README.TXT", "GAMES/BIG.BIN", "EMPTY"]);
+        assert_eq!(fs.read_file("README.TXT").unwrap(), b"hello");
+        assert_eq!(fs.read_file("GAMES\\BIG.BIN").unwrap(), big);
+        assert_eq!(fs.read_file("empty").unwrap(), b"");
+        assert_eq!(fs.cluster_chain(fs.find_path("games/big.bin").unwrap().2.first_cluster).unwrap().len(), 5);
+
+        let extended = fs.read_dir_extended().unwrap();
+        assert_eq!(extended[1].name, "GAMES/");
+        assert_eq!(extended[1].header.meta, "<DIR>");
+        let free = fs.info().free_blocks;
+        drop(fs);
+
+        let mut fs = FatFileSystem::new_mut(&mut image).unwrap();
+        assert!(fs.delete_file("GAMES").is_err());
+        fs.delete_file("games/big.bin").unwrap();
+        fs.delete_file("GAMES").unwrap();
+        assert_eq!(fs.info().free_blocks, free + 6);
+        assert!(matches!(fs.read_file("GAMES/BIG.BIN"), Err(DskError::FileNotFound(_))));
+
+        // Both FAT copies are kept in step
+        let fat1 = fs.read_lba(1).unwrap().to_vec();
+        assert_eq!(fs.read_lba(4).unwrap(), &fat1[..]);
+    }
+
+    #[test]
+    fn test_subdirectory_grows() {
+        let mut image = blank_disk(FormatSpec::ibm_pc_360k(), "");
+        let mut fs = FatFileSystem::new_mut(&mut image).unwrap();
+        fs.create_dir("DIR").unwrap();
+        // A 1K cluster holds 32 entries, two of which are . and ..
+        for i in 0..40 {
+            fs.write_file(&format!("DIR/F{}", i), &[i as u8]).unwrap();
+        }
+        assert_eq!(fs.list_dir("DIR").unwrap().len(), 40);
+        assert_eq!(fs.read_file("dir/f39").unwrap(), vec![39]);
+        let dir = fs.find_path("DIR").unwrap().2;
+        assert_eq!(fs.cluster_chain(dir.first_cluster).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_fat_too_small() {
+        let mut image = blank_disk(FormatSpec::ibm_pc_360k(), "");
+        let mut boot = image.read_sector(0, 0, 1).unwrap().to_vec();
+        boot[0x16..0x18].copy_from_slice(&1u16.to_le_bytes());
+        image.write_sector(0, 0, 1, &boot).unwrap();
+
+        assert!(FatFileSystem::is_fat(&image));
+        assert!(matches!(FatFileSystem::new(&image), Err(DskError::FileSystemError(_))));
+    }
+
+    #[test]
+    fn test_failed_write_keeps_fat() {
+        let mut image = blank_disk(FormatSpec::ibm_pc_360k(), "");
+        // The first data clusters are on side 1 of track 0
+        image.get_disk_mut(1).unwrap().get_track_mut(0).unwrap().clear();
+
+        let mut fs = FatFileSystem::new_mut(&mut image).unwrap();
+        let fat = fs.fat.clone();
+        assert!(fs.write_file("DATA.BIN", &[1; 2000]).is_err());
+        assert_eq!(fs.fat, fat);
+        assert_eq!(fs.free_clusters().len(), fs.bpb.cluster_count());
+    }
+
+    #[test]
+    fn test_media_byte_fallback() {
+        let mut image = blank_disk(FormatSpec::ibm_pc_360k(), "");
+        image.write_sector(0, 0, 1, &[0u8; 512]).unwrap();
+        let fs = FatFileSystem::new(&image).unwrap();
+        assert_eq!(fs.boot_sector().media, 0xFD);
+        assert!(fs.boot_sector().oem_name.is_empty());
+
+        let cpc = DiskImageBuilder::new().build().unwrap();
+        assert!(!FatFileSystem::is_fat(&cpc));
+    }
+}
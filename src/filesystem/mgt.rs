@@ -8,10 +8,22 @@
 /// - First 4 tracks (40 sectors) reserved for directory
 /// - Each file entry is 256 bytes (2 per sector)
 /// - Max 80 directory entries
+/// - SAM MasterDOS disks can extend the directory over more tracks of
+///   side 0, the number of extra tracks being held in byte 255 of the
+///   first entry
+///
+/// File sectors hold 510 bytes of data followed by the track and sector of
+/// the next sector in the file (0, 0 in the last sector). Tracks on side 1
+/// are numbered from 128. Each directory entry also has a 195 byte bitmap
+/// of the sectors it uses, one bit per sector from track 4 onwards.
 
 use crate::error::{DskError, Result};
-use crate::filesystem::{ExtendedDirEntry, FileAttributes, FileHeader, HeaderType};
-use crate::image::DiskImage;
+use crate::filesystem::{
+    disciple, sam, DirEntry, ExtendedDirEntry, FileAttributes, FileHeader, FileSystem,
+    FileSystemInfo, HeaderType, ImageRef,
+};
+use crate::format::{DiskImageFormat, FormatSpec, SideMode};
+use crate::image::{DiskImage, DiskImageBuilder};
 
 /// Number of directory tracks
 pub const MGT_DIR_TRACKS: usize = 4;
@@ -31,6 +43,39 @@ pub const MGT_ENTRIES_PER_SECTOR: usize = 512 / MGT_DIR_ENTRY_SIZE;
 /// Maximum directory entries
 pub const MGT_MAX_DIR_ENTRIES: usize = MGT_DIR_SECTORS * MGT_ENTRIES_PER_SECTOR;
 
+/// Most directory tracks a MasterDOS disk can have
+pub const MGT_MAX_DIR_TRACKS: usize = 39;
+
+/// Offset of the number of extra directory tracks in the first directory entry (MasterDOS)
+const MGT_EXTRA_DIR_TRACKS: usize = 255;
+
+/// Tracks per side
+pub const MGT_TRACKS_PER_SIDE: usize = 80;
+
+/// Bit set in track numbers on side 1
+pub const MGT_SIDE1_TRACK: u8 = 0x80;
+
+/// Data bytes in each file sector, before the next sector pointer
+pub const MGT_SECTOR_DATA_SIZE: usize = 510;
+
+/// Size of the sector address map in a directory entry
+pub const MGT_SECTOR_MAP_SIZE: usize = 195;
+
+/// Offset of the system-specific information in a directory entry
+pub const MGT_DIR_INFO_OFFSET: usize = 210;
+
+/// Size of the header at the start of BASIC, array, CODE and SCREEN$ files
+pub const MGT_FILE_HEADER_SIZE: usize = 9;
+
+/// Length of a 48K snapshot (RAM from 16384)
+pub(crate) const SNAPSHOT_48K_LENGTH: usize = 49152;
+
+/// Length of a DISCiPLE/+D 128K snapshot (port 0x7FFD, then RAM pages 0-7)
+pub(crate) const SNAPSHOT_128K_LENGTH: usize = 1 + 8 * 16384;
+
+/// DISCiPLE/+D directory type of 128K snapshots
+pub(crate) const DISCIPLE_TYPE_SNAPSHOT_128K: u8 = 9;
+
 /// File type codes
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MgtFileType {
@@ -82,6 +127,36 @@ impl MgtFileType {
             MgtFileType::Other(code) => *code,
         }
     }
+
+    /// Check whether files of this type start with a 9 byte header
+    ///
+    /// SAM files of types 0x10-0x14 and DISCiPLE/+D BASIC, array, CODE and
+    /// SCREEN$ files (types 1-4 and 7) do.
+    pub fn has_file_header(&self) -> bool {
+        matches!(
+            self,
+            MgtFileType::SamBasic
+                | MgtFileType::NumericArray
+                | MgtFileType::StringArray
+                | MgtFileType::Code
+                | MgtFileType::Screen
+                | MgtFileType::Other(1..=4 | 7)
+        )
+    }
+
+    /// Length of the file data given in a file header
+    fn header_length(&self, header: &[u8]) -> Option<usize> {
+        if header.len() < MGT_FILE_HEADER_SIZE || !self.has_file_header() {
+            return None;
+        }
+        let length = u16::from_le_bytes([header[1], header[2]]) as usize;
+        match self {
+            // DISCiPLE/+D: type, length, start, program length, autostart
+            MgtFileType::Other(_) => Some(length),
+            // SAM: type, length mod 16K, page offset, unused, pages, start page
+            _ => Some(header[7] as usize * 16384 + length),
+        }
+    }
 }
 
 impl std::fmt::Display for MgtFileType {
@@ -194,12 +269,52 @@ impl MgtDirEntry {
         }
     }
 
+    /// Serialize this entry back into its 256 byte directory form
+    pub fn to_bytes(&self) -> [u8; MGT_DIR_ENTRY_SIZE] {
+        let mut data = [0u8; MGT_DIR_ENTRY_SIZE];
+        let len = self.raw_data.len().min(MGT_DIR_ENTRY_SIZE);
+        data[..len].copy_from_slice(&self.raw_data[..len]);
+
+        data[0] = self.file_type.type_code()
+            | if self.hidden { 0x80 } else { 0 }
+            | if self.protected { 0x40 } else { 0 };
+        data[1..11].fill(b' ');
+        data[1..1 + self.filename.len().min(10)]
+            .copy_from_slice(&self.filename.as_bytes()[..self.filename.len().min(10)]);
+        data[11..13].copy_from_slice(&self.sectors_used.to_be_bytes());
+        data[13] = self.start_track;
+        data[14] = self.start_sector;
+        let map_len = self.sector_map.len().min(MGT_SECTOR_MAP_SIZE);
+        data[15..15 + map_len].copy_from_slice(&self.sector_map[..map_len]);
+        data
+    }
+
+    /// Check whether the sector address map marks a sector as used
+    pub fn uses_sector(&self, track: u8, sector: u8) -> bool {
+        sector_map_bit(track, sector).is_some_and(|bit| {
+            self.sector_map
+                .get(bit / 8)
+                .is_some_and(|&byte| byte & (1 << (bit % 8)) != 0)
+        })
+    }
+
+    /// Length of the file data, from the file header or the file type
+    ///
+    /// `data` is the file as stored, starting with any header.
+    pub fn data_length(&self, data: &[u8]) -> Option<usize> {
+        match self.file_type {
+            MgtFileType::ZxSnapshot => Some(SNAPSHOT_48K_LENGTH),
+            MgtFileType::Other(DISCIPLE_TYPE_SNAPSHOT_128K) => Some(SNAPSHOT_128K_LENGTH),
+            file_type => file_type.header_length(data),
+        }
+    }
+
     /// Check if this entry is a ZX Spectrum type (for Disciple/+D)
     pub fn is_spectrum_type(&self) -> bool {
         matches!(self.file_type, MgtFileType::ZxSnapshot)
     }
 
-    /// Check if this entry is a SAM type
+    /// Check if this entry is a SAM type (including MasterDOS subdirectories, 0x15)
     pub fn is_sam_type(&self) -> bool {
         matches!(
             self.file_type,
@@ -208,6 +323,7 @@ impl MgtDirEntry {
                 | MgtFileType::StringArray
                 | MgtFileType::Code
                 | MgtFileType::Screen
+                | MgtFileType::Other(0x15)
         )
     }
 }
@@ -233,38 +349,151 @@ impl std::fmt::Display for MgtSystemType {
     }
 }
 
+/// Bit in the sector address map for a sector, if it is outside the directory
+///
+/// The map has one bit per sector, least significant bit first, from track
+/// 4 on side 0 through to track 79 on side 1.
+pub fn sector_map_bit(track: u8, sector: u8) -> Option<usize> {
+    let side_track = (track & !MGT_SIDE1_TRACK) as usize;
+    let logical = if track & MGT_SIDE1_TRACK != 0 {
+        side_track + MGT_TRACKS_PER_SIDE
+    } else {
+        side_track
+    };
+    if !(MGT_DIR_TRACKS..MGT_TRACKS_PER_SIDE * 2).contains(&logical)
+        || side_track >= MGT_TRACKS_PER_SIDE
+        || !(1..=MGT_SECTORS_PER_TRACK as u8).contains(&sector)
+    {
+        return None;
+    }
+    Some((logical - MGT_DIR_TRACKS) * MGT_SECTORS_PER_TRACK + sector as usize - 1)
+}
+
+/// Track and sector for a bit in the sector address map
+fn sector_for_bit(bit: usize) -> (u8, u8) {
+    let logical = bit / MGT_SECTORS_PER_TRACK + MGT_DIR_TRACKS;
+    let track = if logical >= MGT_TRACKS_PER_SIDE {
+        (logical - MGT_TRACKS_PER_SIDE) as u8 | MGT_SIDE1_TRACK
+    } else {
+        logical as u8
+    };
+    (track, (bit % MGT_SECTORS_PER_TRACK) as u8 + 1)
+}
+
+/// Encode a filename as 10 space padded bytes
+fn encode_name(name: &str) -> Result<[u8; 10]> {
+    if name.trim().is_empty()
+        || name.len() > 10
+        || !name.bytes().all(|b| (0x20..0x7F).contains(&b))
+    {
+        return Err(DskError::InvalidFilename(name.to_string()));
+    }
+    let mut encoded = [b' '; 10];
+    encoded[..name.len()].copy_from_slice(name.as_bytes());
+    Ok(encoded)
+}
+
 /// Base MGT filesystem implementation
 pub struct MgtFileSystem<'a> {
-    image: &'a DiskImage,
+    image: ImageRef<'a>,
     directory_entries: Vec<MgtDirEntry>,
     system_type: MgtSystemType,
+    dir_tracks: usize,
 }
 
 impl<'a> MgtFileSystem<'a> {
     /// Create a new MGT filesystem from an image
     pub fn new(image: &'a DiskImage) -> Result<Self> {
-        let directory_entries = Self::read_directory(image)?;
-        let system_type = Self::detect_system_type(&directory_entries);
+        let (directory_entries, system_type, dir_tracks) = Self::load_directory(image)?;
 
         Ok(Self {
-            image,
+            image: ImageRef::Shared(image),
             directory_entries,
             system_type,
+            dir_tracks,
         })
     }
 
-    /// Read directory entries from the disk
-    fn read_directory(image: &DiskImage) -> Result<Vec<MgtDirEntry>> {
+    /// Create a new writable MGT filesystem from an image
+    pub fn new_mut(image: &'a mut DiskImage) -> Result<Self> {
+        let (directory_entries, system_type, dir_tracks) = Self::load_directory(image)?;
+
+        Ok(Self {
+            image: ImageRef::Exclusive(image),
+            directory_entries,
+            system_type,
+            dir_tracks,
+        })
+    }
+
+    /// Format specification for an 80 track double sided MGT disk
+    pub fn format_spec() -> FormatSpec {
+        FormatSpec {
+            num_sides: 2,
+            num_tracks: MGT_TRACKS_PER_SIDE as u8,
+            sectors_per_track: MGT_SECTORS_PER_TRACK as u8,
+            sector_size: 512,
+            first_sector_id: 1,
+            gap3_length: 0x17,
+            filler_byte: 0x00,
+            interleave: 1,
+            skew: 0,
+            side_mode: SideMode::Successive,
+        }
+    }
+
+    /// Create a blank MGT disk with an empty directory
+    pub fn blank_image() -> Result<DiskImage> {
+        DiskImageBuilder::new()
+            .format(DiskImageFormat::RawMgt)
+            .spec(Self::format_spec())
+            .build()
+    }
+
+    /// Re-read the directory after it has been modified
+    fn refresh_directory(&mut self) -> Result<()> {
+        (self.directory_entries, self.system_type, self.dir_tracks) = Self::load_directory(self.image.get())?;
+        Ok(())
+    }
+
+    /// Read the directory, its system type and the number of directory tracks
+    ///
+    /// Extra directory tracks are only looked for when the standard directory
+    /// already holds SAM files, as DISCiPLE/+D disks never have them and may
+    /// leave any value in the byte MasterDOS uses to count them.
+    fn load_directory(image: &DiskImage) -> Result<(Vec<MgtDirEntry>, MgtSystemType, usize)> {
+        let entries = Self::read_directory(image, MGT_DIR_TRACKS)?;
+        let system_type = Self::detect_system_type(&entries);
+        if system_type != MgtSystemType::Sam {
+            return Ok((entries, system_type, MGT_DIR_TRACKS));
+        }
+
+        // MasterDOS keeps its extra directory track count in the first entry
+        let dir_tracks = image
+            .read_sector(0, 0, 1)
+            .ok()
+            .and_then(|first| first.get(MGT_EXTRA_DIR_TRACKS))
+            .map(|&extra| MGT_DIR_TRACKS + extra as usize)
+            .filter(|&tracks| tracks <= MGT_MAX_DIR_TRACKS)
+            .unwrap_or(MGT_DIR_TRACKS);
+        if dir_tracks == MGT_DIR_TRACKS {
+            return Ok((entries, system_type, dir_tracks));
+        }
+
+        Ok((Self::read_directory(image, dir_tracks)?, system_type, dir_tracks))
+    }
+
+    /// Read directory entries from the first tracks of side 0
+    fn read_directory(image: &DiskImage, dir_tracks: usize) -> Result<Vec<MgtDirEntry>> {
         let mut entries = Vec::new();
         let mut entry_index = 0;
 
-        // Directory is in the first 4 tracks on side 0
-        // (In MGT format, side 0 and side 1 alternate, but directory is on side 0 tracks 0-3)
+        // Directory is in the first 4 (or more) tracks on side 0
         let disk = image
             .get_disk(0)
             .ok_or_else(|| DskError::filesystem("No disk side 0"))?;
 
-        for track_num in 0..MGT_DIR_TRACKS as u8 {
+        for track_num in 0..dir_tracks as u8 {
             let track = match disk.get_track(track_num) {
                 Some(t) => t,
                 None => continue,
@@ -321,75 +550,92 @@ impl<'a> MgtFileSystem<'a> {
         &self.directory_entries
     }
 
-    /// Read file data by following the sector map
-    /// Returns file data truncated to actual file length (not allocated size)
-    pub fn read_file(&self, entry: &MgtDirEntry) -> Result<Vec<u8>> {
-        let mut data = Vec::new();
-        let sectors_to_read = entry.sectors_used as usize;
+    /// Number of directory tracks, 4 unless extended by MasterDOS
+    pub fn directory_tracks(&self) -> usize {
+        self.dir_tracks
+    }
+
+    /// Sectors in the sector address map that are not in the directory
+    fn data_sectors(&self) -> usize {
+        MGT_SECTOR_MAP_SIZE * 8 - (self.dir_tracks - MGT_DIR_TRACKS) * MGT_SECTORS_PER_TRACK
+    }
 
-        if sectors_to_read == 0 {
-            return Ok(data);
+    /// Side and physical track for an MGT track number
+    fn physical_track(track: u8) -> (u8, u8) {
+        if track & MGT_SIDE1_TRACK != 0 {
+            (1, track & !MGT_SIDE1_TRACK)
+        } else {
+            (0, track)
         }
+    }
 
-        // Start from the start track/sector and follow the allocation
-        let mut current_track = entry.start_track;
-        let mut current_sector = entry.start_sector;
-        let mut sectors_read = 0;
+    /// Read a sector by MGT track number and sector ID
+    fn read_sector(&self, track: u8, sector: u8) -> Result<&[u8]> {
+        let (side, phys_track) = Self::physical_track(track);
+        self.image.get().read_sector(side, phys_track, sector)
+    }
 
-        while sectors_read < sectors_to_read {
-            // Determine which side this track is on
-            // In MGT, logical track N maps to:
-            // - Physical track N/2 on side N%2 (for standard layout)
-            // But our image already has the tracks properly separated
-            let side = if current_track >= 128 {
-                1
-            } else {
-                0
-            };
-            let phys_track = if current_track >= 128 {
-                current_track - 128
-            } else {
-                current_track
-            };
+    /// Read a file as stored by following the chain of sector pointers
+    ///
+    /// Typed files start with their 9 byte header.
+    pub fn read_file_raw(&self, entry: &MgtDirEntry) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(entry.sectors_used as usize * MGT_SECTOR_DATA_SIZE);
+        let mut track = entry.start_track;
+        let mut sector = entry.start_sector;
+
+        for _ in 0..entry.sectors_used {
+            let sector_data = self.read_sector(track, sector)?;
+            if sector_data.len() < MGT_SECTOR_DATA_SIZE + 2 {
+                return Err(DskError::filesystem(format!(
+                    "Sector {} on track {} is too short",
+                    sector, track
+                )));
+            }
 
-            let disk = self.image.get_disk(side).ok_or_else(|| {
-                DskError::filesystem(&format!("No disk side {}", side))
-            })?;
-
-            let track = disk.get_track(phys_track).ok_or_else(|| {
-                DskError::filesystem(&format!("Track {} not found", phys_track))
-            })?;
-
-            let sector = track.get_sector(current_sector).ok_or_else(|| {
-                DskError::filesystem(&format!(
-                    "Sector {} not found on track {}",
-                    current_sector, phys_track
-                ))
-            })?;
-
-            data.extend_from_slice(sector.data());
-            sectors_read += 1;
-
-            // Move to next sector
-            current_sector += 1;
-            if current_sector > 10 {
-                current_sector = 1;
-                current_track += 1;
+            data.extend_from_slice(&sector_data[..MGT_SECTOR_DATA_SIZE]);
+            track = sector_data[MGT_SECTOR_DATA_SIZE];
+            sector = sector_data[MGT_SECTOR_DATA_SIZE + 1];
+            if track == 0 && sector == 0 {
+                break;
             }
         }
 
-        // Trim to actual file size (MGT stores metadata in directory entry, not headers in file data)
-        // For Disciple/+D, actual file size is stored at offset 212-213 in directory entry
-        if entry.raw_data.len() >= 214 {
-            let actual_file_size = u16::from_le_bytes([entry.raw_data[212], entry.raw_data[213]]) as usize;
-            if actual_file_size > 0 && actual_file_size < data.len() {
-                data.truncate(actual_file_size);
-            }
+        Ok(data)
+    }
+
+    /// Read file data by following the chain of sector pointers
+    /// Returns file data without its header, truncated to actual file length (not allocated size)
+    pub fn read_entry(&self, entry: &MgtDirEntry) -> Result<Vec<u8>> {
+        let mut data = self.read_file_raw(entry)?;
+        let length = entry.data_length(&data);
+
+        // MGT keeps a copy of the header in the directory entry, so drop it from the data
+        if entry.file_type.has_file_header() {
+            data.drain(..MGT_FILE_HEADER_SIZE.min(data.len()));
+        }
+        if let Some(length) = length {
+            data.truncate(length);
         }
 
         Ok(data)
     }
 
+    /// Read file data without its header
+    #[deprecated(note = "use `read_entry`; `read_file` now takes a file name, as on every filesystem")]
+    pub fn read_file(&self, entry: &MgtDirEntry) -> Result<Vec<u8>> {
+        self.read_entry(entry)
+    }
+
+    /// Length of a file's data, from the header in its first sector
+    ///
+    /// Files without a header give the number of bytes in their sectors.
+    pub fn file_length(&self, entry: &MgtDirEntry) -> usize {
+        self.read_sector(entry.start_track, entry.start_sector)
+            .ok()
+            .and_then(|first| entry.data_length(first))
+            .unwrap_or(entry.sectors_used as usize * MGT_SECTOR_DATA_SIZE)
+    }
+
     /// Read extended directory listing
     pub fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
         let mut entries = Vec::new();
@@ -397,7 +643,7 @@ impl<'a> MgtFileSystem<'a> {
         for dir_entry in &self.directory_entries {
             // Parse metadata from directory entry (MGT stores metadata in directory, not headers in file data)
             let header = if dir_entry.sectors_used > 0 {
-                match self.read_file(dir_entry) {
+                match self.read_entry(dir_entry) {
                     Ok(data) => self.parse_file_header(dir_entry, &data),
                     Err(_) => FileHeader::default(),
                 }
@@ -443,10 +689,172 @@ impl<'a> MgtFileSystem<'a> {
             .find(|e| e.filename.to_uppercase() == name_upper)
     }
 
+    /// Sectors not used by any file, in sector address map order
+    fn free_sectors(&self) -> Vec<(u8, u8)> {
+        let mut used = [0u8; MGT_SECTOR_MAP_SIZE];
+        for entry in &self.directory_entries {
+            for (byte, map) in used.iter_mut().zip(&entry.sector_map) {
+                *byte |= map;
+            }
+        }
+
+        // Extended directory tracks come first in the map
+        let first = (self.dir_tracks - MGT_DIR_TRACKS) * MGT_SECTORS_PER_TRACK;
+        (first..MGT_SECTOR_MAP_SIZE * 8)
+            .filter(|bit| used[bit / 8] & (1 << (bit % 8)) == 0)
+            .map(sector_for_bit)
+            .collect()
+    }
+
+    /// Side 0 location (track, sector ID, byte offset) of a directory entry
+    fn directory_location(index: usize) -> (u8, u8, usize) {
+        let sector = index / MGT_ENTRIES_PER_SECTOR;
+        (
+            (sector / MGT_SECTORS_PER_TRACK) as u8,
+            (sector % MGT_SECTORS_PER_TRACK) as u8 + 1,
+            (index % MGT_ENTRIES_PER_SECTOR) * MGT_DIR_ENTRY_SIZE,
+        )
+    }
+
+    /// Index of the first erased directory entry
+    fn free_directory_entry(&self) -> Result<usize> {
+        (0..self.dir_tracks * MGT_SECTORS_PER_TRACK * MGT_ENTRIES_PER_SECTOR)
+            .find(|&index| {
+                let (track, sector, offset) = Self::directory_location(index);
+                self.read_sector(track, sector)
+                    .map(|data| data.get(offset).is_some_and(|&status| status & 0x3F == 0))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| DskError::filesystem("Directory is full"))
+    }
+
+    /// Write a 256 byte directory entry
+    fn write_directory_entry(&mut self, index: usize, entry: &[u8]) -> Result<()> {
+        let (track, sector, offset) = Self::directory_location(index);
+        let mut data = self.read_sector(track, sector)?.to_vec();
+        // The first entry also holds the number of extra directory tracks
+        let extra_tracks = data[MGT_EXTRA_DIR_TRACKS];
+        data[offset..offset + MGT_DIR_ENTRY_SIZE].copy_from_slice(&entry[..MGT_DIR_ENTRY_SIZE]);
+        if index == 0 {
+            data[MGT_EXTRA_DIR_TRACKS] = extra_tracks;
+        }
+        self.image.get_mut()?.write_sector(0, track, sector, &data)
+    }
+
+    /// Find a file that can be modified
+    fn find_unprotected(&self, name: &str) -> Result<MgtDirEntry> {
+        let entry = self
+            .find_file(name)
+            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        if entry.protected {
+            return Err(DskError::filesystem(format!("File is protected: {}", name)));
+        }
+        Ok(entry.clone())
+    }
+
+    /// Save a file
+    ///
+    /// `body` is stored as given, so BASIC, array, CODE and SCREEN$ files must
+    /// start with their 9 byte header. `info` holds the system-specific
+    /// directory bytes from offset 210 (up to 46 bytes). Sectors are
+    /// allocated from the first free sector after the directory, linked
+    /// through their next sector pointers and marked in the sector map.
+    pub fn save_file(&mut self, name: &str, file_type: MgtFileType, body: &[u8], info: &[u8]) -> Result<()> {
+        // Fail early rather than after allocating if mounted read-only
+        self.image.get_mut()?;
+
+        let encoded = encode_name(name)?;
+        if self.find_file(name).is_some() {
+            return Err(DskError::filesystem(format!("File already exists: {}", name)));
+        }
+        if info.len() > MGT_DIR_ENTRY_SIZE - MGT_DIR_INFO_OFFSET {
+            return Err(DskError::filesystem("Directory information too long"));
+        }
+
+        let sectors_needed = body.len().div_ceil(MGT_SECTOR_DATA_SIZE).max(1);
+        let free = self.free_sectors();
+        if free.len() < sectors_needed {
+            return Err(DskError::DiskFull);
+        }
+        let index = self.free_directory_entry()?;
+        let sectors = &free[..sectors_needed];
+
+        let mut sector_map = vec![0u8; MGT_SECTOR_MAP_SIZE];
+        for (i, &(track, sector)) in sectors.iter().enumerate() {
+            let start = i * MGT_SECTOR_DATA_SIZE;
+            let chunk = &body[start.min(body.len())..(start + MGT_SECTOR_DATA_SIZE).min(body.len())];
+            let (next_track, next_sector) = sectors.get(i + 1).copied().unwrap_or((0, 0));
+
+            let mut data = chunk.to_vec();
+            data.resize(MGT_SECTOR_DATA_SIZE, 0);
+            data.extend_from_slice(&[next_track, next_sector]);
+
+            let (side, phys_track) = Self::physical_track(track);
+            self.image.get_mut()?.write_sector(side, phys_track, sector, &data)?;
+
+            if let Some(bit) = sector_map_bit(track, sector) {
+                sector_map[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+
+        let mut raw_data = vec![0u8; MGT_DIR_ENTRY_SIZE];
+        raw_data[MGT_DIR_INFO_OFFSET..MGT_DIR_INFO_OFFSET + info.len()].copy_from_slice(info);
+        let entry = MgtDirEntry {
+            index,
+            file_type,
+            hidden: false,
+            protected: false,
+            filename: String::from_utf8_lossy(&encoded).trim_end().to_string(),
+            sectors_used: sectors_needed as u16,
+            start_track: sectors[0].0,
+            start_sector: sectors[0].1,
+            sector_map,
+            raw_data,
+        };
+
+        self.write_directory_entry(index, &entry.to_bytes())?;
+        self.refresh_directory()
+    }
+
+    /// Delete a directory entry, releasing its sectors
+    pub fn delete_entry(&mut self, entry: &MgtDirEntry) -> Result<()> {
+        if entry.protected {
+            return Err(DskError::filesystem(format!("File is protected: {}", entry.filename)));
+        }
+        let mut data = entry.to_bytes();
+        data[0] = 0;
+
+        self.write_directory_entry(entry.index, &data)?;
+        self.refresh_directory()
+    }
+
+    /// Rename a file
+    pub fn rename_file(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        let entry = self.find_unprotected(old_name)?;
+        let encoded = encode_name(new_name)?;
+        if self
+            .find_file(new_name)
+            .is_some_and(|existing| existing.index != entry.index)
+        {
+            return Err(DskError::filesystem(format!("File already exists: {}", new_name)));
+        }
+
+        let mut data = entry.to_bytes();
+        data[1..11].copy_from_slice(&encoded);
+        self.write_directory_entry(entry.index, &data)?;
+        self.refresh_directory()
+    }
+
     /// Get filesystem information
+    #[deprecated(note = "use `disk_info`; `info` now gives the `FileSystem` trait's information")]
     pub fn info(&self) -> MgtFileSystemInfo {
+        self.disk_info()
+    }
+
+    /// Get disk usage information
+    pub fn disk_info(&self) -> MgtFileSystemInfo {
         let total_sectors = 80 * 10 * 2; // 80 tracks * 10 sectors * 2 sides
-        let dir_sectors = MGT_DIR_SECTORS;
+        let dir_sectors = self.dir_tracks * MGT_SECTORS_PER_TRACK;
         let data_sectors = total_sectors - dir_sectors;
 
         let used_sectors: usize = self
@@ -466,6 +874,79 @@ impl<'a> MgtFileSystem<'a> {
     }
 }
 
+impl<'a> FileSystem for MgtFileSystem<'a> {
+    fn from_image<'b>(_image: &'b DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use MgtFileSystem::new() directly",
+        ))
+    }
+
+    fn from_image_mut<'b>(_image: &'b mut DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use MgtFileSystem::new_mut() directly",
+        ))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>> {
+        Ok(self
+            .directory_entries
+            .iter()
+            .map(|e| DirEntry {
+                name: e.filename.clone(),
+                user: 0,
+                extent: 0,
+                size: self.file_length(e),
+                attributes: e.attributes(),
+            })
+            .collect())
+    }
+
+    fn read_file(&self, name: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .find_file(name)
+            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        self.read_entry(entry)
+    }
+
+    /// Save a CODE file at 32768, in SAM format on SAM disks and DISCiPLE/+D format otherwise
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let (file_type, body, info) = match self.system_type {
+            MgtSystemType::Sam => sam::code_file(sam::SAM_DEFAULT_START, None, data)?,
+            _ => disciple::code_file(disciple::DISCIPLE_DEFAULT_START, data)?,
+        };
+        self.save_file(name, file_type, &body, &info)
+    }
+
+    /// Delete a file, releasing its sectors
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        let entry = self.find_unprotected(name)?;
+        self.delete_entry(&entry)
+    }
+
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        MgtFileSystem::read_dir_extended(self)
+    }
+
+    fn info(&self) -> FileSystemInfo {
+        FileSystemInfo {
+            fs_type: self.system_type.to_string(),
+            total_blocks: self.data_sectors(),
+            free_blocks: self.free_sectors().len(),
+            block_size: 512,
+        }
+    }
+
+    fn details(&self) -> Vec<(String, String)> {
+        vec![("Files".to_string(), self.directory_entries.len().to_string())]
+    }
+}
+
 /// MGT filesystem information
 #[derive(Debug)]
 pub struct MgtFileSystemInfo {
@@ -578,6 +1059,114 @@ mod tests {
         assert!(!entry.protected);
     }
 
+    #[test]
+    fn test_sector_map_bits() {
+        assert_eq!(sector_map_bit(3, 10), None);
+        assert_eq!(sector_map_bit(4, 1), Some(0));
+        assert_eq!(sector_map_bit(4, 0), None);
+        assert_eq!(sector_map_bit(79, 10), Some(759));
+        assert_eq!(sector_map_bit(0x80, 1), Some(760));
+        assert_eq!(sector_map_bit(0x80 + 79, 10), Some(MGT_SECTOR_MAP_SIZE * 8 - 1));
+        assert_eq!(sector_map_bit(80, 1), None);
+
+        for bit in [0, 9, 10, 759, 760, 1559] {
+            let (track, sector) = sector_for_bit(bit);
+            assert_eq!(sector_map_bit(track, sector), Some(bit));
+        }
+    }
+
+    #[test]
+    fn test_save_delete_rename() {
+        let mut image = MgtFileSystem::blank_image().unwrap();
+        let data: Vec<u8> = (0..2000).map(|i| (i * 7) as u8).collect();
+        {
+            let mut fs = MgtFileSystem::new_mut(&mut image).unwrap();
+            fs.save_file("FIRST", MgtFileType::Other(8), &[1; 100], &[]).unwrap();
+            fs.save_file("second", MgtFileType::Other(8), &data, &[0xAA; 46]).unwrap();
+            assert!(fs.save_file("FIRST", MgtFileType::Other(8), &[], &[]).is_err());
+            assert!(matches!(
+                fs.save_file("much too long", MgtFileType::Other(8), &[], &[]),
+                Err(DskError::InvalidFilename(_))
+            ));
+        }
+
+        let fs = MgtFileSystem::new(&image).unwrap();
+        let entry = fs.find_file("SECOND").unwrap();
+        assert_eq!(entry.index, 1);
+        assert_eq!(entry.sectors_used, 4);
+        assert_eq!((entry.start_track, entry.start_sector), (4, 2));
+        assert!(entry.uses_sector(4, 5) && !entry.uses_sector(4, 1));
+        assert_eq!(entry.raw_data[MGT_DIR_INFO_OFFSET], 0xAA);
+        assert_eq!(&fs.read_file_raw(entry).unwrap()[..2000], &data[..]);
+        // Sectors are chained through their last two bytes
+        assert_eq!(&image.read_sector(0, 4, 2).unwrap()[510..], &[4, 3]);
+        assert_eq!(&image.read_sector(0, 4, 5).unwrap()[510..], &[0, 0]);
+
+        {
+            let mut fs = MgtFileSystem::new_mut(&mut image).unwrap();
+            fs.delete_file("first").unwrap();
+            fs.rename_file("second", "RENAMED").unwrap();
+            assert!(matches!(fs.delete_file("first"), Err(DskError::FileNotFound(_))));
+
+            // The freed sector and directory entry are reused
+            fs.save_file("THIRD", MgtFileType::Other(8), &[3; 1000], &[]).unwrap();
+            let third = fs.find_file("THIRD").unwrap();
+            assert_eq!(third.index, 0);
+            assert_eq!((third.start_track, third.start_sector), (4, 1));
+            assert_eq!(fs.read_file_raw(third).unwrap()[..1000], [3; 1000]);
+        }
+
+        let fs = MgtFileSystem::new(&image).unwrap();
+        let names: Vec<_> = fs.directory().iter().map(|e| e.filename.as_str()).collect();
+        assert_eq!(names, vec!["THIRD", "RENAMED"]);
+        assert_eq!(fs.disk_info().used_sectors, 6);
+        assert_eq!(FileSystem::info(&fs).free_blocks, 1560 - 6);
+    }
+
+    #[test]
+    fn test_extended_directory_after_sam_files() {
+        let mut image = MgtFileSystem::blank_image().unwrap();
+
+        // A SAM file in the first track and a ZX snapshot in the extra track
+        let mut first = vec![0u8; 512];
+        first[0] = 0x10;
+        first[1..11].copy_from_slice(b"PROGRAM   ");
+        first[MGT_EXTRA_DIR_TRACKS] = 1;
+        image.write_sector(0, 0, 1, &first).unwrap();
+        let mut extra = vec![0u8; 512];
+        extra[0] = 0x05;
+        extra[1..11].copy_from_slice(b"SNAPSHOT  ");
+        image.write_sector(0, 4, 1, &extra).unwrap();
+
+        let fs = MgtFileSystem::new(&image).unwrap();
+        assert_eq!(fs.directory_tracks(), 5);
+        assert_eq!(fs.system_type(), MgtSystemType::Sam);
+        let names: Vec<_> = fs.directory().iter().map(|e| e.filename.as_str()).collect();
+        assert_eq!(names, vec!["PROGRAM", "SNAPSHOT"]);
+    }
+
+    #[test]
+    fn test_disciple_ignores_extra_directory_byte() {
+        let mut image = MgtFileSystem::blank_image().unwrap();
+
+        // +D leaves whatever it likes in byte 255 of the first entry
+        let mut first = vec![0u8; 512];
+        first[0] = 0x05;
+        first[1..11].copy_from_slice(b"SNAPSHOT  ");
+        first[MGT_EXTRA_DIR_TRACKS] = 3;
+        image.write_sector(0, 0, 1, &first).unwrap();
+        let mut data = vec![0u8; 512];
+        data[0] = 0x10;
+        data[1..11].copy_from_slice(b"NOTENTRY  ");
+        image.write_sector(0, 4, 1, &data).unwrap();
+
+        let fs = MgtFileSystem::new(&image).unwrap();
+        assert_eq!(fs.directory_tracks(), MGT_DIR_TRACKS);
+        assert_eq!(fs.system_type(), MgtSystemType::Disciple);
+        let names: Vec<_> = fs.directory().iter().map(|e| e.filename.as_str()).collect();
+        assert_eq!(names, vec!["SNAPSHOT"]);
+    }
+
     #[test]
     fn test_dir_entry_flags() {
         let mut data = vec![0u8; 256];
@@ -2,21 +2,62 @@
 
 /// CP/M filesystem implementation
 pub mod cpm;
+/// Formatting of blank CP/M disks
+pub mod cpm_format;
 /// DISCiPLE/+D filesystem implementation (ZX Spectrum)
 pub mod disciple;
+/// Tatung Einstein DOS filesystem implementation
+pub mod einstein;
+/// FAT12 filesystem implementation (IBM PC, MSX)
+pub mod fat;
 /// MGT filesystem base implementation
 pub mod mgt;
+/// Opus Discovery filesystem implementation (ZX Spectrum)
+pub mod opus;
 /// SAM Coupe filesystem implementation
 pub mod sam;
+/// TR-DOS filesystem implementation (ZX Spectrum Beta Disk)
+pub mod trdos;
 
-pub use cpm::CpmFileSystem;
+pub use cpm::{CpmCheckReport, CpmFileSystem, CpmProblem, CpmProblemKind, DeletedFileReport};
+pub use cpm_format::CpmFormatter;
 pub use disciple::DiscipleFileSystem;
+pub use einstein::EinsteinFileSystem;
+pub use fat::{FatBootSector, FatDirEntry, FatFileSystem};
 pub use mgt::{MgtDirEntry, MgtFileSystem, MgtFileType, MgtSystemType};
+pub use opus::{OpusDirEntry, OpusFileHeader, OpusFileSystem, OpusFileType};
 pub use sam::SamFileSystem;
+pub use trdos::{TrDosDirEntry, TrDosDiskInfo, TrDosFileSystem, TrDosFileType};
 
-use crate::error::Result;
+use crate::error::{DskError, Result};
 use crate::image::DiskImage;
 
+/// Disk image held by a mounted filesystem, either shared (read-only) or exclusive (read-write)
+pub(crate) enum ImageRef<'a> {
+    /// Mounted read-only
+    Shared(&'a DiskImage),
+    /// Mounted read-write
+    Exclusive(&'a mut DiskImage),
+}
+
+impl ImageRef<'_> {
+    /// Get the image for reading
+    pub(crate) fn get(&self) -> &DiskImage {
+        match self {
+            ImageRef::Shared(image) => image,
+            ImageRef::Exclusive(image) => image,
+        }
+    }
+
+    /// Get the image for writing, failing if the filesystem was mounted read-only
+    pub(crate) fn get_mut(&mut self) -> Result<&mut DiskImage> {
+        match self {
+            ImageRef::Shared(_) => Err(DskError::filesystem("Filesystem is mounted read-only")),
+            ImageRef::Exclusive(image) => Ok(image),
+        }
+    }
+}
+
 /// Filesystem type for disk operations
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum FileSystemType {
@@ -27,6 +68,14 @@ pub enum FileSystemType {
     Cpm,
     /// MGT filesystem (DISCiPLE/+D, SAM Coupe)
     Mgt,
+    /// TR-DOS filesystem (ZX Spectrum Beta Disk)
+    TrDos,
+    /// Opus Discovery filesystem (ZX Spectrum)
+    Opus,
+    /// FAT12 filesystem (IBM PC, MSX)
+    Fat,
+    /// Einstein DOS filesystem (Tatung Einstein)
+    Einstein,
 }
 
 impl std::fmt::Display for FileSystemType {
@@ -35,6 +84,10 @@ impl std::fmt::Display for FileSystemType {
             FileSystemType::Auto => write!(f, "Auto"),
             FileSystemType::Cpm => write!(f, "CP/M"),
             FileSystemType::Mgt => write!(f, "MGT"),
+            FileSystemType::TrDos => write!(f, "TR-DOS"),
+            FileSystemType::Opus => write!(f, "Opus"),
+            FileSystemType::Fat => write!(f, "FAT"),
+            FileSystemType::Einstein => write!(f, "Einstein"),
         }
     }
 }
@@ -46,6 +99,10 @@ impl FileSystemType {
             "auto" => Some(FileSystemType::Auto),
             "cpm" | "cp/m" => Some(FileSystemType::Cpm),
             "mgt" | "disciple" | "sam" => Some(FileSystemType::Mgt),
+            "trdos" | "tr-dos" | "beta" => Some(FileSystemType::TrDos),
+            "opus" | "discovery" => Some(FileSystemType::Opus),
+            "fat" | "fat12" | "msdos" | "dos" => Some(FileSystemType::Fat),
+            "einstein" | "xtal" => Some(FileSystemType::Einstein),
             _ => None,
         }
     }
@@ -191,6 +248,104 @@ pub trait FileSystem {
 
     /// Get filesystem information
     fn info(&self) -> FileSystemInfo;
+
+    /// Details particular to the filesystem, such as a disk label or file
+    /// count, as name and value pairs
+    fn details(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// List directory entries with extended information including headers
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        Ok(self
+            .read_dir()?
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| ExtendedDirEntry {
+                name: entry.name,
+                user: entry.user,
+                index,
+                blocks: 0,
+                allocated: 0,
+                size: entry.size,
+                attributes: entry.attributes,
+                header: FileHeader {
+                    file_size: entry.size,
+                    ..FileHeader::default()
+                },
+            })
+            .collect())
+    }
+
+    /// List directory entries with extended information, including deleted
+    /// files on filesystems that keep them
+    fn read_dir_extended_with_deleted(&self) -> Result<Vec<ExtendedDirEntry>> {
+        self.read_dir_extended()
+    }
+
+    /// Read a BASIC program as a text listing
+    ///
+    /// Returns `None` if the file is not a BASIC program the filesystem
+    /// recognises.
+    fn read_basic(&self, name: &str) -> Result<Option<String>> {
+        decode_basic(&self.read_file(name)?)
+    }
+}
+
+/// Decode an AMSDOS or PLUS3DOS BASIC file, header included
+pub(crate) fn decode_basic(data: &[u8]) -> Result<Option<String>> {
+    match crate::amstrad_basic::decode_amstrad_basic_file(data)? {
+        Some(text) => Ok(Some(text)),
+        None => crate::sinclair_basic::decode_sinclair_basic_file(data),
+    }
+}
+
+/// Resolve `Auto` to the filesystem detected on an image
+fn resolve_filesystem(image: &DiskImage, fs_type: FileSystemType) -> FileSystemType {
+    match fs_type {
+        FileSystemType::Auto => image.default_filesystem(),
+        other => other,
+    }
+}
+
+/// Check whether an MGT disk was written by a SAM Coupe
+///
+/// This is how [`open_filesystem`] chooses between the SAM Coupe and
+/// DISCiPLE/+D filesystems. It fails if the directory cannot be read.
+pub fn is_sam_disk(image: &DiskImage) -> Result<bool> {
+    Ok(MgtFileSystem::new(image)?.system_type() == MgtSystemType::Sam)
+}
+
+/// Mount the filesystem of an image (read-only)
+///
+/// `FileSystemType::Auto` detects the filesystem from the image; MGT disks
+/// are mounted as SAM Coupe or DISCiPLE/+D depending on their directory.
+pub fn open_filesystem(image: &DiskImage, fs_type: FileSystemType) -> Result<Box<dyn FileSystem + '_>> {
+    Ok(match resolve_filesystem(image, fs_type) {
+        FileSystemType::Mgt if is_sam_disk(image)? => Box::new(SamFileSystem::new(image)?),
+        FileSystemType::Mgt => Box::new(DiscipleFileSystem::new(image)?),
+        FileSystemType::TrDos => Box::new(TrDosFileSystem::new(image)?),
+        FileSystemType::Opus => Box::new(OpusFileSystem::new(image)?),
+        FileSystemType::Fat => Box::new(FatFileSystem::new(image)?),
+        FileSystemType::Einstein => Box::new(EinsteinFileSystem::new(image)?),
+        FileSystemType::Cpm | FileSystemType::Auto => Box::new(CpmFileSystem::from_image(image)?),
+    })
+}
+
+/// Mount the filesystem of an image (read-write)
+///
+/// As [`open_filesystem`]. Opus Discovery disks are mounted read-only, so
+/// writing to them fails.
+pub fn open_filesystem_mut(image: &mut DiskImage, fs_type: FileSystemType) -> Result<Box<dyn FileSystem + '_>> {
+    Ok(match resolve_filesystem(image, fs_type) {
+        FileSystemType::Mgt if is_sam_disk(image)? => Box::new(SamFileSystem::new_mut(image)?),
+        FileSystemType::Mgt => Box::new(DiscipleFileSystem::new_mut(image)?),
+        FileSystemType::TrDos => Box::new(TrDosFileSystem::new_mut(image)?),
+        FileSystemType::Opus => Box::new(OpusFileSystem::new(image)?),
+        FileSystemType::Fat => Box::new(FatFileSystem::new_mut(image)?),
+        FileSystemType::Einstein => Box::new(EinsteinFileSystem::new_mut(image)?),
+        FileSystemType::Cpm | FileSystemType::Auto => Box::new(CpmFileSystem::from_image_mut(image)?),
+    })
 }
 
 /// Try to parse an AMSDOS header from data
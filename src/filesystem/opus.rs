@@ -0,0 +1,528 @@
+/// Opus Discovery filesystem implementation
+///
+/// ZX Spectrum filesystem used by the Opus Discovery interface. Disks have
+/// 18 sectors of 256 bytes per track, numbered from 0, and sectors are
+/// addressed by logical number counting through the tracks (alternating
+/// sides on double sided disks):
+/// - Sector 0: boot sector (JR, tracks, sectors per track, side flags)
+/// - Sectors 1 onwards: catalogue of 16 byte entries
+/// - Files stored contiguously from the end of the catalogue
+///
+/// Catalogue entries hold the first and last sector, the number of bytes
+/// used in the last sector and a 10 character name. The first entry is
+/// the disk itself: its name is the disk label and its last sector ends
+/// the catalogue. Files start with a 9 byte microdrive style header giving
+/// the type, length, start address, program length and autostart line.
+
+use crate::error::{DskError, Result};
+use crate::filesystem::{
+    DirEntry, ExtendedDirEntry, FileAttributes, FileHeader, FileSystem, FileSystemInfo, HeaderType,
+};
+use crate::format::{FormatSpec, SideMode};
+use crate::image::DiskImage;
+use crate::sinclair_basic::{decode_sinclair_basic, SinclairBasicMode};
+
+/// Sector size in bytes
+pub const OPUS_SECTOR_SIZE: usize = 256;
+
+/// Sectors per track
+pub const OPUS_SECTORS_PER_TRACK: u8 = 18;
+
+/// Catalogue entry size in bytes
+pub const OPUS_ENTRY_SIZE: usize = 16;
+
+/// Size of the header at the start of each file
+pub const OPUS_FILE_HEADER_SIZE: usize = 9;
+
+/// First byte of the boot sector (a Z80 JR instruction)
+pub const OPUS_BOOT_JR: u8 = 0x18;
+
+/// Offsets within the boot sector
+const BOOT_TRACKS: usize = 2;
+const BOOT_SECTORS: usize = 3;
+const BOOT_FLAGS: usize = 4;
+/// Boot sector flag bit for double sided disks
+const BOOT_FLAG_DOUBLE_SIDED: u8 = 0x10;
+
+/// Opus Discovery file types, from the file header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpusFileType {
+    /// BASIC program
+    Basic,
+    /// Numeric array
+    NumericArray,
+    /// String array
+    StringArray,
+    /// CODE (binary)
+    Code,
+    /// Unknown type
+    Unknown(u8),
+}
+
+impl OpusFileType {
+    /// Parse from the header type byte
+    pub fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => OpusFileType::Basic,
+            1 => OpusFileType::NumericArray,
+            2 => OpusFileType::StringArray,
+            3 => OpusFileType::Code,
+            other => OpusFileType::Unknown(other),
+        }
+    }
+}
+
+impl std::fmt::Display for OpusFileType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpusFileType::Basic => write!(f, "BASIC"),
+            OpusFileType::NumericArray => write!(f, "Number Array"),
+            OpusFileType::StringArray => write!(f, "String Array"),
+            OpusFileType::Code => write!(f, "CODE"),
+            OpusFileType::Unknown(code) => write!(f, "Type {}", code),
+        }
+    }
+}
+
+/// Header at the start of an Opus Discovery file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusFileHeader {
+    /// File type
+    pub file_type: OpusFileType,
+    /// Data length in bytes, excluding the header
+    pub length: u16,
+    /// Start address (CODE) or program start (BASIC)
+    pub start: u16,
+    /// Program length without variables (BASIC)
+    pub program_length: u16,
+    /// Autostart line (BASIC, 0x8000 or above for none)
+    pub autostart: u16,
+}
+
+impl OpusFileHeader {
+    /// Parse a file header
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < OPUS_FILE_HEADER_SIZE {
+            return None;
+        }
+        Some(Self {
+            file_type: OpusFileType::from_byte(data[0]),
+            length: u16::from_le_bytes([data[1], data[2]]),
+            start: u16::from_le_bytes([data[3], data[4]]),
+            program_length: u16::from_le_bytes([data[5], data[6]]),
+            autostart: u16::from_le_bytes([data[7], data[8]]),
+        })
+    }
+
+    /// Load/exec parameters as shown in a catalogue, e.g. "CODE 32768,6912"
+    pub fn describe(&self) -> String {
+        match self.file_type {
+            OpusFileType::Code => format!("{} {},{}", self.file_type, self.start, self.length),
+            OpusFileType::Basic if self.autostart < 10000 => {
+                format!("{} LINE {}", self.file_type, self.autostart)
+            }
+            other => format!("{}", other),
+        }
+    }
+}
+
+/// Opus Discovery catalogue entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusDirEntry {
+    /// Catalogue entry index
+    pub index: usize,
+    /// Filename (up to 10 characters, trailing spaces removed)
+    pub filename: String,
+    /// First logical sector
+    pub first_sector: u16,
+    /// Last logical sector
+    pub last_sector: u16,
+    /// Bytes used in the last sector
+    pub last_sector_bytes: u16,
+}
+
+impl OpusDirEntry {
+    /// Parse a catalogue entry, returning `None` for an unused entry
+    pub fn parse(data: &[u8], index: usize) -> Option<Self> {
+        if data.len() < OPUS_ENTRY_SIZE {
+            return None;
+        }
+
+        let first_sector = u16::from_le_bytes([data[0], data[1]]);
+        let last_sector = u16::from_le_bytes([data[2], data[3]]);
+        if (first_sector == 0 && last_sector == 0) || data[6] == 0 {
+            return None;
+        }
+
+        Some(Self {
+            index,
+            filename: String::from_utf8_lossy(&data[6..16]).trim_end().to_string(),
+            first_sector,
+            last_sector,
+            last_sector_bytes: u16::from_le_bytes([data[4], data[5]]),
+        })
+    }
+
+    /// Number of sectors used
+    pub fn sector_count(&self) -> usize {
+        (self.last_sector.saturating_sub(self.first_sector)) as usize + 1
+    }
+
+    /// Size of the file on disk in bytes, including its header
+    pub fn stored_size(&self) -> usize {
+        (self.sector_count() - 1) * OPUS_SECTOR_SIZE + self.last_sector_bytes.min(OPUS_SECTOR_SIZE as u16) as usize
+    }
+}
+
+/// Opus Discovery filesystem
+pub struct OpusFileSystem<'a> {
+    image: &'a DiskImage,
+    num_tracks: u8,
+    num_sides: u8,
+    label: String,
+    catalogue_end: u16,
+    entries: Vec<OpusDirEntry>,
+}
+
+impl<'a> OpusFileSystem<'a> {
+    /// Mount an Opus Discovery filesystem
+    pub fn new(image: &'a DiskImage) -> Result<Self> {
+        let (num_tracks, num_sides) = image
+            .read_sector(0, 0, 0)
+            .ok()
+            .filter(|boot| boot.len() == OPUS_SECTOR_SIZE)
+            .and_then(Self::boot_geometry)
+            .ok_or_else(|| DskError::filesystem("Not an Opus Discovery disk"))?;
+
+        let mut fs = Self {
+            image,
+            num_tracks,
+            num_sides,
+            label: String::new(),
+            catalogue_end: 1,
+            entries: Vec::new(),
+        };
+        fs.read_catalogue()?;
+        Ok(fs)
+    }
+
+    /// Format specification for an Opus Discovery disk
+    pub fn format_spec(num_tracks: u8, num_sides: u8) -> FormatSpec {
+        FormatSpec {
+            num_sides,
+            num_tracks,
+            sectors_per_track: OPUS_SECTORS_PER_TRACK,
+            sector_size: OPUS_SECTOR_SIZE as u16,
+            first_sector_id: 0,
+            gap3_length: 0x0C,
+            filler_byte: 0xE5,
+            interleave: 1,
+            skew: 0,
+            side_mode: if num_sides > 1 { SideMode::Alternate } else { SideMode::SingleSide },
+        }
+    }
+
+    /// Disk geometry (tracks, sides) from a boot sector
+    pub fn boot_geometry(boot: &[u8]) -> Option<(u8, u8)> {
+        if boot.len() <= BOOT_FLAGS || boot[0] != OPUS_BOOT_JR || boot[BOOT_SECTORS] != OPUS_SECTORS_PER_TRACK || boot[BOOT_TRACKS] == 0 {
+            return None;
+        }
+        let sides = if boot[BOOT_FLAGS] & BOOT_FLAG_DOUBLE_SIDED != 0 { 2 } else { 1 };
+        Some((boot[BOOT_TRACKS], sides))
+    }
+
+    /// Read the catalogue, starting with the disk entry in sector 1
+    fn read_catalogue(&mut self) -> Result<()> {
+        let disk_entry = self.read_logical(1)?;
+        let disk_entry = OpusDirEntry::parse(disk_entry, 0)
+            .filter(|e| e.first_sector == 0 && e.last_sector >= 1)
+            .ok_or_else(|| DskError::filesystem("Opus Discovery catalogue has no disk entry"))?;
+        if disk_entry.last_sector as usize >= self.total_sectors() {
+            return Err(DskError::filesystem("Opus Discovery catalogue runs past the end of the disk"));
+        }
+
+        self.label = disk_entry.filename;
+        self.catalogue_end = disk_entry.last_sector;
+
+        let mut index = 0;
+        for sector in 1..=self.catalogue_end {
+            let data = self.read_logical(sector)?;
+            for raw in data.chunks_exact(OPUS_ENTRY_SIZE) {
+                // Skip the disk entry and anything pointing outside the file area
+                if let Some(entry) = OpusDirEntry::parse(raw, index).filter(|e| {
+                    index > 0
+                        && e.first_sector > self.catalogue_end
+                        && e.first_sector <= e.last_sector
+                        && (e.last_sector as usize) < self.total_sectors()
+                }) {
+                    self.entries.push(entry);
+                }
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Total number of sectors on the disk
+    fn total_sectors(&self) -> usize {
+        self.num_tracks as usize * self.num_sides as usize * OPUS_SECTORS_PER_TRACK as usize
+    }
+
+    /// Read a sector by logical number
+    fn read_logical(&self, logical: u16) -> Result<&'a [u8]> {
+        let track = logical as usize / OPUS_SECTORS_PER_TRACK as usize;
+        let id = (logical as usize % OPUS_SECTORS_PER_TRACK as usize) as u8;
+        let sides = self.num_sides as usize;
+        self.image.read_sector((track % sides) as u8, (track / sides) as u8, id)
+    }
+
+    /// Get the disk label
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Get all catalogue entries
+    pub fn directory(&self) -> &[OpusDirEntry] {
+        &self.entries
+    }
+
+    /// Find a file by name (case-insensitive)
+    pub fn find_file(&self, name: &str) -> Option<&OpusDirEntry> {
+        self.entries.iter().find(|e| e.filename.eq_ignore_ascii_case(name))
+    }
+
+    /// Read a file as stored, including its 9 byte header
+    pub fn read_file_raw(&self, entry: &OpusDirEntry) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(entry.sector_count() * OPUS_SECTOR_SIZE);
+        for sector in entry.first_sector..=entry.last_sector {
+            data.extend_from_slice(self.read_logical(sector)?);
+        }
+        data.truncate(entry.stored_size());
+        Ok(data)
+    }
+
+    /// Read the header of a file
+    pub fn file_header(&self, entry: &OpusDirEntry) -> Result<Option<OpusFileHeader>> {
+        Ok(OpusFileHeader::parse(self.read_logical(entry.first_sector)?))
+    }
+
+    /// Decode a BASIC file as text, returning `None` for other file types
+    pub fn read_basic(&self, name: &str) -> Result<Option<String>> {
+        let entry = self
+            .find_file(name)
+            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        let header = match self.file_header(entry)? {
+            Some(header) if header.file_type == OpusFileType::Basic => header,
+            _ => return Ok(None),
+        };
+
+        let mut data = self.read_file(name)?;
+        data.truncate(header.program_length as usize);
+        decode_sinclair_basic(&data, SinclairBasicMode::Mode128K).map(Some)
+    }
+
+    /// Read directory with Opus-specific information
+    pub fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        let mut entries = Vec::new();
+
+        for dir_entry in &self.entries {
+            let opus_header = self.file_header(dir_entry)?;
+            let file_size = opus_header
+                .as_ref()
+                .map_or(dir_entry.stored_size(), |h| h.length as usize);
+
+            entries.push(ExtendedDirEntry {
+                name: dir_entry.filename.clone(),
+                user: 0,
+                index: dir_entry.index,
+                blocks: dir_entry.sector_count(),
+                allocated: dir_entry.sector_count() * OPUS_SECTOR_SIZE,
+                size: file_size,
+                attributes: FileAttributes::default(),
+                header: FileHeader {
+                    header_type: HeaderType::None, // Opus headers carry no checksum or signature
+                    checksum_valid: true,
+                    file_size,
+                    header_size: OPUS_FILE_HEADER_SIZE,
+                    meta: opus_header.map_or_else(String::new, |h| h.describe()),
+                },
+            });
+        }
+
+        Ok(entries)
+    }
+}
+
+impl<'a> FileSystem for OpusFileSystem<'a> {
+    fn from_image<'b>(_image: &'b DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use OpusFileSystem::new() directly",
+        ))
+    }
+
+    fn from_image_mut<'b>(_image: &'b mut DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem("Opus Discovery filesystem is read-only"))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>> {
+        Ok(self
+            .entries
+            .iter()
+            .map(|e| DirEntry {
+                name: e.filename.clone(),
+                user: 0,
+                extent: 0,
+                size: e.stored_size().saturating_sub(OPUS_FILE_HEADER_SIZE),
+                attributes: FileAttributes::default(),
+            })
+            .collect())
+    }
+
+    /// Read a file's data without its header, truncated to the header length
+    fn read_file(&self, name: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .find_file(name)
+            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        let mut data = self.read_file_raw(entry)?;
+        let length = OpusFileHeader::parse(&data).map(|h| h.length as usize);
+
+        data.drain(..OPUS_FILE_HEADER_SIZE.min(data.len()));
+        if let Some(length) = length {
+            data.truncate(length);
+        }
+        Ok(data)
+    }
+
+    fn write_file(&mut self, _name: &str, _data: &[u8]) -> Result<()> {
+        Err(DskError::filesystem("Opus Discovery filesystem is read-only"))
+    }
+
+    fn delete_file(&mut self, _name: &str) -> Result<()> {
+        Err(DskError::filesystem("Opus Discovery filesystem is read-only"))
+    }
+
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        OpusFileSystem::read_dir_extended(self)
+    }
+
+    fn read_basic(&self, name: &str) -> Result<Option<String>> {
+        OpusFileSystem::read_basic(self, name)
+    }
+
+    fn info(&self) -> FileSystemInfo {
+        let used = self
+            .entries
+            .iter()
+            .map(|e| e.last_sector + 1)
+            .max()
+            .unwrap_or(self.catalogue_end + 1) as usize;
+        let total = self.total_sectors();
+        FileSystemInfo {
+            fs_type: "Opus Discovery".to_string(),
+            total_blocks: total - (self.catalogue_end as usize + 1),
+            free_blocks: total.saturating_sub(used),
+            block_size: OPUS_SECTOR_SIZE,
+        }
+    }
+
+    fn details(&self) -> Vec<(String, String)> {
+        if self.label.is_empty() {
+            Vec::new()
+        } else {
+            vec![("Label".to_string(), self.label.clone())]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::DiskImageFormat;
+    use crate::image::DiskImageBuilder;
+
+    /// Build a 40 track single sided disk holding one CODE and one BASIC file
+    fn sample_disk() -> DiskImage {
+        let mut image = DiskImageBuilder::new()
+            .format(DiskImageFormat::Opd)
+            .spec(OpusFileSystem::format_spec(40, 1))
+            .build()
+            .unwrap();
+
+        let mut boot = vec![0u8; OPUS_SECTOR_SIZE];
+        boot[..5].copy_from_slice(&[OPUS_BOOT_JR, 0x20, 40, 18, 0]);
+        image.write_sector(0, 0, 0, &boot).unwrap();
+
+        let mut catalogue = vec![0u8; OPUS_SECTOR_SIZE];
+        let entries: [(u16, u16, u16, &[u8; 10]); 3] = [
+            (0, 6, 256, b"MY DISK   "),
+            (7, 9, 9 + 600 - 512, b"loader    "),
+            (10, 10, 9 + 6, b"run       "),
+        ];
+        for (i, (first, last, bytes, name)) in entries.iter().enumerate() {
+            let raw = &mut catalogue[i * OPUS_ENTRY_SIZE..(i + 1) * OPUS_ENTRY_SIZE];
+            raw[0..2].copy_from_slice(&first.to_le_bytes());
+            raw[2..4].copy_from_slice(&last.to_le_bytes());
+            raw[4..6].copy_from_slice(&bytes.to_le_bytes());
+            raw[6..16].copy_from_slice(*name);
+        }
+        image.write_sector(0, 0, 1, &catalogue).unwrap();
+
+        let mut code = vec![3, 0x58, 0x02, 0x00, 0x80, 0, 0, 0, 0];
+        code.extend((0..600).map(|i| i as u8));
+        code.resize(3 * OPUS_SECTOR_SIZE, 0);
+        for (i, chunk) in code.chunks(OPUS_SECTOR_SIZE).enumerate() {
+            image.write_sector(0, 0, 7 + i as u8, chunk).unwrap();
+        }
+
+        let mut basic = vec![0, 6, 0, 0x5D, 0x5C, 6, 0, 10, 0];
+        basic.extend_from_slice(&[0x00, 0x0A, 0x02, 0x00, 0xF5, 0x0D]);
+        basic.resize(OPUS_SECTOR_SIZE, 0);
+        image.write_sector(0, 0, 10, &basic).unwrap();
+        image
+    }
+
+    #[test]
+    fn test_catalogue() {
+        let image = sample_disk();
+        let fs = OpusFileSystem::new(&image).unwrap();
+        assert_eq!(fs.label(), "MY DISK");
+
+        let names: Vec<_> = fs.read_dir().unwrap().into_iter().map(|e| (e.name, e.size)).collect();
+        assert_eq!(names, vec![("loader".to_string(), 600), ("run".to_string(), 6)]);
+
+        let info = fs.info();
+        assert_eq!(info.total_blocks, 720 - 7);
+        assert_eq!(info.free_blocks, 720 - 11);
+    }
+
+    #[test]
+    fn test_read_file_and_meta() {
+        let image = sample_disk();
+        let fs = OpusFileSystem::new(&image).unwrap();
+
+        let data = fs.read_file("LOADER").unwrap();
+        assert_eq!(data, (0..600).map(|i| i as u8).collect::<Vec<_>>());
+        assert_eq!(fs.read_file_raw(fs.find_file("run").unwrap()).unwrap().len(), 15);
+        assert!(matches!(fs.read_file("missing"), Err(DskError::FileNotFound(_))));
+
+        let extended = fs.read_dir_extended().unwrap();
+        assert_eq!(extended[0].header.meta, "CODE 32768,600");
+        assert_eq!(extended[0].blocks, 3);
+        assert_eq!(extended[1].header.meta, "BASIC LINE 10");
+
+        assert_eq!(fs.read_basic("run").unwrap().unwrap().trim(), "10 PRINT");
+        assert_eq!(fs.read_basic("loader").unwrap(), None);
+    }
+
+    #[test]
+    fn test_not_opus() {
+        let image = DiskImageBuilder::new().build().unwrap();
+        assert!(OpusFileSystem::new(&image).is_err());
+    }
+}
@@ -4,10 +4,22 @@
 ///
 /// Directory entry extensions at offset 0xDC (220):
 /// - 33 bytes for SAM-specific metadata
+/// - Offsets 236-244 give the start page, page offset, length in pages,
+///   length mod 16384 and execution page and offset of CODE files
+///
+/// Addresses are stored as a page and an offset in section C (0x8000-0xBFFF),
+/// page 0 starting at address 16384. Files start with a 9 byte header
+/// holding the type, length mod 16384, page offset, pages and start page.
+///
+/// MasterDOS adds subdirectories, entries of type 0x15. Each one has a tag
+/// at offset 250, and files hold the tag of the directory they are in at
+/// offset 254 (0 for the root). Files are named by path, e.g. GAMES/PACMAN.
 
-use crate::error::Result;
-use crate::filesystem::mgt::{MgtDirEntry, MgtFileSystem, MgtFileType};
-use crate::filesystem::{ExtendedDirEntry, FileHeader, HeaderType};
+use crate::error::{DskError, Result};
+use crate::filesystem::mgt::{
+    MgtDirEntry, MgtFileSystem, MgtFileType, MgtSystemType, MGT_DIR_INFO_OFFSET, MGT_FILE_HEADER_SIZE,
+};
+use crate::filesystem::{DirEntry, ExtendedDirEntry, FileHeader, FileSystem, FileSystemInfo, HeaderType};
 use crate::image::DiskImage;
 
 /// SAM Coupe file types
@@ -74,6 +86,92 @@ pub struct SamHeader {
     pub auto_line: u16,
 }
 
+/// Offset of the screen mode of a SCREEN$ file in a directory entry
+const SAM_SCREEN_MODE: usize = 220;
+
+/// Offset of the start page in a directory entry
+const SAM_START_PAGE: usize = 236;
+
+/// Directory entry type of a MasterDOS subdirectory
+const SAM_TYPE_DIRECTORY: u8 = 0x15;
+
+/// Offset of the tag a MasterDOS subdirectory gives to the files in it
+const MASTERDOS_OWN_TAG: usize = 250;
+
+/// Offset of the tag of the MasterDOS directory holding a file
+const MASTERDOS_DIR_TAG: usize = 254;
+
+/// Directory tag of files in the root directory
+const MASTERDOS_ROOT_TAG: u8 = 0;
+
+/// Deepest nesting of subdirectories followed when listing
+const MASTERDOS_MAX_DEPTH: usize = 32;
+
+/// Page value meaning no execution address
+const SAM_NO_EXEC: u8 = 0xFF;
+
+/// Size of a memory page
+const SAM_PAGE_SIZE: u32 = 16384;
+
+/// Highest address in the 512K of SAM memory
+const SAM_MAX_ADDRESS: u32 = 33 * SAM_PAGE_SIZE;
+
+/// Split an address into a page and an offset in section C
+fn page_offset(address: u32) -> (u8, u16) {
+    (
+        (address / SAM_PAGE_SIZE).saturating_sub(1) as u8 & 0x1F,
+        (address % SAM_PAGE_SIZE) as u16 | 0x8000,
+    )
+}
+
+/// Combine a page and an offset in section C into an address
+fn page_address(page: u8, offset: u16) -> u32 {
+    ((page & 0x1F) as u32 + 1) * SAM_PAGE_SIZE + (offset & 0x3FFF) as u32
+}
+
+/// Load address for CODE files saved without one
+pub(crate) const SAM_DEFAULT_START: u32 = 32768;
+
+/// Build a CODE file loaded at `start`, optionally run from `execute`
+///
+/// Returns the directory file type, the file body starting with its header
+/// and the directory bytes from offset 210.
+pub(crate) fn code_file(start: u32, execute: Option<u32>, data: &[u8]) -> Result<(MgtFileType, Vec<u8>, Vec<u8>)> {
+    if start < SAM_PAGE_SIZE || start as usize + data.len() > SAM_MAX_ADDRESS as usize {
+        return Err(DskError::filesystem(format!(
+            "CODE {},{} is outside SAM memory",
+            start,
+            data.len()
+        )));
+    }
+
+    let (page, offset) = page_offset(start);
+    let pages = (data.len() / SAM_PAGE_SIZE as usize) as u8;
+    let length_mod = (data.len() % SAM_PAGE_SIZE as usize) as u16;
+
+    let mut header = [0u8; MGT_FILE_HEADER_SIZE];
+    header[0] = MgtFileType::Code.type_code();
+    header[1..3].copy_from_slice(&length_mod.to_le_bytes());
+    header[3..5].copy_from_slice(&offset.to_le_bytes());
+    header[5..7].copy_from_slice(&[0xFF, 0xFF]);
+    header[7] = pages;
+    header[8] = page;
+
+    let mut info = [0u8; 35];
+    let at = |offset: usize| offset - MGT_DIR_INFO_OFFSET;
+    info[at(SAM_START_PAGE)] = page;
+    info[at(SAM_START_PAGE + 1)..at(SAM_START_PAGE + 3)].copy_from_slice(&offset.to_le_bytes());
+    info[at(SAM_START_PAGE + 3)] = pages;
+    info[at(SAM_START_PAGE + 4)..at(SAM_START_PAGE + 6)].copy_from_slice(&length_mod.to_le_bytes());
+    let (exec_page, exec_offset) = execute.map_or((SAM_NO_EXEC, 0), page_offset);
+    info[at(SAM_START_PAGE + 6)] = exec_page;
+    info[at(SAM_START_PAGE + 7)..at(SAM_START_PAGE + 9)].copy_from_slice(&exec_offset.to_le_bytes());
+
+    let mut body = header.to_vec();
+    body.extend_from_slice(data);
+    Ok((MgtFileType::Code, body, info.to_vec()))
+}
+
 /// SAM Coupe filesystem
 pub struct SamFileSystem<'a> {
     mgt: MgtFileSystem<'a>,
@@ -86,20 +184,131 @@ impl<'a> SamFileSystem<'a> {
         Ok(Self { mgt })
     }
 
+    /// Create a new writable SAM filesystem from an image
+    pub fn new_mut(image: &'a mut DiskImage) -> Result<Self> {
+        let mgt = MgtFileSystem::new_mut(image)?;
+        Ok(Self { mgt })
+    }
+
     /// Get the underlying MGT filesystem
     pub fn mgt(&self) -> &MgtFileSystem<'a> {
         &self.mgt
     }
 
+    /// Get the underlying MGT filesystem for writing
+    pub fn mgt_mut(&mut self) -> &mut MgtFileSystem<'a> {
+        &mut self.mgt
+    }
+
+    /// List all files
+    #[deprecated(note = "use `FileSystem::read_dir`, or `mgt().directory()` for the raw entries")]
+    pub fn list_files(&self) -> Vec<&MgtDirEntry> {
+        self.mgt.directory().iter().collect()
+    }
+
+    /// Get filesystem info
+    #[deprecated(note = "use `FileSystem::info`, or `mgt().disk_info()` for the MGT details")]
+    pub fn info(&self) -> String {
+        let mgt_info = self.mgt.disk_info();
+        format!(
+            "SAM Coupe Filesystem\n  Files: {}\n  Used: {} KB\n  Free: {} KB",
+            mgt_info.file_count,
+            mgt_info.used_sectors / 2,
+            mgt_info.free_sectors / 2
+        )
+    }
+
+    /// Save a CODE file, optionally with an execution address
+    pub fn save_code(&mut self, name: &str, start: u32, execute: Option<u32>, data: &[u8]) -> Result<()> {
+        let (file_type, body, info) = code_file(start, execute, data)?;
+        self.mgt.save_file(name, file_type, &body, &info)
+    }
+
+    /// Rename a file
+    pub fn rename_file(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        self.mgt.rename_file(old_name, new_name)
+    }
+
+    /// Screen mode (1-4) of a SCREEN$ file, or `None` for other files
+    pub fn screen_mode(&self, path: &str) -> Result<Option<u8>> {
+        let entry = self.find_path(path)?;
+        let mode = entry.raw_data.get(SAM_SCREEN_MODE).copied();
+        Ok(mode.filter(|mode| entry.file_type == MgtFileType::Screen && (1..=4).contains(mode)))
+    }
+
+    /// Check whether an entry is a MasterDOS subdirectory
+    pub fn is_directory(entry: &MgtDirEntry) -> bool {
+        entry.file_type == MgtFileType::Other(SAM_TYPE_DIRECTORY)
+    }
+
+    /// Subdirectory holding a file, or `None` for the root
+    ///
+    /// Tags that match no subdirectory are taken as the root, as SAMDOS
+    /// leaves the tag byte undefined.
+    fn parent(&self, entry: &MgtDirEntry) -> Option<&MgtDirEntry> {
+        let tag = entry.raw_data[MASTERDOS_DIR_TAG];
+        if tag == MASTERDOS_ROOT_TAG {
+            return None;
+        }
+        self.mgt.directory().iter().find(|dir| {
+            Self::is_directory(dir) && dir.index != entry.index && dir.raw_data[MASTERDOS_OWN_TAG] == tag
+        })
+    }
+
+    /// List every file and directory with its full path, depth first
+    pub fn walk(&self) -> Result<Vec<(String, &MgtDirEntry)>> {
+        let mut out = Vec::new();
+        self.walk_into(None, "", &mut out, 0)?;
+        Ok(out)
+    }
+
+    fn walk_into<'s>(
+        &'s self,
+        dir: Option<&MgtDirEntry>,
+        prefix: &str,
+        out: &mut Vec<(String, &'s MgtDirEntry)>,
+        depth: usize,
+    ) -> Result<()> {
+        // Guard against directory loops on damaged disks
+        if depth > MASTERDOS_MAX_DEPTH {
+            return Err(DskError::filesystem("Directories nested too deeply"));
+        }
+        let dir_index = dir.map(|d| d.index);
+        for entry in self.mgt.directory() {
+            if self.parent(entry).map(|p| p.index) != dir_index {
+                continue;
+            }
+            let path = format!("{}{}", prefix, entry.filename);
+            out.push((path.clone(), entry));
+            if Self::is_directory(entry) {
+                self.walk_into(Some(entry), &format!("{}/", path), out, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find a file or directory by path, e.g. GAMES/PACMAN
+    pub fn find_path(&self, path: &str) -> Result<&MgtDirEntry> {
+        let path = path.trim_matches('/');
+        self.walk()?
+            .into_iter()
+            .find(|(p, _)| p.eq_ignore_ascii_case(path))
+            .map(|(_, entry)| entry)
+            .ok_or_else(|| DskError::FileNotFound(path.to_string()))
+    }
+
     /// Read directory with SAM-specific information
+    ///
+    /// Files in MasterDOS subdirectories are named by path, and
+    /// subdirectories end with a '/'.
     pub fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
         let mut entries = Vec::new();
 
-        for dir_entry in self.mgt.directory() {
+        for (path, dir_entry) in self.walk()? {
             let header = self.parse_sam_header(dir_entry);
 
             entries.push(ExtendedDirEntry {
-                name: dir_entry.filename.clone(),
+                name: if Self::is_directory(dir_entry) { format!("{}/", path) } else { path },
                 user: 0,
                 index: dir_entry.index,
                 blocks: dir_entry.sectors_used as usize,
@@ -124,32 +333,24 @@ impl<'a> SamFileSystem<'a> {
 
             match file_type {
                 SamFileType::Code => {
-                    // Start page at offset 220
-                    let start_page = raw[sam_offset];
-                    // Start offset at 221-222 (little endian)
-                    let start_offset = u16::from_le_bytes([raw[sam_offset + 1], raw[sam_offset + 2]]);
-                    // Length modulo 16384 at 223-224
-                    let length_mod = u16::from_le_bytes([raw[sam_offset + 3], raw[sam_offset + 4]]);
-                    // Length pages at 225
-                    let length_pages = raw[sam_offset + 5];
-                    // Execute page at 226
-                    let exec_page = raw[sam_offset + 6];
-                    // Execute offset at 227-228
-                    let exec_offset = u16::from_le_bytes([raw[sam_offset + 7], raw[sam_offset + 8]]);
-
-                    let start_addr = (start_page as u32) * 16384 + start_offset as u32;
-                    let length = (length_pages as u32) * 16384 + length_mod as u32;
-                    let exec_addr = (exec_page as u32) * 16384 + exec_offset as u32;
-
-                    if exec_addr != start_addr {
-                        format!("{} {},{}  EXEC {}", file_type, start_addr, length, exec_addr)
-                    } else {
-                        format!("{} {},{}", file_type, start_addr, length)
+                    let raw = &raw[SAM_START_PAGE..];
+                    // Start page at 236 and offset at 237-238 (little endian)
+                    let start_addr = page_address(raw[0], u16::from_le_bytes([raw[1], raw[2]]));
+                    // Length pages at 239 and length modulo 16384 at 240-241
+                    let length = (raw[3] as u32) * SAM_PAGE_SIZE + u16::from_le_bytes([raw[4], raw[5]]) as u32;
+                    // Execute page at 242 (0xFF for none) and offset at 243-244
+                    let exec_addr = (raw[6] != SAM_NO_EXEC)
+                        .then(|| page_address(raw[6], u16::from_le_bytes([raw[7], raw[8]])));
+
+                    match exec_addr {
+                        Some(exec_addr) if exec_addr != start_addr => {
+                            format!("{} {},{}  EXEC {}", file_type, start_addr, length, exec_addr)
+                        }
+                        _ => format!("{} {},{}", file_type, start_addr, length),
                     }
                 }
                 SamFileType::Screen => {
-                    // Screen mode at offset 220
-                    let mode = raw[sam_offset];
+                    let mode = raw[SAM_SCREEN_MODE];
                     let mode_name = match mode {
                         1 => "Mode 1",
                         2 => "Mode 2",
@@ -199,37 +400,85 @@ impl<'a> SamFileSystem<'a> {
             meta,
         }
     }
+}
+
+impl<'a> FileSystem for SamFileSystem<'a> {
+    fn from_image<'b>(_image: &'b DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem("Use SamFileSystem::new() directly"))
+    }
 
-    /// Read a file by name
+    fn from_image_mut<'b>(_image: &'b mut DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem("Use SamFileSystem::new_mut() directly"))
+    }
+
+    /// List files, named by path
+    fn read_dir(&self) -> Result<Vec<DirEntry>> {
+        Ok(self
+            .walk()?
+            .into_iter()
+            .map(|(path, entry)| DirEntry {
+                name: path,
+                user: 0,
+                extent: 0,
+                size: self.mgt.file_length(entry),
+                attributes: entry.attributes(),
+            })
+            .collect())
+    }
+
+    /// Read a file by path
     /// Returns file data truncated to actual file length (not allocated size)
-    pub fn read_file(&self, name: &str) -> Result<Vec<u8>> {
-        let entry = self
-            .mgt
-            .find_file(name)
-            .ok_or_else(|| crate::error::DskError::FileNotFound(name.to_string()))?;
-        self.mgt.read_file(entry)
+    fn read_file(&self, name: &str) -> Result<Vec<u8>> {
+        self.mgt.read_entry(self.find_path(name)?)
     }
 
-    /// List all files
-    pub fn list_files(&self) -> Vec<&MgtDirEntry> {
-        self.mgt.directory().iter().collect()
+    /// Save a CODE file at 32768
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.save_code(name, SAM_DEFAULT_START, None, data)
     }
 
-    /// Get filesystem info
-    pub fn info(&self) -> String {
-        let mgt_info = self.mgt.info();
-        format!(
-            "SAM Coupe Filesystem\n  Files: {}\n  Used: {} KB\n  Free: {} KB",
-            mgt_info.file_count,
-            mgt_info.used_sectors / 2,
-            mgt_info.free_sectors / 2
-        )
+    /// Delete a file by path, or an empty subdirectory
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        let entry = self.find_path(name)?.clone();
+        if Self::is_directory(&entry)
+            && self
+                .mgt
+                .directory()
+                .iter()
+                .any(|e| self.parent(e).is_some_and(|p| p.index == entry.index))
+        {
+            return Err(DskError::filesystem(format!("Directory is not empty: {}", name)));
+        }
+        self.mgt.delete_entry(&entry)
+    }
+
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        SamFileSystem::read_dir_extended(self)
+    }
+
+    fn info(&self) -> FileSystemInfo {
+        FileSystemInfo {
+            fs_type: MgtSystemType::Sam.to_string(),
+            ..FileSystem::info(&self.mgt)
+        }
+    }
+
+    fn details(&self) -> Vec<(String, String)> {
+        self.mgt.details()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filesystem::mgt::MGT_DIR_ENTRY_SIZE;
+    use crate::filesystem::FileSystemType;
 
     #[test]
     fn test_sam_file_type_display() {
@@ -254,4 +503,135 @@ mod tests {
             SamFileType::Screen
         );
     }
+
+    #[test]
+    fn test_page_offset() {
+        assert_eq!(page_offset(32768), (1, 0x8000));
+        assert_eq!(page_offset(81920 + 5), (4, 0x8005));
+        assert_eq!(page_address(4, 0x8005), 81925);
+    }
+
+    #[test]
+    fn test_save_code() {
+        let mut image = MgtFileSystem::blank_image().unwrap();
+        let data: Vec<u8> = (0..20000).map(|i| (i % 251) as u8).collect();
+        {
+            let mut fs = SamFileSystem::new_mut(&mut image).unwrap();
+            fs.save_code("game", 32768, Some(32800), &data).unwrap();
+            fs.save_code("tables", 65536, None, &[9; 100]).unwrap();
+            assert!(fs.save_code("rom", 0, None, &[0; 10]).is_err());
+        }
+
+        let fs = SamFileSystem::new(&image).unwrap();
+        assert_eq!(fs.mgt().system_type(), crate::filesystem::MgtSystemType::Sam);
+        assert_eq!(fs.read_file("GAME").unwrap(), data);
+        assert_eq!(fs.read_file("tables").unwrap(), vec![9; 100]);
+
+        let entries = fs.read_dir_extended().unwrap();
+        assert_eq!(entries[0].header.meta, "CODE 32768,20000  EXEC 32800");
+        assert_eq!(entries[1].header.meta, "CODE 65536,100");
+
+        let raw = fs.mgt().read_file_raw(fs.mgt().find_file("game").unwrap()).unwrap();
+        assert_eq!(&raw[..MGT_FILE_HEADER_SIZE], &[0x13, 0x20, 0x0E, 0x00, 0x80, 0xFF, 0xFF, 1, 1]);
+    }
+
+    #[test]
+    fn test_open_filesystem() {
+        // Blank disks have no SAM files, so are mounted as DISCiPLE/+D
+        let mut image = MgtFileSystem::blank_image().unwrap();
+        let info = crate::filesystem::open_filesystem(&image, FileSystemType::Auto).unwrap().info();
+        assert_eq!(info.fs_type, "DISCiPLE/+D");
+        assert_eq!(info.free_blocks, 1560);
+        assert!(!crate::filesystem::is_sam_disk(&image).unwrap());
+
+        SamFileSystem::new_mut(&mut image).unwrap().save_code("boot", 32768, None, &[1; 10]).unwrap();
+        {
+            let mut fs = crate::filesystem::open_filesystem_mut(&mut image, FileSystemType::Mgt).unwrap();
+            fs.write_file("data", &[5; 1000]).unwrap();
+        }
+
+        let fs = crate::filesystem::open_filesystem(&image, FileSystemType::Auto).unwrap();
+        let info = fs.info();
+        assert_eq!(info.fs_type, "SAM Coupe");
+        assert_eq!(info.free_blocks, 1560 - 3);
+        assert_eq!(fs.details(), [("Files".to_string(), "2".to_string())]);
+        assert_eq!(fs.read_dir().unwrap()[1].size, 1000);
+        assert_eq!(fs.read_file("DATA").unwrap(), vec![5; 1000]);
+        assert_eq!(fs.read_dir_extended().unwrap()[1].header.meta, "CODE 32768,1000");
+        assert_eq!(fs.read_basic("DATA").unwrap(), None);
+        assert!(crate::filesystem::is_sam_disk(&image).unwrap());
+    }
+
+    #[test]
+    fn test_screen_mode() {
+        let mut image = MgtFileSystem::blank_image().unwrap();
+        {
+            let mut fs = SamFileSystem::new_mut(&mut image).unwrap();
+            let mut info = vec![0u8; 46];
+            info[SAM_SCREEN_MODE - MGT_DIR_INFO_OFFSET] = 4;
+            fs.mgt_mut().save_file("picture", MgtFileType::Screen, &[0; 600], &info).unwrap();
+            fs.save_code("code", 32768, None, &[0; 10]).unwrap();
+        }
+
+        let fs = SamFileSystem::new(&image).unwrap();
+        assert_eq!(fs.screen_mode("PICTURE").unwrap(), Some(4));
+        assert_eq!(fs.screen_mode("code").unwrap(), None);
+        assert!(fs.screen_mode("missing").is_err());
+    }
+
+    #[test]
+    fn test_masterdos_directories() {
+        let mut image = MgtFileSystem::blank_image().unwrap();
+        // An empty SAM file in the first entry, which says there are two
+        // extra directory tracks
+        let mut first = image.read_sector(0, 0, 1).unwrap().to_vec();
+        first[0] = 0x13;
+        first[1..11].copy_from_slice(b"BOOT      ");
+        first[255] = 2;
+        image.write_sector(0, 0, 1, &first).unwrap();
+
+        let tagged = |tag: usize, value: u8, mut info: Vec<u8>| {
+            info.resize(46, 0);
+            info[tag - MGT_DIR_INFO_OFFSET] = value;
+            info
+        };
+        {
+            let mut fs = SamFileSystem::new_mut(&mut image).unwrap();
+            assert_eq!(fs.mgt().directory_tracks(), 6);
+            let (file_type, body, info) = code_file(32768, None, &[1; 600]).unwrap();
+            let mgt = fs.mgt_mut();
+            mgt.save_file("games", MgtFileType::Other(SAM_TYPE_DIRECTORY), &[], &tagged(MASTERDOS_OWN_TAG, 1, vec![]))
+                .unwrap();
+            mgt.save_file("pacman", file_type, &body, &tagged(MASTERDOS_DIR_TAG, 1, info.clone())).unwrap();
+            mgt.save_file("tables", file_type, &body, &info).unwrap();
+        }
+
+        // A file in the extended directory, in the first sector of track 4
+        let mut entry = image.read_sector(0, 0, 2).unwrap()[MGT_DIR_ENTRY_SIZE..].to_vec();
+        entry[1..11].copy_from_slice(b"EXTRA     ");
+        let mut sector = image.read_sector(0, 4, 1).unwrap().to_vec();
+        sector[..MGT_DIR_ENTRY_SIZE].copy_from_slice(&entry);
+        image.write_sector(0, 4, 1, &sector).unwrap();
+
+        let fs = SamFileSystem::new(&image).unwrap();
+        assert!(fs.mgt().directory().iter().skip(1).all(|e| e.start_track >= 6));
+        let names: Vec<String> = fs.read_dir_extended().unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, ["BOOT", "games/", "games/pacman", "tables", "EXTRA"]);
+        assert_eq!(fs.read_dir().unwrap()[2].name, "games/pacman");
+        assert_eq!(fs.read_dir().unwrap()[2].size, 600);
+        assert_eq!(fs.read_file("games/pacman").unwrap(), vec![1; 600]);
+        assert_eq!(fs.read_file("extra").unwrap(), vec![1; 600]);
+        assert!(fs.read_file("pacman").is_err());
+
+        let info = FileSystem::info(&fs);
+        assert_eq!(info.total_blocks, 1560 - 20);
+        assert_eq!(info.free_blocks, 1560 - 20 - 5);
+        assert_eq!(fs.mgt().disk_info().dir_sectors, 60);
+
+        let mut fs = SamFileSystem::new_mut(&mut image).unwrap();
+        assert!(fs.delete_file("games").is_err());
+        fs.delete_file("GAMES/PACMAN").unwrap();
+        fs.delete_file("games/").unwrap();
+        assert_eq!(fs.read_dir().unwrap().len(), 3);
+    }
 }
@@ -0,0 +1,756 @@
+/// TR-DOS filesystem implementation
+///
+/// TR-DOS is the Beta Disk interface DOS for the ZX Spectrum (and clones
+/// such as the Pentagon and Scorpion). Disks have 16 sectors of 256 bytes
+/// per track, counted as logical tracks that alternate between sides:
+/// - Track 0, sectors 1-8: catalogue of 128 entries of 16 bytes
+/// - Track 0, sector 9: disk information (free space, disk type, label)
+/// - Files stored contiguously from the first free logical sector
+///
+/// Catalogue entries hold an 8 character name, a type (B, C, D or #), a
+/// start parameter, a length in bytes, a length in sectors and the first
+/// sector and logical track. BASIC files store the program length in the
+/// start parameter and may be followed by 0x80 0xAA and an autostart line.
+
+use crate::error::{DskError, Result};
+use crate::filesystem::{
+    DirEntry, ExtendedDirEntry, FileAttributes, FileHeader, FileSystem, FileSystemInfo,
+    HeaderType, ImageRef,
+};
+use crate::format::{DiskImageFormat, FormatSpec, SideMode};
+use crate::image::{DiskImage, DiskImageBuilder};
+use crate::sinclair_basic::{decode_sinclair_basic, SinclairBasicMode};
+
+/// Sector size in bytes
+pub const TRDOS_SECTOR_SIZE: usize = 256;
+
+/// Sectors per track
+pub const TRDOS_SECTORS_PER_TRACK: u8 = 16;
+
+/// Catalogue entry size in bytes
+pub const TRDOS_ENTRY_SIZE: usize = 16;
+
+/// Maximum number of catalogue entries
+pub const TRDOS_MAX_ENTRIES: usize = 128;
+
+/// TR-DOS identification byte in the disk information sector
+pub const TRDOS_ID: u8 = 0x10;
+
+/// Sector ID holding the disk information
+const INFO_SECTOR_ID: u8 = 9;
+/// Offsets within the disk information sector
+const INFO_FIRST_FREE_SECTOR: usize = 0xE1;
+const INFO_FIRST_FREE_TRACK: usize = 0xE2;
+const INFO_DISK_TYPE: usize = 0xE3;
+const INFO_FILE_COUNT: usize = 0xE4;
+const INFO_FREE_SECTORS: usize = 0xE5;
+const INFO_ID: usize = 0xE7;
+const INFO_DELETED_COUNT: usize = 0xF4;
+const INFO_LABEL: usize = 0xF5;
+
+/// First catalogue byte marking the end of the catalogue
+const END_OF_CATALOGUE: u8 = 0x00;
+/// First catalogue byte marking a deleted file
+const DELETED_FILE: u8 = 0x01;
+/// Marker ahead of a BASIC autostart line
+const AUTOSTART_MARKER: [u8; 2] = [0x80, 0xAA];
+
+/// TR-DOS file types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrDosFileType {
+    /// BASIC program (B)
+    Basic,
+    /// CODE/binary (C)
+    Code,
+    /// Data array (D)
+    Data,
+    /// Sequential print file (#)
+    Print,
+    /// Other type letter
+    Other(u8),
+}
+
+impl TrDosFileType {
+    /// Parse from the catalogue type byte
+    pub fn from_byte(byte: u8) -> Self {
+        match byte {
+            b'B' => TrDosFileType::Basic,
+            b'C' => TrDosFileType::Code,
+            b'D' => TrDosFileType::Data,
+            b'#' => TrDosFileType::Print,
+            other => TrDosFileType::Other(other),
+        }
+    }
+
+    /// Get the catalogue type byte
+    pub fn to_byte(self) -> u8 {
+        match self {
+            TrDosFileType::Basic => b'B',
+            TrDosFileType::Code => b'C',
+            TrDosFileType::Data => b'D',
+            TrDosFileType::Print => b'#',
+            TrDosFileType::Other(byte) => byte,
+        }
+    }
+}
+
+impl std::fmt::Display for TrDosFileType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrDosFileType::Basic => write!(f, "BASIC"),
+            TrDosFileType::Code => write!(f, "CODE"),
+            TrDosFileType::Data => write!(f, "DATA"),
+            TrDosFileType::Print => write!(f, "PRINT"),
+            TrDosFileType::Other(byte) => write!(f, "Type {}", *byte as char),
+        }
+    }
+}
+
+/// TR-DOS catalogue entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrDosDirEntry {
+    /// Catalogue entry index
+    pub index: usize,
+    /// Filename (up to 8 characters, trailing spaces removed)
+    pub filename: String,
+    /// File type
+    pub file_type: TrDosFileType,
+    /// Start address (CODE) or program length without variables (BASIC)
+    pub start: u16,
+    /// Length in bytes
+    pub length: u16,
+    /// Length in sectors
+    pub sectors: u8,
+    /// First sector (0-15)
+    pub first_sector: u8,
+    /// First logical track
+    pub first_track: u8,
+    /// Whether the file has been deleted
+    pub deleted: bool,
+}
+
+impl TrDosDirEntry {
+    /// Parse a catalogue entry, returning `None` at the end of the catalogue
+    pub fn parse(data: &[u8], index: usize) -> Option<Self> {
+        if data.len() < TRDOS_ENTRY_SIZE || data[0] == END_OF_CATALOGUE {
+            return None;
+        }
+
+        let deleted = data[0] == DELETED_FILE;
+        let name_start = if deleted { 1 } else { 0 };
+        let filename = String::from_utf8_lossy(&data[name_start..8])
+            .trim_end()
+            .to_string();
+
+        Some(Self {
+            index,
+            filename,
+            file_type: TrDosFileType::from_byte(data[8]),
+            start: u16::from_le_bytes([data[9], data[10]]),
+            length: u16::from_le_bytes([data[11], data[12]]),
+            sectors: data[13],
+            first_sector: data[14],
+            first_track: data[15],
+            deleted,
+        })
+    }
+
+    /// Encode the entry as 16 catalogue bytes
+    pub fn to_bytes(&self) -> [u8; TRDOS_ENTRY_SIZE] {
+        let mut bytes = [b' '; TRDOS_ENTRY_SIZE];
+        for (dst, src) in bytes[..8].iter_mut().zip(self.filename.bytes()) {
+            *dst = src;
+        }
+        if self.deleted {
+            bytes[0] = DELETED_FILE;
+        }
+        bytes[8] = self.file_type.to_byte();
+        bytes[9..11].copy_from_slice(&self.start.to_le_bytes());
+        bytes[11..13].copy_from_slice(&self.length.to_le_bytes());
+        bytes[13] = self.sectors;
+        bytes[14] = self.first_sector;
+        bytes[15] = self.first_track;
+        bytes
+    }
+
+    /// Full name with the type letter, e.g. "GAME.B"
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.filename, self.file_type.to_byte() as char)
+    }
+
+    /// Size the file occupies on disk in bytes
+    pub fn allocated_size(&self) -> usize {
+        self.sectors as usize * TRDOS_SECTOR_SIZE
+    }
+}
+
+/// TR-DOS disk information from track 0 sector 9
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrDosDiskInfo {
+    /// First free sector (0-15)
+    pub first_free_sector: u8,
+    /// First free logical track
+    pub first_free_track: u8,
+    /// Disk type (0x16-0x19)
+    pub disk_type: u8,
+    /// Number of catalogue entries in use, including deleted files
+    pub file_count: u8,
+    /// Number of free sectors
+    pub free_sectors: u16,
+    /// Number of deleted files
+    pub deleted_count: u8,
+    /// Disk label (up to 8 characters)
+    pub label: String,
+}
+
+impl TrDosDiskInfo {
+    /// Disk type byte for a geometry
+    pub fn disk_type_for(num_tracks: u8, num_sides: u8) -> u8 {
+        match (num_tracks > 40, num_sides > 1) {
+            (true, true) => 0x16,
+            (false, true) => 0x17,
+            (true, false) => 0x18,
+            (false, false) => 0x19,
+        }
+    }
+
+    /// Geometry (tracks, sides) for a disk type byte
+    pub fn geometry(disk_type: u8) -> Option<(u8, u8)> {
+        match disk_type {
+            0x16 => Some((80, 2)),
+            0x17 => Some((40, 2)),
+            0x18 => Some((80, 1)),
+            0x19 => Some((40, 1)),
+            _ => None,
+        }
+    }
+
+    fn parse(data: &[u8]) -> Self {
+        Self {
+            first_free_sector: data[INFO_FIRST_FREE_SECTOR],
+            first_free_track: data[INFO_FIRST_FREE_TRACK],
+            disk_type: data[INFO_DISK_TYPE],
+            file_count: data[INFO_FILE_COUNT],
+            free_sectors: u16::from_le_bytes([data[INFO_FREE_SECTORS], data[INFO_FREE_SECTORS + 1]]),
+            deleted_count: data[INFO_DELETED_COUNT],
+            label: String::from_utf8_lossy(&data[INFO_LABEL..INFO_LABEL + 8])
+                .trim_end_matches([' ', '\0'])
+                .to_string(),
+        }
+    }
+
+    fn write_to(&self, data: &mut [u8]) {
+        data[INFO_FIRST_FREE_SECTOR] = self.first_free_sector;
+        data[INFO_FIRST_FREE_TRACK] = self.first_free_track;
+        data[INFO_DISK_TYPE] = self.disk_type;
+        data[INFO_FILE_COUNT] = self.file_count;
+        data[INFO_FREE_SECTORS..INFO_FREE_SECTORS + 2].copy_from_slice(&self.free_sectors.to_le_bytes());
+        data[INFO_ID] = TRDOS_ID;
+        data[INFO_DELETED_COUNT] = self.deleted_count;
+        let mut label = [b' '; 8];
+        for (dst, src) in label.iter_mut().zip(self.label.bytes()) {
+            *dst = src;
+        }
+        data[INFO_LABEL..INFO_LABEL + 8].copy_from_slice(&label);
+    }
+}
+
+/// TR-DOS filesystem
+pub struct TrDosFileSystem<'a> {
+    image: ImageRef<'a>,
+    num_sides: u8,
+    info: TrDosDiskInfo,
+    entries: Vec<TrDosDirEntry>,
+}
+
+impl<'a> TrDosFileSystem<'a> {
+    /// Mount a TR-DOS filesystem read-only
+    pub fn new(image: &'a DiskImage) -> Result<Self> {
+        let (num_sides, info, entries) = Self::read_catalogue(image)?;
+        Ok(Self {
+            image: ImageRef::Shared(image),
+            num_sides,
+            info,
+            entries,
+        })
+    }
+
+    /// Mount a TR-DOS filesystem read-write
+    pub fn new_mut(image: &'a mut DiskImage) -> Result<Self> {
+        let (num_sides, info, entries) = Self::read_catalogue(image)?;
+        Ok(Self {
+            image: ImageRef::Exclusive(image),
+            num_sides,
+            info,
+            entries,
+        })
+    }
+
+    /// Check whether an image holds a TR-DOS disk
+    pub fn is_trdos(image: &DiskImage) -> bool {
+        image
+            .read_sector(0, 0, INFO_SECTOR_ID)
+            .is_ok_and(|data| data.len() == TRDOS_SECTOR_SIZE && data[INFO_ID] == TRDOS_ID)
+    }
+
+    /// Format specification for a TR-DOS disk
+    pub fn format_spec(num_tracks: u8, num_sides: u8) -> FormatSpec {
+        FormatSpec {
+            num_sides,
+            num_tracks,
+            sectors_per_track: TRDOS_SECTORS_PER_TRACK,
+            sector_size: TRDOS_SECTOR_SIZE as u16,
+            first_sector_id: 1,
+            gap3_length: 0x20,
+            filler_byte: 0x00,
+            interleave: 1,
+            skew: 0,
+            side_mode: if num_sides > 1 { SideMode::Alternate } else { SideMode::SingleSide },
+        }
+    }
+
+    /// Create a blank, formatted TR-DOS disk
+    pub fn blank_image(num_tracks: u8, num_sides: u8, label: &str) -> Result<DiskImage> {
+        let mut image = DiskImageBuilder::new()
+            .format(DiskImageFormat::Trd)
+            .spec(Self::format_spec(num_tracks, num_sides))
+            .build()?;
+
+        let total_sectors = num_tracks as u16 * num_sides as u16 * TRDOS_SECTORS_PER_TRACK as u16;
+        let info = TrDosDiskInfo {
+            first_free_sector: 0,
+            first_free_track: 1,
+            disk_type: TrDosDiskInfo::disk_type_for(num_tracks, num_sides),
+            file_count: 0,
+            free_sectors: total_sectors - TRDOS_SECTORS_PER_TRACK as u16,
+            deleted_count: 0,
+            label: label.chars().take(8).collect(),
+        };
+
+        let mut data = vec![0u8; TRDOS_SECTOR_SIZE];
+        info.write_to(&mut data);
+        image.write_sector(0, 0, INFO_SECTOR_ID, &data)?;
+        Ok(image)
+    }
+
+    /// Read the disk information and catalogue
+    fn read_catalogue(image: &DiskImage) -> Result<(u8, TrDosDiskInfo, Vec<TrDosDirEntry>)> {
+        if !Self::is_trdos(image) {
+            return Err(DskError::filesystem("Not a TR-DOS disk"));
+        }
+
+        let info = TrDosDiskInfo::parse(image.read_sector(0, 0, INFO_SECTOR_ID)?);
+        let num_sides = TrDosDiskInfo::geometry(info.disk_type)
+            .map(|(_, sides)| sides)
+            .unwrap_or(image.disk_count().clamp(1, 2) as u8);
+
+        let mut entries = Vec::new();
+        'catalogue: for sector_id in 1..=8u8 {
+            let data = image.read_sector(0, 0, sector_id)?;
+            for (i, raw) in data.chunks_exact(TRDOS_ENTRY_SIZE).enumerate() {
+                let index = (sector_id as usize - 1) * (TRDOS_SECTOR_SIZE / TRDOS_ENTRY_SIZE) + i;
+                match TrDosDirEntry::parse(raw, index) {
+                    Some(entry) => entries.push(entry),
+                    None => break 'catalogue,
+                }
+            }
+        }
+
+        Ok((num_sides, info, entries))
+    }
+
+    /// Get the disk information
+    pub fn disk_info(&self) -> &TrDosDiskInfo {
+        &self.info
+    }
+
+    /// Get all catalogue entries, including deleted files
+    pub fn directory(&self) -> &[TrDosDirEntry] {
+        &self.entries
+    }
+
+    /// Find a live file by name, with or without its type letter
+    pub fn find_file(&self, name: &str) -> Option<&TrDosDirEntry> {
+        self.entries
+            .iter()
+            .filter(|e| !e.deleted)
+            .find(|e| e.full_name().eq_ignore_ascii_case(name) || e.filename.eq_ignore_ascii_case(name))
+    }
+
+    /// Physical (side, track, sector ID) of a logical sector
+    fn physical(&self, logical: usize) -> (u8, u8, u8) {
+        let track = logical / TRDOS_SECTORS_PER_TRACK as usize;
+        let sector = logical % TRDOS_SECTORS_PER_TRACK as usize;
+        let sides = self.num_sides as usize;
+        ((track % sides) as u8, (track / sides) as u8, sector as u8 + 1)
+    }
+
+    /// Read the sectors of a file, including any bytes after its length
+    pub(crate) fn read_sectors(&self, entry: &TrDosDirEntry) -> Result<Vec<u8>> {
+        let first = entry.first_track as usize * TRDOS_SECTORS_PER_TRACK as usize + entry.first_sector as usize;
+        let mut data = Vec::with_capacity(entry.allocated_size());
+        for logical in first..first + entry.sectors as usize {
+            let (side, track, id) = self.physical(logical);
+            data.extend_from_slice(self.image.get().read_sector(side, track, id)?);
+        }
+        Ok(data)
+    }
+
+    /// Read a file's data, truncated to its length
+    pub fn read_entry(&self, entry: &TrDosDirEntry) -> Result<Vec<u8>> {
+        let mut data = self.read_sectors(entry)?;
+        data.truncate(entry.length as usize);
+        Ok(data)
+    }
+
+    /// Autostart line of a BASIC file, if it has one
+    pub fn autostart_line(&self, entry: &TrDosDirEntry) -> Result<Option<u16>> {
+        if entry.file_type != TrDosFileType::Basic {
+            return Ok(None);
+        }
+        let data = self.read_sectors(entry)?;
+        let at = entry.length as usize;
+        Ok(match data.get(at..at + 4) {
+            Some(tail) if tail[..2] == AUTOSTART_MARKER => Some(u16::from_le_bytes([tail[2], tail[3]])),
+            _ => None,
+        })
+    }
+
+    /// Decode a BASIC file as text, returning `None` for other file types
+    pub fn read_basic(&self, name: &str) -> Result<Option<String>> {
+        let entry = self
+            .find_file(name)
+            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        if entry.file_type != TrDosFileType::Basic {
+            return Ok(None);
+        }
+
+        let mut data = self.read_entry(entry)?;
+        data.truncate(entry.start as usize);
+        decode_sinclair_basic(&data, SinclairBasicMode::Mode128K).map(Some)
+    }
+
+    /// Describe a file's type and parameters, e.g. "CODE 32768,6912"
+    pub fn describe(&self, entry: &TrDosDirEntry) -> String {
+        match entry.file_type {
+            TrDosFileType::Basic => match self.autostart_line(entry).ok().flatten() {
+                Some(line) if line < 10000 => format!("BASIC LINE {}", line),
+                _ => "BASIC".to_string(),
+            },
+            TrDosFileType::Code => format!("CODE {},{}", entry.start, entry.length),
+            other => other.to_string(),
+        }
+    }
+
+    /// Read the catalogue with type and parameter information
+    ///
+    /// Deleted files are included with a user of 0xE5.
+    pub fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        Ok(self
+            .entries
+            .iter()
+            .map(|entry| ExtendedDirEntry {
+                name: entry.full_name(),
+                user: if entry.deleted { 0xE5 } else { 0 },
+                index: entry.index,
+                blocks: entry.sectors as usize,
+                allocated: entry.allocated_size(),
+                size: entry.length as usize,
+                attributes: FileAttributes::default(),
+                header: FileHeader {
+                    header_type: HeaderType::None,
+                    checksum_valid: true,
+                    file_size: entry.length as usize,
+                    header_size: 0,
+                    meta: self.describe(entry),
+                },
+            })
+            .collect())
+    }
+
+    /// Save a file with an explicit type and start parameter
+    pub fn save_file(&mut self, name: &str, file_type: TrDosFileType, start: u16, data: &[u8]) -> Result<()> {
+        if name.is_empty() || name.len() > 8 || !name.is_ascii() {
+            return Err(DskError::InvalidFilename(name.to_string()));
+        }
+        let entry = TrDosDirEntry {
+            index: 0,
+            filename: name.to_string(),
+            file_type,
+            start,
+            length: u16::try_from(data.len()).map_err(|_| DskError::DiskFull)?,
+            sectors: 0,
+            first_sector: 0,
+            first_track: 0,
+            deleted: false,
+        };
+        if self.find_file(&entry.full_name()).is_some() {
+            return Err(DskError::filesystem(format!("File already exists: {}", entry.full_name())));
+        }
+
+        self.append(entry, data)
+    }
+
+    /// Add a catalogue entry for `data` at the first free sector
+    ///
+    /// The entry's sector count is set from the data length.
+    pub(crate) fn append(&mut self, mut entry: TrDosDirEntry, data: &[u8]) -> Result<()> {
+        let sectors = data.len().div_ceil(TRDOS_SECTOR_SIZE);
+        if self.entries.len() >= TRDOS_MAX_ENTRIES
+            || sectors > u8::MAX as usize
+            || sectors > self.info.free_sectors as usize
+        {
+            return Err(DskError::DiskFull);
+        }
+
+        entry.index = self.entries.len();
+        entry.sectors = sectors as u8;
+        entry.first_sector = self.info.first_free_sector;
+        entry.first_track = self.info.first_free_track;
+
+        let first = entry.first_track as usize * TRDOS_SECTORS_PER_TRACK as usize + entry.first_sector as usize;
+        for (i, chunk) in data.chunks(TRDOS_SECTOR_SIZE).enumerate() {
+            let mut sector = chunk.to_vec();
+            sector.resize(TRDOS_SECTOR_SIZE, 0);
+            let (side, track, id) = self.physical(first + i);
+            self.image.get_mut()?.write_sector(side, track, id, &sector)?;
+        }
+
+        let next = first + sectors;
+        self.info.first_free_track = (next / TRDOS_SECTORS_PER_TRACK as usize) as u8;
+        self.info.first_free_sector = (next % TRDOS_SECTORS_PER_TRACK as usize) as u8;
+        self.info.free_sectors -= sectors as u16;
+        self.info.file_count += 1;
+
+        self.entries.push(entry);
+        self.write_entry(self.entries.len() - 1)?;
+        self.write_info()
+    }
+
+    /// Write a catalogue entry back to track 0
+    fn write_entry(&mut self, position: usize) -> Result<()> {
+        let entry = &self.entries[position];
+        let per_sector = TRDOS_SECTOR_SIZE / TRDOS_ENTRY_SIZE;
+        let sector_id = (entry.index / per_sector) as u8 + 1;
+        let offset = (entry.index % per_sector) * TRDOS_ENTRY_SIZE;
+        let bytes = entry.to_bytes();
+
+        let image = self.image.get_mut()?;
+        let mut data = image.read_sector(0, 0, sector_id)?.to_vec();
+        data[offset..offset + TRDOS_ENTRY_SIZE].copy_from_slice(&bytes);
+        image.write_sector(0, 0, sector_id, &data)
+    }
+
+    /// Write the disk information sector back to track 0
+    fn write_info(&mut self) -> Result<()> {
+        let image = self.image.get_mut()?;
+        let mut data = image.read_sector(0, 0, INFO_SECTOR_ID)?.to_vec();
+        self.info.write_to(&mut data);
+        image.write_sector(0, 0, INFO_SECTOR_ID, &data)
+    }
+}
+
+impl<'a> FileSystem for TrDosFileSystem<'a> {
+    fn from_image<'b>(_image: &'b DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use TrDosFileSystem::new() directly",
+        ))
+    }
+
+    fn from_image_mut<'b>(_image: &'b mut DiskImage) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(DskError::filesystem(
+            "Use TrDosFileSystem::new_mut() directly",
+        ))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>> {
+        Ok(self
+            .entries
+            .iter()
+            .filter(|e| !e.deleted)
+            .map(|e| DirEntry {
+                name: e.full_name(),
+                user: 0,
+                extent: 0,
+                size: e.length as usize,
+                attributes: FileAttributes::default(),
+            })
+            .collect())
+    }
+
+    fn read_file(&self, name: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .find_file(name)
+            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+        self.read_entry(entry)
+    }
+
+    /// Write a file named `NAME.T`, where T is the type letter (CODE if omitted)
+    ///
+    /// CODE files get a start address of 0 and BASIC files are taken to
+    /// have no variables.
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let (base, file_type) = match name.rsplit_once('.') {
+            Some((base, ext)) if ext.len() == 1 => {
+                (base, TrDosFileType::from_byte(ext.as_bytes()[0].to_ascii_uppercase()))
+            }
+            _ => (name, TrDosFileType::Code),
+        };
+        let start = match file_type {
+            TrDosFileType::Basic => data.len() as u16,
+            _ => 0,
+        };
+        self.save_file(base, file_type, start, data)
+    }
+
+    /// Delete a file by marking its catalogue entry
+    ///
+    /// As on TR-DOS itself the space is not reclaimed until the disk is
+    /// compacted.
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        self.image.get_mut()?;
+        let position = self
+            .entries
+            .iter()
+            .position(|e| {
+                !e.deleted && (e.full_name().eq_ignore_ascii_case(name) || e.filename.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| DskError::FileNotFound(name.to_string()))?;
+
+        self.entries[position].deleted = true;
+        self.info.deleted_count += 1;
+        self.write_entry(position)?;
+        self.write_info()
+    }
+
+    fn read_dir_extended(&self) -> Result<Vec<ExtendedDirEntry>> {
+        TrDosFileSystem::read_dir_extended(self)
+    }
+
+    fn read_basic(&self, name: &str) -> Result<Option<String>> {
+        TrDosFileSystem::read_basic(self, name)
+    }
+
+    fn info(&self) -> FileSystemInfo {
+        let total = TrDosDiskInfo::geometry(self.info.disk_type)
+            .map(|(tracks, sides)| tracks as usize * sides as usize * TRDOS_SECTORS_PER_TRACK as usize)
+            .unwrap_or(0);
+        FileSystemInfo {
+            fs_type: "TR-DOS".to_string(),
+            total_blocks: total.saturating_sub(TRDOS_SECTORS_PER_TRACK as usize),
+            free_blocks: self.info.free_sectors as usize,
+            block_size: TRDOS_SECTOR_SIZE,
+        }
+    }
+
+    fn details(&self) -> Vec<(String, String)> {
+        let mut details = Vec::new();
+        if !self.info.label.is_empty() {
+            details.push(("Label".to_string(), self.info.label.clone()));
+        }
+        details.push((
+            "Files".to_string(),
+            format!("{} ({} deleted)", self.info.file_count, self.info.deleted_count),
+        ));
+        details
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic_program() -> Vec<u8> {
+        // 10 PRINT "HI"
+        let line = [0xF5, b'"', b'H', b'I', b'"', 0x0D];
+        let mut data = vec![0x00, 0x0A];
+        data.extend_from_slice(&(line.len() as u16).to_le_bytes());
+        data.extend_from_slice(&line);
+        data
+    }
+
+    #[test]
+    fn test_entry_round_trip() {
+        let bytes = *b"SCREEN  C\x00\x40\x00\x1B\x1B\x03\x01";
+        let entry = TrDosDirEntry::parse(&bytes, 0).unwrap();
+        assert_eq!(entry.full_name(), "SCREEN.C");
+        assert_eq!(entry.start, 0x4000);
+        assert_eq!(entry.length, 6912);
+        assert_eq!(entry.sectors, 27);
+        assert_eq!(entry.to_bytes(), bytes);
+        assert!(TrDosDirEntry::parse(&[0; 16], 0).is_none());
+    }
+
+    #[test]
+    fn test_blank_image() {
+        let image = TrDosFileSystem::blank_image(80, 2, "GAMES").unwrap();
+        let fs = TrDosFileSystem::new(&image).unwrap();
+        assert_eq!(fs.disk_info().free_sectors, 2544);
+        assert_eq!(fs.disk_info().label, "GAMES");
+        assert_eq!(fs.info().fs_type, "TR-DOS");
+        assert!(fs.read_dir().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_read_delete() {
+        let mut image = TrDosFileSystem::blank_image(40, 1, "").unwrap();
+        let mut fs = TrDosFileSystem::new_mut(&mut image).unwrap();
+
+        let code: Vec<u8> = (0..600).map(|i| i as u8).collect();
+        fs.save_file("LOADER", TrDosFileType::Code, 32768, &code).unwrap();
+        fs.write_file("PROG.B", &basic_program()).unwrap();
+        assert!(fs.write_file("PROG.B", &[0]).is_err());
+
+        assert_eq!(fs.disk_info().file_count, 2);
+        assert_eq!(fs.disk_info().first_free_track, 1);
+        assert_eq!(fs.disk_info().first_free_sector, 4);
+        assert_eq!(fs.read_file("loader.c").unwrap(), code);
+        assert_eq!(fs.read_basic("PROG").unwrap().unwrap().trim(), "10 PRINT \"HI\"");
+        assert_eq!(fs.read_basic("LOADER").unwrap(), None);
+
+        fs.delete_file("LOADER.C").unwrap();
+        drop(fs);
+
+        let fs = TrDosFileSystem::new(&image).unwrap();
+        let names: Vec<_> = fs.read_dir().unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["PROG.B"]);
+        assert_eq!(fs.disk_info().deleted_count, 1);
+
+        let extended = fs.read_dir_extended().unwrap();
+        assert_eq!(extended[0].user, 0xE5);
+        assert_eq!(extended[0].header.meta, "CODE 32768,600");
+    }
+
+    #[test]
+    fn test_autostart_line() {
+        let mut image = TrDosFileSystem::blank_image(40, 1, "").unwrap();
+        let mut fs = TrDosFileSystem::new_mut(&mut image).unwrap();
+
+        let program = basic_program();
+        let mut stored = program.clone();
+        stored.extend_from_slice(&[0x80, 0xAA, 10, 0]);
+        let entry = TrDosDirEntry {
+            index: 0,
+            filename: "boot".to_string(),
+            file_type: TrDosFileType::Basic,
+            start: program.len() as u16,
+            length: program.len() as u16,
+            sectors: 0,
+            first_sector: 0,
+            first_track: 0,
+            deleted: false,
+        };
+        fs.append(entry, &stored).unwrap();
+
+        let entry = fs.find_file("boot.B").unwrap();
+        assert_eq!(fs.autostart_line(entry).unwrap(), Some(10));
+        assert_eq!(fs.describe(entry), "BASIC LINE 10");
+        assert_eq!(fs.read_file("boot").unwrap(), program);
+    }
+}
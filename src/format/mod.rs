@@ -14,6 +14,12 @@ pub use specification::{
 };
 
 use crate::filesystem::FileSystemType;
+use crate::io::hfe::{HFE_V1_SIGNATURE, HFE_V3_SIGNATURE};
+use crate::io::imd::IMD_SIGNATURE;
+use crate::io::sad::SAD_SIGNATURE;
+use crate::io::scl::SCL_SIGNATURE;
+use crate::io::scp::SCP_SIGNATURE;
+use crate::io::td0::TD0_SIGNATURE;
 
 /// DSK format type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -24,6 +30,26 @@ pub enum DiskImageFormat {
     ExtendedDSK,
     /// Raw MGT format (819,200 byte sector dump)
     RawMgt,
+    /// HxC Floppy Emulator HFE v1 (raw MFM/FM track bitstreams)
+    HfeV1,
+    /// HxC Floppy Emulator HFE v3 (bitstreams with opcodes)
+    HfeV3,
+    /// ImageDisk IMD (sector records with per-track mode)
+    Imd,
+    /// Sydex Teledisk TD0 (read-only, optionally LZHUF compressed)
+    Td0,
+    /// SuperCard Pro SCP flux (read-only, decoded from MFM/FM flux)
+    Scp,
+    /// Raw sector dump with geometry given by a format specification
+    Raw,
+    /// SAM Coupe SAD (header and sector dump, optionally gzip compressed)
+    Sad,
+    /// TR-DOS TRD (sector dump of a Beta Disk disk)
+    Trd,
+    /// TR-DOS SCL (archive of TR-DOS files)
+    Scl,
+    /// Opus Discovery OPD/OPU (sector dump of an Opus disk)
+    Opd,
 }
 
 impl DiskImageFormat {
@@ -33,6 +59,16 @@ impl DiskImageFormat {
             DiskImageFormat::StandardDSK => STANDARD_DSK_SIGNATURE,
             DiskImageFormat::ExtendedDSK => EXTENDED_DSK_SIGNATURE,
             DiskImageFormat::RawMgt => &[], // Raw MGT has no magic bytes
+            DiskImageFormat::HfeV1 => HFE_V1_SIGNATURE,
+            DiskImageFormat::HfeV3 => HFE_V3_SIGNATURE,
+            DiskImageFormat::Imd => IMD_SIGNATURE,
+            DiskImageFormat::Td0 => TD0_SIGNATURE,
+            DiskImageFormat::Scp => SCP_SIGNATURE,
+            DiskImageFormat::Raw => &[], // Raw dumps have no magic bytes
+            DiskImageFormat::Sad => SAD_SIGNATURE,
+            DiskImageFormat::Trd => &[], // TRD dumps have no magic bytes
+            DiskImageFormat::Scl => SCL_SIGNATURE,
+            DiskImageFormat::Opd => &[], // OPD dumps have no magic bytes
         }
     }
 
@@ -42,6 +78,16 @@ impl DiskImageFormat {
             DiskImageFormat::StandardDSK => "Standard DSK",
             DiskImageFormat::ExtendedDSK => "Extended DSK",
             DiskImageFormat::RawMgt => "Raw MGT",
+            DiskImageFormat::HfeV1 => "HFE v1",
+            DiskImageFormat::HfeV3 => "HFE v3",
+            DiskImageFormat::Imd => "ImageDisk IMD",
+            DiskImageFormat::Td0 => "Teledisk TD0",
+            DiskImageFormat::Scp => "SuperCard Pro SCP",
+            DiskImageFormat::Raw => "Raw sector dump",
+            DiskImageFormat::Sad => "SAM Coupe SAD",
+            DiskImageFormat::Trd => "TR-DOS TRD",
+            DiskImageFormat::Scl => "TR-DOS SCL",
+            DiskImageFormat::Opd => "Opus Discovery OPD",
         }
     }
 
@@ -51,6 +97,16 @@ impl DiskImageFormat {
             DiskImageFormat::StandardDSK => FileSystemType::Cpm,
             DiskImageFormat::ExtendedDSK => FileSystemType::Cpm,
             DiskImageFormat::RawMgt => FileSystemType::Mgt,
+            DiskImageFormat::HfeV1 => FileSystemType::Cpm,
+            DiskImageFormat::HfeV3 => FileSystemType::Cpm,
+            DiskImageFormat::Imd => FileSystemType::Cpm,
+            DiskImageFormat::Td0 => FileSystemType::Cpm,
+            DiskImageFormat::Scp => FileSystemType::Cpm,
+            DiskImageFormat::Raw => FileSystemType::Cpm,
+            DiskImageFormat::Sad => FileSystemType::Mgt,
+            DiskImageFormat::Trd => FileSystemType::TrDos,
+            DiskImageFormat::Scl => FileSystemType::TrDos,
+            DiskImageFormat::Opd => FileSystemType::Opus,
         }
     }
 }
@@ -19,6 +19,8 @@ pub struct FormatSpec {
     pub filler_byte: u8,
     /// Interleave factor (1 = no interleave)
     pub interleave: u8,
+    /// Track skew: how many sector positions each track's layout is rotated from the previous
+    pub skew: u8,
     /// Side arrangement mode
     pub side_mode: SideMode,
 }
@@ -51,6 +53,7 @@ impl FormatSpec {
             gap3_length: 0x4E,
             filler_byte: 0xE5,
             interleave: 1,
+            skew: 0,
             side_mode: if num_sides == 1 {
                 SideMode::SingleSide
             } else {
@@ -70,6 +73,7 @@ impl FormatSpec {
             gap3_length: 0x4E,
             filler_byte: 0xE5,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::SingleSide,
         }
     }
@@ -85,6 +89,7 @@ impl FormatSpec {
             gap3_length: 0x4E,
             filler_byte: 0xE5,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::SingleSide,
         }
     }
@@ -100,6 +105,7 @@ impl FormatSpec {
             gap3_length: 0x4E,
             filler_byte: 0xE5,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::Alternate,
         }
     }
@@ -115,6 +121,7 @@ impl FormatSpec {
             gap3_length: 0x2A,
             filler_byte: 0xE5,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::SingleSide,
         }
     }
@@ -130,6 +137,7 @@ impl FormatSpec {
             gap3_length: 0x2A,
             filler_byte: 0xE5,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::Alternate,
         }
     }
@@ -145,6 +153,7 @@ impl FormatSpec {
             gap3_length: 0x2A,
             filler_byte: 0xE5,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::SingleSide,
         }
     }
@@ -160,6 +169,7 @@ impl FormatSpec {
             gap3_length: 0x2A,
             filler_byte: 0xE5,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::Successive,
         }
     }
@@ -175,6 +185,7 @@ impl FormatSpec {
             gap3_length: 0x50,
             filler_byte: 0xF6,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::Alternate,
         }
     }
@@ -190,6 +201,7 @@ impl FormatSpec {
             gap3_length: 0x50,
             filler_byte: 0xF6,
             interleave: 1,
+            skew: 0,
             side_mode: SideMode::Alternate,
         }
     }
@@ -209,6 +221,12 @@ impl FormatSpec {
         self
     }
 
+    /// Set the track skew
+    pub fn with_skew(mut self, skew: u8) -> Self {
+        self.skew = skew;
+        self
+    }
+
     /// Set the side mode
     pub fn with_side_mode(mut self, side_mode: SideMode) -> Self {
         self.side_mode = side_mode;
@@ -265,10 +283,12 @@ mod tests {
     fn test_with_methods() {
         let spec = FormatSpec::amstrad_system()
             .with_interleave(2)
+            .with_skew(3)
             .with_first_sector_id(0x01)
             .with_filler_byte(0x00);
 
         assert_eq!(spec.interleave, 2);
+        assert_eq!(spec.skew, 3);
         assert_eq!(spec.first_sector_id, 0x01);
         assert_eq!(spec.filler_byte, 0x00);
     }
@@ -3,6 +3,7 @@
 /// This module provides detection and representation of disk specifications
 /// used by CP/M and compatible systems (Amstrad PCW, CPC, Spectrum +3, etc.)
 
+use crate::format::spec::{FormatSpec, SideMode};
 use crate::image::DiskImage;
 use std::fmt;
 
@@ -269,17 +270,26 @@ impl FormatDetector for Einstein {
                 && data[4] == 0x00
                 && data[5] == 0xFA
             {
+                // Two system tracks (boot sector and XTAL DOS), then a one
+                // block directory of 64 entries at the start of track 2
+                let tracks = image.get_disk(0)?.track_count().min(u8::MAX as usize) as u8;
                 let mut spec = DiskSpecification::new();
                 spec.format = "Tatung Einstein".to_string();
                 spec.source = "Signature 00 E1 00 FB 00 FA on first logical sector".to_string();
                 spec.sector_size = 512;
                 spec.sectors_per_track = 10;
-                spec.tracks_per_side = 40;
+                spec.tracks_per_side = tracks;
+                if image.disk_count() == 2 {
+                    spec.side = DiskSpecSide::DoubleAlternate;
+                }
+                if tracks >= 80 {
+                    spec.track = DiskSpecTrack::Double;
+                }
                 spec.block_shift = 4;
                 spec.reserved_tracks = 2;
                 spec.directory_blocks = 1;
-                spec.allocation_size = AllocationSize::Word;
                 spec.fdc_sector_size = 2;
+                spec.update_allocation_size();
                 return Some(spec);
             }
         }
@@ -548,6 +558,20 @@ impl DiskSpecification {
         (self.directory_blocks as usize * self.block_size()) / 32
     }
 
+    /// Number of block pointers held by a single directory entry
+    pub fn blocks_per_extent(&self) -> usize {
+        match self.allocation_size {
+            AllocationSize::Byte => 16,
+            AllocationSize::Word => 8,
+        }
+    }
+
+    /// Extent mask (EXM) - how many extra 16K logical extents one directory entry covers
+    pub fn extent_mask(&self) -> u8 {
+        let entry_bytes = self.blocks_per_extent() * self.block_size();
+        ((entry_bytes / 16384).max(1) - 1) as u8
+    }
+
     /// Get the number of sides
     pub fn side_count(&self) -> u8 {
         if self.side == DiskSpecSide::Single {
@@ -563,6 +587,83 @@ impl DiskSpecification {
         tracks * self.sectors_per_track as usize * self.sector_size as usize
     }
 
+    /// Map a logical track number to the physical (side, track) it is stored on
+    ///
+    /// Logical tracks count across both sides in the order given by `side`:
+    /// - `DoubleAlternate`: T0S0, T0S1, T1S0, T1S1...
+    /// - `DoubleSuccessive`: all of side 0, then all of side 1
+    /// - `DoubleReverse`: all of side 0, then side 1 from the last track back to track 0
+    ///
+    /// Returns `None` if the logical track is beyond the end of the disk.
+    pub fn physical_track(&self, logical_track: usize) -> Option<(u8, u8)> {
+        let tracks_per_side = self.tracks_per_side as usize;
+        if logical_track >= tracks_per_side * self.side_count() as usize {
+            return None;
+        }
+
+        let (side, track) = match self.side {
+            DiskSpecSide::DoubleAlternate => (logical_track % 2, logical_track / 2),
+            DiskSpecSide::DoubleSuccessive if logical_track >= tracks_per_side => {
+                (1, logical_track - tracks_per_side)
+            }
+            DiskSpecSide::DoubleReverse if logical_track >= tracks_per_side => {
+                (1, tracks_per_side * 2 - 1 - logical_track)
+            }
+            _ => (0, logical_track),
+        };
+
+        Some((side as u8, track as u8))
+    }
+
+    /// Encode this specification as the 16-byte PCW/+3 spec block found at the
+    /// start of track 0 sector 0
+    ///
+    /// The format byte is 0 for single-sided and 3 for double-sided disks. The
+    /// checksum byte is left as 0, so the disk is not bootable.
+    pub fn spec_block(&self) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[0] = if self.side_count() == 2 { 3 } else { 0 };
+        block[1] = match self.side {
+            DiskSpecSide::DoubleAlternate => 1,
+            DiskSpecSide::DoubleSuccessive | DiskSpecSide::DoubleReverse => 2,
+            _ => 0,
+        };
+        if self.track == DiskSpecTrack::Double {
+            block[1] |= 0x80;
+        }
+        block[2] = self.tracks_per_side;
+        block[3] = self.sectors_per_track;
+        block[4] = self.fdc_sector_size;
+        block[5] = self.reserved_tracks;
+        block[6] = self.block_shift;
+        block[7] = self.directory_blocks;
+        block[8] = self.gap_read_write;
+        block[9] = self.gap_format;
+        block
+    }
+
+    /// Physical layout needed to hold this specification, numbering sectors from `first_sector_id`
+    pub fn format_spec(&self, first_sector_id: u8) -> FormatSpec {
+        FormatSpec {
+            num_sides: self.side_count(),
+            num_tracks: self.tracks_per_side,
+            sectors_per_track: self.sectors_per_track,
+            sector_size: self.sector_size,
+            first_sector_id,
+            gap3_length: self.gap_format,
+            filler_byte: 0xE5,
+            interleave: 1,
+            skew: 0,
+            side_mode: match self.side {
+                DiskSpecSide::DoubleAlternate => SideMode::Alternate,
+                DiskSpecSide::DoubleSuccessive | DiskSpecSide::DoubleReverse => {
+                    SideMode::Successive
+                }
+                _ => SideMode::SingleSide,
+            },
+        }
+    }
+
     /// Update allocation size based on block count
     fn update_allocation_size(&mut self) {
         if self.block_count() > 255 {
@@ -692,6 +793,23 @@ mod tests {
         assert_eq!(spec.directory_entries(), 64);
     }
 
+    #[test]
+    fn test_extent_mask() {
+        let mut spec = DiskSpecification::new();
+        // 16 x 1K blocks = 16K per entry
+        assert_eq!(spec.blocks_per_extent(), 16);
+        assert_eq!(spec.extent_mask(), 0);
+
+        // 16 x 2K blocks = 32K per entry
+        spec.block_shift = 4;
+        assert_eq!(spec.extent_mask(), 1);
+
+        // 8 x 2K blocks = 16K per entry
+        spec.allocation_size = AllocationSize::Word;
+        assert_eq!(spec.blocks_per_extent(), 8);
+        assert_eq!(spec.extent_mask(), 0);
+    }
+
     #[test]
     fn test_records_per_track() {
         let spec = DiskSpecification::new();
@@ -709,6 +827,48 @@ mod tests {
         assert_eq!(spec.format, "Amstrad CPC DD/SS/ST system");
     }
 
+    #[test]
+    fn test_physical_track() {
+        let mut spec = DiskSpecification::new();
+        assert_eq!(spec.physical_track(5), Some((0, 5)));
+        assert_eq!(spec.physical_track(40), None);
+
+        spec.side = DiskSpecSide::DoubleAlternate;
+        assert_eq!(spec.physical_track(0), Some((0, 0)));
+        assert_eq!(spec.physical_track(1), Some((1, 0)));
+        assert_eq!(spec.physical_track(5), Some((1, 2)));
+        assert_eq!(spec.physical_track(80), None);
+
+        spec.side = DiskSpecSide::DoubleSuccessive;
+        assert_eq!(spec.physical_track(39), Some((0, 39)));
+        assert_eq!(spec.physical_track(40), Some((1, 0)));
+
+        spec.side = DiskSpecSide::DoubleReverse;
+        assert_eq!(spec.physical_track(40), Some((1, 39)));
+        assert_eq!(spec.physical_track(79), Some((1, 0)));
+    }
+
+    #[test]
+    fn test_spec_block() {
+        let mut spec = DiskSpecification::new();
+        spec.side = DiskSpecSide::DoubleAlternate;
+        spec.track = DiskSpecTrack::Double;
+        spec.tracks_per_side = 80;
+        spec.block_shift = 4;
+        spec.directory_blocks = 4;
+
+        let block = spec.spec_block();
+        assert_eq!(&block[..10], &[3, 0x81, 80, 9, 2, 1, 4, 4, 42, 82]);
+
+        let mut parsed = DiskSpecification::new();
+        parse_spec_block(&mut parsed, &block);
+        assert_eq!(parsed.side, spec.side);
+        assert_eq!(parsed.track, spec.track);
+        assert_eq!(parsed.tracks_per_side, 80);
+        assert_eq!(parsed.block_shift, 4);
+        assert_eq!(parsed.directory_blocks, 4);
+    }
+
     #[test]
     fn test_side_display() {
         assert_eq!(format!("{}", DiskSpecSide::Single), "Single");
@@ -92,6 +92,10 @@ impl DiskImageBuilder {
                     track.add_sector(sector);
                 }
 
+                // Lay sectors out physically, rotating each track by the skew
+                let offset = track_num as usize * self.spec.skew as usize;
+                track.reinterleave(self.spec.interleave, offset);
+
                 disk.add_track(track);
             }
 
@@ -153,6 +157,21 @@ mod tests {
         assert_eq!(image.spec().num_tracks, spec.num_tracks);
     }
 
+    #[test]
+    fn test_builder_interleave_and_skew() {
+        let spec = FormatSpec::amstrad_data().with_interleave(2).with_skew(2);
+        let image = DiskImageBuilder::new().spec(spec).build().unwrap();
+        let disk = image.get_disk(0).unwrap();
+
+        let track0 = disk.get_track(0).unwrap().sector_ids();
+        assert_eq!(track0, vec![0xC1, 0xC6, 0xC2, 0xC7, 0xC3, 0xC8, 0xC4, 0xC9, 0xC5]);
+
+        // Track 1 starts two positions further round
+        let track1 = disk.get_track(1).unwrap().sector_ids();
+        assert_eq!(track1, vec![0xC9, 0xC5, 0xC1, 0xC6, 0xC2, 0xC7, 0xC3, 0xC8, 0xC4]);
+        assert!(image.read_sector(0, 1, 0xC5).is_ok());
+    }
+
     #[test]
     fn test_builder_creates_sectors() {
         let image = DiskImageBuilder::new()
@@ -12,7 +12,7 @@ pub mod track;
 pub use builder::DiskImageBuilder;
 pub use disk::Disk;
 pub use sector::{Sector, SectorId, SectorStatus};
-pub use track::{DataRate, RecordingMode, Track};
+pub use track::{DataRate, RecordingMode, SectorPosition, Track};
 
 use crate::error::{DskError, Result};
 use crate::filesystem::FileSystemType;
@@ -22,7 +22,7 @@ use std::path::Path;
 /// Main DSK image container
 #[derive(Debug, Clone)]
 pub struct DiskImage {
-    /// DSK format type (Standard, Extended, or RawMgt)
+    /// Image format type (Standard, Extended, RawMgt, Sad, ...)
     pub(crate) format: DiskImageFormat,
     /// Format specification
     pub(crate) spec: FormatSpec,
@@ -35,19 +35,52 @@ pub struct DiskImage {
 }
 
 impl DiskImage {
-    /// Open a DSK or MGT file from disk
+    /// Open a DSK, MGT, SAD, TRD, SCL, OPD, HFE, IMD, TD0 or SCP file from disk
     ///
     /// Automatically detects file type based on extension:
     /// - `.mgt` files are read as raw MGT format
+    /// - `.sad` and `.sad.gz` files are read as SAM Coupe SAD format
+    /// - `.trd` and `.scl` files are read as TR-DOS disks
+    /// - `.opd` and `.opu` files are read as Opus Discovery disks
+    /// - `.hfe` files are read as HFE (v1 or v3) bitstreams
+    /// - `.imd` files are read as ImageDisk format
+    /// - `.td0` files are read as Teledisk format
+    /// - `.scp` files are decoded from SuperCard Pro flux
+    /// - `.img`, `.raw` and `.cpm` files need a geometry, see [`DiskImage::open_raw`]
     /// - All other extensions are read as DSK format
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         if crate::io::is_mgt_file(&path) {
             crate::io::read_mgt(path)
+        } else if crate::io::is_sad_file(&path) {
+            crate::io::read_sad(path)
+        } else if crate::io::is_trd_file(&path) {
+            crate::io::read_trd(path)
+        } else if crate::io::is_scl_file(&path) {
+            crate::io::read_scl(path)
+        } else if crate::io::is_opd_file(&path) {
+            crate::io::read_opd(path)
+        } else if crate::io::is_hfe_file(&path) {
+            crate::io::read_hfe(path)
+        } else if crate::io::is_imd_file(&path) {
+            crate::io::read_imd(path)
+        } else if crate::io::is_td0_file(&path) {
+            crate::io::read_td0(path)
+        } else if crate::io::is_scp_file(&path) {
+            crate::io::read_scp(path)
+        } else if crate::io::is_raw_file(&path) {
+            Err(DskError::invalid_format(
+                "Raw images have no geometry, open them with a format specification",
+            ))
         } else {
             crate::io::reader::read_dsk(path)
         }
     }
 
+    /// Open a raw sector dump, laid out as described by `spec`
+    pub fn open_raw<P: AsRef<Path>>(path: P, spec: &FormatSpec) -> Result<Self> {
+        crate::io::read_raw(path, spec)
+    }
+
     /// Create a new DSK image with the given specification
     pub fn create(spec: FormatSpec) -> Result<Self> {
         DiskImageBuilder::new().spec(spec).build()
@@ -67,11 +100,18 @@ impl DiskImage {
     ///
     /// This checks the disk specification to determine the appropriate filesystem:
     /// - Returns `FileSystemType::Mgt` if the spec format is "MGT Sam Coupe"
+    /// - Returns `FileSystemType::Einstein` if the spec format is "Tatung Einstein"
     /// - Otherwise falls back to the image format's default filesystem
     pub fn default_filesystem(&self) -> FileSystemType {
         let spec = DiskSpecification::identify(self);
         if spec.format == "MGT Sam Coupe" {
             FileSystemType::Mgt
+        } else if spec.format == "Tatung Einstein" {
+            FileSystemType::Einstein
+        } else if crate::filesystem::TrDosFileSystem::is_trdos(self) {
+            FileSystemType::TrDos
+        } else if crate::filesystem::FatFileSystem::is_fat(self) {
+            FileSystemType::Fat
         } else {
             self.format.default_filesystem()
         }
@@ -114,7 +154,7 @@ impl DiskImage {
         self.disks.len()
     }
 
-    /// Read sector data
+    /// Read sector data (the first copy for weak sectors)
     pub fn read_sector(&self, side: u8, track: u8, sector_id: u8) -> Result<&[u8]> {
         let disk = self.get_disk(side).ok_or(DskError::InvalidTrack {
             side,
@@ -136,7 +176,37 @@ impl DiskImage {
                 id: sector_id,
             })?;
 
-        Ok(sector.data())
+        Ok(sector.copy(0).unwrap_or_default())
+    }
+
+    /// Read sector data the way the FDC would
+    ///
+    /// Repeated reads of a weak sector rotate through its stored copies.
+    pub fn fdc_read_sector(&mut self, side: u8, track: u8, sector_id: u8) -> Result<&[u8]> {
+        let max_side = self.spec.num_sides.saturating_sub(1);
+        let max_track = self.spec.num_tracks.saturating_sub(1);
+
+        let disk = self.get_disk_mut(side).ok_or(DskError::InvalidTrack {
+            side,
+            track,
+            max: max_side,
+        })?;
+
+        let track_obj = disk.get_track_mut(track).ok_or(DskError::InvalidTrack {
+            side,
+            track,
+            max: max_track,
+        })?;
+
+        let sector = track_obj
+            .get_sector_mut(sector_id)
+            .ok_or(DskError::InvalidSector {
+                side,
+                track,
+                id: sector_id,
+            })?;
+
+        Ok(sector.next_copy())
     }
 
     /// Write sector data
@@ -176,8 +246,42 @@ impl DiskImage {
     }
 
     /// Save the DSK image to a file
+    ///
+    /// Saving to a `.hfe` path writes an HFE image (v3 if the image was
+    /// loaded from one, otherwise v1), to a `.imd` path an ImageDisk
+    /// image, to a `.img`, `.raw` or `.cpm` path a raw sector dump laid
+    /// out by the image's format specification, to a `.sad` (or
+    /// compressed `.sad.gz`) path a SAM Coupe SAD image, to a `.trd` or
+    /// `.scl` path a TR-DOS image, to a `.opd` or `.opu` path an Opus
+    /// Discovery image and to a `.mgt` path a raw MGT image. A raw MGT
+    /// image stays in MGT layout when saved to a raw dump path. Other paths
+    /// use the image's format, saving as Extended DSK for formats only
+    /// written to their own extension.
     pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        crate::io::writer::write_dsk(self, path)?;
+        if crate::io::is_mgt_file(&path) {
+            crate::io::write_mgt(self, path)?;
+        } else if crate::io::is_hfe_file(&path) {
+            let format = if self.format == DiskImageFormat::HfeV3 {
+                DiskImageFormat::HfeV3
+            } else {
+                DiskImageFormat::HfeV1
+            };
+            crate::io::write_hfe(self, path, format)?;
+        } else if crate::io::is_imd_file(&path) {
+            crate::io::write_imd(self, path)?;
+        } else if crate::io::is_raw_file(&path) && self.format != DiskImageFormat::RawMgt {
+            crate::io::write_raw(self, path, &self.spec)?;
+        } else if crate::io::is_sad_file(&path) {
+            crate::io::write_sad(self, path)?;
+        } else if crate::io::is_trd_file(&path) {
+            crate::io::write_trd(self, path)?;
+        } else if crate::io::is_scl_file(&path) {
+            crate::io::write_scl(self, path)?;
+        } else if crate::io::is_opd_file(&path) {
+            crate::io::write_opd(self, path)?;
+        } else {
+            crate::io::writer::write_dsk(self, path)?;
+        }
         self.changed = false;
         Ok(())
     }
@@ -354,4 +458,46 @@ mod tests {
         assert_eq!(image.total_capacity(), 2 * 40 * 9 * 512);
         assert_eq!(image.total_capacity() / 1024, 360);
     }
+
+    #[test]
+    fn test_save_converts_to_extended_dsk() {
+        let mut image = DiskImage::create(FormatSpec::spectrum_plus3()).unwrap();
+        image.write_sector(0, 1, 2, &[0x5A; 512]).unwrap();
+
+        let base = std::env::temp_dir().join(format!("dsk_convert_{}", std::process::id()));
+        let imd = base.with_extension("imd");
+        let dsk = base.with_extension("dsk");
+        image.save(&imd).unwrap();
+        let mut loaded = DiskImage::open(&imd).unwrap();
+        assert_eq!(loaded.format(), DiskImageFormat::Imd);
+        loaded.save(&dsk).unwrap();
+
+        let mut magic = [0u8; 8];
+        std::io::Read::read_exact(&mut std::fs::File::open(&dsk).unwrap(), &mut magic).unwrap();
+        let converted = DiskImage::open(&dsk).unwrap();
+        std::fs::remove_file(&imd).unwrap();
+        std::fs::remove_file(&dsk).unwrap();
+
+        assert_eq!(&magic, b"EXTENDED");
+        assert_eq!(converted.format(), DiskImageFormat::ExtendedDSK);
+        assert_eq!(converted.read_sector(0, 1, 2).unwrap(), &[0x5A; 512][..]);
+    }
+
+    #[test]
+    fn test_save_keeps_raw_mgt() {
+        let mut image = crate::filesystem::MgtFileSystem::blank_image().unwrap();
+        image.write_sector(1, 3, 4, &[0xA5; 512]).unwrap();
+
+        let base = std::env::temp_dir().join(format!("dsk_keep_mgt_{}", std::process::id()));
+        for ext in ["dsk", "img"] {
+            let path = base.with_extension(ext);
+            image.save(&path).unwrap();
+            let size = std::fs::metadata(&path).unwrap().len();
+            let loaded = crate::io::read_mgt(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(size, 819_200);
+            assert_eq!(loaded.read_sector(1, 3, 4).unwrap(), &[0xA5; 512][..]);
+        }
+    }
 }
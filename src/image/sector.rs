@@ -1,7 +1,8 @@
 /// Sector data structures
 
 use crate::fdc::{FdcStatus1, FdcStatus2};
-use crate::format::constants::fdc_size_to_bytes;
+use crate::error::{DskError, Result};
+use crate::format::constants::{fdc_size_to_bytes, fdc_size_to_stored_bytes};
 
 /// Sector ID (CHRN) - addressing information for a sector
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -68,8 +69,12 @@ pub struct Sector {
     pub fdc_status2: FdcStatus2,
     /// Actual data length (may differ from advertised size in extended format)
     pub data_length: u16,
-    /// Sector data
+    /// Offset of the sector header from the index in bytes (Offset-Info V5 extension)
+    pub offset: Option<u16>,
+    /// Sector data (every copy back to back for a weak sector)
     data: Vec<u8>,
+    /// Copy returned by the next FDC read of a weak sector
+    read_index: usize,
 }
 
 impl Sector {
@@ -81,19 +86,23 @@ impl Sector {
             fdc_status1: FdcStatus1::new(0),
             fdc_status2: FdcStatus2::new(0),
             data_length: size as u16,
+            offset: None,
             data: vec![0xE5; size], // Default CP/M filler byte
+            read_index: 0,
         }
     }
 
     /// Create a new sector with specific data
     pub fn with_data(id: SectorId, data: Vec<u8>) -> Self {
-        let data_length = data.len() as u16;
+        let data_length = length_field(data.len());
         Self {
             id,
             fdc_status1: FdcStatus1::new(0),
             fdc_status2: FdcStatus2::new(0),
             data_length,
+            offset: None,
             data,
+            read_index: 0,
         }
     }
 
@@ -104,13 +113,15 @@ impl Sector {
         fdc_status2: FdcStatus2,
         data: Vec<u8>,
     ) -> Self {
-        let data_length = data.len() as u16;
+        let data_length = length_field(data.len());
         Self {
             id,
             fdc_status1,
             fdc_status2,
             data_length,
+            offset: None,
             data,
+            read_index: 0,
         }
     }
 
@@ -126,8 +137,84 @@ impl Sector {
 
     /// Set the sector data
     pub fn set_data(&mut self, data: Vec<u8>) {
-        self.data_length = data.len() as u16;
+        self.data_length = length_field(data.len());
         self.data = data;
+        self.read_index = 0;
+    }
+
+    /// Number of data copies held for this sector
+    ///
+    /// Extended DSK stores several reads of a weak (random) sector back to
+    /// back, so stored data that is a whole multiple of the advertised size
+    /// holds one copy per multiple.
+    pub fn copy_count(&self) -> usize {
+        let size = self.advertised_size();
+        if size > 0 && self.data.len() > size && self.data.len().is_multiple_of(size) {
+            self.data.len() / size
+        } else {
+            1
+        }
+    }
+
+    /// Check if this sector holds multiple copies of weak data
+    pub fn is_weak(&self) -> bool {
+        self.copy_count() > 1
+    }
+
+    /// Get each copy of the sector data (a single copy for normal sectors)
+    pub fn copies(&self) -> std::slice::Chunks<'_, u8> {
+        let size = self.data.len() / self.copy_count();
+        self.data.chunks(size.max(1))
+    }
+
+    /// Get one copy of the sector data
+    pub fn copy(&self, index: usize) -> Option<&[u8]> {
+        self.copies().nth(index)
+    }
+
+    /// Get the copy the FDC would return for the next read
+    ///
+    /// Successive calls rotate through the copies of a weak sector, the way
+    /// repeated reads on real hardware return differing data.
+    pub fn next_copy(&mut self) -> &[u8] {
+        let count = self.copy_count();
+        let index = self.read_index % count;
+        self.read_index = (index + 1) % count;
+
+        let size = self.data.len() / count;
+        &self.data[index * size..(index + 1) * size]
+    }
+
+    /// Replace the sector data with copies of weak data
+    ///
+    /// Every copy must be the advertised sector size.
+    pub fn set_copies(&mut self, copies: &[&[u8]]) -> Result<()> {
+        let size = self.advertised_size();
+        if copies.is_empty() {
+            return Err(DskError::invalid_format("Weak sector needs at least one copy"));
+        }
+        if let Some(copy) = copies.iter().find(|c| c.len() != size) {
+            return Err(DskError::invalid_format(format!(
+                "Weak sector copy is {} bytes, expected {}",
+                copy.len(),
+                size
+            )));
+        }
+
+        self.set_data(copies.concat());
+        Ok(())
+    }
+
+    /// Number of bytes stored for this sector in a DSK image
+    ///
+    /// Weak sectors keep every copy, other sectors are limited by the DSK
+    /// stored size rules for their size code.
+    pub fn stored_size(&self) -> usize {
+        if self.is_weak() {
+            self.data.len()
+        } else {
+            self.actual_size().min(fdc_size_to_stored_bytes(self.id.size_code))
+        }
     }
 
     /// Check if this sector has any FDC errors
@@ -184,10 +271,16 @@ impl Sector {
     /// Resize the sector data
     pub fn resize(&mut self, new_size: usize, fill_byte: u8) {
         self.data.resize(new_size, fill_byte);
-        self.data_length = new_size as u16;
+        self.data_length = length_field(new_size);
     }
 }
 
+/// Length for the 16 bit `data_length` field, which saturates for weak
+/// sectors with more copies than it can count
+fn length_field(len: usize) -> u16 {
+    u16::try_from(len).unwrap_or(u16::MAX)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,4 +378,30 @@ mod tests {
         sector.fdc_status2 = FdcStatus2::new(FdcStatus2::CM);
         assert!(sector.is_deleted());
     }
+
+    #[test]
+    fn test_weak_sector_copies() {
+        let id = SectorId::new(0, 0, 1, 2);
+        let data: Vec<u8> = (0..3).flat_map(|c| vec![c as u8; 512]).collect();
+        let mut sector = Sector::with_data(id, data);
+
+        assert!(sector.is_weak());
+        assert_eq!(sector.copy_count(), 3);
+        assert_eq!(sector.copies().len(), 3);
+        assert_eq!(sector.copy(1), Some(&[1u8; 512][..]));
+        assert_eq!(sector.copy(3), None);
+        assert_eq!(sector.stored_size(), 1536);
+
+        let reads: Vec<u8> = (0..4).map(|_| sector.next_copy()[0]).collect();
+        assert_eq!(reads, vec![0, 1, 2, 0]);
+
+        sector.set_copies(&[&[7u8; 512], &[8u8; 512]]).unwrap();
+        assert_eq!(sector.copy_count(), 2);
+        assert_eq!(sector.next_copy()[0], 7);
+        assert!(sector.set_copies(&[&[7u8; 256]]).is_err());
+
+        let plain = Sector::new(id);
+        assert!(!plain.is_weak());
+        assert_eq!(plain.copies().collect::<Vec<_>>(), vec![plain.data()]);
+    }
 }
@@ -69,6 +69,18 @@ impl From<DataRate> for u8 {
     }
 }
 
+/// Position of a sector header on a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectorPosition {
+    /// Sector ID (R)
+    pub sector_id: u8,
+    /// Offset of the sector header from the index in bytes
+    pub offset: u16,
+    /// Bytes from the previous sector header, wrapping round the index for
+    /// the first sector when the track length is known
+    pub distance: Option<u16>,
+}
+
 /// A disk track containing multiple sectors
 #[derive(Debug, Clone)]
 pub struct Track {
@@ -84,6 +96,8 @@ pub struct Track {
     pub data_rate: DataRate,
     /// Recording mode (V5 extension)
     pub recording_mode: RecordingMode,
+    /// Track length in bytes from the index (Offset-Info V5 extension)
+    pub track_length: Option<u16>,
     /// Sectors in this track
     sectors: Vec<Sector>,
     /// Map from sector ID to index in sectors vector for fast lookup
@@ -100,6 +114,7 @@ impl Track {
             filler_byte: 0xE5,
             data_rate: DataRate::Unknown,
             recording_mode: RecordingMode::Unknown,
+            track_length: None,
             sectors: Vec::new(),
             sector_map: HashMap::new(),
         }
@@ -199,17 +214,70 @@ impl Track {
         self.sector_map.contains_key(&sector_id)
     }
 
+    /// Re-order the sectors physically for an interleave factor
+    ///
+    /// Sectors in ascending ID order are treated as the logical sequence and
+    /// placed `interleave` positions apart, with `offset` rotating the whole
+    /// layout for track skew. Sector contents and IDs are not changed.
+    pub fn reinterleave(&mut self, interleave: u8, offset: usize) {
+        let mut logical: Vec<Sector> = std::mem::take(&mut self.sectors);
+        logical.sort_by_key(|s| s.id.sector);
+
+        let order = interleave_order(logical.len(), interleave, offset);
+        let mut slots: Vec<Option<Sector>> = logical.into_iter().map(Some).collect();
+
+        // Recorded header positions no longer match the new layout
+        self.sector_map.clear();
+        for index in order {
+            if let Some(mut sector) = slots[index].take() {
+                sector.offset = None;
+                self.add_sector(sector);
+            }
+        }
+    }
+
+    /// Check if any sector positions are known for this track
+    pub fn has_positions(&self) -> bool {
+        self.track_length.is_some() || self.sectors.iter().any(|s| s.offset.is_some())
+    }
+
+    /// Get the header position of each sector with a known offset, in track order
+    pub fn sector_positions(&self) -> Vec<SectorPosition> {
+        let known: Vec<(u8, u16)> = self
+            .sectors
+            .iter()
+            .filter_map(|s| s.offset.map(|offset| (s.id.sector, offset)))
+            .collect();
+
+        known
+            .iter()
+            .enumerate()
+            .map(|(i, &(sector_id, offset))| {
+                let distance = if i > 0 {
+                    Some(offset.wrapping_sub(known[i - 1].1))
+                } else {
+                    let last = known[known.len() - 1].1;
+                    self.track_length
+                        .filter(|&length| length > 0)
+                        .map(|length| length.wrapping_sub(last).wrapping_add(offset))
+                };
+                SectorPosition { sector_id, offset, distance }
+            })
+            .collect()
+    }
+
     /// Read sectors in logical order (sorted by sector ID)
     ///
     /// Appends sector data to the provided buffer, reading sectors
     /// in ascending order by their sector ID (as a real FDC would).
+    /// Weak sectors contribute their first copy.
     pub fn read_logical(&self, data: &mut Vec<u8>) {
         let mut sector_ids: Vec<u8> = self.sectors.iter().map(|s| s.id.sector).collect();
         sector_ids.sort();
 
         for sector_id in sector_ids {
             if let Some(sector) = self.get_sector(sector_id) {
-                data.extend_from_slice(sector.data());
+                data.extend_from_slice(sector.copy(0).unwrap_or_default());
             }
         }
     }
@@ -225,11 +293,61 @@ impl Track {
     }
 }
 
+/// Physical layout of `count` logical sectors for an interleave factor
+///
+/// Returns the logical index held at each physical position. Each sector goes
+/// `interleave` positions after the previous one, moving on to the next free
+/// position when that is taken, starting from position `offset`.
+pub(crate) fn interleave_order(count: usize, interleave: u8, offset: usize) -> Vec<usize> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let step = interleave.max(1) as usize;
+    let mut slots: Vec<Option<usize>> = vec![None; count];
+    let mut position = offset % count;
+
+    for logical in 0..count {
+        while slots[position].is_some() {
+            position = (position + 1) % count;
+        }
+        slots[position] = Some(logical);
+        position = (position + step) % count;
+    }
+
+    slots.into_iter().flatten().collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::image::sector::SectorId;
 
+    #[test]
+    fn test_interleave_order() {
+        assert_eq!(interleave_order(9, 1, 0), vec![0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(interleave_order(9, 2, 0), vec![0, 5, 1, 6, 2, 7, 3, 8, 4]);
+        assert_eq!(interleave_order(10, 2, 0), vec![0, 5, 1, 6, 2, 7, 3, 8, 4, 9]);
+        assert_eq!(interleave_order(9, 1, 2), vec![7, 8, 0, 1, 2, 3, 4, 5, 6]);
+        assert!(interleave_order(0, 2, 0).is_empty());
+    }
+
+    #[test]
+    fn test_reinterleave() {
+        let mut track = Track::new(0, 0);
+        for id in 1..=9 {
+            track.add_sector(Sector::with_data(SectorId::new(0, 0, id, 2), vec![id; 512]));
+        }
+
+        track.reinterleave(3, 0);
+        assert_eq!(track.sector_ids(), vec![1, 4, 7, 2, 5, 8, 3, 6, 9]);
+        assert_eq!(track.get_sector(5).unwrap().data(), &[5; 512]);
+        assert_eq!(track.read_logical_to_vec().len(), 9 * 512);
+
+        track.reinterleave(1, 0);
+        assert_eq!(track.sector_ids(), (1..=9).collect::<Vec<u8>>());
+    }
+
     #[test]
     fn test_new_track() {
         let track = Track::new(0, 0);
@@ -0,0 +1,620 @@
+/// MFM and FM track bitstreams
+///
+/// A track is encoded into the stream of bit cells a drive reads, laid out
+/// IBM System/34 style for MFM and IBM 3740 style for FM, with the gaps,
+/// sync runs, address marks and CRCs the FDC expects. Decoding scans such a
+/// stream for address marks and rebuilds the sectors.
+///
+/// FM is written at the MFM cell rate, each FM cell taking two stream bits,
+/// so tracks of both kinds share one nominal length.
+
+use crate::fdc::{FdcStatus1, FdcStatus2};
+use crate::format::constants::fdc_size_to_bytes;
+use crate::image::{RecordingMode, Sector, SectorId, Track};
+
+/// Stream bits in a 300rpm double density track (250kbps MFM)
+pub(crate) const DD_TRACK_BITS: usize = 100_000;
+
+/// Stream bits in a 300rpm high density track (500kbps MFM)
+pub(crate) const HD_TRACK_BITS: usize = 200_000;
+
+/// MFM A1 sync mark with missing clock
+const MFM_SYNC_A1: u16 = 0x4489;
+/// MFM C2 index sync mark with missing clock
+const MFM_SYNC_C2: u16 = 0x5224;
+/// MFM gap filler
+const MFM_GAP: u8 = 0x4E;
+/// FM gap filler
+const FM_GAP: u8 = 0xFF;
+/// Index address mark
+const INDEX_MARK: u8 = 0xFC;
+/// ID address mark
+const ID_MARK: u8 = 0xFE;
+/// Data address mark
+const DATA_MARK: u8 = 0xFB;
+/// Deleted data address mark
+const DELETED_DATA_MARK: u8 = 0xF8;
+/// FM clock pattern for ID and data address marks
+const FM_MARK_CLOCK: u8 = 0xC7;
+/// FM clock pattern for the index address mark
+const FM_INDEX_CLOCK: u8 = 0xD7;
+/// Furthest a data mark may follow its ID field, in bytes
+const MAX_ID_TO_DATA: usize = 64;
+
+/// Sequence of bit cells, earliest first
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BitStream {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitStream {
+    /// Create an empty stream
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a stream of `len` empty cells (no flux transitions)
+    pub(crate) fn blank(len: usize) -> Self {
+        Self {
+            bytes: vec![0; len.div_ceil(8)],
+            len,
+        }
+    }
+
+    /// Create a stream from bytes holding cells least significant bit first
+    pub(crate) fn from_lsb_bytes(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.iter().map(|b| b.reverse_bits()).collect(),
+            len: bytes.len() * 8,
+        }
+    }
+
+    /// Get the stream as bytes holding cells least significant bit first
+    pub(crate) fn to_lsb_bytes(&self) -> Vec<u8> {
+        self.bytes.iter().map(|b| b.reverse_bits()).collect()
+    }
+
+    /// Append a cell
+    pub(crate) fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            self.bytes[self.len / 8] |= 0x80 >> (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// Get the cell at `index`
+    pub(crate) fn get(&self, index: usize) -> bool {
+        self.bytes[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    /// Number of cells in the stream
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Shorten the stream to `len` cells
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.len = len;
+            self.bytes.truncate(len.div_ceil(8));
+            if let Some(last) = self.bytes.last_mut() {
+                if !len.is_multiple_of(8) {
+                    *last &= 0xFF << (8 - len % 8);
+                }
+            }
+        }
+    }
+}
+
+/// CRC-CCITT as used by the FDC for ID and data fields
+pub(crate) fn crc16(crc: u16, data: &[u8]) -> u16 {
+    data.iter().fold(crc, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// CRC of a field starting with address mark `mark`
+///
+/// MFM fields also cover the three A1 sync bytes ahead of the mark.
+fn field_crc(fm: bool, mark: u8, data: &[u8]) -> u16 {
+    let crc = if fm { 0xFFFF } else { crc16(0xFFFF, &[0xA1, 0xA1, 0xA1]) };
+    crc16(crc16(crc, &[mark]), data)
+}
+
+/// Cell writer for one track
+struct Encoder {
+    stream: BitStream,
+    fm: bool,
+    last_bit: bool,
+}
+
+impl Encoder {
+    fn cell(&mut self, bit: bool) {
+        self.stream.push(bit);
+        if self.fm {
+            self.stream.push(false);
+        }
+    }
+
+    fn byte(&mut self, value: u8) {
+        for i in (0..8).rev() {
+            let bit = value & (1 << i) != 0;
+            let clock = self.fm || (!self.last_bit && !bit);
+            self.cell(clock);
+            self.cell(bit);
+            self.last_bit = bit;
+        }
+    }
+
+    fn repeat(&mut self, value: u8, count: usize) {
+        for _ in 0..count {
+            self.byte(value);
+        }
+    }
+
+    fn bytes(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.byte(byte);
+        }
+    }
+
+    /// Raw MFM mark with a missing clock bit
+    fn mfm_sync(&mut self, raw: u16) {
+        for i in (0..16).rev() {
+            self.cell(raw & (1 << i) != 0);
+        }
+        self.last_bit = raw & 1 != 0;
+    }
+
+    /// FM mark byte with its own clock pattern
+    fn fm_mark(&mut self, value: u8, clock: u8) {
+        for i in (0..8).rev() {
+            self.cell(clock & (1 << i) != 0);
+            self.cell(value & (1 << i) != 0);
+        }
+        self.last_bit = value & 1 != 0;
+    }
+
+    /// Sync run followed by an ID or data address mark
+    fn address_mark(&mut self, mark: u8) {
+        if self.fm {
+            self.repeat(0x00, 6);
+            self.fm_mark(mark, FM_MARK_CLOCK);
+        } else {
+            self.repeat(0x00, 12);
+            for _ in 0..3 {
+                self.mfm_sync(MFM_SYNC_A1);
+            }
+            self.byte(mark);
+        }
+    }
+
+    /// Field contents followed by its CRC, deliberately broken for `crc_error`
+    fn field(&mut self, mark: u8, data: &[u8], crc_error: bool) {
+        let mut crc = field_crc(self.fm, mark, data);
+        if crc_error {
+            crc ^= 0xFFFF;
+        }
+        self.bytes(data);
+        self.bytes(&crc.to_be_bytes());
+    }
+}
+
+/// Encode a track into a stream of at least `length` cells
+///
+/// The end of the track is padded with gap filler up to `length`; a track
+/// that needs more room comes back longer. Sectors carry their FDC status
+/// through: CRC errors in ST1/ST2 give bad ID or data CRCs, deleted data
+/// uses the deleted data mark and a missing data mark omits the data field.
+/// Weak sectors are written with their first copy. Tracks with no sectors
+/// are left unformatted.
+pub(crate) fn encode_track(track: &Track, length: usize) -> BitStream {
+    if track.is_empty() {
+        return BitStream::blank(length);
+    }
+
+    let fm = track.recording_mode == RecordingMode::FM;
+    let gap = if fm { FM_GAP } else { MFM_GAP };
+    let mut enc = Encoder {
+        stream: BitStream::new(),
+        fm,
+        last_bit: false,
+    };
+
+    // Gap 4a, index mark and gap 1
+    if fm {
+        enc.repeat(gap, 40);
+        enc.repeat(0x00, 6);
+        enc.fm_mark(INDEX_MARK, FM_INDEX_CLOCK);
+        enc.repeat(gap, 26);
+    } else {
+        enc.repeat(gap, 80);
+        enc.repeat(0x00, 12);
+        for _ in 0..3 {
+            enc.mfm_sync(MFM_SYNC_C2);
+        }
+        enc.byte(INDEX_MARK);
+        enc.repeat(gap, 50);
+    }
+
+    for sector in track.sectors() {
+        let data_crc_error = sector.fdc_status2.data_field_error();
+        let id_crc_error = sector.fdc_status1.data_error() && !data_crc_error;
+
+        let id = [sector.id.track, sector.id.side, sector.id.sector, sector.id.size_code];
+        enc.address_mark(ID_MARK);
+        enc.field(ID_MARK, &id, id_crc_error);
+        enc.repeat(gap, if fm { 11 } else { 22 });
+
+        if !sector.fdc_status2.missing_data_mark() {
+            let mark = if sector.is_deleted() { DELETED_DATA_MARK } else { DATA_MARK };
+            let mut data = sector.copy(0).unwrap_or_default().to_vec();
+            data.resize(sector.advertised_size(), track.filler_byte);
+
+            enc.address_mark(mark);
+            enc.field(mark, &data, data_crc_error);
+            enc.repeat(gap, track.gap3_length as usize);
+        }
+    }
+
+    // Gap 4b up to the end of the track
+    let natural = enc.stream.len();
+    while enc.stream.len() < length {
+        enc.byte(gap);
+    }
+    enc.stream.truncate(length.max(natural));
+    enc.stream
+}
+
+/// Address mark found in a stream
+struct Mark {
+    /// Mark byte
+    value: u8,
+    /// Cell where the mark (MFM: its first A1 sync) starts
+    start: usize,
+    /// Cell where the field after the mark starts
+    field: usize,
+}
+
+/// Expand an FM mark and clock into its 32 stream cells
+fn fm_pattern(value: u8, clock: u8) -> u32 {
+    let mut pattern = 0u32;
+    for i in (0..8).rev() {
+        let clock_cell = ((clock >> i) & 1) as u32;
+        let data_cell = ((value >> i) & 1) as u32;
+        pattern = (pattern << 4) | (clock_cell << 3) | (data_cell << 1);
+    }
+    pattern
+}
+
+/// Read a decoded byte starting at cell `pos`
+fn read_byte(stream: &BitStream, pos: usize, fm: bool) -> Option<u8> {
+    let (cells, first, step) = if fm { (32, 2, 4) } else { (16, 1, 2) };
+    if pos + cells > stream.len() {
+        return None;
+    }
+    Some((0..8).fold(0u8, |byte, i| (byte << 1) | stream.get(pos + first + i * step) as u8))
+}
+
+/// Read up to `count` decoded bytes starting at cell `pos`
+fn read_bytes(stream: &BitStream, pos: usize, count: usize, fm: bool) -> Vec<u8> {
+    let cells = if fm { 32 } else { 16 };
+    (0..count)
+        .map_while(|i| read_byte(stream, pos + i * cells, fm))
+        .collect()
+}
+
+/// Raw 16 cells starting at `pos`
+fn raw_word(stream: &BitStream, pos: usize) -> Option<u16> {
+    if pos + 16 > stream.len() {
+        return None;
+    }
+    Some((0..16).fold(0u16, |word, i| (word << 1) | stream.get(pos + i) as u16))
+}
+
+/// Find the ID and data address marks in a stream
+fn find_marks(stream: &BitStream, fm: bool) -> Vec<Mark> {
+    let mut marks = Vec::new();
+    let fm_marks: Vec<(u32, u8)> = [ID_MARK, DATA_MARK, 0xFA, 0xF9, DELETED_DATA_MARK]
+        .iter()
+        .map(|&m| (fm_pattern(m, FM_MARK_CLOCK), m))
+        .collect();
+
+    let mut reg = 0u32;
+    let mut i = 0;
+    while i < stream.len() {
+        reg = (reg << 1) | stream.get(i) as u32;
+        i += 1;
+
+        if fm {
+            if let Some(&(_, value)) = fm_marks.iter().find(|(p, _)| *p == reg) {
+                marks.push(Mark {
+                    value,
+                    start: i - 32,
+                    field: i,
+                });
+                reg = 0;
+            }
+        } else if reg as u16 == MFM_SYNC_A1 {
+            let start = i - 16;
+            let mut pos = i;
+            while raw_word(stream, pos) == Some(MFM_SYNC_A1) {
+                pos += 16;
+            }
+            if let Some(value) = read_byte(stream, pos, false) {
+                if matches!(value, ID_MARK | 0xF8..=0xFB) {
+                    marks.push(Mark {
+                        value,
+                        start,
+                        field: pos + 16,
+                    });
+                }
+            }
+            i = pos;
+            reg = 0;
+        }
+    }
+
+    marks
+}
+
+/// ID field waiting for its data field
+struct PendingId {
+    id: SectorId,
+    crc_ok: bool,
+    start: usize,
+    end: usize,
+}
+
+/// Sector for an ID field with no data field
+fn sector_without_data(pending: &PendingId, cells: usize) -> Sector {
+    let st1 = FdcStatus1::MA | if pending.crc_ok { 0 } else { FdcStatus1::DE };
+    let mut sector = Sector::with_status(
+        pending.id,
+        FdcStatus1::new(st1),
+        FdcStatus2::new(FdcStatus2::MD),
+        Vec::new(),
+    );
+    sector.offset = Some((pending.start / cells) as u16);
+    sector
+}
+
+/// Decode a track stream as MFM or FM
+fn decode_with(stream: &BitStream, track_num: u8, side: u8, fm: bool) -> Track {
+    let cells = if fm { 32 } else { 16 };
+    let sync_len = if fm { 6 } else { 12 };
+
+    let mut track = Track::new(track_num, side);
+    track.recording_mode = if fm { RecordingMode::FM } else { RecordingMode::MFM };
+    track.track_length = Some((stream.len() / cells).min(u16::MAX as usize) as u16);
+
+    let mut pending: Option<PendingId> = None;
+    let mut last_data_end: Option<usize> = None;
+    let mut gap3: Option<u8> = None;
+
+    for mark in find_marks(stream, fm) {
+        if mark.value == ID_MARK {
+            if let Some(p) = pending.take() {
+                track.add_sector(sector_without_data(&p, cells));
+            }
+
+            let field = read_bytes(stream, mark.field, 6, fm);
+            if field.len() < 6 {
+                continue;
+            }
+            let crc = u16::from_be_bytes([field[4], field[5]]);
+
+            if gap3.is_none() {
+                if let Some(end) = last_data_end {
+                    let between = mark.start.saturating_sub(end) / cells;
+                    gap3 = between
+                        .checked_sub(sync_len)
+                        .and_then(|g| u8::try_from(g).ok());
+                }
+            }
+
+            pending = Some(PendingId {
+                id: SectorId::new(field[0], field[1], field[2], field[3]),
+                crc_ok: field_crc(fm, ID_MARK, &field[..4]) == crc,
+                start: mark.start,
+                end: mark.field + 6 * cells,
+            });
+            continue;
+        }
+
+        let Some(p) = pending.take() else {
+            continue;
+        };
+        if mark.start.saturating_sub(p.end) / cells > MAX_ID_TO_DATA {
+            track.add_sector(sector_without_data(&p, cells));
+            continue;
+        }
+
+        let size = fdc_size_to_bytes(p.id.size_code);
+        let mut field = read_bytes(stream, mark.field, size + 2, fm);
+        let complete = field.len() == size + 2;
+        let data_ok = complete && {
+            let crc = u16::from_be_bytes([field[size], field[size + 1]]);
+            field_crc(fm, mark.value, &field[..size]) == crc
+        };
+        last_data_end = Some(mark.field + field.len() * cells);
+        field.truncate(size);
+
+        let deleted = matches!(mark.value, 0xF8 | 0xF9);
+        let st1 = if p.crc_ok && data_ok { 0 } else { FdcStatus1::DE };
+        let st2 = if deleted { FdcStatus2::CM } else { 0 } | if data_ok { 0 } else { FdcStatus2::DD };
+
+        let mut sector = Sector::with_status(p.id, FdcStatus1::new(st1), FdcStatus2::new(st2), field);
+        sector.offset = Some((p.start / cells) as u16);
+        track.add_sector(sector);
+    }
+
+    if let Some(p) = pending {
+        track.add_sector(sector_without_data(&p, cells));
+    }
+
+    track.gap3_length = gap3.unwrap_or(if fm { 0x1B } else { 0x4E });
+    track
+}
+
+/// Decode a track stream back into sectors
+///
+/// MFM is tried first, then FM when no MFM sectors turn up. Sector offsets
+/// and the track length are recorded in decoded bytes from the index, and
+/// the gap 3 length is measured from the first pair of sectors.
+pub(crate) fn decode_track(stream: &BitStream, track_num: u8, side: u8) -> Track {
+    let track = decode_with(stream, track_num, side, false);
+    if !track.is_empty() {
+        return track;
+    }
+
+    let fm_track = decode_with(stream, track_num, side, true);
+    if fm_track.is_empty() {
+        track
+    } else {
+        fm_track
+    }
+}
+
+/// Decode one revolution from a stream that runs on past the index
+///
+/// The first `revolution` cells are one turn of the disk; cells after them
+/// let sectors that cross the index (such as oversized sectors) be read in
+/// full. Only sectors whose ID starts within the revolution are kept.
+pub(crate) fn decode_revolution(stream: &BitStream, revolution: usize, track_num: u8, side: u8) -> Track {
+    let mut track = decode_track(stream, track_num, side);
+    let cells = if track.recording_mode == RecordingMode::FM { 32 } else { 16 };
+    let length = revolution / cells;
+
+    let sectors = track.sectors().to_vec();
+    track.clear();
+    for sector in sectors {
+        if sector.offset.is_none_or(|o| (o as usize) < length) {
+            track.add_sector(sector);
+        }
+    }
+    track.track_length = Some(length.min(u16::MAX as usize) as u16);
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_track(mode: RecordingMode) -> Track {
+        let mut track = Track::new(3, 0);
+        track.recording_mode = mode;
+        track.gap3_length = 0x2A;
+        for i in 0..4u8 {
+            let data: Vec<u8> = (0..256).map(|b| (b as u8).wrapping_mul(i + 1)).collect();
+            track.add_sector(Sector::with_data(SectorId::new(3, 0, 0xC1 + i, 1), data));
+        }
+        track
+    }
+
+    #[test]
+    fn test_crc16() {
+        // ID field of track 0, side 0, sector 1, 512 bytes
+        assert_eq!(field_crc(false, ID_MARK, &[0, 0, 1, 2]), 0xCA6F);
+        assert_eq!(crc16(0xFFFF, b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn test_bitstream_lsb_bytes() {
+        let stream = BitStream::from_lsb_bytes(&[0x01, 0x80]);
+        assert!(stream.get(0));
+        assert!(stream.get(15));
+        assert_eq!(stream.len(), 16);
+        assert_eq!(stream.to_lsb_bytes(), vec![0x01, 0x80]);
+    }
+
+    #[test]
+    fn test_mfm_round_trip() {
+        let track = sample_track(RecordingMode::MFM);
+        let stream = encode_track(&track, DD_TRACK_BITS);
+        assert_eq!(stream.len(), DD_TRACK_BITS);
+
+        let decoded = decode_track(&stream, 3, 0);
+        assert_eq!(decoded.recording_mode, RecordingMode::MFM);
+        assert_eq!(decoded.sector_count(), 4);
+        assert_eq!(decoded.gap3_length, 0x2A);
+        assert_eq!(decoded.track_length, Some(6250));
+        for (original, sector) in track.sectors().iter().zip(decoded.sectors()) {
+            assert_eq!(sector.id, original.id);
+            assert_eq!(sector.data(), original.data());
+            assert!(!sector.has_error());
+        }
+
+        // Gap 4a, sync and index mark, gap 1 then the first ID sync
+        assert_eq!(decoded.sectors()[0].offset, Some(80 + 12 + 4 + 50 + 12));
+    }
+
+    #[test]
+    fn test_fm_round_trip() {
+        let track = sample_track(RecordingMode::FM);
+        let stream = encode_track(&track, DD_TRACK_BITS);
+
+        let decoded = decode_track(&stream, 3, 0);
+        assert_eq!(decoded.recording_mode, RecordingMode::FM);
+        assert_eq!(decoded.sector_count(), 4);
+        assert_eq!(decoded.gap3_length, 0x2A);
+        assert_eq!(decoded.sectors()[2].data(), track.sectors()[2].data());
+    }
+
+    #[test]
+    fn test_status_round_trip() {
+        let mut track = sample_track(RecordingMode::MFM);
+        let sectors = track.sectors_mut();
+        sectors[0].fdc_status2 = FdcStatus2::new(FdcStatus2::CM);
+        sectors[1].fdc_status1 = FdcStatus1::new(FdcStatus1::DE);
+        sectors[1].fdc_status2 = FdcStatus2::new(FdcStatus2::DD);
+        sectors[2].fdc_status1 = FdcStatus1::new(FdcStatus1::DE);
+        sectors[3].fdc_status1 = FdcStatus1::new(FdcStatus1::MA);
+        sectors[3].fdc_status2 = FdcStatus2::new(FdcStatus2::MD);
+
+        let decoded = decode_track(&encode_track(&track, DD_TRACK_BITS), 3, 0);
+        let status: Vec<(u8, u8)> = decoded
+            .sectors()
+            .iter()
+            .map(|s| (s.fdc_status1.0, s.fdc_status2.0))
+            .collect();
+        assert_eq!(
+            status,
+            vec![
+                (0, FdcStatus2::CM),
+                (FdcStatus1::DE, FdcStatus2::DD),
+                (FdcStatus1::DE, 0),
+                (FdcStatus1::MA, FdcStatus2::MD),
+            ]
+        );
+        assert_eq!(decoded.sectors()[1].data(), track.sectors()[1].data());
+        assert!(decoded.sectors()[3].data().is_empty());
+    }
+
+    #[test]
+    fn test_unformatted_track() {
+        let track = Track::new(0, 0);
+        let stream = encode_track(&track, DD_TRACK_BITS);
+        assert_eq!(stream.len(), DD_TRACK_BITS);
+        assert!(decode_track(&stream, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_long_track_grows() {
+        let mut track = Track::new(0, 0);
+        for i in 0..7u8 {
+            track.add_sector(Sector::new(SectorId::new(0, 0, i + 1, 3)));
+        }
+        let stream = encode_track(&track, DD_TRACK_BITS);
+        assert!(stream.len() > DD_TRACK_BITS);
+        assert_eq!(decode_track(&stream, 0, 0).sector_count(), 7);
+    }
+}
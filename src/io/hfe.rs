@@ -0,0 +1,346 @@
+/// HFE file reader and writer
+///
+/// HFE images from the HxC Floppy Emulator (and Gotek drives running its
+/// firmware) hold each track as the raw MFM/FM cell stream:
+/// - 512 byte header starting "HXCPICFE" (v1) or "HXCHFEV3" (v3)
+/// - Track list of 4 byte entries: offset in 512 byte blocks, length in bytes
+/// - Track data in 512 byte blocks, 256 bytes of side 0 then 256 of side 1
+/// - Cells stored least significant bit first
+///
+/// Version 3 tracks may also hold opcodes (0xF0-0xF4 once bit reversed,
+/// so stored as 0x0F, 0x8F, 0x4F, 0xCF and 0x2F) for index, bit rate
+/// changes, skipped bits and random (weak) data. Opcodes are understood
+/// on reading; tracks are written without them.
+
+use crate::error::{DskError, Result};
+use crate::format::DiskImageFormat;
+use crate::image::{DataRate, Disk, DiskImage, Track};
+use crate::io::bitstream::{decode_track, encode_track, BitStream, DD_TRACK_BITS, HD_TRACK_BITS};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// HFE v1 signature
+pub const HFE_V1_SIGNATURE: &[u8] = b"HXCPICFE";
+
+/// HFE v3 signature
+pub const HFE_V3_SIGNATURE: &[u8] = b"HXCHFEV3";
+
+/// Size of an HFE block
+pub const HFE_BLOCK_SIZE: usize = 512;
+
+/// Track encoding for ISO/IBM MFM
+const ISOIBM_MFM_ENCODING: u8 = 0x00;
+/// Track encoding for ISO/IBM FM
+const ISOIBM_FM_ENCODING: u8 = 0x02;
+/// Floppy interface mode for a generic Shugart double density drive
+const GENERIC_SHUGART_DD_MODE: u8 = 0x07;
+/// Longest track side that fits the 16 bit (both sides) track length
+const MAX_SIDE_BYTES: usize = 0x7FFE;
+
+/// v3 opcodes (after bit reversal of the stored byte)
+const OPCODE_NOP: u8 = 0xF0;
+const OPCODE_SETINDEX: u8 = 0xF1;
+const OPCODE_SETBITRATE: u8 = 0xF2;
+const OPCODE_SKIPBITS: u8 = 0xF3;
+const OPCODE_RAND: u8 = 0xF4;
+
+/// Check if a file is likely an HFE file based on extension
+pub fn is_hfe_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("hfe"))
+        .unwrap_or(false)
+}
+
+/// Read an HFE (v1 or v3) file from disk
+pub fn read_hfe<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
+    let filename = path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string());
+
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    if data.len() < HFE_BLOCK_SIZE {
+        return Err(DskError::invalid_format("HFE file too small for header"));
+    }
+
+    let format = if data.starts_with(HFE_V1_SIGNATURE) {
+        DiskImageFormat::HfeV1
+    } else if data.starts_with(HFE_V3_SIGNATURE) {
+        DiskImageFormat::HfeV3
+    } else {
+        return Err(DskError::invalid_format("Missing HFE signature"));
+    };
+
+    let num_tracks = data[9];
+    let num_sides = data[10].clamp(1, 2);
+    let bit_rate = u16::from_le_bytes([data[12], data[13]]);
+    let list_offset = u16::from_le_bytes([data[18], data[19]]) as usize * HFE_BLOCK_SIZE;
+    let data_rate = if bit_rate > 300 { DataRate::High } else { DataRate::SingleDouble };
+
+    let mut disks: Vec<Disk> = (0..num_sides).map(Disk::new).collect();
+
+    for track_num in 0..num_tracks {
+        let entry = list_offset + track_num as usize * 4;
+        if entry + 4 > data.len() {
+            return Err(DskError::parse(entry, "HFE track list truncated"));
+        }
+        let offset = u16::from_le_bytes([data[entry], data[entry + 1]]) as usize * HFE_BLOCK_SIZE;
+        let length = u16::from_le_bytes([data[entry + 2], data[entry + 3]]) as usize;
+
+        for disk in disks.iter_mut() {
+            let side = disk.side_number;
+            let raw = side_bytes(&data, offset, length / 2, side)?;
+            let stream = if format == DiskImageFormat::HfeV3 {
+                v3_stream(&raw)
+            } else {
+                BitStream::from_lsb_bytes(&raw)
+            };
+
+            let mut track = decode_track(&stream, track_num, side);
+            track.data_rate = data_rate;
+            disk.add_track(track);
+        }
+    }
+
+    let spec = crate::io::reader::build_format_spec(&disks, num_sides, num_tracks);
+
+    Ok(DiskImage {
+        format,
+        spec,
+        disks,
+        changed: false,
+        filename,
+    })
+}
+
+/// Collect one side's bytes from a track's interleaved blocks
+fn side_bytes(data: &[u8], offset: usize, length: usize, side: u8) -> Result<Vec<u8>> {
+    let half = HFE_BLOCK_SIZE / 2;
+    let mut bytes = Vec::with_capacity(length);
+
+    for block in 0..length.div_ceil(half) {
+        let start = offset + block * HFE_BLOCK_SIZE + side as usize * half;
+        let count = half.min(length - block * half);
+        let chunk = data
+            .get(start..start + count)
+            .ok_or_else(|| DskError::parse(start, "HFE track data truncated"))?;
+        bytes.extend_from_slice(chunk);
+    }
+
+    Ok(bytes)
+}
+
+/// Build a cell stream from v3 track bytes, acting on any opcodes
+fn v3_stream(raw: &[u8]) -> BitStream {
+    let mut stream = BitStream::new();
+    let mut random = 0x2545_F491u32;
+    let mut bytes = raw.iter();
+
+    let push_bits = |stream: &mut BitStream, byte: u8, from: u32| {
+        for bit in from..8 {
+            stream.push(byte & (1 << bit) != 0);
+        }
+    };
+
+    // Bytes are stored least significant bit first, so opcodes and their
+    // operands only read as such once bit reversed
+    while let Some(&byte) = bytes.next() {
+        match byte.reverse_bits() {
+            OPCODE_NOP | OPCODE_SETINDEX => {}
+            OPCODE_SETBITRATE => {
+                bytes.next();
+            }
+            OPCODE_SKIPBITS => {
+                let skip = bytes.next().map(|b| b.reverse_bits()).unwrap_or(0).min(8) as u32;
+                if let Some(&next) = bytes.next() {
+                    push_bits(&mut stream, next, skip);
+                }
+            }
+            OPCODE_RAND => {
+                random ^= random << 13;
+                random ^= random >> 17;
+                random ^= random << 5;
+                push_bits(&mut stream, random as u8, 0);
+            }
+            _ => push_bits(&mut stream, byte, 0),
+        }
+    }
+
+    stream
+}
+
+/// Write an HFE file, as v3 when `format` is `HfeV3` and v1 otherwise
+pub fn write_hfe<P: AsRef<Path>>(image: &DiskImage, path: P, format: DiskImageFormat) -> Result<()> {
+    let data = build_hfe(image, format)?;
+    let mut file = File::create(path)?;
+    file.write_all(&data)?;
+    Ok(())
+}
+
+/// Build the complete HFE file contents
+///
+/// Fails if a track is too long for the HFE track length field.
+fn build_hfe(image: &DiskImage, format: DiskImageFormat) -> Result<Vec<u8>> {
+    let num_sides = image.disks.len().clamp(1, 2) as u8;
+    let num_tracks = image
+        .disks
+        .iter()
+        .map(|d| d.track_count())
+        .max()
+        .unwrap_or(0)
+        .min(u8::MAX as usize) as u8;
+
+    let tracks = || image.disks.iter().flat_map(|d| d.tracks());
+    let high_density = tracks().any(|t| t.data_rate == DataRate::High);
+    let all_fm = tracks().any(|t| !t.is_empty())
+        && tracks()
+            .filter(|t| !t.is_empty())
+            .all(|t| t.recording_mode == crate::image::RecordingMode::FM);
+    let nominal_bits = if high_density { HD_TRACK_BITS } else { DD_TRACK_BITS };
+
+    // Header block
+    let mut header = vec![0xFFu8; HFE_BLOCK_SIZE];
+    let signature = if format == DiskImageFormat::HfeV3 { HFE_V3_SIGNATURE } else { HFE_V1_SIGNATURE };
+    header[..8].copy_from_slice(signature);
+    header[8] = 0; // Format revision
+    header[9] = num_tracks;
+    header[10] = num_sides;
+    header[11] = if all_fm { ISOIBM_FM_ENCODING } else { ISOIBM_MFM_ENCODING };
+    header[12..14].copy_from_slice(&(if high_density { 500u16 } else { 250u16 }).to_le_bytes());
+    header[14..16].copy_from_slice(&300u16.to_le_bytes());
+    header[16] = GENERIC_SHUGART_DD_MODE;
+    header[17] = 0x01;
+    header[18..20].copy_from_slice(&1u16.to_le_bytes());
+    header[20] = 0xFF; // Write allowed
+    header[21] = 0xFF; // Single step
+    // Bytes 22-25: no alternate track 0 encodings (left 0xFF)
+
+    let list_blocks = (num_tracks as usize * 4).div_ceil(HFE_BLOCK_SIZE).max(1);
+    let mut list = vec![0xFFu8; list_blocks * HFE_BLOCK_SIZE];
+    let mut track_data = Vec::new();
+    let mut block = 1 + list_blocks;
+
+    for track_num in 0..num_tracks {
+        let streams: Vec<BitStream> = (0..2u8)
+            .map(|side| {
+                let blank = Track::new(track_num, side);
+                let track = image
+                    .get_disk(side)
+                    .and_then(|d| d.get_track(track_num))
+                    .unwrap_or(&blank);
+                encode_track(track, nominal_bits)
+            })
+            .collect();
+
+        let side_len = streams
+            .iter()
+            .map(|s| s.len().div_ceil(8))
+            .max()
+            .unwrap_or(0);
+        if side_len > MAX_SIDE_BYTES {
+            return Err(DskError::invalid_format(format!(
+                "Track {} is too long for HFE ({} bytes per side, at most {})",
+                track_num, side_len, MAX_SIDE_BYTES
+            )));
+        }
+
+        let sides: Vec<Vec<u8>> = streams
+            .iter()
+            .map(|s| {
+                let mut bytes = s.to_lsb_bytes();
+                bytes.resize(side_len, 0);
+                bytes
+            })
+            .collect();
+
+        let half = HFE_BLOCK_SIZE / 2;
+        let blocks = side_len.div_ceil(half);
+        let mut buffer = vec![0u8; blocks * HFE_BLOCK_SIZE];
+        for b in 0..blocks {
+            for (side, bytes) in sides.iter().enumerate() {
+                let chunk = &bytes[b * half..side_len.min((b + 1) * half)];
+                let start = b * HFE_BLOCK_SIZE + side * half;
+                buffer[start..start + chunk.len()].copy_from_slice(chunk);
+            }
+        }
+
+        let entry = track_num as usize * 4;
+        list[entry..entry + 2].copy_from_slice(&(block as u16).to_le_bytes());
+        list[entry + 2..entry + 4].copy_from_slice(&((side_len * 2) as u16).to_le_bytes());
+
+        block += blocks;
+        track_data.extend_from_slice(&buffer);
+    }
+
+    let mut out = header;
+    out.extend_from_slice(&list);
+    out.extend_from_slice(&track_data);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::FormatSpec;
+
+    #[test]
+    fn test_is_hfe_file() {
+        assert!(is_hfe_file("game.hfe"));
+        assert!(is_hfe_file("GAME.HFE"));
+        assert!(!is_hfe_file("game.dsk"));
+    }
+
+    #[test]
+    fn test_v3_opcodes() {
+        // As found in a v3 file: data, NOP, SETBITRATE 0x48, SKIPBITS 4,
+        // data, SETINDEX, then a data byte that would be SETINDEX unreversed
+        let raw = [0x01, 0x0F, 0x4F, 0x12, 0xCF, 0x20, 0xF0, 0x8F, 0xF1];
+        let stream = v3_stream(&raw);
+        assert_eq!(stream.len(), 20);
+        assert!(stream.get(0));
+        assert!((1..8).all(|i| !stream.get(i)));
+        assert!((8..12).all(|i| stream.get(i)));
+        assert!(stream.get(12));
+        assert!((13..16).all(|i| !stream.get(i)));
+        assert!((16..20).all(|i| stream.get(i)));
+    }
+
+    #[test]
+    fn test_hfe_round_trip() {
+        let mut image = DiskImage::create(FormatSpec::spectrum_plus3()).unwrap();
+        image.write_sector(0, 2, 3, &[0x5A; 512]).unwrap();
+
+        for format in [DiskImageFormat::HfeV1, DiskImageFormat::HfeV3] {
+            let path = std::env::temp_dir().join(format!("dsk_hfe_{:?}_{}.hfe", format, std::process::id()));
+            write_hfe(&image, &path, format).unwrap();
+            let loaded = DiskImage::open(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(loaded.format(), format);
+            assert_eq!(loaded.disk_count(), 1);
+            assert_eq!(loaded.disks()[0].track_count(), 40);
+            assert_eq!(loaded.read_sector(0, 2, 3).unwrap(), &[0x5A; 512][..]);
+            assert_eq!(loaded.spec().sectors_per_track, 9);
+            assert_eq!(loaded.spec().first_sector_id, 1);
+        }
+    }
+
+    #[test]
+    fn test_hfe_track_too_long() {
+        let mut image = DiskImage::create(FormatSpec::spectrum_plus3()).unwrap();
+        let track = image.get_disk_mut(0).unwrap().get_track_mut(3).unwrap();
+        for id in 10..40 {
+            let mut sector = track.sectors()[0].clone();
+            sector.id.sector = id;
+            track.add_sector(sector);
+        }
+
+        let message = build_hfe(&image, DiskImageFormat::HfeV1).map(|_| ()).unwrap_err().to_string();
+        assert!(message.contains("Track 3"), "{}", message);
+    }
+}
@@ -0,0 +1,486 @@
+/// ImageDisk (IMD) file reader and writer
+///
+/// IMD files from Dave Dunfield's ImageDisk hold:
+/// - An ASCII header "IMD v.vv: dd/mm/yyyy hh:mm:ss" and comment ending 0x1A
+/// - For each track: mode, cylinder, head (with map flags), sector count
+///   and size code, then the sector numbering map, optional cylinder and
+///   head maps, an optional size table (size code 0xFF) and the sector records
+/// - Sector records flag unavailable, compressed (single fill byte),
+///   deleted and data error sectors
+///
+/// The mode is the data rate and FM/MFM recording; 300kbps (a double
+/// density disk read at 360rpm) is treated as 250kbps.
+///
+/// IMD holds a single read of each sector and only a data field error flag,
+/// so weak sector copies beyond the first and ID field CRC errors cannot be
+/// written. [`imd_losses`] lists what an image would lose.
+
+use crate::error::{DskError, Result};
+use crate::fdc::{FdcStatus1, FdcStatus2};
+use crate::format::constants::fdc_size_to_bytes;
+use crate::format::DiskImageFormat;
+use crate::image::{DataRate, Disk, DiskImage, RecordingMode, Sector, SectorId, Track};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// IMD header signature
+pub const IMD_SIGNATURE: &[u8] = b"IMD ";
+
+/// Comment terminator
+const COMMENT_END: u8 = 0x1A;
+/// Head byte flag for a sector cylinder map
+const CYLINDER_MAP_FLAG: u8 = 0x80;
+/// Head byte flag for a sector head map
+const HEAD_MAP_FLAG: u8 = 0x40;
+/// Size code for a per-sector size table
+const SIZE_TABLE: u8 = 0xFF;
+
+/// Check if a file is likely an IMD file based on extension
+pub fn is_imd_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("imd"))
+        .unwrap_or(false)
+}
+
+/// Read an IMD file from disk
+pub fn read_imd<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
+    let filename = path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string());
+
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    let mut image = parse_imd(&data)?;
+    image.filename = filename;
+    Ok(image)
+}
+
+/// Parse the contents of an IMD file
+fn parse_imd(data: &[u8]) -> Result<DiskImage> {
+    if !data.starts_with(IMD_SIGNATURE) {
+        return Err(DskError::invalid_format("Missing IMD signature"));
+    }
+
+    let mut pos = data
+        .iter()
+        .position(|&b| b == COMMENT_END)
+        .ok_or_else(|| DskError::parse(0, "IMD comment not terminated"))?
+        + 1;
+
+    let mut tracks = Vec::new();
+    while pos < data.len() {
+        tracks.push(read_track(data, &mut pos)?);
+    }
+
+    let num_sides = tracks.iter().map(|t| t.side_number + 1).max().unwrap_or(1);
+    let num_tracks = tracks.iter().map(|t| t.track_number as usize + 1).max().unwrap_or(0);
+
+    let mut disks: Vec<Disk> = (0..num_sides).map(Disk::new).collect();
+    for disk in disks.iter_mut() {
+        for track_num in 0..num_tracks {
+            let track = tracks
+                .iter()
+                .find(|t| t.track_number as usize == track_num && t.side_number == disk.side_number)
+                .cloned()
+                .unwrap_or_else(|| Track::new(track_num as u8, disk.side_number));
+            disk.add_track(track);
+        }
+    }
+
+    let num_tracks = num_tracks.min(u8::MAX as usize) as u8;
+    let spec = crate::io::reader::build_format_spec(&disks, num_sides, num_tracks);
+
+    Ok(DiskImage {
+        format: DiskImageFormat::Imd,
+        spec,
+        disks,
+        changed: false,
+        filename: None,
+    })
+}
+
+/// Take `count` bytes at `pos`, moving past them
+fn take<'a>(data: &'a [u8], pos: &mut usize, count: usize) -> Result<&'a [u8]> {
+    let bytes = data
+        .get(*pos..*pos + count)
+        .ok_or_else(|| DskError::parse(*pos, "IMD track data truncated"))?;
+    *pos += count;
+    Ok(bytes)
+}
+
+/// Read one track record
+fn read_track(data: &[u8], pos: &mut usize) -> Result<Track> {
+    let start = *pos;
+    let header = take(data, pos, 5)?;
+    let (mode, cylinder, head, count, size_code) = (header[0], header[1], header[2], header[3] as usize, header[4]);
+
+    let (data_rate, recording_mode) = match mode {
+        0 => (DataRate::High, RecordingMode::FM),
+        1 | 2 => (DataRate::SingleDouble, RecordingMode::FM),
+        3 => (DataRate::High, RecordingMode::MFM),
+        4 | 5 => (DataRate::SingleDouble, RecordingMode::MFM),
+        _ => return Err(DskError::parse(start, format!("Unknown IMD track mode {}", mode))),
+    };
+
+    let side = head & 0x01;
+    let mut track = Track::new(cylinder, side);
+    track.data_rate = data_rate;
+    track.recording_mode = recording_mode;
+
+    let numbers = take(data, pos, count)?.to_vec();
+    let cylinders = if head & CYLINDER_MAP_FLAG != 0 {
+        take(data, pos, count)?.to_vec()
+    } else {
+        vec![cylinder; count]
+    };
+    let heads = if head & HEAD_MAP_FLAG != 0 {
+        take(data, pos, count)?.to_vec()
+    } else {
+        vec![side; count]
+    };
+    let sizes: Vec<(u8, usize)> = if size_code == SIZE_TABLE {
+        take(data, pos, count * 2)?
+            .chunks_exact(2)
+            .map(|w| {
+                let bytes = u16::from_le_bytes([w[0], w[1]]) as usize;
+                (size_code_for(bytes), bytes)
+            })
+            .collect()
+    } else {
+        vec![(size_code, fdc_size_to_bytes(size_code)); count]
+    };
+
+    for i in 0..count {
+        let (code, size) = sizes[i];
+        let id = SectorId::new(cylinders[i], heads[i], numbers[i], code);
+        let record = take(data, pos, 1)?[0];
+
+        let sector_data = match record {
+            0x00 => Vec::new(),
+            0x01 | 0x03 | 0x05 | 0x07 => take(data, pos, size)?.to_vec(),
+            0x02 | 0x04 | 0x06 | 0x08 => vec![take(data, pos, 1)?[0]; size],
+            _ => return Err(DskError::parse(*pos - 1, format!("Unknown IMD sector record {}", record))),
+        };
+
+        let (mut st1, mut st2) = (0, 0);
+        if record == 0x00 {
+            st1 |= FdcStatus1::MA;
+            st2 |= FdcStatus2::MD;
+        }
+        if matches!(record, 0x03 | 0x04 | 0x07 | 0x08) {
+            st2 |= FdcStatus2::CM;
+        }
+        if matches!(record, 0x05..=0x08) {
+            st1 |= FdcStatus1::DE;
+            st2 |= FdcStatus2::DD;
+        }
+
+        track.add_sector(Sector::with_status(id, FdcStatus1::new(st1), FdcStatus2::new(st2), sector_data));
+    }
+
+    Ok(track)
+}
+
+/// Smallest size code holding `bytes`
+fn size_code_for(bytes: usize) -> u8 {
+    (0..8u8).find(|&code| fdc_size_to_bytes(code) >= bytes).unwrap_or(8)
+}
+
+/// Write an IMD file to disk
+pub fn write_imd<P: AsRef<Path>>(image: &DiskImage, path: P) -> Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(&build_imd(image))?;
+    Ok(())
+}
+
+/// Describe each sector whose state an IMD file cannot hold
+///
+/// Weak sectors keep only their first copy (flagged as a data error), and
+/// sectors with a CRC error in the ID field alone are written as good.
+pub fn imd_losses(image: &DiskImage) -> Vec<String> {
+    let mut losses = Vec::new();
+
+    for disk in &image.disks {
+        for track in disk.tracks() {
+            for sector in track.sectors() {
+                let place = format!(
+                    "Side {} track {} sector {}",
+                    disk.side_number, track.track_number, sector.id.sector
+                );
+                if sector.is_weak() {
+                    losses.push(format!("{}: {} weak copies reduced to one", place, sector.copy_count()));
+                }
+                if id_crc_error(sector) {
+                    losses.push(format!("{}: ID field CRC error not kept", place));
+                }
+            }
+        }
+    }
+
+    losses
+}
+
+/// Check for a CRC error in the ID field but not the data field
+fn id_crc_error(sector: &Sector) -> bool {
+    sector.fdc_status1.data_error() && !sector.fdc_status2.data_field_error()
+}
+
+/// Build the complete IMD file contents
+///
+/// Tracks are written cylinder by cylinder, alternating heads. Uniform
+/// sectors are compressed. See [`imd_losses`] for what cannot be kept.
+fn build_imd(image: &DiskImage) -> Vec<u8> {
+    let mut out = format!("IMD 1.18: {}\r\nCreated by dskmanager", timestamp()).into_bytes();
+    out.push(COMMENT_END);
+
+    let num_tracks = image.disks.iter().map(|d| d.track_count()).max().unwrap_or(0);
+    for track_num in 0..num_tracks {
+        for disk in &image.disks {
+            if let Some(track) = disk.get_track(track_num as u8) {
+                write_track(&mut out, track, disk.side_number);
+            }
+        }
+    }
+
+    out
+}
+
+/// Append one track record
+fn write_track(out: &mut Vec<u8>, track: &Track, side: u8) {
+    let sectors = track.sectors();
+    let fm = track.recording_mode == RecordingMode::FM;
+    let mode = match (track.data_rate, fm) {
+        (DataRate::High, true) => 0,
+        (_, true) => 2,
+        (DataRate::High, false) => 3,
+        (_, false) => 5,
+    };
+
+    let cylinder_map = sectors.iter().any(|s| s.id.track != track.track_number);
+    let head_map = sectors.iter().any(|s| s.id.side != side);
+    let uniform_size = sectors
+        .first()
+        .map(|first| first.id.size_code)
+        .filter(|&code| code <= 6 && sectors.iter().all(|s| s.id.size_code == code));
+
+    let mut head = side;
+    if cylinder_map {
+        head |= CYLINDER_MAP_FLAG;
+    }
+    if head_map {
+        head |= HEAD_MAP_FLAG;
+    }
+
+    out.extend_from_slice(&[mode, track.track_number, head, sectors.len() as u8]);
+    out.push(uniform_size.unwrap_or(if sectors.is_empty() { 2 } else { SIZE_TABLE }));
+    out.extend(sectors.iter().map(|s| s.id.sector));
+    if cylinder_map {
+        out.extend(sectors.iter().map(|s| s.id.track));
+    }
+    if head_map {
+        out.extend(sectors.iter().map(|s| s.id.side));
+    }
+    if uniform_size.is_none() {
+        for sector in sectors {
+            out.extend_from_slice(&(sector.advertised_size().min(u16::MAX as usize) as u16).to_le_bytes());
+        }
+    }
+
+    for sector in sectors {
+        let data = sector.copy(0).unwrap_or_default();
+        if data.is_empty() || sector.fdc_status2.missing_data_mark() {
+            out.push(0x00);
+            continue;
+        }
+
+        let mut field = data.to_vec();
+        field.resize(sector.advertised_size().min(u16::MAX as usize), track.filler_byte);
+
+        let compressed = field.iter().all(|&b| b == field[0]);
+        let deleted = sector.is_deleted();
+        // IMD's error flag reads back as a data field error, so an ID field
+        // CRC error on its own cannot be flagged
+        let error = sector.fdc_status2.data_field_error() || sector.is_weak();
+        let record = 1 + compressed as u8 + if error { 4 } else { 0 } + if deleted { 2 } else { 0 };
+
+        out.push(record);
+        if compressed {
+            out.push(field[0]);
+        } else {
+            out.extend_from_slice(&field);
+        }
+    }
+}
+
+/// Current time as "dd/mm/yyyy hh:mm:ss" (UTC)
+fn timestamp() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:02}/{:02}/{:04} {:02}:{:02}:{:02}",
+        day,
+        month,
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_imd_file() {
+        assert!(is_imd_file("cpm.imd"));
+        assert!(is_imd_file("CPM.IMD"));
+        assert!(!is_imd_file("cpm.dsk"));
+    }
+
+    #[test]
+    fn test_parse_imd() {
+        let mut data = b"IMD 1.18: 01/02/2003 04:05:06\r\ncomment".to_vec();
+        data.push(COMMENT_END);
+        // 250kbps MFM, cylinder 0, head 1 with cylinder map, 3 sectors of 128 bytes
+        data.extend_from_slice(&[5, 0, 0x81, 3, 0]);
+        data.extend_from_slice(&[1, 2, 3]);
+        data.extend_from_slice(&[0, 0, 9]);
+        data.push(0x01);
+        data.extend(0..128u8);
+        data.extend_from_slice(&[0x04, 0xAA]);
+        data.push(0x00);
+
+        let image = parse_imd(&data).unwrap();
+        assert_eq!(image.format(), DiskImageFormat::Imd);
+        assert_eq!(image.disk_count(), 2);
+        assert!(image.disks()[0].get_track(0).unwrap().is_empty());
+
+        let track = image.disks()[1].get_track(0).unwrap();
+        assert_eq!(track.recording_mode, RecordingMode::MFM);
+        assert_eq!(track.sectors()[0].data()[127], 127);
+
+        let deleted = track.get_sector(2).unwrap();
+        assert!(deleted.is_deleted());
+        assert_eq!(deleted.data(), &[0xAA; 128][..]);
+
+        let missing = &track.sectors()[2];
+        assert_eq!(missing.id.track, 9);
+        assert!(missing.data().is_empty());
+        assert!(missing.fdc_status2.missing_data_mark());
+    }
+
+    #[test]
+    fn test_imd_round_trip() {
+        let mut track = Track::new(0, 0);
+        track.recording_mode = RecordingMode::FM;
+        track.data_rate = DataRate::SingleDouble;
+        track.add_sector(Sector::with_data(SectorId::new(0, 0, 1, 1), (0..=255).collect()));
+        track.add_sector(Sector::with_status(
+            SectorId::new(0, 0, 2, 2),
+            FdcStatus1::new(FdcStatus1::DE),
+            FdcStatus2::new(FdcStatus2::DD | FdcStatus2::CM),
+            vec![0xE5; 512],
+        ));
+        track.add_sector(Sector::with_data(SectorId::new(5, 1, 3, 1), vec![0x11; 256]));
+
+        let mut disk = Disk::new(0);
+        disk.add_track(track);
+        let image = DiskImage {
+            format: DiskImageFormat::ExtendedDSK,
+            spec: crate::io::reader::build_format_spec(std::slice::from_ref(&disk), 1, 1),
+            disks: vec![disk],
+            changed: false,
+            filename: None,
+        };
+
+        let loaded = parse_imd(&build_imd(&image)).unwrap();
+        let original = image.disks()[0].get_track(0).unwrap();
+        let track = loaded.disks()[0].get_track(0).unwrap();
+
+        assert_eq!(track.recording_mode, RecordingMode::FM);
+        assert_eq!(track.sector_count(), 3);
+        for (a, b) in original.sectors().iter().zip(track.sectors()) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.data(), b.data());
+            assert_eq!(a.fdc_status1, b.fdc_status1);
+            assert_eq!(a.fdc_status2, b.fdc_status2);
+        }
+    }
+
+    #[test]
+    fn test_imd_losses() {
+        let mut track = Track::new(0, 0);
+        let mut weak = Sector::with_status(
+            SectorId::new(0, 0, 1, 1),
+            FdcStatus1::new(FdcStatus1::DE),
+            FdcStatus2::new(FdcStatus2::DD),
+            vec![0x11; 256],
+        );
+        weak.set_copies(&[&[0x11; 256], &[0x22; 256]]).unwrap();
+        track.add_sector(weak);
+        track.add_sector(Sector::with_status(
+            SectorId::new(0, 0, 2, 1),
+            FdcStatus1::new(FdcStatus1::DE),
+            FdcStatus2::new(0),
+            vec![0x33; 256],
+        ));
+        track.add_sector(Sector::with_data(SectorId::new(0, 0, 3, 1), vec![0x44; 256]));
+
+        let mut disk = Disk::new(0);
+        disk.add_track(track);
+        let image = DiskImage {
+            format: DiskImageFormat::ExtendedDSK,
+            spec: crate::io::reader::build_format_spec(std::slice::from_ref(&disk), 1, 1),
+            disks: vec![disk],
+            changed: false,
+            filename: None,
+        };
+
+        assert_eq!(
+            imd_losses(&image),
+            vec![
+                "Side 0 track 0 sector 1: 2 weak copies reduced to one".to_string(),
+                "Side 0 track 0 sector 2: ID field CRC error not kept".to_string(),
+            ]
+        );
+
+        let loaded = parse_imd(&build_imd(&image)).unwrap();
+        let track = loaded.disks()[0].get_track(0).unwrap();
+        let weak = track.get_sector(1).unwrap();
+        assert_eq!(weak.data(), &[0x11; 256][..]);
+        assert!(weak.fdc_status2.data_field_error());
+        let id_error = track.get_sector(2).unwrap();
+        assert_eq!(id_error.data(), &[0x33; 256][..]);
+        assert!(!id_error.fdc_status1.data_error());
+        assert!(!id_error.fdc_status2.data_field_error());
+    }
+
+    #[test]
+    fn test_timestamp_format() {
+        let stamp = timestamp();
+        assert_eq!(stamp.len(), 19);
+        assert_eq!(&stamp[2..3], "/");
+        assert_eq!(&stamp[5..6], "/");
+    }
+}
@@ -98,6 +98,7 @@ pub fn read_mgt<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
         gap3_length: 0x17,
         filler_byte: 0x00,
         interleave: 1,
+        skew: 0,
         side_mode: SideMode::Successive,
     };
 
@@ -2,11 +2,40 @@
 
 /// Reader implementation for DSK files
 pub mod reader;
+/// MFM/FM track bitstream encoding and decoding
+pub(crate) mod bitstream;
+/// Reader and writer implementation for HFE files
+pub mod hfe;
+/// Reader and writer implementation for ImageDisk (IMD) files
+pub mod imd;
+/// Reader and writer implementation for Opus Discovery (OPD/OPU) files
+pub mod opd;
+/// Reader and writer implementation for raw sector dumps
+pub mod raw;
+/// Reader and writer implementation for SAM Coupe SAD files
+pub mod sad;
+/// Reader and writer implementation for TR-DOS SCL files
+pub mod scl;
+/// Reader implementation for SuperCard Pro (SCP) flux files
+pub mod scp;
+/// Reader implementation for Teledisk (TD0) files
+pub mod td0;
+/// Reader and writer implementation for TR-DOS TRD files
+pub mod trd;
 /// Reader implementation for MGT files
 pub mod mgt_reader;
 /// Writer implementation for DSK files
 pub mod writer;
 
+pub use hfe::{is_hfe_file, read_hfe, write_hfe};
+pub use imd::{imd_losses, is_imd_file, read_imd, write_imd};
 pub use mgt_reader::{is_mgt_file, read_mgt};
+pub use opd::{is_opd_file, read_opd, write_opd};
+pub use raw::{is_raw_file, read_raw, read_raw_specification, write_raw, write_raw_specification};
 pub use reader::read_dsk;
-pub use writer::write_dsk;
+pub use sad::{is_sad_file, read_sad, write_sad};
+pub use scl::{is_scl_file, read_scl, write_scl};
+pub use scp::{is_scp_file, read_scp};
+pub use td0::{is_td0_file, read_td0};
+pub use trd::{is_trd_file, read_trd, write_trd};
+pub use writer::{write_dsk, write_mgt};
@@ -0,0 +1,137 @@
+/// OPD file reader and writer
+///
+/// OPD (and OPU) images are sector dumps of Opus Discovery disks with no
+/// header:
+/// - 18 sectors of 256 bytes per track, sectors in order from sector 0
+/// - Tracks alternating between sides on double sided disks
+///
+/// The geometry is taken from the Opus boot sector, or from the file size
+/// if the boot sector is not recognised.
+
+use crate::error::{DskError, Result};
+use crate::filesystem::opus::{OpusFileSystem, OPUS_SECTORS_PER_TRACK, OPUS_SECTOR_SIZE};
+use crate::format::DiskImageFormat;
+use crate::image::DiskImage;
+use crate::io::raw::{build_raw, parse_raw, spec_track_order};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// File extensions recognised as Opus Discovery images
+pub const OPD_EXTENSIONS: &[&str] = &["opd", "opu"];
+
+/// Bytes in one track
+const OPD_TRACK_SIZE: usize = OPUS_SECTORS_PER_TRACK as usize * OPUS_SECTOR_SIZE;
+
+/// Check if a file is likely an Opus Discovery image based on extension
+pub fn is_opd_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| OPD_EXTENSIONS.iter().any(|o| e.eq_ignore_ascii_case(o)))
+        .unwrap_or(false)
+}
+
+/// Read an OPD file from disk
+pub fn read_opd<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
+    let filename = path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string());
+
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    let mut image = parse_opd(&data)?;
+    image.filename = filename;
+    Ok(image)
+}
+
+/// Parse the contents of an OPD file
+fn parse_opd(data: &[u8]) -> Result<DiskImage> {
+    let tracks = data.len() / OPD_TRACK_SIZE;
+    if data.is_empty() || !data.len().is_multiple_of(OPD_TRACK_SIZE) {
+        return Err(DskError::invalid_format(format!(
+            "OPD file size {} is not a whole number of tracks",
+            data.len()
+        )));
+    }
+
+    let (num_tracks, num_sides) = OpusFileSystem::boot_geometry(data)
+        .filter(|&(t, s)| t as usize * s as usize == tracks)
+        .or_else(|| match tracks {
+            1..=80 => Some((tracks as u8, 1)),
+            81..=168 if tracks.is_multiple_of(2) => Some(((tracks / 2) as u8, 2)),
+            _ => None,
+        })
+        .ok_or_else(|| DskError::invalid_format(format!("OPD file has {} tracks", tracks)))?;
+
+    let spec = OpusFileSystem::format_spec(num_tracks, num_sides);
+    let mut image = parse_raw(data, &spec, &spec_track_order(&spec))?;
+    image.format = DiskImageFormat::Opd;
+    Ok(image)
+}
+
+/// Write an OPD file
+///
+/// The image is written with 18 sectors of 256 bytes per track; missing
+/// sectors are written as the filler byte.
+pub fn write_opd<P: AsRef<Path>>(image: &DiskImage, path: P) -> Result<()> {
+    let data = build_opd(image);
+    File::create(path)?.write_all(&data)?;
+    Ok(())
+}
+
+/// Build the OPD file contents
+fn build_opd(image: &DiskImage) -> Vec<u8> {
+    let num_sides = image.disks.len().clamp(1, 2) as u8;
+    let spec = OpusFileSystem::format_spec(image.spec.num_tracks, num_sides);
+    build_raw(image, &spec, &spec_track_order(&spec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::FileSystemType;
+
+    fn boot_sector(tracks: u8, flags: u8) -> Vec<u8> {
+        let mut data = vec![0u8; OPUS_SECTOR_SIZE];
+        data[..5].copy_from_slice(&[0x18, 0x20, tracks, 18, flags]);
+        data
+    }
+
+    #[test]
+    fn test_is_opd_file() {
+        assert!(is_opd_file("game.opd"));
+        assert!(is_opd_file("GAME.OPU"));
+        assert!(!is_opd_file("game.mgt"));
+    }
+
+    #[test]
+    fn test_opd_geometry() {
+        // 40 tracks double sided and 80 tracks single sided have the same size
+        let mut data = boot_sector(40, 0x10);
+        data.resize(80 * OPD_TRACK_SIZE, 0);
+        data[OPD_TRACK_SIZE] = 0x42;
+
+        let image = parse_opd(&data).unwrap();
+        assert_eq!(image.format(), DiskImageFormat::Opd);
+        assert_eq!(image.format().default_filesystem(), FileSystemType::Opus);
+        assert_eq!(image.disk_count(), 2);
+        assert_eq!(image.read_sector(1, 0, 0).unwrap()[0], 0x42);
+        assert_eq!(build_opd(&image), data);
+
+        let dsk = std::env::temp_dir().join(format!("dsk_opd_dsk_{}.dsk", std::process::id()));
+        crate::io::write_dsk(&image, &dsk).unwrap();
+        let written = std::fs::read(&dsk).unwrap();
+        std::fs::remove_file(&dsk).unwrap();
+        assert!(written.starts_with(b"EXTENDED"));
+
+        data[..OPUS_SECTOR_SIZE].copy_from_slice(&[0; OPUS_SECTOR_SIZE]);
+        let image = parse_opd(&data).unwrap();
+        assert_eq!(image.disk_count(), 1);
+        assert_eq!(image.read_sector(0, 1, 0).unwrap()[0], 0x42);
+
+        assert!(parse_opd(&data[..1000]).is_err());
+    }
+}
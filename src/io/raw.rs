@@ -0,0 +1,236 @@
+/// Raw sector dump reader and writer
+///
+/// Raw images (`.img`, `.raw`, `.cpm`) as used by cpmtools and many
+/// emulators are plain sector data with no header, so the geometry must be
+/// given by a `FormatSpec` or `DiskSpecification`:
+/// - Sectors of each track in ascending ID order from the first sector ID
+/// - Tracks in the spec's side order: side 0 only, alternating sides,
+///   all of side 0 then all of side 1, or (specifications only) side 1
+///   from the last track back to track 0
+///
+/// Reading lays sectors out with the spec's interleave and skew.
+
+use crate::error::{DskError, Result};
+use crate::format::{DiskImageFormat, DiskSpecification, FormatSpec, SideMode};
+use crate::image::{DiskImage, DiskImageBuilder};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// File extensions recognised as raw sector dumps
+pub const RAW_EXTENSIONS: &[&str] = &["img", "raw", "cpm"];
+
+/// Check if a file is likely a raw sector dump based on extension
+pub fn is_raw_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| RAW_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r)))
+        .unwrap_or(false)
+}
+
+/// Order of (side, track) pairs in a raw image laid out by a `FormatSpec`
+pub(crate) fn spec_track_order(spec: &FormatSpec) -> Vec<(u8, u8)> {
+    let sides = spec.num_sides.max(1);
+    match spec.side_mode {
+        SideMode::Alternate => (0..spec.num_tracks)
+            .flat_map(|t| (0..sides).map(move |s| (s, t)))
+            .collect(),
+        SideMode::Successive => (0..sides)
+            .flat_map(|s| (0..spec.num_tracks).map(move |t| (s, t)))
+            .collect(),
+        SideMode::SingleSide => (0..spec.num_tracks).map(|t| (0, t)).collect(),
+    }
+}
+
+/// Order of (side, track) pairs in a raw image laid out by a `DiskSpecification`
+fn specification_track_order(spec: &DiskSpecification) -> Vec<(u8, u8)> {
+    let tracks = spec.tracks_per_side as usize * spec.side_count() as usize;
+    (0..tracks).filter_map(|t| spec.physical_track(t)).collect()
+}
+
+/// Read a raw sector dump with the geometry of `spec`
+pub fn read_raw<P: AsRef<Path>>(path: P, spec: &FormatSpec) -> Result<DiskImage> {
+    let data = read_file(&path)?;
+    let mut image = parse_raw(&data, spec, &spec_track_order(spec))?;
+    image.filename = file_name(&path);
+    Ok(image)
+}
+
+/// Read a raw sector dump with the geometry of a CP/M disk specification
+///
+/// Sector IDs are numbered from `first_sector_id`.
+pub fn read_raw_specification<P: AsRef<Path>>(
+    path: P,
+    spec: &DiskSpecification,
+    first_sector_id: u8,
+) -> Result<DiskImage> {
+    let data = read_file(&path)?;
+    let format_spec = spec.format_spec(first_sector_id);
+    let mut image = parse_raw(&data, &format_spec, &specification_track_order(spec))?;
+    image.filename = file_name(&path);
+    Ok(image)
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn file_name<P: AsRef<Path>>(path: P) -> Option<String> {
+    path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string())
+}
+
+/// Build an image from raw sector data stored in `order`
+pub(crate) fn parse_raw(data: &[u8], spec: &FormatSpec, order: &[(u8, u8)]) -> Result<DiskImage> {
+    let sector_size = spec.sector_size as usize;
+    let track_size = spec.sectors_per_track as usize * sector_size;
+    let expected = order.len() * track_size;
+    if data.len() != expected {
+        return Err(DskError::invalid_format(format!(
+            "Raw image should be {} bytes for this geometry, got {}",
+            expected,
+            data.len()
+        )));
+    }
+
+    let mut image = DiskImageBuilder::new()
+        .format(DiskImageFormat::Raw)
+        .spec(spec.clone())
+        .build()?;
+
+    for (&(side, track), track_data) in order.iter().zip(data.chunks_exact(track_size)) {
+        for (index, sector_data) in track_data.chunks_exact(sector_size).enumerate() {
+            let id = spec.first_sector_id.wrapping_add(index as u8);
+            image.write_sector(side, track, id, sector_data)?;
+        }
+    }
+
+    image.changed = false;
+    Ok(image)
+}
+
+/// Write an image as a raw sector dump with the geometry of `spec`
+///
+/// Missing sectors are written as the spec's filler byte and sectors of
+/// another size are padded or cut to the spec's sector size.
+pub fn write_raw<P: AsRef<Path>>(image: &DiskImage, path: P, spec: &FormatSpec) -> Result<()> {
+    let data = build_raw(image, spec, &spec_track_order(spec));
+    File::create(path)?.write_all(&data)?;
+    Ok(())
+}
+
+/// Write an image as a raw sector dump with the geometry of a CP/M disk specification
+///
+/// Sector IDs are numbered from `first_sector_id`.
+pub fn write_raw_specification<P: AsRef<Path>>(
+    image: &DiskImage,
+    path: P,
+    spec: &DiskSpecification,
+    first_sector_id: u8,
+) -> Result<()> {
+    let format_spec = spec.format_spec(first_sector_id);
+    let data = build_raw(image, &format_spec, &specification_track_order(spec));
+    File::create(path)?.write_all(&data)?;
+    Ok(())
+}
+
+/// Collect the raw sector data of an image in `order`
+pub(crate) fn build_raw(image: &DiskImage, spec: &FormatSpec, order: &[(u8, u8)]) -> Vec<u8> {
+    let sector_size = spec.sector_size as usize;
+    let mut out = Vec::with_capacity(order.len() * spec.sectors_per_track as usize * sector_size);
+
+    for &(side, track) in order {
+        for index in 0..spec.sectors_per_track {
+            let id = spec.first_sector_id.wrapping_add(index);
+            let mut data = image
+                .read_sector(side, track, id)
+                .map(|d| d.to_vec())
+                .unwrap_or_default();
+            data.resize(sector_size, spec.filler_byte);
+            out.extend_from_slice(&data);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::DiskSpecSide;
+
+    fn numbered(spec: &FormatSpec, tracks: usize) -> Vec<u8> {
+        let track_size = spec.sectors_per_track as usize * spec.sector_size as usize;
+        (0..tracks).flat_map(|t| vec![t as u8; track_size]).collect()
+    }
+
+    #[test]
+    fn test_is_raw_file() {
+        assert!(is_raw_file("disk.img"));
+        assert!(is_raw_file("DISK.RAW"));
+        assert!(is_raw_file("disk.cpm"));
+        assert!(!is_raw_file("disk.dsk"));
+    }
+
+    #[test]
+    fn test_side_orders() {
+        let mut spec = FormatSpec::amstrad_data_ds();
+        spec.num_tracks = 2;
+        assert_eq!(spec_track_order(&spec), vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+        spec.side_mode = SideMode::Successive;
+        assert_eq!(spec_track_order(&spec), vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+
+        let mut specification = DiskSpecification::new();
+        specification.tracks_per_side = 2;
+        specification.side = DiskSpecSide::DoubleReverse;
+        assert_eq!(
+            specification_track_order(&specification),
+            vec![(0, 0), (0, 1), (1, 1), (1, 0)]
+        );
+    }
+
+    #[test]
+    fn test_parse_raw() {
+        let mut spec = FormatSpec::ibm_pc_360k();
+        spec.interleave = 2;
+        let data = numbered(&spec, 80);
+
+        let image = parse_raw(&data, &spec, &spec_track_order(&spec)).unwrap();
+        assert_eq!(image.format(), DiskImageFormat::Raw);
+        assert_eq!(image.read_sector(1, 0, 1).unwrap(), &[1; 512][..]);
+        assert_eq!(image.read_sector(0, 3, 9).unwrap(), &[6; 512][..]);
+        assert_eq!(image.disks()[0].get_track(0).unwrap().sector_ids()[..3], [1, 6, 2]);
+
+        assert_eq!(build_raw(&image, &spec, &spec_track_order(&spec)), data);
+        assert!(parse_raw(&data[1..], &spec, &spec_track_order(&spec)).is_err());
+    }
+
+    #[test]
+    fn test_raw_specification_round_trip() {
+        let mut specification = DiskSpecification::new();
+        specification.side = DiskSpecSide::DoubleReverse;
+        let spec = specification.format_spec(1);
+        let data = numbered(&spec, 80);
+
+        let path = std::env::temp_dir().join(format!("dsk_raw_{}.img", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let image = read_raw_specification(&path, &specification, 1).unwrap();
+        assert_eq!(image.read_sector(1, 39, 1).unwrap(), &[40; 512][..]);
+
+        let dsk = std::env::temp_dir().join(format!("dsk_raw_dsk_{}.dsk", std::process::id()));
+        crate::io::write_dsk(&image, &dsk).unwrap();
+        let written = std::fs::read(&dsk).unwrap();
+        std::fs::remove_file(&dsk).unwrap();
+        assert!(written.starts_with(b"EXTENDED"));
+
+        write_raw_specification(&image, &path, &specification, 1).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, data);
+    }
+}
@@ -30,6 +30,15 @@ pub fn read_dsk<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
             DiskImageFormat::StandardDSK => read_standard_dsk(file, &disk_info, filename),
             DiskImageFormat::ExtendedDSK => read_extended_dsk(file, &disk_info, filename),
         DiskImageFormat::RawMgt => Err(DskError::invalid_format("RawMgt format should use read_mgt")),
+        DiskImageFormat::HfeV1 | DiskImageFormat::HfeV3 => Err(DskError::invalid_format("HFE format should use read_hfe")),
+        DiskImageFormat::Imd => Err(DskError::invalid_format("IMD format should use read_imd")),
+        DiskImageFormat::Td0 => Err(DskError::invalid_format("TD0 format should use read_td0")),
+        DiskImageFormat::Scp => Err(DskError::invalid_format("SCP format should use read_scp")),
+        DiskImageFormat::Raw => Err(DskError::invalid_format("Raw format should use read_raw")),
+        DiskImageFormat::Sad => Err(DskError::invalid_format("SAD format should use read_sad")),
+        DiskImageFormat::Trd => Err(DskError::invalid_format("TRD format should use read_trd")),
+        DiskImageFormat::Scl => Err(DskError::invalid_format("SCL format should use read_scl")),
+        DiskImageFormat::Opd => Err(DskError::invalid_format("OPD format should use read_opd")),
     }
 }
 
@@ -110,6 +119,13 @@ fn read_extended_dsk(mut file: File, disk_info: &[u8], filename: Option<String>)
         disks.push(disk);
     }
 
+    // Anything after the last track may be an Offset-Info block
+    let mut trailer = Vec::new();
+    file.read_to_end(&mut trailer)?;
+    if trailer.starts_with(OFFSET_INFO_MARKER) {
+        apply_offset_info(&mut disks, &trailer[OFFSET_INFO_MARKER.len()..]);
+    }
+
     // Create format spec
     let spec = build_format_spec(&disks, num_sides, num_tracks);
 
@@ -212,8 +228,36 @@ fn read_track(file: &mut File, track_num: u8, side: u8, track_size: usize) -> Re
     Ok(track)
 }
 
+/// Apply an Offset-Info (V5 extension) block to the tracks read
+///
+/// The block holds, for each track in file order, its length in bytes
+/// followed by the header offset of each sector, all as little-endian words.
+/// Zero marks an unknown length or offset, and a truncated block leaves the
+/// remaining positions unknown.
+fn apply_offset_info(disks: &mut [Disk], data: &[u8]) {
+    let mut words = data
+        .chunks_exact(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]));
+
+    for disk in disks.iter_mut() {
+        for track in disk.tracks_mut() {
+            let Some(length) = words.next() else {
+                return;
+            };
+            track.track_length = (length != 0).then_some(length);
+
+            for sector in track.sectors_mut() {
+                let Some(offset) = words.next() else {
+                    return;
+                };
+                sector.offset = (offset != 0).then_some(offset);
+            }
+        }
+    }
+}
+
 /// Build a format specification from the disk structure
-fn build_format_spec(disks: &[Disk], num_sides: u8, num_tracks: u8) -> FormatSpec {
+pub(crate) fn build_format_spec(disks: &[Disk], num_sides: u8, num_tracks: u8) -> FormatSpec {
     // Try to detect format from first non-empty track
     let mut sectors_per_track = 9;
     let mut sector_size = 512;
@@ -243,6 +287,7 @@ fn build_format_spec(disks: &[Disk], num_sides: u8, num_tracks: u8) -> FormatSpe
         gap3_length,
         filler_byte,
         interleave: 1,
+        skew: 0,
         side_mode: if num_sides == 1 {
             crate::format::spec::SideMode::SingleSide
         } else {
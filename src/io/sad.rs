@@ -0,0 +1,251 @@
+/// SAD file reader and writer
+///
+/// SAD images, used by SimCoupe and other SAM Coupe tools, are sector dumps
+/// with a small header:
+/// - 22 byte header: "Aley's disk backup", then sides, tracks per side,
+///   sectors per track and sector size divided by 64
+/// - Sector data for all of side 0, then all of side 1, each track's
+///   sectors in order from sector 1
+///
+/// The whole file may be gzip compressed; compressed files are recognised
+/// by their gzip magic when reading and written for `.gz` paths.
+
+use crate::error::{DskError, Result};
+use crate::format::{bytes_to_fdc_size, DiskImageFormat, FormatSpec, SideMode};
+use crate::image::{Disk, DiskImage, Sector, SectorId, Track};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// SAD signature
+pub const SAD_SIGNATURE: &[u8] = b"Aley's disk backup";
+
+/// Size of the SAD header
+pub const SAD_HEADER_SIZE: usize = 22;
+
+/// Gzip magic bytes
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+/// First sector ID on SAM disks
+const SAD_FIRST_SECTOR_ID: u8 = 1;
+
+/// Check if a file is likely a SAD file based on extension
+///
+/// Both `.sad` and gzip compressed `.sad.gz` names are recognised.
+pub fn is_sad_file<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    let has_extension = |p: &Path, ext: &str| {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+    };
+
+    has_extension(path, "sad")
+        || (has_extension(path, "gz") && path.file_stem().is_some_and(|s| has_extension(Path::new(s), "sad")))
+}
+
+/// Read a SAD file (optionally gzip compressed) from disk
+pub fn read_sad<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
+    let filename = path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string());
+
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    if data.starts_with(GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+        data = decompressed;
+    }
+
+    let mut image = parse_sad(&data)?;
+    image.filename = filename;
+    Ok(image)
+}
+
+/// Parse the (decompressed) contents of a SAD file
+fn parse_sad(data: &[u8]) -> Result<DiskImage> {
+    if data.len() < SAD_HEADER_SIZE || !data.starts_with(SAD_SIGNATURE) {
+        return Err(DskError::invalid_format("Missing SAD signature"));
+    }
+
+    let num_sides = data[18];
+    let num_tracks = data[19];
+    let sectors_per_track = data[20];
+    let sector_size = data[21] as usize * 64;
+
+    if !(1..=2).contains(&num_sides) {
+        return Err(DskError::invalid_format(format!("SAD image has {} sides", num_sides)));
+    }
+    let size_code = bytes_to_fdc_size(sector_size).ok_or_else(|| {
+        DskError::invalid_format(format!("Unsupported SAD sector size {}", sector_size))
+    })?;
+
+    let track_size = sectors_per_track as usize * sector_size;
+    let expected = SAD_HEADER_SIZE + num_sides as usize * num_tracks as usize * track_size;
+    if data.len() < expected {
+        return Err(DskError::invalid_format(format!(
+            "SAD file should be {} bytes, got {}",
+            expected,
+            data.len()
+        )));
+    }
+
+    let mut disks = Vec::with_capacity(num_sides as usize);
+    let mut offset = SAD_HEADER_SIZE;
+    for side in 0..num_sides {
+        let mut disk = Disk::with_capacity(side, num_tracks as usize);
+        for track_num in 0..num_tracks {
+            let mut track = Track::new(track_num, side);
+            track.gap3_length = 0x17;
+            track.filler_byte = 0x00;
+
+            for index in 0..sectors_per_track {
+                let id = SectorId::new(track_num, side, SAD_FIRST_SECTOR_ID + index, size_code);
+                track.add_sector(Sector::with_data(id, data[offset..offset + sector_size].to_vec()));
+                offset += sector_size;
+            }
+            disk.add_track(track);
+        }
+        disks.push(disk);
+    }
+
+    let spec = FormatSpec {
+        num_sides,
+        num_tracks,
+        sectors_per_track,
+        sector_size: sector_size as u16,
+        first_sector_id: SAD_FIRST_SECTOR_ID,
+        gap3_length: 0x17,
+        filler_byte: 0x00,
+        interleave: 1,
+        skew: 0,
+        side_mode: if num_sides == 1 { SideMode::SingleSide } else { SideMode::Successive },
+    };
+
+    Ok(DiskImage {
+        format: DiskImageFormat::Sad,
+        spec,
+        disks,
+        changed: false,
+        filename: None,
+    })
+}
+
+/// Write a SAD file, gzip compressed when the path ends in `.gz`
+///
+/// The geometry comes from the image's format specification; missing
+/// sectors are written as zeros.
+pub fn write_sad<P: AsRef<Path>>(image: &DiskImage, path: P) -> Result<()> {
+    let compress = path
+        .as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("gz"));
+
+    let data = build_sad(image)?;
+    let mut file = File::create(path)?;
+    if compress {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(&data)?;
+        encoder.finish()?;
+    } else {
+        file.write_all(&data)?;
+    }
+    Ok(())
+}
+
+/// Build the uncompressed SAD file contents
+fn build_sad(image: &DiskImage) -> Result<Vec<u8>> {
+    let spec = &image.spec;
+    let num_sides = image.disks.len().clamp(1, 2) as u8;
+    let sector_size = spec.sector_size as usize;
+    if !sector_size.is_multiple_of(64) || sector_size / 64 > u8::MAX as usize {
+        return Err(DskError::invalid_format(format!(
+            "Sector size {} cannot be stored in a SAD image",
+            sector_size
+        )));
+    }
+
+    let mut out = Vec::with_capacity(
+        SAD_HEADER_SIZE + num_sides as usize * spec.num_tracks as usize * spec.sectors_per_track as usize * sector_size,
+    );
+    out.extend_from_slice(SAD_SIGNATURE);
+    out.extend_from_slice(&[num_sides, spec.num_tracks, spec.sectors_per_track, (sector_size / 64) as u8]);
+
+    for side in 0..num_sides {
+        for track in 0..spec.num_tracks {
+            for index in 0..spec.sectors_per_track {
+                let id = spec.first_sector_id.wrapping_add(index);
+                let mut data = image.read_sector(side, track, id).map(|d| d.to_vec()).unwrap_or_default();
+                data.resize(sector_size, 0);
+                out.extend_from_slice(&data);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::FileSystemType;
+
+    #[test]
+    fn test_is_sad_file() {
+        assert!(is_sad_file("game.sad"));
+        assert!(is_sad_file("GAME.SAD"));
+        assert!(is_sad_file("game.sad.gz"));
+        assert!(!is_sad_file("game.gz"));
+        assert!(!is_sad_file("game.mgt"));
+    }
+
+    #[test]
+    fn test_parse_sad() {
+        let mut data = SAD_SIGNATURE.to_vec();
+        data.extend_from_slice(&[1, 2, 2, 8]);
+        for i in 0..4u8 {
+            data.extend_from_slice(&[i; 512]);
+        }
+
+        let image = parse_sad(&data).unwrap();
+        assert_eq!(image.format(), DiskImageFormat::Sad);
+        assert_eq!(image.format().default_filesystem(), FileSystemType::Mgt);
+        assert_eq!(image.read_sector(0, 1, 2).unwrap(), &[3; 512][..]);
+        assert_eq!(build_sad(&image).unwrap(), data);
+
+        assert!(parse_sad(&data[..100]).is_err());
+    }
+
+    #[test]
+    fn test_sad_gzip_round_trip() {
+        let mut data = SAD_SIGNATURE.to_vec();
+        data.extend_from_slice(&[2, 80, 10, 8]);
+        data.resize(SAD_HEADER_SIZE + 819_200, 0);
+        data[SAD_HEADER_SIZE + 80 * 10 * 512] = 0x42;
+        let image = parse_sad(&data).unwrap();
+
+        let path = std::env::temp_dir().join(format!("dsk_sad_{}.sad.gz", std::process::id()));
+        write_sad(&image, &path).unwrap();
+        let compressed = std::fs::read(&path).unwrap();
+        let loaded = DiskImage::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(compressed.starts_with(GZIP_MAGIC));
+        assert!(compressed.len() < data.len());
+        assert_eq!(loaded.format(), DiskImageFormat::Sad);
+        assert_eq!(loaded.read_sector(1, 0, 1).unwrap()[0], 0x42);
+
+        let dsk = std::env::temp_dir().join(format!("dsk_sad_dsk_{}.dsk", std::process::id()));
+        crate::io::write_dsk(&loaded, &dsk).unwrap();
+        let written = std::fs::read(&dsk).unwrap();
+        std::fs::remove_file(&dsk).unwrap();
+        assert!(written.starts_with(b"EXTENDED"));
+    }
+}
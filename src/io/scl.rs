@@ -0,0 +1,187 @@
+/// SCL file reader and writer
+///
+/// SCL is a compact archive of TR-DOS files:
+/// - "SINCLAIR" signature and a file count byte
+/// - 14 byte header per file: name, type, start, length and length in
+///   sectors, as in the TR-DOS catalogue without the disk position
+/// - Sector data of each file in turn
+/// - 32-bit little endian sum of all preceding bytes
+///
+/// Reading unpacks the files onto a blank 80 track double sided TR-DOS
+/// disk; writing packs the live files of a TR-DOS disk.
+
+use crate::error::{DskError, Result};
+use crate::filesystem::trdos::{TrDosDirEntry, TrDosFileSystem};
+use crate::format::DiskImageFormat;
+use crate::image::DiskImage;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// SCL signature
+pub const SCL_SIGNATURE: &[u8] = b"SINCLAIR";
+
+/// Size of each file header
+const SCL_FILE_HEADER_SIZE: usize = 14;
+
+/// Check if a file is likely a SCL file based on extension
+pub fn is_scl_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("scl"))
+        .unwrap_or(false)
+}
+
+/// Read a SCL file from disk
+pub fn read_scl<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
+    let filename = path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string());
+
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    let mut image = parse_scl(&data)?;
+    image.filename = filename;
+    Ok(image)
+}
+
+/// Parse the contents of a SCL file onto a blank TR-DOS disk
+fn parse_scl(data: &[u8]) -> Result<DiskImage> {
+    if data.len() < SCL_SIGNATURE.len() + 1 || !data.starts_with(SCL_SIGNATURE) {
+        return Err(DskError::invalid_format("Missing SCL signature"));
+    }
+
+    let count = data[8] as usize;
+    let headers_end = 9 + count * SCL_FILE_HEADER_SIZE;
+    let headers = data
+        .get(9..headers_end)
+        .ok_or_else(|| DskError::parse(9, "SCL file headers truncated"))?;
+
+    let entries: Vec<TrDosDirEntry> = headers
+        .chunks_exact(SCL_FILE_HEADER_SIZE)
+        .enumerate()
+        .map(|(index, header)| {
+            let mut bytes = [0u8; 16];
+            bytes[..SCL_FILE_HEADER_SIZE].copy_from_slice(header);
+            TrDosDirEntry::parse(&bytes, index)
+                .ok_or_else(|| DskError::parse(9 + index * SCL_FILE_HEADER_SIZE, "Empty SCL file name"))
+        })
+        .collect::<Result<_>>()?;
+
+    let data_size: usize = entries.iter().map(|e| e.allocated_size()).sum();
+    let data_end = headers_end + data_size;
+    let stored = data
+        .get(data_end..data_end + 4)
+        .ok_or_else(|| DskError::parse(headers_end, "SCL file data truncated"))?;
+    let stored = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]);
+    let checksum = scl_checksum(&data[..data_end]);
+    if stored != checksum {
+        return Err(DskError::integrity(format!(
+            "SCL checksum mismatch: stored {:08X}, calculated {:08X}",
+            stored, checksum
+        )));
+    }
+
+    let mut image = TrDosFileSystem::blank_image(80, 2, "")?;
+    {
+        let mut fs = TrDosFileSystem::new_mut(&mut image)?;
+        let mut offset = headers_end;
+        for entry in entries {
+            let size = entry.allocated_size();
+            fs.append(entry, &data[offset..offset + size])?;
+            offset += size;
+        }
+    }
+
+    image.format = DiskImageFormat::Scl;
+    image.changed = false;
+    Ok(image)
+}
+
+/// Sum of all bytes, as stored at the end of a SCL file
+fn scl_checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32))
+}
+
+/// Write the files of a TR-DOS disk as a SCL file
+///
+/// Deleted files are left out.
+pub fn write_scl<P: AsRef<Path>>(image: &DiskImage, path: P) -> Result<()> {
+    let data = build_scl(image)?;
+    File::create(path)?.write_all(&data)?;
+    Ok(())
+}
+
+/// Build the SCL file contents
+fn build_scl(image: &DiskImage) -> Result<Vec<u8>> {
+    let fs = TrDosFileSystem::new(image)?;
+    let entries: Vec<&TrDosDirEntry> = fs.directory().iter().filter(|e| !e.deleted).collect();
+
+    let mut out = SCL_SIGNATURE.to_vec();
+    out.push(entries.len() as u8);
+    for entry in &entries {
+        out.extend_from_slice(&entry.to_bytes()[..SCL_FILE_HEADER_SIZE]);
+    }
+    for entry in &entries {
+        let mut data = fs.read_sectors(entry)?;
+        data.resize(entry.allocated_size(), 0);
+        out.extend_from_slice(&data);
+    }
+
+    let checksum = scl_checksum(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::trdos::TrDosFileType;
+    use crate::filesystem::{FileSystem, FileSystemType};
+
+    #[test]
+    fn test_is_scl_file() {
+        assert!(is_scl_file("game.scl"));
+        assert!(is_scl_file("GAME.SCL"));
+        assert!(!is_scl_file("game.trd"));
+    }
+
+    #[test]
+    fn test_scl_round_trip() {
+        let mut image = TrDosFileSystem::blank_image(80, 2, "").unwrap();
+        {
+            let mut fs = TrDosFileSystem::new_mut(&mut image).unwrap();
+            fs.save_file("SCREEN", TrDosFileType::Code, 16384, &[7; 6912]).unwrap();
+            fs.write_file("GONE.C", &[1; 10]).unwrap();
+            fs.write_file("boot.B", &[0x00, 0x0A, 0x02, 0x00, 0xF5, 0x0D]).unwrap();
+            fs.delete_file("GONE.C").unwrap();
+        }
+
+        let data = build_scl(&image).unwrap();
+        assert_eq!(data[8], 2);
+        assert_eq!(data.len(), 9 + 2 * 14 + (27 + 1) * 256 + 4);
+
+        let loaded = parse_scl(&data).unwrap();
+        assert_eq!(loaded.format(), DiskImageFormat::Scl);
+        assert_eq!(loaded.default_filesystem(), FileSystemType::TrDos);
+        let fs = TrDosFileSystem::new(&loaded).unwrap();
+        assert_eq!(fs.read_file("SCREEN.C").unwrap(), vec![7; 6912]);
+        assert_eq!(fs.find_file("SCREEN").unwrap().start, 16384);
+        assert_eq!(fs.find_file("boot").unwrap().first_track, 2);
+        assert_eq!(build_scl(&loaded).unwrap(), data);
+
+        let dsk = std::env::temp_dir().join(format!("dsk_scl_dsk_{}.dsk", std::process::id()));
+        crate::io::write_dsk(&loaded, &dsk).unwrap();
+        let written = std::fs::read(&dsk).unwrap();
+        std::fs::remove_file(&dsk).unwrap();
+        assert!(written.starts_with(b"EXTENDED"));
+
+        let mut corrupt = data.clone();
+        corrupt[20] ^= 0xFF;
+        assert!(matches!(parse_scl(&corrupt), Err(DskError::IntegrityError(_))));
+        assert!(parse_scl(&data[..40]).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+/// SuperCard Pro (SCP) flux file reader
+///
+/// SCP files, as saved by Greaseweazle and the SuperCard Pro, hold the
+/// magnetic flux transitions of each track:
+/// - 16 byte header: "SCP", version, disk type, revolutions, start and end
+///   track, flags, cell width, heads, resolution and checksum
+/// - Track offsets table of 168 u32 entries (track = cylinder * 2 + head)
+/// - Per track "TRK" and track number, then index time, flux count and
+///   data offset for each revolution
+/// - Flux intervals as big-endian u16 ticks of 25ns (times resolution + 1),
+///   with 0 adding 65536 ticks to the next interval
+///
+/// Flux is turned into MFM/FM cells by a simple PLL and decoded one
+/// revolution at a time, reading on into the next revolution so sectors
+/// crossing the index come back whole. Revolutions are then merged: a good
+/// read replaces a bad one, and sectors whose data changes between bad
+/// reads become weak sectors holding each distinct copy.
+///
+/// SCP images are read-only; they save as Extended DSK.
+
+use crate::error::{DskError, Result};
+use crate::format::DiskImageFormat;
+use crate::image::{DataRate, Disk, DiskImage, Track};
+use crate::io::bitstream::{decode_revolution, BitStream};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// SCP signature
+pub const SCP_SIGNATURE: &[u8] = b"SCP";
+
+/// Size of the SCP header
+const HEADER_SIZE: usize = 0x10;
+/// Entries in the track offsets table
+const MAX_TRACKS: usize = 168;
+/// Track data header signature
+const TRACK_SIGNATURE: &[u8] = b"TRK";
+/// Length of one tick at the base resolution, in nanoseconds
+const TICK_NS: f64 = 25.0;
+/// Cell length at or below which a track is high density, in nanoseconds
+const HD_CELL_NS: f64 = 1500.0;
+/// How far the PLL follows each flux interval
+const PLL_GAIN: f64 = 0.05;
+/// Furthest the PLL clock may drift from the nominal cell, as a fraction
+const PLL_RANGE: f64 = 0.1;
+
+/// Check if a file is likely an SCP file based on extension
+pub fn is_scp_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("scp"))
+        .unwrap_or(false)
+}
+
+/// Read an SCP file from disk
+pub fn read_scp<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
+    let filename = path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string());
+
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    let mut image = parse_scp(&data)?;
+    image.filename = filename;
+    Ok(image)
+}
+
+/// Read a little-endian u32 at `offset`
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| DskError::parse(offset, "SCP data truncated"))
+}
+
+/// Parse the contents of an SCP file
+fn parse_scp(data: &[u8]) -> Result<DiskImage> {
+    if data.len() < HEADER_SIZE + MAX_TRACKS * 4 {
+        return Err(DskError::invalid_format("SCP file too small for header"));
+    }
+    if !data.starts_with(SCP_SIGNATURE) {
+        return Err(DskError::invalid_format("Missing SCP signature"));
+    }
+
+    let revolutions = data[5] as usize;
+    let end_track = (data[7] as usize).min(MAX_TRACKS - 1);
+    if data[9] != 0 && data[9] != 16 {
+        return Err(DskError::invalid_format(format!(
+            "Unsupported SCP cell width of {} bits",
+            data[9]
+        )));
+    }
+    let tick_ns = TICK_NS * (data[11] as f64 + 1.0);
+
+    let mut tracks = Vec::new();
+    for index in 0..=end_track {
+        let offset = read_u32(data, HEADER_SIZE + index * 4)? as usize;
+        if offset == 0 {
+            continue;
+        }
+        if data.get(offset..offset + 3) != Some(TRACK_SIGNATURE) {
+            return Err(DskError::parse(offset, "Missing SCP track header"));
+        }
+
+        let number = data.get(offset + 3).copied().unwrap_or(index as u8);
+        let mut flux = Vec::with_capacity(revolutions);
+        for rev in 0..revolutions {
+            let entry = offset + 4 + rev * 12;
+            let count = read_u32(data, entry + 4)? as usize;
+            let start = offset + read_u32(data, entry + 8)? as usize;
+            flux.push(read_flux(data, start, count, tick_ns)?);
+        }
+
+        tracks.push(decode_flux_track(&flux, number / 2, number % 2));
+    }
+
+    let num_sides = tracks.iter().map(|t| t.side_number + 1).max().unwrap_or(1);
+    let num_tracks = tracks.iter().map(|t| t.track_number as usize + 1).max().unwrap_or(0);
+
+    let mut disks: Vec<Disk> = (0..num_sides).map(Disk::new).collect();
+    for disk in disks.iter_mut() {
+        for track_num in 0..num_tracks {
+            let track = tracks
+                .iter()
+                .find(|t| t.track_number as usize == track_num && t.side_number == disk.side_number)
+                .cloned()
+                .unwrap_or_else(|| Track::new(track_num as u8, disk.side_number));
+            disk.add_track(track);
+        }
+    }
+
+    let num_tracks = num_tracks.min(u8::MAX as usize) as u8;
+    let spec = crate::io::reader::build_format_spec(&disks, num_sides, num_tracks);
+
+    Ok(DiskImage {
+        format: DiskImageFormat::Scp,
+        spec,
+        disks,
+        changed: false,
+        filename: None,
+    })
+}
+
+/// Read `count` flux intervals at `start`, in nanoseconds
+fn read_flux(data: &[u8], start: usize, count: usize, tick_ns: f64) -> Result<Vec<f64>> {
+    let raw = data
+        .get(start..start + count * 2)
+        .ok_or_else(|| DskError::parse(start, "SCP flux data truncated"))?;
+
+    let mut flux = Vec::with_capacity(count);
+    let mut carry = 0u32;
+    for pair in raw.chunks_exact(2) {
+        let ticks = u16::from_be_bytes([pair[0], pair[1]]) as u32;
+        if ticks == 0 {
+            carry += 0x10000;
+        } else {
+            flux.push((carry + ticks) as f64 * tick_ns);
+            carry = 0;
+        }
+    }
+    Ok(flux)
+}
+
+/// Estimate the nominal cell length from the shortest common interval
+///
+/// The shortest MFM interval (and the FM interval for a 1 bit) is two
+/// cells, and makes up a large share of any formatted track.
+fn nominal_cell(flux: &[f64]) -> f64 {
+    let mut sorted = flux.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted.get(sorted.len() / 10).map(|&t| t / 2.0).unwrap_or(2000.0)
+}
+
+/// Turn flux intervals into cells with a simple PLL
+///
+/// Returns the stream and the number of cells before interval `split`.
+fn flux_to_stream(flux: &[f64], cell: f64, split: usize) -> (BitStream, usize) {
+    let mut stream = BitStream::new();
+    let mut clock = cell;
+    let mut split_at = 0;
+
+    for (i, &interval) in flux.iter().enumerate() {
+        if i == split {
+            split_at = stream.len();
+        }
+
+        let cells = (interval / clock).round().max(1.0);
+        for _ in 1..cells as usize {
+            stream.push(false);
+        }
+        stream.push(true);
+
+        clock += (interval / cells - clock) * PLL_GAIN;
+        clock = clock.clamp(cell * (1.0 - PLL_RANGE), cell * (1.0 + PLL_RANGE));
+    }
+
+    if split >= flux.len() {
+        split_at = stream.len();
+    }
+    (stream, split_at)
+}
+
+/// Decode every revolution of a track and merge them
+fn decode_flux_track(revolutions: &[Vec<f64>], track_num: u8, side: u8) -> Track {
+    let all: Vec<f64> = revolutions.iter().flatten().copied().collect();
+    let cell = nominal_cell(&all);
+
+    let decoded: Vec<Track> = revolutions
+        .iter()
+        .enumerate()
+        .map(|(i, rev)| {
+            let mut flux = rev.clone();
+            if let Some(next) = revolutions.get(i + 1) {
+                flux.extend_from_slice(next);
+            }
+            let (stream, length) = flux_to_stream(&flux, cell, rev.len());
+            decode_revolution(&stream, length, track_num, side)
+        })
+        .collect();
+
+    let mut track = merge_revolutions(decoded).unwrap_or_else(|| Track::new(track_num, side));
+    track.data_rate = if cell <= HD_CELL_NS { DataRate::High } else { DataRate::SingleDouble };
+    track
+}
+
+/// Merge reads of the same track from several revolutions
+///
+/// The revolution with the most sectors is the base. A sector read with
+/// errors is replaced by an error-free read from another revolution; if
+/// there is none and its data differs between reads, it becomes a weak
+/// sector holding each distinct copy.
+fn merge_revolutions(revolutions: Vec<Track>) -> Option<Track> {
+    let base_index = (0..revolutions.len()).max_by_key(|&i| {
+        (revolutions[i].sector_count(), std::cmp::Reverse(i))
+    })?;
+    let base = &revolutions[base_index];
+
+    let mut merged = base.clone();
+    merged.clear();
+
+    for (position, sector) in base.sectors().iter().enumerate() {
+        // Match sectors by ID and occurrence, as IDs may repeat on a track
+        let occurrence = base.sectors()[..position]
+            .iter()
+            .filter(|s| s.id == sector.id)
+            .count();
+        let reads: Vec<_> = revolutions
+            .iter()
+            .filter_map(|t| t.sectors().iter().filter(|s| s.id == sector.id).nth(occurrence))
+            .collect();
+
+        let mut sector = sector.clone();
+        if sector.has_error() {
+            if let Some(good) = reads.iter().find(|s| !s.has_error()) {
+                sector = (*good).clone();
+            } else if sector.fdc_status2.data_field_error() {
+                let size = sector.advertised_size();
+                let mut copies: Vec<&[u8]> = Vec::new();
+                for read in &reads {
+                    if read.data().len() == size && !copies.contains(&read.data()) {
+                        copies.push(read.data());
+                    }
+                }
+                if copies.len() > 1 {
+                    let _ = sector.set_copies(&copies);
+                }
+            }
+        }
+        merged.add_sector(sector);
+    }
+
+    Some(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fdc::{FdcStatus1, FdcStatus2};
+    use crate::image::{Sector, SectorId};
+    use crate::io::bitstream::{encode_track, DD_TRACK_BITS};
+
+    /// Flux ticks (25ns) between the transitions of a cell stream at 2us a cell
+    fn stream_flux(stream: &BitStream) -> Vec<u16> {
+        let mut flux = Vec::new();
+        let mut cells = 0u16;
+        for i in 0..stream.len() {
+            cells += 1;
+            if stream.get(i) {
+                flux.push(cells * 80);
+                cells = 0;
+            }
+        }
+        flux
+    }
+
+    fn sample_track(weak: &[u8]) -> Track {
+        let mut track = Track::new(1, 0);
+        for i in 1..=4u8 {
+            let data = if i == 3 { weak.to_vec() } else { vec![i; 512] };
+            track.add_sector(Sector::with_data(SectorId::new(1, 0, i, 2), data));
+        }
+        // A sector too long to fit before the index
+        track.add_sector(Sector::with_data(SectorId::new(1, 0, 5, 6), vec![0xE5; 8192]));
+        let bad = track.get_sector_mut(3).unwrap();
+        bad.fdc_status1 = FdcStatus1::new(FdcStatus1::DE);
+        bad.fdc_status2 = FdcStatus2::new(FdcStatus2::DD);
+        track
+    }
+
+    /// Build an SCP file holding track 2 (cylinder 1, head 0)
+    fn sample_scp() -> Vec<u8> {
+        let revolutions: Vec<Vec<u16>> = [0x11u8, 0x22]
+            .iter()
+            .map(|&b| {
+                let mut stream = encode_track(&sample_track(&[b; 512]), DD_TRACK_BITS);
+                stream.truncate(DD_TRACK_BITS);
+                stream_flux(&stream)
+            })
+            .collect();
+
+        let mut data = vec![0u8; HEADER_SIZE + MAX_TRACKS * 4];
+        data[..3].copy_from_slice(SCP_SIGNATURE);
+        data[3] = 0x24;
+        data[5] = revolutions.len() as u8;
+        data[7] = 2;
+
+        let track_offset = data.len();
+        data[HEADER_SIZE + 8..HEADER_SIZE + 12].copy_from_slice(&(track_offset as u32).to_le_bytes());
+        data.extend_from_slice(TRACK_SIGNATURE);
+        data.push(2);
+
+        let mut flux_offset = 4 + revolutions.len() * 12;
+        for rev in &revolutions {
+            data.extend_from_slice(&200_000_000u32.to_le_bytes());
+            data.extend_from_slice(&(rev.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(flux_offset as u32).to_le_bytes());
+            flux_offset += rev.len() * 2;
+        }
+        for rev in &revolutions {
+            for &ticks in rev {
+                data.extend_from_slice(&ticks.to_be_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_is_scp_file() {
+        assert!(is_scp_file("dump.scp"));
+        assert!(is_scp_file("DUMP.SCP"));
+        assert!(!is_scp_file("dump.dsk"));
+    }
+
+    #[test]
+    fn test_read_flux_overflow() {
+        let flux = read_flux(&[0x00, 0x00, 0x00, 0x10, 0x00, 0x50], 0, 3, TICK_NS).unwrap();
+        assert_eq!(flux, vec![(0x10010 as f64) * TICK_NS, 80.0 * TICK_NS]);
+    }
+
+    #[test]
+    fn test_parse_scp() {
+        let image = parse_scp(&sample_scp()).unwrap();
+        assert_eq!(image.format(), DiskImageFormat::Scp);
+        assert_eq!(image.disks()[0].track_count(), 2);
+        assert!(image.disks()[0].get_track(0).unwrap().is_empty());
+
+        let track = image.disks()[0].get_track(1).unwrap();
+        assert_eq!(track.data_rate, DataRate::SingleDouble);
+        assert_eq!(track.sector_count(), 5);
+        assert_eq!(track.get_sector(1).unwrap().data(), &[1; 512][..]);
+        assert!(!track.get_sector(1).unwrap().has_error());
+
+        let weak = track.get_sector(3).unwrap();
+        assert!(weak.is_weak());
+        assert_eq!(weak.copies().collect::<Vec<_>>(), vec![&[0x11; 512][..], &[0x22; 512][..]]);
+
+        // The oversized sector reads on past the index
+        let long = track.get_sector(5).unwrap();
+        assert_eq!(long.data().len(), 8192);
+        assert!(long.fdc_status2.data_field_error());
+    }
+}
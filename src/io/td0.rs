@@ -0,0 +1,662 @@
+/// Teledisk (TD0) file reader
+///
+/// TD0 files from Sydex Teledisk hold:
+/// - A 12 byte header: "TD" (normal) or "td" (advanced compression),
+///   version, data rate, stepping (bit 7: comment present), sides and CRC
+/// - With advanced compression, everything after the header is LZHUF
+///   compressed (LZSS with adaptive Huffman coding)
+/// - An optional comment block: CRC, length, timestamp and text
+/// - For each track: sector count (0xFF ends the image), cylinder, head
+///   (bit 7: FM) and CRC, then each sector's CHRN, flags and CRC followed
+///   by its data block (raw, repeated 2 byte pattern or run-length encoded)
+///
+/// Duplicated sector IDs are kept as separate sectors on the track.
+///
+/// Teledisk images are read-only; they save as Extended DSK.
+
+use crate::error::{DskError, Result};
+use crate::fdc::{FdcStatus1, FdcStatus2};
+use crate::format::constants::fdc_size_to_bytes;
+use crate::format::DiskImageFormat;
+use crate::image::{DataRate, Disk, DiskImage, RecordingMode, Sector, SectorId, Track};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// TD0 signature for normal images
+pub const TD0_SIGNATURE: &[u8] = b"TD";
+
+/// TD0 signature for images with advanced (LZHUF) compression
+pub const TD0_ADVANCED_SIGNATURE: &[u8] = b"td";
+
+/// Size of the TD0 header
+const HEADER_SIZE: usize = 12;
+/// Size of the comment block header
+const COMMENT_HEADER_SIZE: usize = 10;
+/// Sector count marking the end of the image
+const END_OF_IMAGE: u8 = 0xFF;
+
+/// Sector flag: data read with a CRC error
+const SECTOR_CRC_ERROR: u8 = 0x02;
+/// Sector flag: deleted data address mark
+const SECTOR_DELETED: u8 = 0x04;
+/// Sector flag: data skipped as unallocated by DOS
+const SECTOR_SKIPPED: u8 = 0x10;
+/// Sector flag: ID field with no data field
+const SECTOR_NO_DATA: u8 = 0x20;
+
+/// Check if a file is likely a TD0 file based on extension
+pub fn is_td0_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("td0"))
+        .unwrap_or(false)
+}
+
+/// Read a TD0 file from disk
+pub fn read_td0<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
+    let filename = path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string());
+
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    let mut image = parse_td0(&data)?;
+    image.filename = filename;
+    Ok(image)
+}
+
+/// Teledisk CRC (polynomial 0xA097, initial value 0)
+fn td0_crc(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0xA097 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// Parse the contents of a TD0 file
+fn parse_td0(data: &[u8]) -> Result<DiskImage> {
+    if data.len() < HEADER_SIZE {
+        return Err(DskError::invalid_format("TD0 file too small for header"));
+    }
+
+    let advanced = data.starts_with(TD0_ADVANCED_SIGNATURE);
+    if !advanced && !data.starts_with(TD0_SIGNATURE) {
+        return Err(DskError::invalid_format("Missing TD0 signature"));
+    }
+
+    let header = &data[..HEADER_SIZE];
+    if td0_crc(&header[..10]) != u16::from_le_bytes([header[10], header[11]]) {
+        return Err(DskError::integrity("TD0 header CRC mismatch"));
+    }
+
+    let version = header[4];
+    if advanced && version < 20 {
+        return Err(DskError::invalid_format(format!(
+            "TD0 version {}.{} advanced compression is not supported",
+            version / 10,
+            version % 10
+        )));
+    }
+
+    let body = if advanced {
+        lzhuf_decompress(&data[HEADER_SIZE..])
+    } else {
+        data[HEADER_SIZE..].to_vec()
+    };
+
+    let data_rate = match header[5] & 0x03 {
+        2 => DataRate::High,
+        _ => DataRate::SingleDouble,
+    };
+    let fm_disk = header[5] & 0x80 != 0;
+
+    let mut pos = 0;
+    if header[7] & 0x80 != 0 {
+        let comment = take(&body, &mut pos, COMMENT_HEADER_SIZE)?;
+        let length = u16::from_le_bytes([comment[2], comment[3]]) as usize;
+        take(&body, &mut pos, length)?;
+    }
+
+    let mut tracks = Vec::new();
+    loop {
+        let count = take(&body, &mut pos, 1)?[0];
+        if count == END_OF_IMAGE {
+            break;
+        }
+
+        let track_header = take(&body, &mut pos, 3)?;
+        let (cylinder, head) = (track_header[0], track_header[1]);
+
+        let mut track = Track::new(cylinder, head & 0x01);
+        track.data_rate = data_rate;
+        track.recording_mode = if fm_disk || head & 0x80 != 0 {
+            RecordingMode::FM
+        } else {
+            RecordingMode::MFM
+        };
+
+        for _ in 0..count {
+            let sector = read_sector(&body, &mut pos, track.filler_byte)?;
+            track.add_sector(sector);
+        }
+        tracks.push(track);
+    }
+
+    let num_sides = tracks
+        .iter()
+        .map(|t| t.side_number + 1)
+        .chain(std::iter::once(if header[9] == 1 { 1 } else { 2 }))
+        .max()
+        .unwrap_or(1);
+    let num_tracks = tracks.iter().map(|t| t.track_number as usize + 1).max().unwrap_or(0);
+
+    let mut disks: Vec<Disk> = (0..num_sides).map(Disk::new).collect();
+    for disk in disks.iter_mut() {
+        for track_num in 0..num_tracks {
+            let track = tracks
+                .iter()
+                .find(|t| t.track_number as usize == track_num && t.side_number == disk.side_number)
+                .cloned()
+                .unwrap_or_else(|| Track::new(track_num as u8, disk.side_number));
+            disk.add_track(track);
+        }
+    }
+
+    let num_tracks = num_tracks.min(u8::MAX as usize) as u8;
+    let spec = crate::io::reader::build_format_spec(&disks, num_sides, num_tracks);
+
+    Ok(DiskImage {
+        format: DiskImageFormat::Td0,
+        spec,
+        disks,
+        changed: false,
+        filename: None,
+    })
+}
+
+/// Take `count` bytes at `pos`, moving past them
+fn take<'a>(data: &'a [u8], pos: &mut usize, count: usize) -> Result<&'a [u8]> {
+    let bytes = data
+        .get(*pos..*pos + count)
+        .ok_or_else(|| DskError::parse(*pos, "TD0 data truncated"))?;
+    *pos += count;
+    Ok(bytes)
+}
+
+/// Read a sector header and its data block
+fn read_sector(data: &[u8], pos: &mut usize, filler: u8) -> Result<Sector> {
+    let header = take(data, pos, 6)?;
+    let id = SectorId::new(header[0], header[1], header[2], header[3]);
+    let flags = header[4];
+    let size = fdc_size_to_bytes(id.size_code);
+
+    let (mut st1, mut st2) = (0, 0);
+    if flags & SECTOR_CRC_ERROR != 0 {
+        st1 |= FdcStatus1::DE;
+        st2 |= FdcStatus2::DD;
+    }
+    if flags & SECTOR_DELETED != 0 {
+        st2 |= FdcStatus2::CM;
+    }
+
+    let sector_data = if flags & SECTOR_NO_DATA != 0 {
+        st1 |= FdcStatus1::MA;
+        st2 |= FdcStatus2::MD;
+        Vec::new()
+    } else if flags & SECTOR_SKIPPED != 0 || id.size_code > 6 {
+        vec![filler; size]
+    } else {
+        let length = u16::from_le_bytes(take(data, pos, 2)?.try_into().unwrap()) as usize;
+        let block = take(data, pos, length)?;
+        decode_sector_data(block, size, *pos - length)?
+    };
+
+    Ok(Sector::with_status(id, FdcStatus1::new(st1), FdcStatus2::new(st2), sector_data))
+}
+
+/// Expand a sector data block (encoding byte followed by encoded data)
+fn decode_sector_data(block: &[u8], size: usize, offset: usize) -> Result<Vec<u8>> {
+    let (&method, encoded) = block
+        .split_first()
+        .ok_or_else(|| DskError::parse(offset, "Empty TD0 sector data block"))?;
+    let truncated = || DskError::parse(offset, "TD0 sector data block truncated");
+
+    let mut out = Vec::with_capacity(size);
+    match method {
+        // Raw
+        0 => out.extend_from_slice(encoded),
+        // Repeated 2 byte pattern
+        1 => {
+            let count = u16::from_le_bytes([
+                *encoded.first().ok_or_else(truncated)?,
+                *encoded.get(1).ok_or_else(truncated)?,
+            ]) as usize;
+            let pattern = encoded.get(2..4).ok_or_else(truncated)?;
+            for _ in 0..count {
+                out.extend_from_slice(pattern);
+            }
+        }
+        // Run-length encoded fragments
+        2 => {
+            let mut i = 0;
+            while out.len() < size && i < encoded.len() {
+                let kind = encoded[i];
+                let count = *encoded.get(i + 1).ok_or_else(truncated)? as usize;
+                i += 2;
+                if kind == 0 {
+                    out.extend_from_slice(encoded.get(i..i + count).ok_or_else(truncated)?);
+                    i += count;
+                } else {
+                    let length = kind as usize * 2;
+                    let pattern = encoded.get(i..i + length).ok_or_else(truncated)?;
+                    for _ in 0..count {
+                        out.extend_from_slice(pattern);
+                    }
+                    i += length;
+                }
+            }
+        }
+        _ => {
+            return Err(DskError::parse(
+                offset,
+                format!("Unknown TD0 sector encoding {}", method),
+            ))
+        }
+    }
+
+    out.resize(size, 0);
+    Ok(out)
+}
+
+/// LZHUF ring buffer size
+const LZ_N: usize = 4096;
+/// LZHUF longest match
+const LZ_F: usize = 60;
+/// LZHUF shortest match less one
+const LZ_THRESHOLD: usize = 2;
+/// Number of character codes (literals and match lengths)
+const LZ_N_CHAR: usize = 256 - LZ_THRESHOLD + LZ_F;
+/// Size of the Huffman tree
+const LZ_T: usize = LZ_N_CHAR * 2 - 1;
+/// Root of the Huffman tree
+const LZ_R: usize = LZ_T - 1;
+/// Frequency at which the tree is rebuilt
+const LZ_MAX_FREQ: u16 = 0x8000;
+
+/// Code lengths for the upper 6 bits of a match position
+const LZ_POSITION_LENGTHS: [(u8, usize); 6] = [(3, 1), (4, 3), (5, 8), (6, 12), (7, 24), (8, 16)];
+
+/// Adaptive Huffman tree shared by the LZHUF coder
+struct HuffmanTree {
+    freq: Vec<u16>,
+    parent: Vec<usize>,
+    son: Vec<usize>,
+}
+
+impl HuffmanTree {
+    fn new() -> Self {
+        let mut tree = Self {
+            freq: vec![0; LZ_T + 1],
+            parent: vec![0; LZ_T + LZ_N_CHAR],
+            son: vec![0; LZ_T],
+        };
+
+        for i in 0..LZ_N_CHAR {
+            tree.freq[i] = 1;
+            tree.son[i] = i + LZ_T;
+            tree.parent[i + LZ_T] = i;
+        }
+
+        let mut i = 0;
+        for j in LZ_N_CHAR..=LZ_R {
+            tree.freq[j] = tree.freq[i] + tree.freq[i + 1];
+            tree.son[j] = i;
+            tree.parent[i] = j;
+            tree.parent[i + 1] = j;
+            i += 2;
+        }
+
+        tree.freq[LZ_T] = 0xFFFF;
+        tree.parent[LZ_R] = 0;
+        tree
+    }
+
+    /// Rebuild the tree with halved frequencies
+    fn reconstruct(&mut self) {
+        let mut j = 0;
+        for i in 0..LZ_T {
+            if self.son[i] >= LZ_T {
+                self.freq[j] = self.freq[i].div_ceil(2);
+                self.son[j] = self.son[i];
+                j += 1;
+            }
+        }
+
+        let mut i = 0;
+        for j in LZ_N_CHAR..LZ_T {
+            let f = self.freq[i] + self.freq[i + 1];
+            let mut k = j;
+            while k > 0 && f < self.freq[k - 1] {
+                k -= 1;
+            }
+            self.freq.copy_within(k..j, k + 1);
+            self.freq[k] = f;
+            self.son.copy_within(k..j, k + 1);
+            self.son[k] = i;
+            i += 2;
+        }
+
+        for i in 0..LZ_T {
+            let k = self.son[i];
+            self.parent[k] = i;
+            if k < LZ_T {
+                self.parent[k + 1] = i;
+            }
+        }
+    }
+
+    /// Count one more use of code `c`, keeping the tree ordered
+    fn update(&mut self, c: usize) {
+        if self.freq[LZ_R] == LZ_MAX_FREQ {
+            self.reconstruct();
+        }
+
+        let mut c = self.parent[c + LZ_T];
+        loop {
+            self.freq[c] += 1;
+            let k = self.freq[c];
+
+            if k > self.freq[c + 1] {
+                let mut l = c + 1;
+                while k > self.freq[l + 1] {
+                    l += 1;
+                }
+                self.freq[c] = self.freq[l];
+                self.freq[l] = k;
+
+                let i = self.son[c];
+                self.parent[i] = l;
+                if i < LZ_T {
+                    self.parent[i + 1] = l;
+                }
+
+                let j = self.son[l];
+                self.son[l] = i;
+                self.parent[j] = c;
+                if j < LZ_T {
+                    self.parent[j + 1] = c;
+                }
+                self.son[c] = j;
+
+                c = l;
+            }
+
+            c = self.parent[c];
+            if c == 0 {
+                break;
+            }
+        }
+    }
+}
+
+/// Bit reader, most significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Option<usize> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as usize)
+    }
+
+    fn byte(&mut self) -> Option<usize> {
+        (0..8).try_fold(0, |acc, _| Some((acc << 1) | self.bit()?))
+    }
+}
+
+/// First-byte lookup of match position upper bits and their code lengths
+fn position_tables() -> ([u8; 256], [u8; 256]) {
+    let (mut code, mut len) = ([0u8; 256], [0u8; 256]);
+    let mut index = 0;
+    let mut value = 0u8;
+    for &(bits, count) in &LZ_POSITION_LENGTHS {
+        for _ in 0..count {
+            for _ in 0..1usize << (8 - bits) {
+                code[index] = value;
+                len[index] = bits;
+                index += 1;
+            }
+            value += 1;
+        }
+    }
+    (code, len)
+}
+
+/// Decompress Teledisk advanced compression (LZHUF) data
+///
+/// Decoding runs until the input is used up.
+fn lzhuf_decompress(input: &[u8]) -> Vec<u8> {
+    let (d_code, d_len) = position_tables();
+    let mut tree = HuffmanTree::new();
+    let mut bits = BitReader { data: input, pos: 0 };
+    let mut ring = [b' '; LZ_N];
+    let mut r = LZ_N - LZ_F;
+    let mut out = Vec::new();
+
+    'decode: loop {
+        let mut c = tree.son[LZ_R];
+        while c < LZ_T {
+            let Some(bit) = bits.bit() else {
+                break 'decode;
+            };
+            c = tree.son[c + bit];
+        }
+        c -= LZ_T;
+        tree.update(c);
+
+        if c < 256 {
+            out.push(c as u8);
+            ring[r] = c as u8;
+            r = (r + 1) & (LZ_N - 1);
+            continue;
+        }
+
+        let Some(first) = bits.byte() else {
+            break;
+        };
+        let mut i = first;
+        let upper = (d_code[first] as usize) << 6;
+        for _ in 0..d_len[first] - 2 {
+            let Some(bit) = bits.bit() else {
+                break 'decode;
+            };
+            i = (i << 1) | bit;
+        }
+        let position = upper | (i & 0x3F);
+
+        let start = (r + LZ_N - position - 1) & (LZ_N - 1);
+        for k in 0..c - 255 + LZ_THRESHOLD {
+            let byte = ring[(start + k) & (LZ_N - 1)];
+            out.push(byte);
+            ring[r] = byte;
+            r = (r + 1) & (LZ_N - 1);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bit writer for building LZHUF test input
+    struct BitWriter {
+        bytes: Vec<u8>,
+        len: usize,
+    }
+
+    impl BitWriter {
+        fn put(&mut self, bit: bool) {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if bit {
+                self.bytes[self.len / 8] |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+
+        fn put_bits(&mut self, value: usize, count: u8) {
+            for i in (0..count).rev() {
+                self.put(value & (1 << i) != 0);
+            }
+        }
+    }
+
+    /// Encode one character code the way LZHUF's encoder does
+    fn encode_char(tree: &mut HuffmanTree, out: &mut BitWriter, c: usize) {
+        let mut path = Vec::new();
+        let mut k = tree.parent[c + LZ_T];
+        loop {
+            path.push(k & 1 == 1);
+            k = tree.parent[k];
+            if k == LZ_R {
+                break;
+            }
+        }
+        for &bit in path.iter().rev() {
+            out.put(bit);
+        }
+        tree.update(c);
+    }
+
+    /// Encode a match position with the static position codes
+    fn encode_position(out: &mut BitWriter, position: usize) {
+        let (d_code, d_len) = position_tables();
+        let upper = (position >> 6) as u8;
+        let first = d_code.iter().position(|&c| c == upper).unwrap();
+        out.put_bits(first >> (8 - d_len[first]), d_len[first]);
+        out.put_bits(position & 0x3F, 6);
+    }
+
+    #[test]
+    fn test_lzhuf_decompress() {
+        let mut tree = HuffmanTree::new();
+        let mut out = BitWriter { bytes: Vec::new(), len: 0 };
+
+        // "ABCD" then a 6 byte match starting 4 bytes back: "ABCDAB"
+        for &c in b"ABCD" {
+            encode_char(&mut tree, &mut out, c as usize);
+        }
+        encode_char(&mut tree, &mut out, 255 - LZ_THRESHOLD + 6);
+        encode_position(&mut out, 3);
+
+        let decoded = lzhuf_decompress(&out.bytes);
+        assert_eq!(&decoded[..10], b"ABCDABCDAB");
+    }
+
+    #[test]
+    fn test_lzhuf_reconstruct() {
+        let mut tree = HuffmanTree::new();
+        let mut out = BitWriter { bytes: Vec::new(), len: 0 };
+
+        // Enough symbols to force the tree to be rebuilt
+        let text: Vec<u8> = (0..40_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for &c in &text {
+            encode_char(&mut tree, &mut out, c as usize);
+        }
+
+        let decoded = lzhuf_decompress(&out.bytes);
+        assert_eq!(&decoded[..text.len()], &text[..]);
+    }
+
+    #[test]
+    fn test_decode_sector_data() {
+        assert_eq!(decode_sector_data(&[0, 1, 2, 3, 4], 4, 0).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(decode_sector_data(&[1, 3, 0, 0xAB, 0xCD], 6, 0).unwrap(), vec![0xAB, 0xCD, 0xAB, 0xCD, 0xAB, 0xCD]);
+        assert_eq!(
+            decode_sector_data(&[2, 0, 2, 9, 8, 1, 2, 0xE5, 0xE6], 6, 0).unwrap(),
+            vec![9, 8, 0xE5, 0xE6, 0xE5, 0xE6]
+        );
+        assert!(decode_sector_data(&[7], 4, 0).is_err());
+    }
+
+    /// Build a small TD0 image body: one track of two sectors
+    fn sample_body() -> Vec<u8> {
+        let mut body = Vec::new();
+        // Comment block: CRC, length, timestamp, text
+        body.extend_from_slice(&[0, 0, 5, 0, 99, 0, 1, 0, 0, 0]);
+        body.extend_from_slice(b"test\0");
+        // Track 0, head 0, two sectors
+        body.extend_from_slice(&[2, 0, 0, 0]);
+        // Sector 1: 128 bytes of a repeated pattern, deleted with CRC error
+        body.extend_from_slice(&[0, 0, 1, 0, SECTOR_DELETED | SECTOR_CRC_ERROR, 0]);
+        body.extend_from_slice(&[5, 0, 1, 64, 0, 0x12, 0x34]);
+        // Sector 2: no data field
+        body.extend_from_slice(&[0, 0, 2, 0, SECTOR_NO_DATA, 0]);
+        body.push(END_OF_IMAGE);
+        body
+    }
+
+    fn sample_header(signature: &[u8]) -> Vec<u8> {
+        let mut header = signature.to_vec();
+        header.extend_from_slice(&[0, 0, 21, 0, 1, 0x80, 0, 1]);
+        let crc = td0_crc(&header);
+        header.extend_from_slice(&crc.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn test_parse_td0() {
+        let mut data = sample_header(TD0_SIGNATURE);
+        data.extend_from_slice(&sample_body());
+
+        let image = parse_td0(&data).unwrap();
+        assert_eq!(image.format(), DiskImageFormat::Td0);
+        assert_eq!(image.disk_count(), 1);
+
+        let track = image.disks()[0].get_track(0).unwrap();
+        assert_eq!(track.recording_mode, RecordingMode::MFM);
+
+        let first = track.get_sector(1).unwrap();
+        assert_eq!(&first.data()[..4], &[0x12, 0x34, 0x12, 0x34]);
+        assert_eq!(first.data().len(), 128);
+        assert!(first.is_deleted());
+        assert!(first.fdc_status2.data_field_error());
+
+        let second = track.get_sector(2).unwrap();
+        assert!(second.data().is_empty());
+        assert!(second.fdc_status2.missing_data_mark());
+
+        data[11] ^= 0xFF;
+        assert!(parse_td0(&data).is_err());
+    }
+
+    #[test]
+    fn test_parse_advanced_td0() {
+        let mut tree = HuffmanTree::new();
+        let mut out = BitWriter { bytes: Vec::new(), len: 0 };
+        for &c in &sample_body() {
+            encode_char(&mut tree, &mut out, c as usize);
+        }
+
+        let mut data = sample_header(TD0_ADVANCED_SIGNATURE);
+        data.extend_from_slice(&out.bytes);
+
+        let image = parse_td0(&data).unwrap();
+        let sector = image.disks()[0].get_track(0).unwrap().get_sector(1).unwrap();
+        assert_eq!(&sector.data()[..2], &[0x12, 0x34]);
+    }
+}
@@ -0,0 +1,143 @@
+/// TRD file reader and writer
+///
+/// TRD images are sector dumps of TR-DOS disks with no header:
+/// - 16 sectors of 256 bytes per track, sectors in order from sector 1
+/// - Logical tracks alternating between sides on double sided disks
+///
+/// The geometry is taken from the disk type in the TR-DOS information
+/// sector. Many TRD files stop after the last used track, so short files
+/// are padded out to the full disk.
+
+use crate::error::{DskError, Result};
+use crate::filesystem::trdos::{TrDosDiskInfo, TrDosFileSystem, TRDOS_ID, TRDOS_SECTOR_SIZE, TRDOS_SECTORS_PER_TRACK};
+use crate::format::DiskImageFormat;
+use crate::image::DiskImage;
+use crate::io::raw::{build_raw, parse_raw, spec_track_order};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Bytes in one logical TR-DOS track
+const TRD_TRACK_SIZE: usize = TRDOS_SECTORS_PER_TRACK as usize * TRDOS_SECTOR_SIZE;
+
+/// Offset of the disk type byte in a TRD file (track 0, sector 9, byte 0xE3)
+const TRD_DISK_TYPE_OFFSET: usize = 8 * TRDOS_SECTOR_SIZE + 0xE3;
+
+/// Offset of the TR-DOS ID byte in a TRD file (track 0, sector 9, byte 0xE7)
+const TRD_ID_OFFSET: usize = 8 * TRDOS_SECTOR_SIZE + 0xE7;
+
+/// Check if a file is likely a TRD file based on extension
+pub fn is_trd_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("trd"))
+        .unwrap_or(false)
+}
+
+/// Read a TRD file from disk
+pub fn read_trd<P: AsRef<Path>>(path: P) -> Result<DiskImage> {
+    let filename = path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string());
+
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    let mut image = parse_trd(data)?;
+    image.filename = filename;
+    Ok(image)
+}
+
+/// Parse the contents of a TRD file
+fn parse_trd(mut data: Vec<u8>) -> Result<DiskImage> {
+    if data.is_empty() || !data.len().is_multiple_of(TRDOS_SECTOR_SIZE) {
+        return Err(DskError::invalid_format(format!(
+            "TRD file size {} is not a whole number of sectors",
+            data.len()
+        )));
+    }
+
+    // Without a TR-DOS info sector assume the usual 80 track double sided disk
+    let (min_tracks, num_sides) = data
+        .get(TRD_ID_OFFSET)
+        .filter(|&&id| id == TRDOS_ID)
+        .and_then(|_| TrDosDiskInfo::geometry(data[TRD_DISK_TYPE_OFFSET]))
+        .unwrap_or((80, 2));
+
+    let logical_tracks = data.len().div_ceil(TRD_TRACK_SIZE);
+    let num_tracks = logical_tracks.div_ceil(num_sides as usize).max(min_tracks as usize);
+    if num_tracks > u8::MAX as usize {
+        return Err(DskError::invalid_format(format!("TRD file has {} tracks", num_tracks)));
+    }
+
+    let spec = TrDosFileSystem::format_spec(num_tracks as u8, num_sides);
+    data.resize(num_tracks * num_sides as usize * TRD_TRACK_SIZE, 0);
+
+    let mut image = parse_raw(&data, &spec, &spec_track_order(&spec))?;
+    image.format = DiskImageFormat::Trd;
+    Ok(image)
+}
+
+/// Write a TRD file
+///
+/// The image is written with 16 sectors of 256 bytes per track; missing
+/// sectors are written as zeros.
+pub fn write_trd<P: AsRef<Path>>(image: &DiskImage, path: P) -> Result<()> {
+    let data = build_trd(image);
+    File::create(path)?.write_all(&data)?;
+    Ok(())
+}
+
+/// Build the TRD file contents
+fn build_trd(image: &DiskImage) -> Vec<u8> {
+    let num_sides = image.disks.len().clamp(1, 2) as u8;
+    let spec = TrDosFileSystem::format_spec(image.spec.num_tracks, num_sides);
+    build_raw(image, &spec, &spec_track_order(&spec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::{FileSystem, FileSystemType};
+
+    #[test]
+    fn test_is_trd_file() {
+        assert!(is_trd_file("game.trd"));
+        assert!(is_trd_file("GAME.TRD"));
+        assert!(!is_trd_file("game.scl"));
+    }
+
+    #[test]
+    fn test_trd_round_trip() {
+        let mut image = TrDosFileSystem::blank_image(80, 2, "TEST").unwrap();
+        {
+            let mut fs = TrDosFileSystem::new_mut(&mut image).unwrap();
+            fs.write_file("DATA.C", &[0x5A; 5000]).unwrap();
+        }
+        assert_eq!(image.default_filesystem(), FileSystemType::TrDos);
+
+        let data = build_trd(&image);
+        assert_eq!(data.len(), 655_360);
+        assert_eq!(data[TRD_TRACK_SIZE], 0x5A);
+
+        let loaded = parse_trd(data.clone()).unwrap();
+        assert_eq!(loaded.format(), DiskImageFormat::Trd);
+        assert_eq!(build_trd(&loaded), data);
+
+        let dsk = std::env::temp_dir().join(format!("dsk_trd_dsk_{}.dsk", std::process::id()));
+        crate::io::write_dsk(&loaded, &dsk).unwrap();
+        let written = std::fs::read(&dsk).unwrap();
+        std::fs::remove_file(&dsk).unwrap();
+        assert!(written.starts_with(b"EXTENDED"));
+
+        // Files cut short after the last used track are padded
+        let truncated = parse_trd(data[..3 * TRD_TRACK_SIZE].to_vec()).unwrap();
+        assert_eq!(truncated.disk_count(), 2);
+        let fs = TrDosFileSystem::new(&truncated).unwrap();
+        assert_eq!(fs.read_file("DATA").unwrap(), vec![0x5A; 5000]);
+
+        assert!(parse_trd(vec![0; 100]).is_err());
+    }
+}
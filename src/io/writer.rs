@@ -1,6 +1,6 @@
 /// DSK file writer
 
-use crate::error::Result;
+use crate::error::{DskError, Result};
 use crate::format::constants::*;
 use crate::format::DiskImageFormat;
 use crate::image::DiskImage;
@@ -9,18 +9,37 @@ use std::io::{Write};
 use std::path::Path;
 
 /// Write a DSK file to disk
+///
+/// DSK and raw MGT images are written in their own format; images from
+/// formats only written to their own extension are written as Extended DSK.
 pub fn write_dsk<P: AsRef<Path>>(image: &DiskImage, path: P) -> Result<()> {
-    let mut file = File::create(path)?;
-
     match image.format {
-        DiskImageFormat::StandardDSK => write_standard_dsk(&mut file, image),
-        DiskImageFormat::ExtendedDSK => write_extended_dsk(&mut file, image),
-        DiskImageFormat::RawMgt => write_mgt(&mut file, image),
+        DiskImageFormat::StandardDSK => write_standard_dsk(&mut File::create(path)?, image),
+        DiskImageFormat::ExtendedDSK => write_extended_dsk(&mut File::create(path)?, image),
+        DiskImageFormat::RawMgt => write_mgt(image, path),
+        // IMD, HFE, raw dumps, SAD, TRD, SCL and OPD are only written to their
+        // own paths, and Teledisk and SCP images are read-only, so they save
+        // as Extended DSK
+        DiskImageFormat::Imd
+        | DiskImageFormat::Raw
+        | DiskImageFormat::Sad
+        | DiskImageFormat::Trd
+        | DiskImageFormat::Scl
+        | DiskImageFormat::Opd
+        | DiskImageFormat::HfeV1
+        | DiskImageFormat::HfeV3
+        | DiskImageFormat::Td0
+        | DiskImageFormat::Scp => write_extended_dsk(&mut File::create(path)?, image),
     }
 }
 
-/// Write a raw MGT file
-fn write_mgt(file: &mut File, image: &DiskImage) -> Result<()> {
+/// Write a raw MGT file to disk
+pub fn write_mgt<P: AsRef<Path>>(image: &DiskImage, path: P) -> Result<()> {
+    write_mgt_tracks(&mut File::create(path)?, image)
+}
+
+/// Write the tracks of a raw MGT file
+fn write_mgt_tracks(file: &mut File, image: &DiskImage) -> Result<()> {
     // MGT format: all side 0 tracks, then all side 1 tracks
     // 80 tracks per side, 2 sides, 10 sectors per track, 512 bytes per sector
 
@@ -88,7 +107,9 @@ fn write_standard_dsk(file: &mut File, image: &DiskImage) -> Result<()> {
     disk_info[DISK_INFO_SIDE_COUNT_OFFSET] = image.spec.num_sides;
 
     // Set track size (in bytes, little-endian)
-    let track_size_bytes = (track_size as u16).to_le_bytes();
+    let track_size_bytes = u16::try_from(track_size)
+        .map_err(|_| DskError::invalid_format(format!("Tracks of {} bytes are too long for Standard DSK", track_size)))?
+        .to_le_bytes();
     disk_info[DISK_INFO_TRACK_SIZE_OFFSET] = track_size_bytes[0];
     disk_info[DISK_INFO_TRACK_SIZE_OFFSET + 1] = track_size_bytes[1];
 
@@ -126,7 +147,12 @@ fn write_extended_dsk(file: &mut File, image: &DiskImage) -> Result<()> {
     for disk in &image.disks {
         for track in disk.tracks() {
             let track_size = calculate_single_track_size(track);
-            let size_units = ((track_size + 255) / 256) as u8; // Round up
+            let size_units = u8::try_from(track_size.div_ceil(256)).map_err(|_| {
+                DskError::invalid_format(format!(
+                    "Track {} side {} is {} bytes, too long for Extended DSK",
+                    track.track_number, track.side_number, track_size
+                ))
+            })?;
             let offset = DISK_INFO_EXT_TRACK_SIZE_OFFSET + track_index;
             if offset < disk_info.len() {
                 disk_info[offset] = size_units;
@@ -145,6 +171,33 @@ fn write_extended_dsk(file: &mut File, image: &DiskImage) -> Result<()> {
         }
     }
 
+    let has_positions = image
+        .disks
+        .iter()
+        .any(|disk| disk.tracks().iter().any(|t| t.has_positions()));
+    if has_positions {
+        write_offset_info(file, image)?;
+    }
+
+    Ok(())
+}
+
+/// Write the Offset-Info (V5 extension) block after the track data
+///
+/// Unknown lengths and offsets are written as 0, which reads back as unknown.
+fn write_offset_info(file: &mut File, image: &DiskImage) -> Result<()> {
+    let mut block = OFFSET_INFO_MARKER.to_vec();
+
+    for disk in &image.disks {
+        for track in disk.tracks() {
+            block.extend_from_slice(&track.track_length.unwrap_or(0).to_le_bytes());
+            for sector in track.sectors() {
+                block.extend_from_slice(&sector.offset.unwrap_or(0).to_le_bytes());
+            }
+        }
+    }
+
+    file.write_all(&block)?;
     Ok(())
 }
 
@@ -185,13 +238,19 @@ fn write_track(file: &mut File, track: &crate::image::Track, track_size: usize)
         sib[4] = sector.fdc_status1.0;
         sib[5] = sector.fdc_status2.0;
 
-        // Calculate stored size based on DSK format rules
-        let max_stored = fdc_size_to_stored_bytes(sector.id.size_code);
+        // Calculate stored size based on DSK format rules (weak sectors keep every copy)
         let sector_data = sector.data();
-        let stored_len = sector_data.len().min(max_stored);
+        let stored_len = sector.stored_size();
 
         // Write data length for extended format
-        let data_len_bytes = (stored_len as u16).to_le_bytes();
+        let data_len_bytes = u16::try_from(stored_len)
+            .map_err(|_| {
+                DskError::invalid_format(format!(
+                    "Sector {} on track {} side {} is {} bytes, too long for DSK",
+                    sector.id.sector, track.track_number, track.side_number, stored_len
+                ))
+            })?
+            .to_le_bytes();
         sib[6] = data_len_bytes[0];
         sib[7] = data_len_bytes[1];
 
@@ -209,12 +268,11 @@ fn write_track(file: &mut File, track: &crate::image::Track, track_size: usize)
 }
 
 /// Calculate the size of a track in bytes (including track info block)
-/// Uses DSK format stored size rules (max 6144 bytes per sector)
+/// Uses DSK format stored size rules (max 6144 bytes per sector, except weak copies)
 fn calculate_single_track_size(track: &crate::image::Track) -> usize {
     let mut size = TRACK_INFO_BLOCK_SIZE;
     for sector in track.sectors() {
-        let max_stored = fdc_size_to_stored_bytes(sector.id.size_code);
-        size += sector.actual_size().min(max_stored);
+        size += sector.stored_size();
     }
     size
 }
@@ -239,7 +297,8 @@ fn calculate_track_size(image: &DiskImage) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::image::{Sector, SectorId, Track};
+    use crate::format::DiskImageFormat;
+    use crate::image::{DiskImage, Sector, SectorId, Track};
 
     #[test]
     fn test_calculate_single_track_size() {
@@ -253,4 +312,93 @@ mod tests {
         let size = calculate_single_track_size(&track);
         assert_eq!(size, 256 + 9 * 512); // Track info + 9 * 512-byte sectors
     }
+
+    #[test]
+    fn test_weak_sector_round_trip() {
+        let mut image = DiskImage::builder()
+            .format(DiskImageFormat::ExtendedDSK)
+            .num_tracks(2)
+            .build()
+            .unwrap();
+
+        let copies: Vec<Vec<u8>> = (0..3u8).map(|c| vec![c; 512]).collect();
+        let copy_refs: Vec<&[u8]> = copies.iter().map(|c| c.as_slice()).collect();
+        let sector = image.disks[0].get_track_mut(1).unwrap().get_sector_mut(0xC2).unwrap();
+        sector.set_copies(&copy_refs).unwrap();
+
+        let track = image.disks[0].get_track(1).unwrap();
+        assert_eq!(calculate_single_track_size(track), 256 + 11 * 512);
+
+        let path = std::env::temp_dir().join(format!("dsk_weak_{}.dsk", std::process::id()));
+        write_dsk(&image, &path).unwrap();
+        let mut loaded = crate::io::reader::read_dsk(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let sector = loaded.disks[0].get_track(1).unwrap().get_sector(0xC2).unwrap();
+        assert_eq!(sector.copy_count(), 3);
+        assert_eq!(sector.copies().collect::<Vec<_>>(), copy_refs);
+
+        assert_eq!(loaded.read_sector(0, 1, 0xC2).unwrap(), &copies[0][..]);
+        let reads: Vec<u8> = (0..4)
+            .map(|_| loaded.fdc_read_sector(0, 1, 0xC2).unwrap()[0])
+            .collect();
+        assert_eq!(reads, vec![0, 1, 2, 0]);
+        assert_eq!(loaded.read_sector(0, 1, 0xC3).unwrap().len(), 512);
+    }
+
+    #[test]
+    fn test_weak_sector_too_long() {
+        let mut image = DiskImage::builder()
+            .format(DiskImageFormat::ExtendedDSK)
+            .num_tracks(2)
+            .build()
+            .unwrap();
+
+        // Eight 8K copies need more than the 16 bit data length
+        let sector = image.disks[0].get_track_mut(1).unwrap().get_sector_mut(0xC2).unwrap();
+        sector.id.size_code = 6;
+        let copy = vec![0x55; 8192];
+        sector.set_copies(&[&copy[..]; 8]).unwrap();
+        assert_eq!(sector.stored_size(), 65536);
+        assert_eq!(sector.copies().len(), 8);
+
+        let path = std::env::temp_dir().join(format!("dsk_weak_long_{}.dsk", std::process::id()));
+        let message = write_dsk(&image, &path).unwrap_err().to_string();
+        std::fs::remove_file(&path).ok();
+        assert!(message.contains("Track 1 side 0"), "{}", message);
+    }
+
+    #[test]
+    fn test_offset_info_round_trip() {
+        let mut image = DiskImage::builder()
+            .format(DiskImageFormat::ExtendedDSK)
+            .num_tracks(2)
+            .build()
+            .unwrap();
+
+        let track = image.disks[0].get_track_mut(1).unwrap();
+        track.track_length = Some(6250);
+        for (i, sector) in track.sectors_mut().iter_mut().enumerate() {
+            sector.offset = Some(100 + i as u16 * 650);
+        }
+
+        let path = std::env::temp_dir().join(format!("dsk_offsets_{}.dsk", std::process::id()));
+        write_dsk(&image, &path).unwrap();
+        let loaded = crate::io::reader::read_dsk(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let track0 = loaded.disks[0].get_track(0).unwrap();
+        assert!(!track0.has_positions());
+        assert_eq!(track0.track_length, None);
+        assert_eq!(track0.sectors()[0].offset, None);
+
+        let track1 = loaded.disks[0].get_track(1).unwrap();
+        assert_eq!(track1.track_length, Some(6250));
+        let positions = track1.sector_positions();
+        assert_eq!(positions.len(), 9);
+        assert_eq!(positions[0].offset, 100);
+        assert_eq!(positions[0].distance, Some(6250 - 5300 + 100));
+        assert_eq!(positions[1].distance, Some(650));
+        assert_eq!(positions[8].offset, 5300);
+    }
 }
@@ -7,7 +7,7 @@ A Rust library for reading and writing DSK disk image files with CP/M filesystem
 
 - Read and write Standard, Extended and SamDisk Extended DSK formats
 - Track and sector abstraction with FDC status codes
-- CP/M filesystem support for reading files
+- CP/M filesystem support for reading and writing files
 - Idiomatic Rust API with comprehensive error handling
 
 ## Quick Start
@@ -84,6 +84,10 @@ pub mod io;
 pub mod map;
 /// Copy protection detection
 pub mod protection;
+/// Screen file rendering to PNG and PPM images
+pub mod screen;
+/// ZX Spectrum snapshots in the .SNA and .Z80 formats
+pub mod snapshot;
 
 // Re-export common types
 pub use amstrad_basic::{decode_amstrad_basic, decode_amstrad_basic_file, can_decode_amstrad_basic};
@@ -92,16 +96,18 @@ pub use boot::BootDetection;
 pub use error::{DskError, Result};
 pub use fdc::{FdcStatus1, FdcStatus2};
 pub use filesystem::{
-    CpmFileSystem, DirEntry, DiscipleFileSystem, ExtendedDirEntry, FileAttributes, FileHeader,
+    CpmCheckReport, CpmFileSystem, CpmFormatter, CpmProblem, CpmProblemKind, DeletedFileReport, DirEntry, DiscipleFileSystem, EinsteinFileSystem, ExtendedDirEntry, FatBootSector, FatDirEntry, FatFileSystem, FileAttributes, FileHeader,
     FileSystem, FileSystemInfo, FileSystemType, HeaderType, MgtDirEntry, MgtFileSystem, MgtFileType,
-    MgtSystemType, SamFileSystem,
+    MgtSystemType, OpusDirEntry, OpusFileHeader, OpusFileSystem, OpusFileType, SamFileSystem, TrDosDirEntry, TrDosDiskInfo, TrDosFileSystem, TrDosFileType,
 };
-pub use filesystem::try_parse_header;
+pub use filesystem::{is_sam_disk, open_filesystem, open_filesystem_mut, try_parse_header};
 pub use format::{
     AllocationSize, DiskSpecSide, DiskSpecTrack, DiskSpecification, DiskImageFormat,
     FormatSpec, SideMode,
 };
 pub use image::{
-    DataRate, Disk, DiskImage, DiskImageBuilder, RecordingMode, Sector, SectorId, SectorStatus,
-    Track,
+    DataRate, Disk, DiskImage, DiskImageBuilder, RecordingMode, Sector, SectorId, SectorPosition,
+    SectorStatus, Track,
 };
+pub use screen::{render_cpc, render_sam, render_spectrum, Screen};
+pub use snapshot::{SpectrumSnapshot, Z80Registers};
@@ -104,6 +104,15 @@ fn get_track(disk: &Disk, track_idx: u8) -> Option<&Track> {
     disk.get_track(track_idx)
 }
 
+/// Describe the stored copies of a weak sector for a detection reason
+fn weak_copies(sector: &Sector) -> String {
+    if sector.is_weak() {
+        format!(", {} copies", sector.copy_count())
+    } else {
+        String::new()
+    }
+}
+
 /// Search for a signature pattern across all sectors
 fn find_signature_in_disk(disk: &Disk, pattern: &[u8]) -> Option<(usize, usize, usize)> {
     for t_idx in 0..disk.track_count() {
@@ -386,7 +395,7 @@ fn detect_speedlock(disk: &Disk) -> Option<ProtectionResult> {
                     if sector.id.sector == 193 && sector.fdc_status1.0 == 32 {
                         return Some(ProtectionResult::new(
                             "Speedlock 1989/1990",
-                            "probably, unsigned".to_string(),
+                            format!("probably, unsigned{}", weak_copies(sector)),
                         ));
                     }
                 }
@@ -615,7 +624,7 @@ fn detect_rainbow_arts(disk: &Disk) -> Option<ProtectionResult> {
         if sector.id.sector == 198 && sector.fdc_status1.0 == 32 && sector.fdc_status2.0 == 32 {
             return Some(ProtectionResult::new(
                 "Rainbow Arts",
-                format!("weak sector T40/S{}", s_idx),
+                format!("weak sector T40/S{}{}", s_idx, weak_copies(sector)),
             ));
         }
     }
@@ -696,7 +705,10 @@ fn detect_kbi(disk: &Disk) -> Option<ProtectionResult> {
         if track39.sector_count() == 10 && track38.sector_count() == 9 {
             let sector9 = track39.get_sector_by_index(9)?;
             if sector9.fdc_status1.0 == 32 && sector9.fdc_status2.0 == 32 {
-                return Some(ProtectionResult::new("KBI-10", "weak sector T39/S9".to_string()));
+                return Some(ProtectionResult::new(
+                    "KBI-10",
+                    format!("weak sector T39/S9{}", weak_copies(sector9)),
+                ));
             }
         }
     }
@@ -976,4 +988,32 @@ mod tests {
         let result = ProtectionResult::new("Speedlock 1987", "signed T0/S0 +42");
         assert_eq!(result.to_string(), "Speedlock 1987 (signed T0/S0 +42)");
     }
+
+    #[test]
+    fn test_kbi10_weak_copies() {
+        let mut disk = Disk::new(0);
+
+        for t in 0..40 {
+            let mut track = Track::new(t, 0);
+            let count = if t == 39 { 10 } else { 9 };
+            for s in 0..count {
+                let id = SectorId::new(t, 0, 1 + s, 2);
+                if t == 39 && s == 9 {
+                    let data: Vec<u8> = (0..3).flat_map(|c| vec![c as u8; 512]).collect();
+                    track.add_sector(Sector::with_status(
+                        id,
+                        crate::fdc::FdcStatus1::new(32),
+                        crate::fdc::FdcStatus2::new(32),
+                        data,
+                    ));
+                } else {
+                    track.add_sector(Sector::new(id));
+                }
+            }
+            disk.add_track(track);
+        }
+
+        let result = detect(&disk).unwrap();
+        assert_eq!(result.to_string(), "KBI-10 (weak sector T39/S9, 3 copies)");
+    }
 }
@@ -0,0 +1,474 @@
+/// Screen file rendering to PNG and PPM images
+///
+/// Decodes screen memory dumps from:
+/// - ZX Spectrum: 6912 byte SCREEN$ of 6144 bytes of pixels in the
+///   interleaved display file order followed by 768 attribute bytes
+/// - Amstrad CPC: 16K screens in mode 0 (16 colours), 1 (4 colours) or 2
+///   (2 colours), coloured by firmware colour numbers for each pen
+/// - SAM Coupe: mode 1 (Spectrum layout), mode 2 (linear with an attribute
+///   per byte), mode 3 (4 colours) and mode 4 (16 colours), optionally
+///   followed by the 16 byte palette saved with SAM SCREEN$ files
+///
+/// CPC screens are rendered 640 pixels wide in every mode so they keep
+/// their proportions; Spectrum and SAM screens at their own resolution.
+
+use crate::error::{DskError, Result};
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Size of a Spectrum SCREEN$ file
+pub const SPECTRUM_SCREEN_SIZE: usize = 6912;
+
+/// Size of the Spectrum (and SAM mode 1) pixel data
+const SPECTRUM_PIXELS_SIZE: usize = 6144;
+
+/// Width of Spectrum and SAM screens in pixels
+const SPECTRUM_WIDTH: usize = 256;
+
+/// Height of Spectrum and SAM screens in pixels
+const SPECTRUM_HEIGHT: usize = 192;
+
+/// Intensity of normal (not BRIGHT) Spectrum colours
+const SPECTRUM_NORMAL: u8 = 0xD7;
+
+/// Smallest CPC screen, up to the last byte of the last line
+pub const CPC_SCREEN_SIZE: usize = 16336;
+
+/// Width of CPC screens as rendered
+const CPC_WIDTH: usize = 640;
+
+/// Height of CPC screens in pixels
+const CPC_HEIGHT: usize = 200;
+
+/// Bytes in each line of a CPC screen
+const CPC_BYTES_PER_LINE: usize = 80;
+
+/// Firmware colours of the CPC pens at power on (flashing pens 14 and 15 in their first colour)
+pub const CPC_DEFAULT_PALETTE: [u8; 16] = [1, 24, 20, 6, 26, 0, 2, 8, 10, 12, 14, 16, 18, 22, 1, 16];
+
+/// Size of SAM mode 3 and 4 screens
+pub const SAM_SCREEN_SIZE: usize = 24576;
+
+/// Offset of the attributes in a SAM mode 2 screen
+const SAM_MODE2_ATTRIBUTES: usize = 8192;
+
+/// Size of a SAM mode 2 screen
+const SAM_MODE2_SIZE: usize = SAM_MODE2_ATTRIBUTES + SPECTRUM_PIXELS_SIZE;
+
+/// Bytes in each line of a SAM mode 3 or 4 screen
+const SAM_BYTES_PER_LINE: usize = 128;
+
+/// SAM colour look-up table at power on, matching the Spectrum colours
+pub const SAM_DEFAULT_PALETTE: [u8; 16] = [0, 17, 34, 51, 68, 85, 102, 119, 8, 25, 42, 59, 76, 93, 110, 127];
+
+/// Decoded screen image in 24-bit RGB
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Screen {
+    /// Create a screen by colouring each pixel, row by row
+    fn from_fn(width: usize, height: usize, mut colour: impl FnMut(usize, usize) -> [u8; 3]) -> Self {
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                rgb.extend_from_slice(&colour(x, y));
+            }
+        }
+        Self { width, height, rgb }
+    }
+
+    /// Width in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Colour of a pixel
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let offset = (y * self.width + x) * 3;
+        [self.rgb[offset], self.rgb[offset + 1], self.rgb[offset + 2]]
+    }
+
+    /// Build a binary PPM (P6) image
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend_from_slice(&self.rgb);
+        out
+    }
+
+    /// Build a PNG image
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        // Each row is preceded by its filter type, 0 for none
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in self.rgb.chunks_exact(self.width * 3) {
+            encoder.write_all(&[0])?;
+            encoder.write_all(row)?;
+        }
+        let image_data = encoder.finish()?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, deflate, no filter choice, no interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_png_chunk(&mut out, b"IHDR", &header);
+        write_png_chunk(&mut out, b"IDAT", &image_data);
+        write_png_chunk(&mut out, b"IEND", &[]);
+        Ok(out)
+    }
+
+    /// Save the screen, as PNG or PPM according to the file extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let data = match extension.as_deref() {
+            Some("png") => self.to_png()?,
+            Some("ppm") => self.to_ppm(),
+            _ => {
+                return Err(DskError::invalid_format(
+                    "Screen images must have a .png or .ppm extension",
+                ))
+            }
+        };
+        File::create(path)?.write_all(&data)?;
+        Ok(())
+    }
+}
+
+/// Append a PNG chunk with its length and CRC
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Check a screen has enough data
+fn check_size(data: &[u8], size: usize, what: &str) -> Result<()> {
+    if data.len() < size {
+        return Err(DskError::invalid_format(format!(
+            "{} needs {} bytes, got {}",
+            what,
+            size,
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Offset of a pixel row in the Spectrum display file
+///
+/// The screen is in three thirds of 64 rows, each holding the first row
+/// of its 8 character rows, then the second and so on.
+fn spectrum_row_offset(y: usize) -> usize {
+    ((y & 0xC0) << 5) | ((y & 0x07) << 8) | ((y & 0x38) << 2)
+}
+
+/// Ink and paper numbers (0-15, 8-15 BRIGHT) for an attribute byte
+///
+/// `flash_swapped` gives the second FLASH frame, where flashing cells
+/// have their ink and paper swapped.
+fn attribute_colours(attribute: u8, flash_swapped: bool) -> (u8, u8) {
+    let bright = (attribute & 0x40) >> 3;
+    let ink = (attribute & 0x07) | bright;
+    let paper = ((attribute >> 3) & 0x07) | bright;
+    if flash_swapped && attribute & 0x80 != 0 {
+        (paper, ink)
+    } else {
+        (ink, paper)
+    }
+}
+
+/// RGB for a Spectrum colour (0-15, 8-15 BRIGHT)
+fn spectrum_rgb(colour: u8) -> [u8; 3] {
+    let level = if colour & 0x08 != 0 { 0xFF } else { SPECTRUM_NORMAL };
+    let on = |bit: u8| if colour & bit != 0 { level } else { 0 };
+    [on(0x02), on(0x04), on(0x01)]
+}
+
+/// Render a Spectrum SCREEN$
+///
+/// `flash_swapped` renders the second FLASH frame, with the ink and paper
+/// of flashing cells swapped.
+pub fn render_spectrum(data: &[u8], flash_swapped: bool) -> Result<Screen> {
+    check_size(data, SPECTRUM_SCREEN_SIZE, "Spectrum screen")?;
+    Ok(Screen::from_fn(SPECTRUM_WIDTH, SPECTRUM_HEIGHT, |x, y| {
+        let (ink, paper) = attribute_colours(data[SPECTRUM_PIXELS_SIZE + (y / 8) * 32 + x / 8], flash_swapped);
+        let set = data[spectrum_row_offset(y) + x / 8] & (0x80 >> (x % 8)) != 0;
+        spectrum_rgb(if set { ink } else { paper })
+    }))
+}
+
+/// RGB for a CPC firmware colour number (0-26)
+///
+/// Colour numbers are 9 × green + 3 × red + blue, each at one of three levels.
+pub fn cpc_rgb(colour: u8) -> [u8; 3] {
+    let colour = colour.min(26);
+    let level = |value: u8| [0x00, 0x80, 0xFF][value as usize];
+    [level((colour / 3) % 3), level(colour / 9), level(colour % 3)]
+}
+
+/// Render a CPC screen in mode 0, 1 or 2
+///
+/// `palette` gives the firmware colour of each pen, as set by INK in a
+/// loader; the power on colours are used without one.
+pub fn render_cpc(data: &[u8], mode: u8, palette: Option<&[u8]>) -> Result<Screen> {
+    check_size(data, CPC_SCREEN_SIZE, "CPC screen")?;
+    let pixels_per_byte = match mode {
+        0 => 2,
+        1 => 4,
+        2 => 8,
+        _ => return Err(DskError::invalid_format(format!("CPC screen mode {} is not 0, 1 or 2", mode))),
+    };
+    let palette = palette.unwrap_or(&CPC_DEFAULT_PALETTE);
+    let scale = CPC_WIDTH / (CPC_BYTES_PER_LINE * pixels_per_byte);
+
+    Ok(Screen::from_fn(CPC_WIDTH, CPC_HEIGHT, |x, y| {
+        let x = x / scale;
+        let byte = data[(y / 8) * CPC_BYTES_PER_LINE + (y % 8) * 2048 + x / pixels_per_byte];
+        let pixel = x % pixels_per_byte;
+        let bit = |n: usize| ((byte >> n) & 1) as usize;
+        let pen = match mode {
+            // Pixel bits are spread across the byte, lowest pen bit leftmost
+            0 => bit(7 - pixel) | bit(3 - pixel) << 1 | bit(5 - pixel) << 2 | bit(1 - pixel) << 3,
+            1 => bit(7 - pixel) | bit(3 - pixel) << 1,
+            _ => bit(7 - pixel),
+        };
+        cpc_rgb(palette.get(pen).copied().unwrap_or(CPC_DEFAULT_PALETTE[pen]))
+    }))
+}
+
+/// Pen colours set by INK statements in a Locomotive BASIC listing
+///
+/// Starts from the power on colours; returns `None` if the listing sets no
+/// inks. Flashing inks take their first colour.
+pub fn cpc_palette_from_basic(listing: &str) -> Option<[u8; 16]> {
+    let mut palette = CPC_DEFAULT_PALETTE;
+    let mut found = false;
+    let upper = listing.to_uppercase();
+
+    for (start, _) in upper.match_indices("INK") {
+        // Skip keywords ending in INK, such as a variable named THINK
+        if upper[..start].chars().last().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            continue;
+        }
+        let mut numbers = upper[start + 3..]
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .take(2)
+            .map(|s| s.trim_end_matches(|c: char| !c.is_ascii_digit()).parse::<usize>());
+        if let (Some(Ok(pen)), Some(Ok(colour))) = (numbers.next(), numbers.next()) {
+            if pen < 16 && colour <= 26 {
+                palette[pen] = colour as u8;
+                found = true;
+            }
+        }
+    }
+    found.then_some(palette)
+}
+
+/// RGB for a SAM palette colour (0-127)
+///
+/// Bits 4-6 are the high bits of blue, red and green, bits 0-2 the low
+/// bits, and bit 3 brightens all three.
+pub fn sam_rgb(colour: u8) -> [u8; 3] {
+    let bright = (colour >> 3) & 1;
+    let channel = |low: u8, high: u8| {
+        let level = ((colour >> high) & 1) << 2 | ((colour >> low) & 1) << 1 | bright;
+        (level as u16 * 255 / 7) as u8
+    };
+    [channel(1, 5), channel(2, 6), channel(0, 4)]
+}
+
+/// Render a SAM screen in mode 1, 2, 3 or 4
+///
+/// The 16 byte colour look-up table saved after the screen in SAM SCREEN$
+/// files is used if present, otherwise the power on palette.
+/// `flash_swapped` renders the second FLASH frame of mode 1 and 2 screens.
+pub fn render_sam(data: &[u8], mode: u8, flash_swapped: bool) -> Result<Screen> {
+    let size = match mode {
+        1 => SPECTRUM_SCREEN_SIZE,
+        2 => SAM_MODE2_SIZE,
+        3 | 4 => SAM_SCREEN_SIZE,
+        _ => return Err(DskError::invalid_format(format!("SAM screen mode {} is not 1 to 4", mode))),
+    };
+    check_size(data, size, "SAM screen")?;
+    let palette = data.get(size..size + 16).unwrap_or(&SAM_DEFAULT_PALETTE);
+    let colour = |pen: u8| sam_rgb(palette[pen as usize & 0x0F]);
+
+    Ok(match mode {
+        1 => Screen::from_fn(SPECTRUM_WIDTH, SPECTRUM_HEIGHT, |x, y| {
+            let (ink, paper) = attribute_colours(data[SPECTRUM_PIXELS_SIZE + (y / 8) * 32 + x / 8], flash_swapped);
+            let set = data[spectrum_row_offset(y) + x / 8] & (0x80 >> (x % 8)) != 0;
+            colour(if set { ink } else { paper })
+        }),
+        2 => Screen::from_fn(SPECTRUM_WIDTH, SPECTRUM_HEIGHT, |x, y| {
+            let offset = y * 32 + x / 8;
+            let (ink, paper) = attribute_colours(data[SAM_MODE2_ATTRIBUTES + offset], flash_swapped);
+            colour(if data[offset] & (0x80 >> (x % 8)) != 0 { ink } else { paper })
+        }),
+        3 => Screen::from_fn(SPECTRUM_WIDTH * 2, SPECTRUM_HEIGHT, |x, y| {
+            let byte = data[y * SAM_BYTES_PER_LINE + x / 4];
+            colour((byte >> (6 - (x % 4) * 2)) & 0x03)
+        }),
+        _ => Screen::from_fn(SPECTRUM_WIDTH, SPECTRUM_HEIGHT, |x, y| {
+            let byte = data[y * SAM_BYTES_PER_LINE + x / 2];
+            colour(if x % 2 == 0 { byte >> 4 } else { byte & 0x0F })
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spectrum() {
+        let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/SCREEN.SCR")).unwrap();
+        let screen = render_spectrum(&data, false).unwrap();
+        assert_eq!((screen.width(), screen.height()), (256, 192));
+
+        // Black ink on white paper throughout
+        let ink_pixels: usize = data[..6144].iter().map(|b| b.count_ones() as usize).sum();
+        let black = (0..192)
+            .flat_map(|y| (0..256).map(move |x| (x, y)))
+            .filter(|&(x, y)| screen.pixel(x, y) == [0, 0, 0])
+            .count();
+        assert_eq!(black, ink_pixels);
+        assert_eq!(screen.pixel(0, 0), [0xD7, 0xD7, 0xD7]);
+
+        // Row 1 is 256 bytes on, row 8 is 32 bytes on; flashing bright red on blue
+        let mut data = vec![0u8; SPECTRUM_SCREEN_SIZE];
+        data[256] = 0x80;
+        data[32] = 0x01;
+        data[6144] = 0x80 | 0x40 | (1 << 3) | 2;
+        let screen = render_spectrum(&data, false).unwrap();
+        assert_eq!(screen.pixel(0, 1), [0xFF, 0, 0]);
+        assert_eq!(screen.pixel(1, 1), [0, 0, 0xFF]);
+        assert_eq!(screen.pixel(7, 8), [0, 0, 0]);
+        let screen = render_spectrum(&data, true).unwrap();
+        assert_eq!(screen.pixel(0, 1), [0, 0, 0xFF]);
+
+        assert!(render_spectrum(&data[..6000], false).is_err());
+    }
+
+    #[test]
+    fn test_cpc() {
+        assert_eq!(cpc_rgb(0), [0, 0, 0]);
+        assert_eq!(cpc_rgb(1), [0, 0, 0x80]);
+        assert_eq!(cpc_rgb(6), [0xFF, 0, 0]);
+        assert_eq!(cpc_rgb(24), [0xFF, 0xFF, 0]);
+        assert_eq!(cpc_rgb(26), [0xFF, 0xFF, 0xFF]);
+
+        // Second line starts at 2048; mode 1 pixel 0 uses bits 7 and 3
+        let mut data = vec![0u8; 16384];
+        data[2048] = 0x88;
+        data[2049] = 0x80;
+        let screen = render_cpc(&data, 1, None).unwrap();
+        assert_eq!((screen.width(), screen.height()), (640, 200));
+        assert_eq!(screen.pixel(0, 1), cpc_rgb(6));
+        assert_eq!(screen.pixel(1, 1), cpc_rgb(6));
+        assert_eq!(screen.pixel(2, 1), cpc_rgb(1));
+        assert_eq!(screen.pixel(8, 1), cpc_rgb(24));
+
+        // Mode 0 pixel 0 uses bits 7, 3, 5 and 1
+        data[80] = 0xAA;
+        let screen = render_cpc(&data, 0, Some(&[0; 16])).unwrap();
+        assert_eq!(screen.pixel(0, 8), cpc_rgb(0));
+        let mut palette = [0u8; 16];
+        palette[15] = 26;
+        let screen = render_cpc(&data, 0, Some(&palette)).unwrap();
+        assert_eq!(screen.pixel(3, 8), cpc_rgb(26));
+        assert_eq!(screen.pixel(4, 8), cpc_rgb(0));
+
+        let screen = render_cpc(&data, 2, None).unwrap();
+        assert_eq!(screen.pixel(0, 8), cpc_rgb(24));
+        assert_eq!(screen.pixel(1, 8), cpc_rgb(1));
+
+        assert!(render_cpc(&data, 3, None).is_err());
+        assert!(render_cpc(&data[..16000], 1, None).is_err());
+    }
+
+    #[test]
+    fn test_cpc_palette_from_basic() {
+        let listing = "10 MODE 1:BORDER 0:INK 0,0:INK 1,26\n20 ink 3 , 13:PRINT \"THINK 2,2\"\n";
+        let palette = cpc_palette_from_basic(listing).unwrap();
+        assert_eq!(&palette[..4], &[0, 26, 20, 13]);
+        assert_eq!(palette[4..], CPC_DEFAULT_PALETTE[4..]);
+        assert!(cpc_palette_from_basic("10 PRINT \"HELLO\"").is_none());
+    }
+
+    #[test]
+    fn test_sam() {
+        assert_eq!(sam_rgb(0), [0, 0, 0]);
+        assert_eq!(sam_rgb(127), [255, 255, 255]);
+        assert_eq!(sam_rgb(34), [218, 0, 0]);
+        assert_eq!(sam_rgb(8), [36, 36, 36]);
+
+        let mut data = vec![0u8; SAM_SCREEN_SIZE];
+        data[128] = 0x2F;
+        let screen = render_sam(&data, 4, false).unwrap();
+        assert_eq!((screen.width(), screen.height()), (256, 192));
+        assert_eq!(screen.pixel(0, 1), sam_rgb(34));
+        assert_eq!(screen.pixel(1, 1), sam_rgb(127));
+
+        let screen = render_sam(&data, 3, false).unwrap();
+        assert_eq!(screen.width(), 512);
+        assert_eq!(screen.pixel(0, 1), sam_rgb(0));
+        assert_eq!(screen.pixel(1, 1), sam_rgb(34));
+        assert_eq!(screen.pixel(3, 1), sam_rgb(51));
+
+        // Palette saved after the screen
+        data.extend_from_slice(&[100; 16]);
+        assert_eq!(render_sam(&data, 4, false).unwrap().pixel(0, 0), sam_rgb(100));
+
+        // Mode 2 attributes at 8192, one per byte
+        let mut data = vec![0u8; SAM_MODE2_SIZE];
+        data[32] = 0x80;
+        data[8192 + 32] = 0x42;
+        let screen = render_sam(&data, 2, false).unwrap();
+        assert_eq!(screen.pixel(0, 1), sam_rgb(SAM_DEFAULT_PALETTE[10]));
+        assert_eq!(screen.pixel(0, 0), sam_rgb(0));
+
+        assert!(render_sam(&data, 5, false).is_err());
+        assert!(render_sam(&data[..100], 1, false).is_err());
+    }
+
+    #[test]
+    fn test_output() {
+        let screen = Screen::from_fn(3, 2, |x, y| [x as u8, y as u8, 7]);
+        let ppm = screen.to_ppm();
+        assert!(ppm.starts_with(b"P6\n3 2\n255\n"));
+        assert_eq!(ppm.len(), 11 + 18);
+
+        let png = screen.to_png().unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x03\0\0\0\x02\x08\x02"));
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+
+        let mut decoder = flate2::read::ZlibDecoder::new(&png[33 + 8..png.len() - 16]);
+        let mut rows = Vec::new();
+        std::io::Read::read_to_end(&mut decoder, &mut rows).unwrap();
+        assert_eq!(rows, [0, 0, 0, 7, 1, 0, 7, 2, 0, 7, 0, 0, 1, 7, 1, 1, 7, 2, 1, 7]);
+
+        assert!(screen.save("screen.gif").is_err());
+    }
+}
@@ -0,0 +1,453 @@
+/// ZX Spectrum snapshots in the .SNA and .Z80 formats
+///
+/// A snapshot holds the Z80 registers and the RAM of a 48K or 128K
+/// Spectrum. Snapshots are read from disk filesystems (such as the
+/// DISCiPLE/+D) and written in the two formats most emulators load:
+/// - .SNA: 27 byte register header followed by the RAM; 48K snapshots keep
+///   the program counter on the stack
+/// - .Z80: version 3 header followed by the RAM pages, each compressed
+///   with the ED ED run length encoding
+
+use crate::error::{DskError, Result};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Size of a Spectrum memory page
+pub const SPECTRUM_PAGE_SIZE: usize = 16384;
+
+/// RAM pages in the order they appear from address 16384 on a 48K Spectrum
+const PAGES_48K: [u8; 3] = [5, 2, 0];
+
+/// Length of the .SNA register header
+const SNA_HEADER_SIZE: usize = 27;
+
+/// Length of the .Z80 version 3 additional header
+const Z80_V3_HEADER_SIZE: u16 = 54;
+
+/// .Z80 block length marking an uncompressed page
+const Z80_UNCOMPRESSED: u16 = 0xFFFF;
+
+/// Z80 processor registers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Z80Registers {
+    /// AF register pair (A in the high byte)
+    pub af: u16,
+    /// BC register pair
+    pub bc: u16,
+    /// DE register pair
+    pub de: u16,
+    /// HL register pair
+    pub hl: u16,
+    /// Alternate AF' register pair
+    pub af_alt: u16,
+    /// Alternate BC' register pair
+    pub bc_alt: u16,
+    /// Alternate DE' register pair
+    pub de_alt: u16,
+    /// Alternate HL' register pair
+    pub hl_alt: u16,
+    /// IX index register
+    pub ix: u16,
+    /// IY index register
+    pub iy: u16,
+    /// Stack pointer
+    pub sp: u16,
+    /// Program counter
+    pub pc: u16,
+    /// Interrupt vector register
+    pub i: u8,
+    /// Memory refresh register
+    pub r: u8,
+    /// Interrupt flip-flop 1 (interrupts enabled)
+    pub iff1: bool,
+    /// Interrupt flip-flop 2
+    pub iff2: bool,
+    /// Interrupt mode (0-2)
+    pub im: u8,
+}
+
+/// ZX Spectrum snapshot
+#[derive(Debug, Clone)]
+pub struct SpectrumSnapshot {
+    /// Processor state
+    pub registers: Z80Registers,
+    /// Border colour (0-7)
+    pub border: u8,
+    /// Last value written to port 0x7FFD, selecting the paged RAM and ROM (128K only)
+    pub port_7ffd: u8,
+    /// RAM from 16384 (48K) or RAM pages 0-7 in order (128K)
+    ram: Vec<u8>,
+}
+
+impl SpectrumSnapshot {
+    /// Create a 48K snapshot from the 48K of RAM from address 16384
+    pub fn new_48k(registers: Z80Registers, ram: &[u8]) -> Result<Self> {
+        if ram.len() != PAGES_48K.len() * SPECTRUM_PAGE_SIZE {
+            return Err(DskError::invalid_format(format!(
+                "48K snapshot RAM is {} bytes, expected 49152",
+                ram.len()
+            )));
+        }
+        Ok(Self {
+            registers,
+            border: 0,
+            port_7ffd: 0,
+            ram: ram.to_vec(),
+        })
+    }
+
+    /// Create a 128K snapshot from RAM pages 0-7 and the paging port value
+    pub fn new_128k(registers: Z80Registers, port_7ffd: u8, ram: &[u8]) -> Result<Self> {
+        if ram.len() != 8 * SPECTRUM_PAGE_SIZE {
+            return Err(DskError::invalid_format(format!(
+                "128K snapshot RAM is {} bytes, expected 131072",
+                ram.len()
+            )));
+        }
+        Ok(Self {
+            registers,
+            border: 0,
+            port_7ffd,
+            ram: ram.to_vec(),
+        })
+    }
+
+    /// Check whether this is a 128K snapshot
+    pub fn is_128k(&self) -> bool {
+        self.ram.len() > PAGES_48K.len() * SPECTRUM_PAGE_SIZE
+    }
+
+    /// RAM page paged in at 49152
+    pub fn paged_bank(&self) -> u8 {
+        if self.is_128k() {
+            self.port_7ffd & 0x07
+        } else {
+            0
+        }
+    }
+
+    /// Contents of a RAM page, if the machine has it
+    ///
+    /// A 48K Spectrum has pages 5, 2 and 0, at 16384, 32768 and 49152.
+    pub fn page(&self, bank: u8) -> Option<&[u8]> {
+        let index = if self.is_128k() {
+            (bank < 8).then_some(bank as usize)?
+        } else {
+            PAGES_48K.iter().position(|&b| b == bank)?
+        };
+        Some(&self.ram[index * SPECTRUM_PAGE_SIZE..(index + 1) * SPECTRUM_PAGE_SIZE])
+    }
+
+    /// Offset into the RAM of an address, or `None` for the ROM
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        let bank = match address >> 14 {
+            0 => return None,
+            1 => 5,
+            2 => 2,
+            _ => self.paged_bank(),
+        };
+        let index = if self.is_128k() {
+            bank as usize
+        } else {
+            PAGES_48K.iter().position(|&b| b == bank)?
+        };
+        Some(index * SPECTRUM_PAGE_SIZE + (address as usize % SPECTRUM_PAGE_SIZE))
+    }
+
+    /// Read a byte of memory, or `None` for the ROM
+    pub fn read_byte(&self, address: u16) -> Option<u8> {
+        self.ram_offset(address).map(|offset| self.ram[offset])
+    }
+
+    /// Read a little endian word of memory
+    fn read_word(&self, address: u16) -> Option<u16> {
+        let low = self.read_byte(address)?;
+        let high = self.read_byte(address.wrapping_add(1))?;
+        Some(u16::from_le_bytes([low, high]))
+    }
+
+    /// Pop a word from the stack, failing if the stack is in ROM
+    pub(crate) fn pop(&mut self) -> Result<u16> {
+        let value = self
+            .read_word(self.registers.sp)
+            .ok_or_else(|| DskError::filesystem("Snapshot stack is outside RAM"))?;
+        self.registers.sp = self.registers.sp.wrapping_add(2);
+        Ok(value)
+    }
+
+    /// Build the .SNA file contents
+    ///
+    /// 48K snapshots push the program counter onto the stack, so need two
+    /// free bytes of RAM below the stack pointer.
+    pub fn to_sna(&self) -> Result<Vec<u8>> {
+        let regs = &self.registers;
+        let mut sp = regs.sp;
+        let mut ram = self.ram.clone();
+
+        if !self.is_128k() {
+            sp = sp.wrapping_sub(2);
+            let (low, high) = match (self.ram_offset(sp), self.ram_offset(sp.wrapping_add(1))) {
+                (Some(low), Some(high)) => (low, high),
+                _ => return Err(DskError::filesystem("No room in RAM to push the program counter")),
+            };
+            let [pc_low, pc_high] = regs.pc.to_le_bytes();
+            ram[low] = pc_low;
+            ram[high] = pc_high;
+        }
+
+        let mut out = Vec::with_capacity(SNA_HEADER_SIZE + ram.len() + 4);
+        out.push(regs.i);
+        for pair in [regs.hl_alt, regs.de_alt, regs.bc_alt, regs.af_alt, regs.hl, regs.de, regs.bc, regs.iy, regs.ix] {
+            out.extend_from_slice(&pair.to_le_bytes());
+        }
+        out.push(if regs.iff2 { 0x04 } else { 0x00 });
+        out.push(regs.r);
+        out.extend_from_slice(&regs.af.to_le_bytes());
+        out.extend_from_slice(&sp.to_le_bytes());
+        out.push(regs.im);
+        out.push(self.border & 0x07);
+
+        let page = |bank: u8| &ram[bank as usize * SPECTRUM_PAGE_SIZE..(bank as usize + 1) * SPECTRUM_PAGE_SIZE];
+        if !self.is_128k() {
+            out.extend_from_slice(&ram);
+        } else {
+            // Pages 5, 2 and the paged page, then the rest in order
+            let paged = self.paged_bank();
+            for bank in [5, 2, paged] {
+                out.extend_from_slice(page(bank));
+            }
+            out.extend_from_slice(&regs.pc.to_le_bytes());
+            out.push(self.port_7ffd);
+            out.push(0);
+            for bank in (0..8).filter(|&b| b != 5 && b != 2 && b != paged) {
+                out.extend_from_slice(page(bank));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Build the .Z80 (version 3) file contents
+    pub fn to_z80(&self) -> Vec<u8> {
+        let regs = &self.registers;
+        let [a, f] = regs.af.to_be_bytes();
+        let [a_alt, f_alt] = regs.af_alt.to_be_bytes();
+
+        let mut out = Vec::with_capacity(32 + Z80_V3_HEADER_SIZE as usize + self.ram.len());
+        out.extend_from_slice(&[a, f]);
+        out.extend_from_slice(&regs.bc.to_le_bytes());
+        out.extend_from_slice(&regs.hl.to_le_bytes());
+        // A zero program counter marks a version 2 or 3 file
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&regs.sp.to_le_bytes());
+        out.push(regs.i);
+        out.push(regs.r & 0x7F);
+        out.push((regs.r >> 7) | ((self.border & 0x07) << 1));
+        for pair in [regs.de, regs.bc_alt, regs.de_alt, regs.hl_alt] {
+            out.extend_from_slice(&pair.to_le_bytes());
+        }
+        out.extend_from_slice(&[a_alt, f_alt]);
+        out.extend_from_slice(&regs.iy.to_le_bytes());
+        out.extend_from_slice(&regs.ix.to_le_bytes());
+        out.push(regs.iff1 as u8);
+        out.push(regs.iff2 as u8);
+        out.push(regs.im & 0x03);
+
+        // Additional header: program counter, hardware and paging, the rest zero
+        let mut extra = vec![0u8; Z80_V3_HEADER_SIZE as usize];
+        extra[0..2].copy_from_slice(&regs.pc.to_le_bytes());
+        if self.is_128k() {
+            extra[2] = 4;
+            extra[3] = self.port_7ffd;
+        }
+        out.extend_from_slice(&Z80_V3_HEADER_SIZE.to_le_bytes());
+        out.extend_from_slice(&extra);
+
+        // Pages are numbered 3-10 for 128K RAM pages 0-7; 8, 4 and 5 on a 48K
+        let blocks: Vec<(u8, &[u8])> = if self.is_128k() {
+            (0..8).map(|bank| (bank + 3, self.page(bank).unwrap_or_default())).collect()
+        } else {
+            [(8, 5), (4, 2), (5, 0)]
+                .iter()
+                .map(|&(number, bank)| (number, self.page(bank).unwrap_or_default()))
+                .collect()
+        };
+        for (number, data) in blocks {
+            let packed = compress_z80(data);
+            if packed.len() < SPECTRUM_PAGE_SIZE {
+                out.extend_from_slice(&(packed.len() as u16).to_le_bytes());
+                out.push(number);
+                out.extend_from_slice(&packed);
+            } else {
+                out.extend_from_slice(&Z80_UNCOMPRESSED.to_le_bytes());
+                out.push(number);
+                out.extend_from_slice(data);
+            }
+        }
+        out
+    }
+
+    /// Save the snapshot, as .SNA or .Z80 according to the file extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let data = match extension.as_deref() {
+            Some("sna") => self.to_sna()?,
+            Some("z80") => self.to_z80(),
+            _ => {
+                return Err(DskError::invalid_format(
+                    "Snapshot files must have a .sna or .z80 extension",
+                ))
+            }
+        };
+        File::create(path)?.write_all(&data)?;
+        Ok(())
+    }
+}
+
+/// Compress a page with the .Z80 run length encoding
+///
+/// Runs of five or more bytes, and runs of two or more ED bytes, become
+/// ED ED count byte. A single ED is followed by a literal byte so it can't
+/// start a run marker.
+fn compress_z80(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        let run = data[i..].iter().take(255).take_while(|&&b| b == byte).count();
+        if run >= 5 || (byte == 0xED && run >= 2) {
+            out.extend_from_slice(&[0xED, 0xED, run as u8, byte]);
+            i += run;
+        } else {
+            out.push(byte);
+            i += 1;
+            if byte == 0xED && i < data.len() {
+                out.push(data[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress_z80(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            if data[i..].starts_with(&[0xED, 0xED]) {
+                out.extend(std::iter::repeat_n(data[i + 3], data[i + 2] as usize));
+                i += 4;
+            } else {
+                out.push(data[i]);
+                i += 1;
+            }
+        }
+        out
+    }
+
+    fn registers() -> Z80Registers {
+        Z80Registers {
+            af: 0x1244,
+            bc: 0x0102,
+            hl: 0x0304,
+            de: 0x0506,
+            af_alt: 0xAABB,
+            iy: 0x5C3A,
+            sp: 0x8000,
+            pc: 0x1234,
+            i: 0x3F,
+            r: 0x85,
+            iff1: true,
+            iff2: true,
+            im: 1,
+            ..Z80Registers::default()
+        }
+    }
+
+    #[test]
+    fn test_compress_z80() {
+        let data = [1, 1, 1, 1, 1, 0xED, 0xED, 0xED, 0, 2, 2, 0xED, 3];
+        let packed = compress_z80(&data);
+        assert_eq!(packed, [0xED, 0xED, 5, 1, 0xED, 0xED, 3, 0xED, 0, 2, 2, 0xED, 3]);
+        assert_eq!(decompress_z80(&packed), data);
+
+        // A single ED keeps the next byte out of a run
+        let data = [0xED, 7, 7, 7, 7, 7, 7];
+        assert_eq!(compress_z80(&data), [0xED, 7, 0xED, 0xED, 5, 7]);
+    }
+
+    #[test]
+    fn test_48k() {
+        let mut ram = vec![0u8; 49152];
+        ram[0x4000] = 0x42;
+        let mut snapshot = SpectrumSnapshot::new_48k(registers(), &ram).unwrap();
+        snapshot.border = 2;
+        assert!(!snapshot.is_128k());
+        assert_eq!(snapshot.read_byte(0x8000), Some(0x42));
+        assert_eq!(snapshot.read_byte(0x1000), None);
+
+        let sna = snapshot.to_sna().unwrap();
+        assert_eq!(sna.len(), 27 + 49152);
+        assert_eq!(&sna[21..27], &[0x44, 0x12, 0xFE, 0x7F, 1, 2]);
+        assert_eq!(sna[19], 0x04);
+        assert_eq!(&sna[27 + 0x3FFE..27 + 0x4001], &[0x34, 0x12, 0x42]);
+
+        let z80 = snapshot.to_z80();
+        assert_eq!(&z80[..2], &[0x12, 0x44]);
+        assert_eq!(&z80[6..13], &[0, 0, 0x00, 0x80, 0x3F, 0x05, 0x05]);
+        assert_eq!(&z80[30..34], &[54, 0, 0x34, 0x12]);
+        assert_eq!(z80[34], 0);
+        let block = &z80[86..];
+        let length = u16::from_le_bytes([block[0], block[1]]) as usize;
+        assert_eq!(block[2], 8);
+        assert_eq!(decompress_z80(&block[3..3 + length]), vec![0; 16384]);
+        let block = &block[3 + length..];
+        let length = u16::from_le_bytes([block[0], block[1]]) as usize;
+        assert_eq!(block[2], 4);
+        assert_eq!(decompress_z80(&block[3..3 + length])[0], 0x42);
+
+        let mut snapshot = SpectrumSnapshot::new_48k(Z80Registers::default(), &ram).unwrap();
+        snapshot.registers.sp = 0x4000;
+        assert!(snapshot.to_sna().is_err());
+        assert!(snapshot.pop().is_ok());
+        assert!(SpectrumSnapshot::new_48k(registers(), &ram[..100]).is_err());
+    }
+
+    #[test]
+    fn test_128k() {
+        let ram: Vec<u8> = (0..8).flat_map(|bank| vec![bank as u8; 16384]).collect();
+        let snapshot = SpectrumSnapshot::new_128k(registers(), 0x13, &ram).unwrap();
+        assert!(snapshot.is_128k());
+        assert_eq!(snapshot.paged_bank(), 3);
+        assert_eq!(snapshot.read_byte(0xC000), Some(3));
+        assert_eq!(snapshot.read_byte(0x4000), Some(5));
+
+        let sna = snapshot.to_sna().unwrap();
+        assert_eq!(sna.len(), 27 + 8 * 16384 + 4);
+        let banks: Vec<u8> = [27, 27 + 16384, 27 + 32768, 27 + 49156, 27 + 65540]
+            .iter()
+            .map(|&offset| sna[offset])
+            .collect();
+        assert_eq!(banks, [5, 2, 3, 0, 1]);
+        assert_eq!(&sna[27 + 49152..27 + 49156], &[0x34, 0x12, 0x13, 0]);
+
+        let z80 = snapshot.to_z80();
+        assert_eq!(&z80[34..36], &[4, 0x13]);
+        let mut block = &z80[86..];
+        for bank in 0..8u8 {
+            let length = u16::from_le_bytes([block[0], block[1]]) as usize;
+            assert_eq!(block[2], bank + 3);
+            assert_eq!(decompress_z80(&block[3..3 + length]), vec![bank; 16384]);
+            block = &block[3 + length..];
+        }
+        assert!(block.is_empty());
+    }
+}